@@ -0,0 +1,467 @@
+use std::collections::BTreeMap;
+use std::fmt::{self, Display, Formatter};
+use std::rc::Rc;
+
+use object::builtins::BuiltIns;
+use object::{CompiledFunction, Object};
+use parser::lexer::token::Span;
+
+use crate::compiler::Bytecode;
+use crate::op_code::{Instructions, OpCodeError, Opcode, disassemble_instruction};
+use crate::source_map::{Mapping, SourceMap};
+use crate::verifier::{VerifyError, verify};
+use crate::vm::GLOBAL_SIZE;
+
+// A compiled program on disk (`.mkc`). All integers are big-endian:
+//
+//     magic "MKC\0", format version: u16
+//     constant count: u32, then per constant a tag byte and
+//         0 integer:  i64
+//         1 string:   length: u32, UTF-8 bytes
+//         2 function: name length: u32, UTF-8 name, locals: u32, parameters: u32, length: u32, instructions
+//         3 float:    IEEE 754 bits: u64
+//     instruction length: u32, instructions
+//     source length: u32, UTF-8 source, empty if it wasn't saved
+//     main mapping count: u32, then per mapping
+//         instruction offset: u32, span start: u32, span end: u32
+//     function count: u32, then per function
+//         constant index: u32, mapping count: u32, mappings as above
+pub const MAGIC: &[u8; 4] = b"MKC\0";
+pub const FORMAT_VERSION: u16 = 3;
+
+const TAG_INTEGER: u8 = 0;
+const TAG_STRING: u8 = 1;
+const TAG_FUNCTION: u8 = 2;
+const TAG_FLOAT: u8 = 3;
+
+/// The part of an artifact that could not be encoded or decoded.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Section {
+    Header,
+    ConstantCount,
+    Constant(usize),
+    Instructions,
+    SourceMap,
+}
+
+impl Display for Section {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Section::Header => write!(f, "header"),
+            Section::ConstantCount => write!(f, "constant count"),
+            Section::Constant(index) => write!(f, "constant {}", index),
+            Section::Instructions => write!(f, "instructions"),
+            Section::SourceMap => write!(f, "source map"),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum ArtifactErrorKind {
+    NotAnArtifact,
+    UnsupportedVersion(u16),
+    Truncated,
+    UnknownConstantTag(u8),
+    InvalidUtf8,
+    TrailingBytes(usize),
+    UnsupportedConstant(&'static str),
+    TooLarge,
+}
+
+impl Display for ArtifactErrorKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            ArtifactErrorKind::NotAnArtifact => write!(f, "not a compiled monkey program"),
+            ArtifactErrorKind::UnsupportedVersion(version) => write!(
+                f,
+                "format version {} is not supported (expected {})",
+                version, FORMAT_VERSION
+            ),
+            ArtifactErrorKind::Truncated => write!(f, "unexpected end of file"),
+            ArtifactErrorKind::UnknownConstantTag(tag) => {
+                write!(f, "unknown constant tag 0x{:02x}", tag)
+            }
+            ArtifactErrorKind::InvalidUtf8 => write!(f, "string is not valid UTF-8"),
+            ArtifactErrorKind::TrailingBytes(count) => {
+                write!(f, "{} unexpected byte(s) after the source map", count)
+            }
+            ArtifactErrorKind::UnsupportedConstant(type_name) => {
+                write!(f, "{} constants cannot be saved", type_name)
+            }
+            ArtifactErrorKind::TooLarge => write!(f, "too large to save"),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ArtifactError {
+    pub section: Section,
+    pub kind: ArtifactErrorKind,
+}
+
+impl Display for ArtifactError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.section, self.kind)
+    }
+}
+
+/// Why [`load`] refused an artifact.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum LoadError {
+    Decode(ArtifactError),
+    Verify(Vec<VerifyError>),
+}
+
+impl Display for LoadError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            LoadError::Decode(err) => write!(f, "{}", err),
+            LoadError::Verify(errors) => {
+                let messages: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+                write!(f, "{}", messages.join("\n"))
+            }
+        }
+    }
+}
+
+fn error(section: Section, kind: ArtifactErrorKind) -> ArtifactError {
+    ArtifactError { section, kind }
+}
+
+/// A program read back from an artifact, with the source it was compiled
+/// from so runtime errors can point into it.
+pub struct Artifact {
+    pub bytecode: Bytecode,
+    /// Empty if the artifact was saved without its source.
+    pub source: String,
+}
+
+/// Saves `bytecode` together with `source`, the program it was compiled
+/// from, and its source map. With an empty `source` the source map is
+/// left out too, as its spans would point at nothing.
+pub fn encode(bytecode: &Bytecode, source: &str) -> Result<Vec<u8>, ArtifactError> {
+    let mut bytes = MAGIC.to_vec();
+    bytes.extend_from_slice(&FORMAT_VERSION.to_be_bytes());
+    push_len(&mut bytes, bytecode.constants.len(), Section::ConstantCount)?;
+    for (index, constant) in bytecode.constants.iter().enumerate() {
+        let section = Section::Constant(index);
+        match &**constant {
+            Object::Integer(value) => {
+                bytes.push(TAG_INTEGER);
+                bytes.extend_from_slice(&value.to_be_bytes());
+            }
+            Object::String(value) => {
+                bytes.push(TAG_STRING);
+                push_len(&mut bytes, value.len(), section)?;
+                bytes.extend_from_slice(value.as_bytes());
+            }
+            Object::CompiledFunction(function) => {
+                bytes.push(TAG_FUNCTION);
+                push_len(&mut bytes, function.name.len(), section)?;
+                bytes.extend_from_slice(function.name.as_bytes());
+                push_len(&mut bytes, function.num_locals, section)?;
+                push_len(&mut bytes, function.num_parameters, section)?;
+                push_len(&mut bytes, function.instructions.len(), section)?;
+                bytes.extend_from_slice(&function.instructions);
+            }
+            Object::Float(value) => {
+                bytes.push(TAG_FLOAT);
+                bytes.extend_from_slice(&value.to_bits().to_be_bytes());
+            }
+            other => {
+                return Err(error(
+                    section,
+                    ArtifactErrorKind::UnsupportedConstant(other.type_name()),
+                ));
+            }
+        }
+    }
+    let instructions = &bytecode.instructions.bytes;
+    push_len(&mut bytes, instructions.len(), Section::Instructions)?;
+    bytes.extend_from_slice(instructions);
+
+    let section = Section::SourceMap;
+    push_len(&mut bytes, source.len(), section)?;
+    bytes.extend_from_slice(source.as_bytes());
+    let empty = SourceMap::default();
+    let source_map = if source.is_empty() {
+        &empty
+    } else {
+        &bytecode.source_map
+    };
+    push_mappings(&mut bytes, &source_map.main)?;
+    push_len(&mut bytes, source_map.functions.len(), section)?;
+    for (index, mappings) in &source_map.functions {
+        push_len(&mut bytes, *index, section)?;
+        push_mappings(&mut bytes, mappings)?;
+    }
+    Ok(bytes)
+}
+
+fn push_mappings(bytes: &mut Vec<u8>, mappings: &[Mapping]) -> Result<(), ArtifactError> {
+    let section = Section::SourceMap;
+    push_len(bytes, mappings.len(), section)?;
+    for mapping in mappings {
+        push_len(bytes, mapping.offset, section)?;
+        push_len(bytes, mapping.span.start, section)?;
+        push_len(bytes, mapping.span.end, section)?;
+    }
+    Ok(())
+}
+
+fn push_len(bytes: &mut Vec<u8>, len: usize, section: Section) -> Result<(), ArtifactError> {
+    let len = u32::try_from(len).map_err(|_| error(section, ArtifactErrorKind::TooLarge))?;
+    bytes.extend_from_slice(&len.to_be_bytes());
+    Ok(())
+}
+
+/// Reads an artifact and checks its bytecode with [`verify`], as anything
+/// read from a file should be before a VM runs it.
+pub fn load(bytes: &[u8]) -> Result<Artifact, LoadError> {
+    let artifact = decode(bytes).map_err(LoadError::Decode)?;
+    verify(&artifact.bytecode).map_err(LoadError::Verify)?;
+    Ok(artifact)
+}
+
+/// Reads an artifact back. The instructions themselves are not checked;
+/// use [`load`] to run `verifier::verify` on them too.
+pub fn decode(bytes: &[u8]) -> Result<Artifact, ArtifactError> {
+    let mut reader = Reader { bytes, pos: 0 };
+
+    let magic = reader.take(MAGIC.len(), Section::Header)?;
+    if magic != MAGIC {
+        return Err(error(Section::Header, ArtifactErrorKind::NotAnArtifact));
+    }
+    let version = u16::from_be_bytes(reader.array(Section::Header)?);
+    if version != FORMAT_VERSION {
+        return Err(error(
+            Section::Header,
+            ArtifactErrorKind::UnsupportedVersion(version),
+        ));
+    }
+
+    let count = reader.len(Section::ConstantCount)?;
+    let mut constants = Vec::new();
+    for index in 0..count {
+        let section = Section::Constant(index);
+        let constant = match reader.array::<1>(section)?[0] {
+            TAG_INTEGER => Object::Integer(i64::from_be_bytes(reader.array(section)?)),
+            TAG_STRING => Object::String(reader.string(section)?.into()),
+            TAG_FUNCTION => {
+                let name = reader.string(section)?;
+                let num_locals = reader.len(section)?;
+                let num_parameters = reader.len(section)?;
+                let len = reader.len(section)?;
+                let instructions = reader.take(len, section)?.to_vec();
+                Object::CompiledFunction(Rc::new(CompiledFunction {
+                    instructions,
+                    num_locals,
+                    num_parameters,
+                    name,
+                }))
+            }
+            TAG_FLOAT => Object::Float(f64::from_bits(u64::from_be_bytes(reader.array(section)?))),
+            tag => {
+                return Err(error(section, ArtifactErrorKind::UnknownConstantTag(tag)));
+            }
+        };
+        constants.push(Rc::new(constant));
+    }
+
+    let len = reader.len(Section::Instructions)?;
+    let instructions = reader.take(len, Section::Instructions)?.to_vec();
+
+    let source = reader.string(Section::SourceMap)?;
+    let main = reader.mappings()?;
+    let mut functions = BTreeMap::new();
+    for _ in 0..reader.len(Section::SourceMap)? {
+        let index = reader.len(Section::SourceMap)?;
+        functions.insert(index, reader.mappings()?);
+    }
+    let trailing = bytes.len() - reader.pos;
+    if trailing > 0 {
+        return Err(error(
+            Section::SourceMap,
+            ArtifactErrorKind::TrailingBytes(trailing),
+        ));
+    }
+
+    Ok(Artifact {
+        bytecode: Bytecode {
+            instructions: Instructions {
+                bytes: instructions,
+            },
+            constants,
+            source_map: SourceMap { main, functions },
+            host_builtins: Default::default(),
+            // not stored, so every slot is made available
+            num_globals: GLOBAL_SIZE,
+        },
+        source,
+    })
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, count: usize, section: Section) -> Result<&'a [u8], ArtifactError> {
+        let bytes = self
+            .pos
+            .checked_add(count)
+            .and_then(|end| self.bytes.get(self.pos..end))
+            .ok_or_else(|| error(section, ArtifactErrorKind::Truncated))?;
+        self.pos += count;
+        Ok(bytes)
+    }
+
+    fn array<const N: usize>(&mut self, section: Section) -> Result<[u8; N], ArtifactError> {
+        let bytes = self.take(N, section)?;
+        Ok(bytes.try_into().expect("take returns exactly N bytes"))
+    }
+
+    fn len(&mut self, section: Section) -> Result<usize, ArtifactError> {
+        Ok(u32::from_be_bytes(self.array(section)?) as usize)
+    }
+
+    fn mappings(&mut self) -> Result<Vec<Mapping>, ArtifactError> {
+        let count = self.len(Section::SourceMap)?;
+        let mut mappings = Vec::new();
+        for _ in 0..count {
+            let offset = self.len(Section::SourceMap)?;
+            let start = self.len(Section::SourceMap)?;
+            let end = self.len(Section::SourceMap)?;
+            mappings.push(Mapping {
+                offset,
+                span: Span { start, end },
+            });
+        }
+        Ok(mappings)
+    }
+
+    fn string(&mut self, section: Section) -> Result<String, ArtifactError> {
+        let len = self.len(section)?;
+        match std::str::from_utf8(self.take(len, section)?) {
+            Ok(value) => Ok(value.to_string()),
+            Err(_) => Err(error(section, ArtifactErrorKind::InvalidUtf8)),
+        }
+    }
+}
+
+/// A readable listing of `bytecode` that needs no source: the constant
+/// pool, with each function disassembled under its constant, then the
+/// top-level instructions and a summary. `OpConst`, `OpClosure` and
+/// `OpGetBuiltin` are annotated with what they refer to, and jumps with
+/// their target.
+pub fn describe(bytecode: &Bytecode) -> Result<String, (Section, OpCodeError)> {
+    let mut output = format!("format version {}\n", FORMAT_VERSION);
+    let mut instruction_count = 0;
+
+    output.push_str(&format!("\nconstants ({}):\n", bytecode.constants.len()));
+    for (index, constant) in bytecode.constants.iter().enumerate() {
+        match &**constant {
+            Object::CompiledFunction(function) => {
+                output.push_str(&format!(
+                    "{:>4}: function ({} parameter(s), {} local(s))\n",
+                    index, function.num_parameters, function.num_locals
+                ));
+                let (listing, count) =
+                    listing(&function.instructions, &bytecode.constants, "        ")
+                        .map_err(|e| (Section::Constant(index), e))?;
+                output.push_str(&listing);
+                instruction_count += count;
+            }
+            Object::String(value) => {
+                output.push_str(&format!("{:>4}: string {:?}\n", index, value))
+            }
+            other => output.push_str(&format!(
+                "{:>4}: {} {}\n",
+                index,
+                other.type_name().to_lowercase(),
+                other
+            )),
+        }
+    }
+
+    output.push_str("\ninstructions:\n");
+    let (listing, count) = listing(&bytecode.instructions.bytes, &bytecode.constants, "")
+        .map_err(|e| (Section::Instructions, e))?;
+    output.push_str(&listing);
+    instruction_count += count;
+
+    output.push_str(&format!(
+        "\n{} instruction(s) in {} byte(s), {} constant(s)\n",
+        instruction_count,
+        bytecode.instructions.len(),
+        bytecode.constants.len()
+    ));
+    Ok(output)
+}
+
+/// What `--disassemble` prints: the top-level instructions, then each
+/// function under a header naming its constant, annotated as in
+/// [`describe`].
+pub fn disassemble(bytecode: &Bytecode) -> Result<String, (Section, OpCodeError)> {
+    let (main, _) = listing(&bytecode.instructions.bytes, &bytecode.constants, "")
+        .map_err(|e| (Section::Instructions, e))?;
+    let mut output = format!("== main ==\n{}", main);
+    for (index, constant) in bytecode.constants.iter().enumerate() {
+        if let Object::CompiledFunction(function) = &**constant {
+            let (body, _) = listing(&function.instructions, &bytecode.constants, "")
+                .map_err(|e| (Section::Constant(index), e))?;
+            output.push_str(&format!(
+                "\n== fn constant {} ({}, {}) ==\n{}",
+                index,
+                counted(function.num_parameters, "param"),
+                counted(function.num_locals, "local"),
+                body
+            ));
+        }
+    }
+    Ok(output)
+}
+
+fn counted(count: usize, noun: &str) -> String {
+    format!("{} {}{}", count, noun, if count == 1 { "" } else { "s" })
+}
+
+fn listing(
+    instructions: &[u8],
+    constants: &[Rc<Object>],
+    indent: &str,
+) -> Result<(String, usize), OpCodeError> {
+    let mut output = String::new();
+    let mut count = 0;
+    let mut pos = 0;
+    while pos < instructions.len() {
+        let (text, width) = disassemble_instruction(instructions, pos)?;
+        output.push_str(&format!("{}{:04} {}", indent, pos, text));
+        if let Some(note) = annotation(instructions, pos, constants) {
+            output.push_str(&format!(" ; {}", note));
+        }
+        output.push('\n');
+        count += 1;
+        pos += width;
+    }
+    Ok((output, count))
+}
+
+fn annotation(instructions: &[u8], pos: usize, constants: &[Rc<Object>]) -> Option<String> {
+    let operand = |width: usize| -> usize {
+        instructions[pos + 1..pos + 1 + width]
+            .iter()
+            .fold(0, |value, &byte| (value << 8) | byte as usize)
+    };
+    match Opcode::try_from(instructions[pos]).ok()? {
+        Opcode::OpConst => Some(match &**constants.get(operand(2))? {
+            Object::String(value) => format!("{:?}", value),
+            other => other.to_string(),
+        }),
+        Opcode::OpClosure => Some(format!("function {}", operand(2))),
+        Opcode::OpGetBuiltin => Some(BuiltIns.get(operand(1))?.0.to_string()),
+        Opcode::OpJump | Opcode::OpJumpNotTruthy => Some(format!("-> {:04}", operand(2))),
+        _ => None,
+    }
+}
@@ -0,0 +1,290 @@
+#[cfg(test)]
+mod tests {
+    use crate::artifact::{
+        ArtifactError, ArtifactErrorKind, FORMAT_VERSION, LoadError, MAGIC, Section, decode,
+        describe, disassemble, encode, load,
+    };
+    use crate::compiler::{Bytecode, Compiler};
+    use crate::op_code::{Instructions, OpCodeError, Opcode, make_instructions};
+    use crate::source_map::line_column;
+    use crate::verifier::{Location, VerifyError, VerifyErrorKind};
+    use crate::vm::VM;
+    use object::{CompiledFunction, Object};
+    use parser::parse;
+    use std::rc::Rc;
+
+    const PROGRAM: &str = "let greet = fn(name) { \"hello \" + name }; \
+        let adder = fn(x) { fn(y) { x + y } }; \
+        len(greet(\"monkey\")) + adder(1)(2)";
+
+    fn compile(input: &str) -> Bytecode {
+        let program = parse(input).unwrap();
+        Compiler::new().compile(&program).unwrap()
+    }
+
+    fn run(bytecode: Bytecode) -> Rc<Object> {
+        let mut vm = VM::new(bytecode);
+        vm.run().unwrap();
+        vm.last_popped_stack_elm().unwrap().into_rc_object()
+    }
+
+    fn decode_error(bytes: &[u8]) -> ArtifactError {
+        match decode(bytes) {
+            Ok(_) => panic!("decoded corrupted artifact {:?}", bytes),
+            Err(e) => e,
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let bytecode = compile(PROGRAM);
+        let decoded = decode(&encode(&bytecode, PROGRAM).unwrap()).unwrap();
+
+        assert_eq!(decoded.source, PROGRAM);
+        assert_eq!(decoded.bytecode.instructions, bytecode.instructions);
+        assert_eq!(decoded.bytecode.constants, bytecode.constants);
+        assert_eq!(decoded.bytecode.source_map, bytecode.source_map);
+        assert_eq!(*run(decoded.bytecode), Object::Integer(15));
+    }
+
+    #[test]
+    fn test_runtime_errors_point_at_the_original_line() {
+        let source = "let add = fn(a, b) {\n  a + b\n};\nadd(1, \"one\");";
+        let decoded = decode(&encode(&compile(source), source).unwrap()).unwrap();
+
+        let error = VM::new(decoded.bytecode).run().unwrap_err();
+        let span = error.span().expect("the error should have a location");
+        assert_eq!(line_column(&decoded.source, span.start), (2, 3));
+        assert!(
+            error
+                .render(&decoded.source)
+                .starts_with("VM error at 2:3: type error: cannot add INTEGER and STRING"),
+            "{}",
+            error.render(&decoded.source)
+        );
+    }
+
+    #[test]
+    fn test_source_map_is_left_out_without_the_source() {
+        let decoded = decode(&encode(&compile(PROGRAM), "").unwrap()).unwrap();
+
+        assert_eq!(decoded.source, "");
+        assert_eq!(decoded.bytecode.source_map, Default::default());
+    }
+
+    #[test]
+    fn test_round_trip_floats() {
+        let bytecode = compile("let half = 0.5; [half * 3, -0.0, 1.0 / 3.0]");
+        let decoded = decode(&encode(&bytecode, "").unwrap()).unwrap().bytecode;
+
+        assert_eq!(decoded.constants, bytecode.constants);
+        assert_eq!(run(decoded).to_string(), "[1.5, -0.0, 0.3333333333333333]");
+    }
+
+    #[test]
+    fn test_header_errors() {
+        assert_eq!(
+            decode_error(b"MK"),
+            ArtifactError {
+                section: Section::Header,
+                kind: ArtifactErrorKind::Truncated
+            }
+        );
+        assert_eq!(
+            decode_error(b"ELF\x7f\x00\x01"),
+            ArtifactError {
+                section: Section::Header,
+                kind: ArtifactErrorKind::NotAnArtifact
+            }
+        );
+        let mut future = MAGIC.to_vec();
+        future.extend_from_slice(&(FORMAT_VERSION + 1).to_be_bytes());
+        assert_eq!(
+            decode_error(&future),
+            ArtifactError {
+                section: Section::Header,
+                kind: ArtifactErrorKind::UnsupportedVersion(FORMAT_VERSION + 1)
+            }
+        );
+    }
+
+    #[test]
+    fn test_section_errors() {
+        let bytes = encode(&compile("\"a\"; 1"), "").unwrap();
+        // header (6), count (4), string tag + length + "a" (6), integer (9),
+        // instruction length (4), instructions, then an empty source map:
+        // source length, mapping count and function count (12)
+        let kind = |bytes: &[u8]| decode_error(bytes);
+
+        assert_eq!(kind(&bytes[..8]).section, Section::ConstantCount);
+        assert_eq!(
+            kind(&bytes[..14]),
+            ArtifactError {
+                section: Section::Constant(0),
+                kind: ArtifactErrorKind::Truncated
+            }
+        );
+        assert_eq!(kind(&bytes[..20]).section, Section::Constant(1));
+        assert_eq!(
+            kind(&bytes[..bytes.len() - 13]).section,
+            Section::Instructions
+        );
+        assert_eq!(kind(&bytes[..bytes.len() - 1]).section, Section::SourceMap);
+
+        let mut unknown_tag = bytes.clone();
+        unknown_tag[16] = 9;
+        assert_eq!(
+            kind(&unknown_tag),
+            ArtifactError {
+                section: Section::Constant(1),
+                kind: ArtifactErrorKind::UnknownConstantTag(9)
+            }
+        );
+
+        let mut invalid_utf8 = bytes.clone();
+        invalid_utf8[15] = 0xff;
+        assert_eq!(
+            kind(&invalid_utf8),
+            ArtifactError {
+                section: Section::Constant(0),
+                kind: ArtifactErrorKind::InvalidUtf8
+            }
+        );
+
+        let mut trailing = bytes.clone();
+        trailing.push(0);
+        assert_eq!(
+            kind(&trailing),
+            ArtifactError {
+                section: Section::SourceMap,
+                kind: ArtifactErrorKind::TrailingBytes(1)
+            }
+        );
+    }
+
+    #[test]
+    fn test_load_verifies_the_bytecode() {
+        assert!(load(&encode(&compile(PROGRAM), PROGRAM).unwrap()).is_ok());
+
+        let bytecode = Bytecode {
+            instructions: make_instructions(Opcode::OpConst, &[5]),
+            constants: vec![],
+            source_map: Default::default(),
+            host_builtins: Default::default(),
+            num_globals: 0,
+        };
+        let bytes = encode(&bytecode, "").unwrap();
+        assert!(decode(&bytes).is_ok());
+        assert_eq!(
+            load(&bytes).err(),
+            Some(LoadError::Verify(vec![VerifyError {
+                location: Location::Main { offset: 0 },
+                kind: VerifyErrorKind::ConstantOutOfRange { index: 5, len: 0 },
+            }]))
+        );
+        assert_eq!(
+            load(b"MK").err(),
+            Some(LoadError::Decode(ArtifactError {
+                section: Section::Header,
+                kind: ArtifactErrorKind::Truncated,
+            }))
+        );
+    }
+
+    #[test]
+    fn test_unsupported_constant() {
+        let bytecode = Bytecode {
+            instructions: Instructions { bytes: vec![] },
+            constants: vec![Rc::new(Object::Null)],
+            source_map: Default::default(),
+            host_builtins: Default::default(),
+            num_globals: 0,
+        };
+        let error = encode(&bytecode, "").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "constant 0: NULL constants cannot be saved"
+        );
+    }
+
+    #[test]
+    fn test_describe() {
+        let bytecode = compile("let f = fn(s) { len(s) + 1 }; f(\"hi\")");
+        assert_eq!(
+            describe(&bytecode).unwrap(),
+            "format version 3\n\
+             \n\
+             constants (3):\n\
+             \x20  0: integer 1\n\
+             \x20  1: function (1 parameter(s), 1 local(s))\n\
+             \x20       0000 OpGetBuiltin 0 ; len\n\
+             \x20       0002 OpGetLocal 0\n\
+             \x20       0004 OpCall 1\n\
+             \x20       0006 OpConst 0 ; 1\n\
+             \x20       0009 OpAdd\n\
+             \x20       0010 OpReturnValue\n\
+             \x20  2: string \"hi\"\n\
+             \n\
+             instructions:\n\
+             0000 OpClosure 1 0 ; function 1\n\
+             0004 OpSetGlobal 0\n\
+             0007 OpGetGlobal 0\n\
+             0010 OpConst 2 ; \"hi\"\n\
+             0013 OpCall 1\n\
+             0015 OpPop\n\
+             \n\
+             12 instruction(s) in 16 byte(s), 3 constant(s)\n"
+        );
+    }
+
+    #[test]
+    fn test_disassemble() {
+        let bytecode = compile("let f = fn(x) { if (x) { 1 } else { 2 } }; f(true)");
+        assert_eq!(
+            disassemble(&bytecode).unwrap(),
+            "== main ==\n\
+             0000 OpClosure 2 0 ; function 2\n\
+             0004 OpSetGlobal 0\n\
+             0007 OpGetGlobal 0\n\
+             0010 OpTrue\n\
+             0011 OpCall 1\n\
+             0013 OpPop\n\
+             \n\
+             == fn constant 2 (1 param, 1 local) ==\n\
+             0000 OpGetLocal 0\n\
+             0002 OpJumpNotTruthy 11 ; -> 0011\n\
+             0005 OpConst 0 ; 1\n\
+             0008 OpJump 14 ; -> 0014\n\
+             0011 OpConst 1 ; 2\n\
+             0014 OpReturnValue\n"
+        );
+    }
+
+    #[test]
+    fn test_describe_reports_the_broken_section() {
+        let bytecode = Bytecode {
+            instructions: Instructions { bytes: vec![] },
+            constants: vec![Rc::new(Object::CompiledFunction(Rc::new(
+                CompiledFunction {
+                    instructions: vec![0xee],
+                    num_locals: 0,
+                    num_parameters: 0,
+                    name: String::new(),
+                },
+            )))],
+            source_map: Default::default(),
+            host_builtins: Default::default(),
+            num_globals: 0,
+        };
+        assert_eq!(
+            describe(&bytecode).unwrap_err(),
+            (
+                Section::Constant(0),
+                OpCodeError::InvalidOpcodeByte {
+                    byte: 0xee,
+                    position: Some(0)
+                }
+            )
+        );
+    }
+}
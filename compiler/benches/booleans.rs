@@ -0,0 +1,47 @@
+// Runs a branch-heavy loop that stores booleans and nulls in arrays and
+// passes them to builtins, and reports the average run time. The VM hands
+// out shared `true`, `false` and `null` objects, so none of this allocates
+// a new one.
+//
+//     cargo bench -p monkey-compiler --bench booleans
+
+use std::time::{Duration, Instant};
+
+use compiler::compiler::Compiler;
+use compiler::vm::VM;
+use parser::parse;
+
+const ITERATIONS: usize = 200_000;
+const RUNS: u32 = 5;
+
+fn run(input: &str) -> Duration {
+    let program = parse(input).unwrap();
+    let bytecode = Compiler::new().compile(&program).unwrap();
+    let mut vm = VM::new(bytecode);
+    let start = Instant::now();
+    vm.run().unwrap();
+    start.elapsed()
+}
+
+fn main() {
+    let input = format!(
+        "let i = 0; let hits = 0; \
+         while (i < {}) {{ \
+             let even = i % 2 == 0; \
+             let flags = [even, !even, i % 3 == 0, if (even) {{ 1 }}]; \
+             if (contains(flags, true)) {{ hits = hits + 1; }} \
+             i = i + 1; \
+         }}; \
+         hits",
+        ITERATIONS
+    );
+    run(&input);
+
+    let total: Duration = (0..RUNS).map(|_| run(&input)).sum();
+    println!(
+        "{} iterations: {:.2?} per run ({} runs)",
+        ITERATIONS,
+        total / RUNS,
+        RUNS
+    );
+}
@@ -0,0 +1,47 @@
+// Compiles a generated program binding 5,000 globals plus 2,500 parameters
+// that inner closures capture, and reports the average compile time. Symbol names
+// are shared `Rc<str>`s, so defining, resolving and capturing a name should
+// not allocate a copy of it each time.
+//
+//     cargo bench -p monkey-compiler --bench identifiers
+
+use std::time::{Duration, Instant};
+
+use compiler::compiler::Compiler;
+use parser::ast::Node;
+use parser::parse;
+
+const IDENTIFIERS: usize = 5_000;
+const RUNS: u32 = 20;
+
+fn generate() -> String {
+    let mut input = String::new();
+    for i in 0..IDENTIFIERS / 2 {
+        input.push_str(&format!(
+            "let value_{i} = {i}; \
+             let make_{i} = fn(param_{i}) {{ fn() {{ param_{i} + value_{i} }} }};\n"
+        ));
+    }
+    input
+}
+
+fn compile(program: &Node) -> Duration {
+    let start = Instant::now();
+    let bytecode = Compiler::new().compile(program).unwrap();
+    let elapsed = start.elapsed();
+    assert!(!bytecode.instructions.is_empty());
+    elapsed
+}
+
+fn main() {
+    let program = parse(&generate()).unwrap();
+    compile(&program);
+
+    let total: Duration = (0..RUNS).map(|_| compile(&program)).sum();
+    println!(
+        "{} identifiers: {:.2?} per compile ({} runs)",
+        IDENTIFIERS,
+        total / RUNS,
+        RUNS
+    );
+}
@@ -0,0 +1,50 @@
+// Builds arrays with `xs = push(xs, i)` in a loop and reports how the
+// run time scales with the element count. With in-place appends doubling
+// the count should roughly double the time; a copying `push` quadruples it.
+//
+//     cargo bench -p monkey-compiler --bench push
+
+use std::time::{Duration, Instant};
+
+use compiler::compiler::Compiler;
+use compiler::vm::VM;
+use parser::parse;
+
+fn build_array(count: usize) -> Duration {
+    let input = format!(
+        "let xs = []; let i = 0; \
+         while (i < {}) {{ xs = push(xs, i); i = i + 1; }}; \
+         len(xs)",
+        count
+    );
+    let program = parse(&input).unwrap();
+    let bytecode = Compiler::new().compile(&program).unwrap();
+    let mut vm = VM::new(bytecode);
+
+    let start = Instant::now();
+    vm.run().unwrap();
+    let elapsed = start.elapsed();
+
+    assert_eq!(
+        vm.last_popped_stack_elm().map(|value| value.to_string()),
+        Some(count.to_string())
+    );
+    elapsed
+}
+
+fn main() {
+    let mut previous: Option<Duration> = None;
+    for count in [25_000, 50_000, 100_000] {
+        let elapsed = build_array(count);
+        match previous {
+            Some(previous) => println!(
+                "{:>7} elements: {:>10.2?} ({:.2}x the previous run)",
+                count,
+                elapsed,
+                elapsed.as_secs_f64() / previous.as_secs_f64()
+            ),
+            None => println!("{:>7} elements: {:>10.2?}", count, elapsed),
+        }
+        previous = Some(elapsed);
+    }
+}
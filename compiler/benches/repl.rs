@@ -0,0 +1,29 @@
+// Feeds a session one small input after another, as a REPL user would, and
+// reports the average time per input. Each input starts a new VM, which
+// only allocates the global slots defined so far, so short sessions stay
+// cheap instead of paying for every possible global each time.
+//
+//     cargo bench -p monkey-compiler --bench repl
+
+use std::time::{Duration, Instant};
+
+use compiler::Session;
+
+const INPUTS: usize = 2_000;
+
+fn main() {
+    let mut session = Session::new();
+    let start = Instant::now();
+    for i in 0..INPUTS {
+        session
+            .eval(&format!("let x_{i} = {i}; x_{i} + 1;"))
+            .unwrap();
+    }
+    let elapsed: Duration = start.elapsed();
+    println!(
+        "{} inputs: {:.2?} per input ({} globals at the end)",
+        INPUTS,
+        elapsed / INPUTS as u32,
+        session.globals.len()
+    );
+}
@@ -0,0 +1,47 @@
+// Concatenates strings in a loop, looks each result up in a hash and
+// reports the average run time. Strings are shared `Rc<str>`s, so loading a
+// constant or hashing a key doesn't copy it, and each `+` allocates once.
+//
+//     cargo bench -p monkey-compiler --bench strings
+
+use std::time::{Duration, Instant};
+
+use compiler::compiler::Compiler;
+use compiler::vm::VM;
+use parser::parse;
+
+const ITERATIONS: usize = 200_000;
+const RUNS: u32 = 5;
+
+fn run(input: &str) -> Duration {
+    let program = parse(input).unwrap();
+    let bytecode = Compiler::new().compile(&program).unwrap();
+    let mut vm = VM::new(bytecode);
+    let start = Instant::now();
+    vm.run().unwrap();
+    start.elapsed()
+}
+
+fn main() {
+    let input = format!(
+        "let i = 0; let hits = 0; let line = \"\"; \
+         let seen = {{\"monkey\": 1, \"banana\": 2}}; \
+         while (i < {}) {{ \
+             let word = if (i % 2 == 0) {{ \"mon\" + \"key\" }} else {{ \"ban\" + \"ana\" }}; \
+             hits = hits + seen[word]; \
+             line = if (len(line) > 200) {{ word }} else {{ line + \" \" + word }}; \
+             i = i + 1; \
+         }}; \
+         hits",
+        ITERATIONS
+    );
+    run(&input);
+
+    let total: Duration = (0..RUNS).map(|_| run(&input)).sum();
+    println!(
+        "{} iterations: {:.2?} per run ({} runs)",
+        ITERATIONS,
+        total / RUNS,
+        RUNS
+    );
+}
@@ -1,19 +1,39 @@
 use object::builtins::BuiltIns;
+use std::collections::HashMap;
 use std::fmt;
+use std::io::Write;
 use std::rc::Rc;
 
-use object::Object;
+use object::{BuiltinFunc, HashKey, Object, checked_floor_div};
 use parser::ast::{BlockStatement, Expression, Integer, Literal, Node, Statement};
-use parser::lexer::token::TokenKind;
+use parser::lexer::token::{Span, TokenKind};
 
 use crate::op_code::Opcode::*;
-use crate::op_code::{Instructions, OpCodeError, Opcode, cast_u8_to_opcode, make_instructions};
+use crate::op_code::{
+    Instructions, OpCodeError, Opcode, cast_u8_to_opcode, definitions, make, make_instructions,
+    read_operands,
+};
+use crate::source_map::{Mapping, SourceMap, excerpt, line_column};
 use crate::symbol_table::{Symbol, SymbolScope, SymbolTable};
+use crate::warning::{Exit, Terminator, Warning, WarningKind};
 
 struct CompilationScope {
     instructions: Instructions,
     last_instruction: EmittedInstruction,
     previous_instruction: EmittedInstruction,
+    mappings: Vec<Mapping>,
+    /// The `while` loops enclosing the code being compiled, innermost last.
+    loops: Vec<LoopContext>,
+    /// For a function bound by a global `let`, the global's slot, through
+    /// which the function refers to itself.
+    self_global: Option<usize>,
+}
+
+struct LoopContext {
+    /// Where the condition check starts; the target of `continue`.
+    start: usize,
+    /// `OpJump`s emitted for `break`, patched once the loop's end is known.
+    breaks: Vec<usize>,
 }
 
 impl Default for CompilationScope {
@@ -28,20 +48,58 @@ impl Default for CompilationScope {
                 opcode: OpNull,
                 position: 0,
             },
+            mappings: vec![],
+            loops: vec![],
+            self_global: None,
         }
     }
 }
 
 pub struct Compiler {
     pub constants: Vec<Rc<Object>>,
+    /// Where each integer, boolean and string constant already is, so a
+    /// repeated literal reuses its slot.
+    interned: HashMap<HashKey, usize>,
     pub symbol_table: SymbolTable,
     scopes: Vec<CompilationScope>,
     scope_index: usize,
+    verbose: Option<Box<dyn Write>>,
+    warnings: Vec<Warning>,
+    warn_shadowing: bool,
+    /// The statement or expression being compiled, for the source map.
+    current_span: Option<Span>,
+    /// Whether a `break` or `continue` here could jump out of the innermost
+    /// loop. It can't while the operand stack holds values it would leave
+    /// behind, as in `f(if (c) { break; })`.
+    can_leave_loop: bool,
+    /// Whether the expression about to be compiled is what the enclosing
+    /// function returns, so a call to that function can reuse its frame.
+    tail_position: bool,
+    /// Set by a global `let` for the function literal it binds.
+    pending_self_global: Option<usize>,
+    function_mappings: SourceMap,
+    /// Globals defined before this compiler was created, by earlier REPL
+    /// inputs. A `let` may redefine them.
+    inherited_globals: usize,
+    /// Builtins the host added with `register_builtin`, numbered after the
+    /// standard ones.
+    host_builtins: Rc<Vec<(String, BuiltinFunc)>>,
+    /// Set by the first `compile`, after which no builtins can be added.
+    started: bool,
 }
 
 pub struct Bytecode {
     pub instructions: Instructions,
     pub constants: Vec<Rc<Object>>,
+    /// Lets the VM say where a runtime error came from. Artifacts store it
+    /// along with the source.
+    pub source_map: SourceMap,
+    /// The builtins registered by the host, which `OpGetBuiltin` indexes
+    /// after the standard ones. Artifacts don't store them either.
+    pub host_builtins: Rc<Vec<(String, BuiltinFunc)>>,
+    /// How many global slots the program's definitions take, earlier REPL
+    /// inputs' included.
+    pub num_globals: usize,
 }
 
 #[derive(Clone)]
@@ -53,10 +111,58 @@ pub struct EmittedInstruction {
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub enum CompileError {
     ExpectedIdentifier,
-    UndefinedVariable(String),
+    UndefinedVariable {
+        name: String,
+        span: Span,
+    },
+    /// Assignment to a name that is not a global or a local of the current
+    /// function.
+    CannotAssign {
+        name: String,
+        scope: SymbolScope,
+        span: Span,
+    },
+    /// `break` or `continue` with no enclosing loop in the same function.
+    OutsideLoop {
+        keyword: TokenKind,
+        span: Span,
+    },
+    /// `break` or `continue` nested inside an expression that is still
+    /// being evaluated, rather than directly in the loop body or an `if`
+    /// statement there.
+    InsideExpression {
+        keyword: TokenKind,
+        span: Span,
+    },
     UnexpectedPrefixOperator(TokenKind),
     UnexpectedInfixOperator(TokenKind),
     ScopeUnderflow,
+    UnexpandedMacro,
+    /// More constants, variables, elements or arguments than the operand
+    /// of the instruction that refers to them can count.
+    TooMany {
+        what: &'static str,
+        count: usize,
+        limit: usize,
+    },
+    /// A jump to an offset past what its operand can hold.
+    JumpOutOfRange(usize),
+    /// A `let` for a name the same scope already defines. `previous` is
+    /// `None` when the REPL bound the name rather than the source.
+    DuplicateDefinition {
+        name: String,
+        span: Span,
+        previous: Option<Span>,
+    },
+    /// `register_builtin` after the compiler has compiled something, whose
+    /// symbols could no longer see the new builtin.
+    BuiltinAfterCompile(String),
+    /// A hash literal key that is itself an array, hash or function
+    /// literal, which can never be hashed.
+    UnusableAsHashKey {
+        type_name: &'static str,
+        span: Span,
+    },
     Opcode(OpCodeError),
 }
 
@@ -64,7 +170,28 @@ impl fmt::Display for CompileError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             CompileError::ExpectedIdentifier => write!(f, "expected identifier"),
-            CompileError::UndefinedVariable(name) => write!(f, "undefined variable '{}'", name),
+            CompileError::UndefinedVariable { name, .. } => {
+                write!(f, "undefined variable '{}'", name)
+            }
+            CompileError::CannotAssign { name, scope, .. } => match scope {
+                SymbolScope::Builtin => write!(f, "cannot assign to builtin '{}'", name),
+                SymbolScope::Function => {
+                    write!(f, "cannot assign to '{}' inside its own body", name)
+                }
+                _ => write!(
+                    f,
+                    "cannot assign to '{}', which belongs to an enclosing function",
+                    name
+                ),
+            },
+            CompileError::OutsideLoop { keyword, .. } => {
+                write!(f, "{} outside a loop", keyword)
+            }
+            CompileError::InsideExpression { keyword, .. } => write!(
+                f,
+                "{} can't be used inside an expression, only as a statement of the loop body or of an `if` in it",
+                keyword
+            ),
             CompileError::UnexpectedPrefixOperator(op) => {
                 write!(f, "unexpected prefix op: {}", op)
             }
@@ -72,11 +199,72 @@ impl fmt::Display for CompileError {
                 write!(f, "unexpected infix op: {}", op)
             }
             CompileError::ScopeUnderflow => write!(f, "cannot leave the root compilation scope"),
+            CompileError::UnexpandedMacro => {
+                write!(
+                    f,
+                    "macro literals are only allowed in top-level let statements"
+                )
+            }
+            CompileError::TooMany { what, count, limit } => {
+                write!(f, "too many {} ({}); limit is {}", what, count, limit)
+            }
+            CompileError::JumpOutOfRange(target) => write!(
+                f,
+                "jump target {} is out of range; limit is {}",
+                target,
+                u16::MAX
+            ),
+            CompileError::DuplicateDefinition { name, .. } => {
+                write!(f, "'{}' is already defined in this scope", name)
+            }
+            CompileError::BuiltinAfterCompile(name) => write!(
+                f,
+                "cannot register builtin '{}' after compilation has started",
+                name
+            ),
+            CompileError::UnusableAsHashKey { type_name, .. } => {
+                write!(f, "unusable as hash key: {}", type_name)
+            }
             CompileError::Opcode(err) => write!(f, "opcode error: {}", err),
         }
     }
 }
 
+impl CompileError {
+    /// The code the error is about, for the errors that point at a name or
+    /// keyword.
+    pub fn span(&self) -> Option<&Span> {
+        match self {
+            CompileError::UndefinedVariable { span, .. }
+            | CompileError::CannotAssign { span, .. }
+            | CompileError::OutsideLoop { span, .. }
+            | CompileError::InsideExpression { span, .. }
+            | CompileError::DuplicateDefinition { span, .. }
+            | CompileError::UnusableAsHashKey { span, .. } => Some(span),
+            _ => None,
+        }
+    }
+
+    /// The error as the REPL and CLI print it: prefixed with the line and
+    /// column of its span and followed by the offending source line, in the
+    /// layout runtime errors use.
+    pub fn render(&self, source: &str) -> String {
+        match self.span() {
+            Some(span) => {
+                let (line, column) = line_column(source, span.start);
+                format!(
+                    "compile error at {}:{}: {}\n{}",
+                    line,
+                    column,
+                    self,
+                    excerpt(source, span)
+                )
+            }
+            None => format!("compile error: {}", self),
+        }
+    }
+}
+
 impl Default for Compiler {
     fn default() -> Self {
         Self::new()
@@ -96,15 +284,77 @@ impl Compiler {
 
         Compiler {
             constants: vec![],
+            interned: HashMap::new(),
             symbol_table,
             scopes: vec![main_scope],
             scope_index: 0,
+            verbose: None,
+            warnings: vec![],
+            warn_shadowing: false,
+            current_span: None,
+            can_leave_loop: false,
+            tail_position: false,
+            pending_self_global: None,
+            function_mappings: SourceMap::default(),
+            inherited_globals: 0,
+            host_builtins: Rc::new(vec![]),
+            started: false,
+        }
+    }
+
+    /// Makes `func` callable from scripts as `name`, hiding a standard
+    /// builtin or an earlier registration of the same name. Must happen
+    /// before the first `compile`; the VM finds the function through the
+    /// bytecode.
+    pub fn register_builtin(&mut self, name: &str, func: BuiltinFunc) -> Result<(), CompileError> {
+        if self.started {
+            return Err(CompileError::BuiltinAfterCompile(name.to_string()));
         }
+        let index = BuiltIns.len() + self.host_builtins.len();
+        let limit = usize::from(u8::MAX) + 1;
+        if index >= limit {
+            return Err(CompileError::TooMany {
+                what: "builtins",
+                count: index + 1,
+                limit,
+            });
+        }
+        self.symbol_table.define_builtin(index, name);
+        Rc::make_mut(&mut self.host_builtins).push((name.to_string(), func));
+        Ok(())
+    }
+
+    /// Reports symbol definitions, constants, scope changes and jump patches
+    /// to `writer`, one event per line, as compilation proceeds.
+    pub fn set_verbose(&mut self, writer: Box<dyn Write>) {
+        self.verbose = Some(writer);
+    }
+
+    /// Also warn when a binding hides one from an enclosing scope. Hiding a
+    /// builtin is always reported.
+    pub fn set_warn_shadowing(&mut self, enabled: bool) {
+        self.warn_shadowing = enabled;
+    }
+
+    fn log_symbol(&mut self, symbol: &Symbol) {
+        log(
+            &mut self.verbose,
+            format_args!(
+                "scope {}: define {} as {:?} {}",
+                self.scope_index, symbol.name, symbol.scope, symbol.index
+            ),
+        );
     }
 
     pub fn new_with_state(symbol_table: SymbolTable, constants: Vec<Rc<Object>>) -> Self {
         let mut compiler = Self::new();
+        for (index, constant) in constants.iter().enumerate() {
+            if let Ok(key) = HashKey::try_from(constant.as_ref()) {
+                compiler.interned.entry(key).or_insert(index);
+            }
+        }
         compiler.constants = constants;
+        compiler.inherited_globals = symbol_table.num_definitions();
         compiler.symbol_table = symbol_table;
         for (key, value) in BuiltIns.iter().enumerate() {
             compiler.symbol_table.define_builtin(key, value.0);
@@ -113,8 +363,10 @@ impl Compiler {
     }
 
     pub fn compile(&mut self, node: &Node) -> Result<Bytecode, CompileError> {
+        self.started = true;
         match node {
             Node::Program(p) => {
+                self.check_reachable(&p.body);
                 for stmt in &p.body {
                     self.compile_stmt(stmt)?;
                 }
@@ -131,77 +383,226 @@ impl Compiler {
     }
 
     fn compile_stmt(&mut self, s: &Statement) -> Result<(), CompileError> {
+        let outer = self.current_span.replace(s.span().clone());
+        let result = self.compile_stmt_kind(s);
+        self.current_span = outer;
+        result
+    }
+
+    fn compile_stmt_kind(&mut self, s: &Statement) -> Result<(), CompileError> {
         match s {
             Statement::Let(let_statement) => {
                 let name = match &let_statement.identifier.kind {
                     TokenKind::IDENTIFIER { name } => name,
                     _ => return Err(CompileError::ExpectedIdentifier),
                 };
-                self.compile_expr(&let_statement.expr)?;
-                let symbol = self.symbol_table.define(name);
-                if symbol.scope == SymbolScope::Global {
-                    self.emit(Opcode::OpSetGlobal, &[symbol.index]);
+                let previous = self.symbol_table.defined_here(name);
+                if let Some(previous) = &previous
+                    && !(previous.scope == SymbolScope::Global
+                        && previous.index < self.inherited_globals)
+                {
+                    return Err(CompileError::DuplicateDefinition {
+                        name: name.clone(),
+                        span: let_statement.identifier.span.clone(),
+                        previous: previous.span.clone(),
+                    });
+                }
+                // a function bound to a global calls itself through it, as
+                // in the interpreter, so it sees the name being reassigned
+                if self.symbol_table.is_global_scope()
+                    && matches!(&let_statement.expr, Expression::FUNCTION(f) if f.name == *name)
+                {
+                    self.check_shadowing(name, &let_statement.identifier.span);
+                    let symbol = self
+                        .symbol_table
+                        .define(name, let_statement.identifier.span.clone());
+                    self.log_symbol(&symbol);
+                    self.pending_self_global = Some(symbol.index);
+                    self.compile_expr(&let_statement.expr)?;
+                    return self.store_symbol(&symbol);
+                }
+                // a `let` that binds a new name, as in a block, must leave
+                // the variable it reads from alone
+                if !(previous.is_some() && self.compile_push_in_place(name, &let_statement.expr)?) {
+                    self.compile_expr(&let_statement.expr)?;
+                }
+                self.check_shadowing(name, &let_statement.identifier.span);
+                let symbol = self
+                    .symbol_table
+                    .define(name, let_statement.identifier.span.clone());
+                self.log_symbol(&symbol);
+                self.store_symbol(&symbol)?;
+                Ok(())
+            }
+            Statement::Assign(assign) => {
+                let name = &assign.name.name;
+                let symbol = self.symbol_table.resolve(name).ok_or_else(|| {
+                    CompileError::UndefinedVariable {
+                        name: name.clone(),
+                        span: assign.name.span.clone(),
+                    }
+                })?;
+                // closures capture by value, so there is no slot to write a
+                // free variable back to
+                let set = match &symbol.scope {
+                    SymbolScope::Global => Opcode::OpSetGlobal,
+                    SymbolScope::Local => Opcode::OpSetLocal,
+                    scope => {
+                        return Err(CompileError::CannotAssign {
+                            name: name.clone(),
+                            scope: scope.clone(),
+                            span: assign.name.span.clone(),
+                        });
+                    }
+                };
+                if !self.compile_push_in_place(name, &assign.expr)? {
+                    self.compile_expr(&assign.expr)?;
+                }
+                self.emit(set, &[symbol.index])?;
+                Ok(())
+            }
+            // the collection is moved out of its variable when nothing else
+            // could observe it missing, so OpSetIndex can update it in place
+            Statement::IndexAssign(assign) => {
+                let name = &assign.name.name;
+                let symbol = self.symbol_table.resolve(name).ok_or_else(|| {
+                    CompileError::UndefinedVariable {
+                        name: name.clone(),
+                        span: assign.name.span.clone(),
+                    }
+                })?;
+                let (take, set) = match &symbol.scope {
+                    SymbolScope::Global if self.scope_index == 0 => {
+                        (OpTakeGlobal, Opcode::OpSetGlobal)
+                    }
+                    SymbolScope::Global => (OpGetGlobal, Opcode::OpSetGlobal),
+                    SymbolScope::Local => (OpTakeLocal, Opcode::OpSetLocal),
+                    scope => {
+                        return Err(CompileError::CannotAssign {
+                            name: name.clone(),
+                            scope: scope.clone(),
+                            span: assign.name.span.clone(),
+                        });
+                    }
+                };
+                if cannot_read(&assign.index, name) && cannot_read(&assign.value, name) {
+                    self.emit(take, &[symbol.index])?;
                 } else {
-                    self.emit(Opcode::OpSetLocal, &[symbol.index]);
+                    self.load_symbol(&symbol)?;
                 }
+                self.compile_operand(&assign.index)?;
+                self.compile_operand(&assign.value)?;
+                self.emit(OpSetIndex, &[])?;
+                self.emit(set, &[symbol.index])?;
                 Ok(())
             }
             Statement::Return(r) => {
+                self.tail_position = true;
                 self.compile_expr(&r.argument)?;
-                self.emit(Opcode::OpReturnValue, &[]);
+                self.emit(Opcode::OpReturnValue, &[])?;
+                Ok(())
+            }
+            Statement::Break(b) => {
+                self.check_can_leave_loop(TokenKind::BREAK, &b.span)?;
+                let jump = self.emit(OpJump, &[Self::PLACEHOLDER_ADDRESS])?;
+                let scope = &mut self.scopes[self.scope_index];
+                scope.loops.last_mut().unwrap().breaks.push(jump);
+                Ok(())
+            }
+            Statement::Continue(c) => {
+                self.check_can_leave_loop(TokenKind::CONTINUE, &c.span)?;
+                let start = self.scopes[self.scope_index].loops.last().unwrap().start;
+                self.emit(OpJump, &[start])?;
                 Ok(())
             }
             Statement::Expr(e) => {
                 self.compile_expr(e)?;
-                self.emit(OpPop, &[]);
+                self.emit(OpPop, &[])?;
                 Ok(())
             }
         }
     }
 
+    // Like `compile_expr`, for an operand compiled after others that are
+    // already on the stack, so it must not leave the loop.
+    fn compile_operand(&mut self, e: &Expression) -> Result<(), CompileError> {
+        let can_leave_loop = std::mem::replace(&mut self.can_leave_loop, false);
+        let result = self.compile_expr(e);
+        self.can_leave_loop = can_leave_loop;
+        result
+    }
+
+    fn check_can_leave_loop(&self, keyword: TokenKind, span: &Span) -> Result<(), CompileError> {
+        let span = span.clone();
+        if self.scopes[self.scope_index].loops.is_empty() {
+            Err(CompileError::OutsideLoop { keyword, span })
+        } else if !self.can_leave_loop {
+            Err(CompileError::InsideExpression { keyword, span })
+        } else {
+            Ok(())
+        }
+    }
+
+    // Subexpressions can't leave the loop, except for the parts of an `if`
+    // or `while`, which inherit whether the expression as a whole could.
     fn compile_expr(&mut self, e: &Expression) -> Result<(), CompileError> {
+        let outer = self.current_span.replace(e.span().clone());
+        let can_leave_loop = std::mem::replace(&mut self.can_leave_loop, false);
+        let tail = std::mem::take(&mut self.tail_position);
+        let result = self.compile_expr_kind(e, can_leave_loop, tail);
+        self.can_leave_loop = can_leave_loop;
+        self.current_span = outer;
+        result
+    }
+
+    fn compile_expr_kind(
+        &mut self,
+        e: &Expression,
+        can_leave_loop: bool,
+        tail: bool,
+    ) -> Result<(), CompileError> {
         match e {
             Expression::IDENTIFIER(identifier) => {
                 let symbol = self.symbol_table.resolve(&identifier.name);
                 match symbol {
                     Some(symbol) => {
-                        self.load_symbol(&symbol);
+                        self.load_symbol(&symbol)?;
                     }
                     None => {
-                        return Err(CompileError::UndefinedVariable(identifier.name.clone()));
+                        return Err(CompileError::UndefinedVariable {
+                            name: identifier.name.clone(),
+                            span: identifier.span.clone(),
+                        });
                     }
                 }
             }
             Expression::LITERAL(l) => match l {
-                Literal::Integer(i) => {
-                    let int = Object::Integer(i.raw);
-                    let operands = vec![self.add_constant(int)];
-                    self.emit(OpConst, &operands);
-                }
+                Literal::Integer(i) => self.emit_constant(Object::Integer(i.raw))?,
+                Literal::Float(f) => self.emit_constant(Object::Float(f.raw))?,
                 Literal::Boolean(i) => {
                     if i.raw {
-                        self.emit(OpTrue, &[]);
+                        self.emit(OpTrue, &[])?;
                     } else {
-                        self.emit(OpFalse, &[]);
+                        self.emit(OpFalse, &[])?;
                     }
                 }
-                Literal::String(s) => {
-                    let string_object = Object::String(s.raw.clone());
-                    let operands = vec![self.add_constant(string_object)];
-                    self.emit(OpConst, &operands);
+                Literal::Null(_) => {
+                    self.emit(OpNull, &[])?;
                 }
+                Literal::String(s) => self.emit_constant(Object::String(s.raw.as_str().into()))?,
                 Literal::Array(array) => {
                     for element in array.elements.iter() {
                         self.compile_expr(element)?;
                     }
-                    self.emit(OpArray, &[array.elements.len()]);
+                    self.emit(OpArray, &[array.elements.len()])?;
                 }
                 Literal::Hash(hash) => {
+                    self.check_hash_keys(hash)?;
                     for (key, value) in hash.elements.iter() {
                         self.compile_expr(key)?;
                         self.compile_expr(value)?;
                     }
-                    self.emit(OpHash, &[hash.elements.len() * 2]);
+                    self.emit(OpHash, &[hash.elements.len() * 2])?;
                 }
             },
             Expression::PREFIX(prefix) => {
@@ -211,11 +612,13 @@ impl Compiler {
                 self.compile_expr(&prefix.operand)?;
                 match prefix.op.kind {
                     TokenKind::MINUS => {
-                        self.emit(OpMinus, &[]);
+                        self.emit(OpMinus, &[])?;
                     }
                     TokenKind::BANG => {
-                        self.emit(OpBang, &[]);
+                        self.emit(OpBang, &[])?;
                     }
+                    // unary plus leaves its operand as it is
+                    TokenKind::PLUS => {}
                     _ => {
                         return Err(CompileError::UnexpectedPrefixOperator(
                             prefix.op.kind.clone(),
@@ -228,61 +631,47 @@ impl Compiler {
                     return folded;
                 }
 
-                if infix.op.kind == TokenKind::LT {
-                    self.compile_expr(&infix.right)?;
-                    self.compile_expr(&infix.left)?;
-                    self.emit(Opcode::OpGreaterThan, &[]);
-                    return Ok(());
-                }
-                if infix.op.kind == TokenKind::LTE {
-                    self.compile_expr(&infix.left)?;
-                    self.compile_expr(&infix.right)?;
-                    self.emit(Opcode::OpGreaterThan, &[]);
-                    self.emit(OpBang, &[]);
-                    return Ok(());
-                }
-                if infix.op.kind == TokenKind::GTE {
-                    self.compile_expr(&infix.right)?;
-                    self.compile_expr(&infix.left)?;
-                    self.emit(Opcode::OpGreaterThan, &[]);
-                    self.emit(OpBang, &[]);
-                    return Ok(());
+                if matches!(infix.op.kind, TokenKind::AND | TokenKind::OR) {
+                    return self.compile_logical(infix);
                 }
                 self.compile_expr(&infix.left)?;
                 self.compile_expr(&infix.right)?;
                 match infix.op.kind {
-                    TokenKind::PLUS => self.emit(OpAdd, &[]),
-                    TokenKind::MINUS => self.emit(OpSub, &[]),
-                    TokenKind::ASTERISK => self.emit(OpMul, &[]),
-                    TokenKind::SLASH => self.emit(OpDiv, &[]),
-                    TokenKind::PERCENT => self.emit(Opcode::OpModulo, &[]),
-                    TokenKind::GT => self.emit(Opcode::OpGreaterThan, &[]),
-                    TokenKind::EQ => self.emit(Opcode::OpEqual, &[]),
-                    TokenKind::NotEq => self.emit(Opcode::OpNotEqual, &[]),
+                    TokenKind::PLUS => self.emit(OpAdd, &[])?,
+                    TokenKind::MINUS => self.emit(OpSub, &[])?,
+                    TokenKind::ASTERISK => self.emit(OpMul, &[])?,
+                    TokenKind::SLASH => self.emit(OpDiv, &[])?,
+                    TokenKind::DIV => self.emit(OpFloorDiv, &[])?,
+                    TokenKind::PERCENT => self.emit(Opcode::OpModulo, &[])?,
+                    TokenKind::GT => self.emit(Opcode::OpGreaterThan, &[])?,
+                    TokenKind::GTE => self.emit(Opcode::OpGreaterOrEqual, &[])?,
+                    // not `b > a`, so a type error names the operands in
+                    // source order
+                    TokenKind::LT => self.emit(Opcode::OpLessThan, &[])?,
+                    TokenKind::LTE => self.emit(Opcode::OpLessOrEqual, &[])?,
+                    TokenKind::EQ => self.emit(Opcode::OpEqual, &[])?,
+                    TokenKind::NotEq => self.emit(Opcode::OpNotEqual, &[])?,
                     _ => {
                         return Err(CompileError::UnexpectedInfixOperator(infix.op.kind.clone()));
                     }
                 };
             }
             Expression::IF(if_node) => {
+                self.can_leave_loop = can_leave_loop;
                 self.compile_expr(&if_node.condition)?;
-                let jump_not_truthy = self.emit(OpJumpNotTruthy, &[Self::PLACEHOLDER_ADDRESS]);
-                self.compile_block_statement(&if_node.consequent)?;
-                if self.last_instruction_is(OpPop) {
-                    self.remove_last_pop();
-                }
+                let jump_not_truthy = self.emit(OpJumpNotTruthy, &[Self::PLACEHOLDER_ADDRESS])?;
+                self.tail_position = tail;
+                self.compile_branch(&if_node.consequent)?;
 
-                let jump_pos = self.emit(OpJump, &[Self::PLACEHOLDER_ADDRESS]);
+                let jump_pos = self.emit(OpJump, &[Self::PLACEHOLDER_ADDRESS])?;
                 let after_consequence_location = self.current_instruction().bytes.len();
                 self.change_operand(jump_not_truthy, after_consequence_location)?;
 
                 if let Some(alternate) = &if_node.alternate {
-                    self.compile_block_statement(alternate)?;
-                    if self.last_instruction_is(OpPop) {
-                        self.remove_last_pop();
-                    }
+                    self.tail_position = tail;
+                    self.compile_branch(alternate)?;
                 } else {
-                    self.emit(OpNull, &[]);
+                    self.emit(OpNull, &[])?;
                 }
 
                 let after_alternative_location = self.current_instruction().bytes.len();
@@ -290,111 +679,342 @@ impl Compiler {
             }
             Expression::While(while_node) => {
                 let loop_start = self.current_instruction().bytes.len();
+                self.can_leave_loop = can_leave_loop;
                 self.compile_expr(&while_node.condition)?;
-                let jump_not_truthy = self.emit(OpJumpNotTruthy, &[Self::PLACEHOLDER_ADDRESS]);
-                self.compile_block_statement(&while_node.body)?;
-                self.emit(OpJump, &[loop_start]);
+                let jump_not_truthy = self.emit(OpJumpNotTruthy, &[Self::PLACEHOLDER_ADDRESS])?;
+
+                self.scopes[self.scope_index].loops.push(LoopContext {
+                    start: loop_start,
+                    breaks: vec![],
+                });
+                self.can_leave_loop = true;
+                self.compile_scoped_block(&while_node.body)?;
+                let context = self.scopes[self.scope_index].loops.pop().unwrap();
+
+                self.emit(OpJump, &[loop_start])?;
+                let after_loop = self.current_instruction().bytes.len();
+                self.change_operand(jump_not_truthy, after_loop)?;
+                for jump in context.breaks {
+                    self.change_operand(jump, after_loop)?;
+                }
+                self.emit(OpNull, &[])?;
+            }
+            // lowered to a loop over hidden locals holding the sequence, its
+            // length and the current index; the index is bumped at the top,
+            // so `continue` can jump straight back there
+            Expression::ForIn(for_node) => {
+                self.can_leave_loop = can_leave_loop;
+                self.compile_expr(&for_node.iterable)?;
+                self.emit(OpIter, &[])?;
+                let depth = self.scopes[self.scope_index].loops.len();
+                let len = self.define_hidden(&format!("for len {}", depth), &for_node.span);
+                let sequence = self.define_hidden(&format!("for seq {}", depth), &for_node.span);
+                let index = self.define_hidden(&format!("for index {}", depth), &for_node.span);
+                self.store_symbol(&len)?;
+                self.store_symbol(&sequence)?;
+                self.emit_constant(Object::Integer(-1))?;
+                self.store_symbol(&index)?;
+
+                let loop_start = self.current_instruction().bytes.len();
+                self.load_symbol(&index)?;
+                self.emit_constant(Object::Integer(1))?;
+                self.emit(OpAdd, &[])?;
+                self.store_symbol(&index)?;
+                self.load_symbol(&len)?;
+                self.load_symbol(&index)?;
+                self.emit(Opcode::OpGreaterThan, &[])?;
+                let jump_not_truthy = self.emit(OpJumpNotTruthy, &[Self::PLACEHOLDER_ADDRESS])?;
+
+                self.load_symbol(&sequence)?;
+                self.load_symbol(&index)?;
+                self.emit(OpIndex, &[])?;
+                let variable = &for_node.variable;
+                self.check_shadowing(&variable.name, &variable.span);
+                let symbol = self
+                    .symbol_table
+                    .define(&variable.name, variable.span.clone());
+                self.log_symbol(&symbol);
+                self.store_symbol(&symbol)?;
+
+                self.scopes[self.scope_index].loops.push(LoopContext {
+                    start: loop_start,
+                    breaks: vec![],
+                });
+                self.can_leave_loop = true;
+                self.compile_scoped_block(&for_node.body)?;
+                let context = self.scopes[self.scope_index].loops.pop().unwrap();
+
+                self.emit(OpJump, &[loop_start])?;
                 let after_loop = self.current_instruction().bytes.len();
                 self.change_operand(jump_not_truthy, after_loop)?;
-                self.emit(OpNull, &[]);
+                for jump in context.breaks {
+                    self.change_operand(jump, after_loop)?;
+                }
+                self.emit(OpNull, &[])?;
             }
             Expression::Index(index) => {
                 self.compile_expr(&index.object)?;
                 self.compile_expr(&index.index)?;
-                self.emit(OpIndex, &[]);
+                self.emit(OpIndex, &[])?;
             }
             Expression::FUNCTION(f) => {
+                let self_global = self.pending_self_global.take();
                 self.enter_scope();
-                if !f.name.is_empty() {
-                    self.symbol_table.define_function_name(&f.name);
+                self.scopes[self.scope_index].self_global = self_global;
+                if !f.name.is_empty() && self_global.is_none() {
+                    let symbol = self
+                        .symbol_table
+                        .define_function_name(&f.name, f.span.clone());
+                    self.log_symbol(&symbol);
                 }
                 for param in &f.params {
-                    self.symbol_table.define(&param.name);
+                    self.check_shadowing(&param.name, &param.span);
+                    let symbol = self.symbol_table.define(&param.name, param.span.clone());
+                    self.log_symbol(&symbol);
                 }
+                self.tail_position = true;
                 self.compile_block_statement(&f.body)?;
                 if self.last_instruction_is(OpPop) {
-                    if self.is_tail_recursive_call(&f.body, &f.name) {
-                        let prev = self.scopes[self.scope_index].previous_instruction.clone();
-                        if prev.opcode == OpCall {
-                            self.scopes[self.scope_index].instructions.bytes[prev.position] =
-                                Opcode::OpTailCall as u8;
-                        }
-                    }
                     self.replace_last_pop_with_return();
                 }
                 if !self.last_instruction_is(OpReturnValue) {
-                    self.emit(OpReturn, &[]);
+                    self.emit(OpReturn, &[])?;
                 }
                 let num_locals = self.symbol_table.num_definitions();
                 let free_symbols = self.symbol_table.free_symbols().to_vec();
-                let instructions = self.leave_scope()?;
+                let (instructions, mappings) = self.leave_scope()?;
                 for symbol in &free_symbols {
-                    self.load_symbol(symbol);
+                    self.load_symbol(symbol)?;
                 }
 
                 let compiled_function = Rc::new(object::CompiledFunction {
                     instructions: instructions.bytes,
                     num_locals,
                     num_parameters: f.params.len(),
+                    name: f.name.clone(),
                 });
 
-                let operands = vec![
-                    self.add_constant(Object::CompiledFunction(compiled_function)),
-                    free_symbols.len(),
-                ];
-                self.emit(OpClosure, &operands);
+                let index = self.add_constant(Object::CompiledFunction(compiled_function));
+                self.function_mappings.functions.insert(index, mappings);
+                self.emit(OpClosure, &[index, free_symbols.len()])?;
             }
             Expression::FunctionCall(fc) => {
+                if self.try_constant_fold_call(fc)? {
+                    return Ok(());
+                }
                 self.compile_expr(&fc.callee)?;
                 for arg in &fc.arguments {
                     self.compile_expr(arg)?;
                 }
-                self.emit(OpCall, &[fc.arguments.len()]);
+                let call = if tail && self.calls_itself(fc) {
+                    OpTailCall
+                } else {
+                    OpCall
+                };
+                self.emit(call, &[fc.arguments.len()])?;
+            }
+            Expression::MethodCall(mc) => {
+                let Some(symbol) = self.symbol_table.resolve(&mc.method.name) else {
+                    return Err(CompileError::UndefinedVariable {
+                        name: mc.method.name.clone(),
+                        span: mc.dot.clone(),
+                    });
+                };
+                self.load_symbol(&symbol)?;
+                self.compile_expr(&mc.receiver)?;
+                for arg in &mc.arguments {
+                    self.compile_expr(arg)?;
+                }
+                self.emit(OpCall, &[mc.arguments.len() + 1])?;
             }
+            Expression::Macro(_) => return Err(CompileError::UnexpandedMacro),
+        }
+
+        Ok(())
+    }
+
+    // `let xs = push(xs, value)` and `xs = push(xs, value)` move `xs` out of
+    // its slot instead of copying the reference, so the builtin sees the only
+    // reference and can append in place. The slot is overwritten by the `let`
+    // or assignment straight after, so nothing
+    // can observe the move as long as `value` cannot read `xs`.
+    fn compile_push_in_place(
+        &mut self,
+        name: &str,
+        expr: &Expression,
+    ) -> Result<bool, CompileError> {
+        let Expression::FunctionCall(call) = expr else {
+            return Ok(false);
+        };
+        let (Expression::IDENTIFIER(callee), [Expression::IDENTIFIER(target), value]) =
+            (&*call.callee, call.arguments.as_slice())
+        else {
+            return Ok(false);
+        };
+        if callee.name != "push" || target.name != name || !cannot_read(value, name) {
+            return Ok(false);
+        }
+
+        let push = self.symbol_table.resolve(&callee.name);
+        let symbol = self.symbol_table.resolve(name);
+        let (Some(push), Some(symbol)) = (push, symbol) else {
+            return Ok(false);
+        };
+        let take = match symbol.scope {
+            SymbolScope::Global if self.scope_index == 0 => OpTakeGlobal,
+            SymbolScope::Local => OpTakeLocal,
+            _ => return Ok(false),
+        };
+        if push.scope != SymbolScope::Builtin {
+            return Ok(false);
         }
 
+        self.load_symbol(&push)?;
+        self.emit(take, &[symbol.index])?;
+        self.compile_operand(value)?;
+        self.emit(OpCall, &[2])?;
+        Ok(true)
+    }
+
+    fn store_symbol(&mut self, symbol: &Symbol) -> Result<(), CompileError> {
+        if symbol.scope == SymbolScope::Global {
+            self.emit(Opcode::OpSetGlobal, &[symbol.index])?;
+        } else {
+            self.emit(Opcode::OpSetLocal, &[symbol.index])?;
+        }
         Ok(())
     }
 
-    fn load_symbol(&mut self, symbol: &Rc<Symbol>) {
+    // Names with a space can't clash with anything in the source.
+    fn define_hidden(&mut self, name: &str, span: &Span) -> Rc<Symbol> {
+        let symbol = self.symbol_table.define(name, span.clone());
+        self.log_symbol(&symbol);
+        symbol
+    }
+
+    fn load_symbol(&mut self, symbol: &Rc<Symbol>) -> Result<(), CompileError> {
         match symbol.scope {
-            SymbolScope::Global => self.emit(OpGetGlobal, &[symbol.index]),
-            SymbolScope::Local => self.emit(OpGetLocal, &[symbol.index]),
-            SymbolScope::Builtin => self.emit(OpGetBuiltin, &[symbol.index]),
-            SymbolScope::Free => self.emit(OpGetFree, &[symbol.index]),
-            SymbolScope::Function => self.emit(OpCurrentClosure, &[]),
+            SymbolScope::Global => self.emit(OpGetGlobal, &[symbol.index])?,
+            SymbolScope::Local => self.emit(OpGetLocal, &[symbol.index])?,
+            SymbolScope::Builtin => self.emit(OpGetBuiltin, &[symbol.index])?,
+            SymbolScope::Free => self.emit(OpGetFree, &[symbol.index])?,
+            SymbolScope::Function => self.emit(OpCurrentClosure, &[])?,
         };
+        Ok(())
     }
 
     pub fn bytecode(&self) -> Bytecode {
         Bytecode {
             instructions: self.current_instruction().clone(),
             constants: self.constants.clone(),
+            source_map: self.source_map(),
+            host_builtins: Rc::clone(&self.host_builtins),
+            num_globals: self.symbol_table.num_definitions(),
         }
     }
 
     pub fn add_constant(&mut self, obj: Object) -> usize {
+        let key = HashKey::try_from(&obj).ok();
+        if let Some(&index) = key.as_ref().and_then(|key| self.interned.get(key)) {
+            return index;
+        }
+        log(
+            &mut self.verbose,
+            format_args!("constant {} = {}", self.constants.len(), obj),
+        );
         self.constants.push(Rc::new(obj));
-        self.constants.len() - 1
+        let index = self.constants.len() - 1;
+        if let Some(key) = key {
+            self.interned.insert(key, index);
+        }
+        index
+    }
+
+    fn emit_constant(&mut self, obj: Object) -> Result<(), CompileError> {
+        let index = self.add_constant(obj);
+        self.emit(OpConst, &[index])?;
+        Ok(())
     }
 
-    pub fn emit(&mut self, op: Opcode, operands: &[usize]) -> usize {
-        let ins = make_instructions(op, operands);
+    pub fn emit(&mut self, op: Opcode, operands: &[usize]) -> Result<usize, CompileError> {
+        let ins = make(op, operands).map_err(|err| operand_error(op, err))?;
         let pos = self.add_instructions(&ins);
         self.set_last_instruction(op, pos);
-        pos
+        self.map_instruction(pos);
+        Ok(pos)
+    }
+
+    fn map_instruction(&mut self, pos: usize) {
+        let Some(span) = &self.current_span else {
+            return;
+        };
+        let mappings = &mut self.scopes[self.scope_index].mappings;
+        if mappings.last().is_none_or(|last| last.span != *span) {
+            mappings.push(Mapping {
+                offset: pos,
+                span: span.clone(),
+            });
+        }
+    }
+
+    /// Where each instruction of the last compiled program, and of every
+    /// function compiled so far, came from in the source.
+    pub fn source_map(&self) -> SourceMap {
+        SourceMap {
+            main: self.scopes[0].mappings.clone(),
+            functions: self.function_mappings.functions.clone(),
+        }
     }
 
     fn compile_block_statement(
         &mut self,
         block_statement: &BlockStatement,
     ) -> Result<(), CompileError> {
-        for stmt in &block_statement.body {
+        self.check_reachable(&block_statement.body);
+        // a function body or `if` branch in tail position passes that on to
+        // its last expression statement
+        let tail = std::mem::take(&mut self.tail_position);
+        let last = block_statement.body.len().saturating_sub(1);
+        for (i, stmt) in block_statement.body.iter().enumerate() {
+            self.tail_position = tail && i == last && matches!(stmt, Statement::Expr(_));
             self.compile_stmt(stmt)?;
         }
         Ok(())
     }
 
+    // The names a block defines are only visible inside it. The table is
+    // restored even on error, since the REPL keeps it for the next input.
+    fn compile_scoped_block(
+        &mut self,
+        block_statement: &BlockStatement,
+    ) -> Result<(), CompileError> {
+        self.symbol_table = SymbolTable::new_block(Rc::new(self.symbol_table.clone()));
+        let result = self.compile_block_statement(block_statement);
+        if let Some(outer) = self.symbol_table.end_block() {
+            self.symbol_table = outer;
+        }
+        result
+    }
+
+    // An `if` branch leaves the value of its last expression statement on
+    // the stack, or null if it ends with a `let`, an assignment or nothing.
+    // One ending with `return`, `break` or `continue` never gets that far.
+    fn compile_branch(&mut self, block_statement: &BlockStatement) -> Result<(), CompileError> {
+        self.compile_scoped_block(block_statement)?;
+        match block_statement.body.last() {
+            Some(Statement::Expr(_)) => {
+                if self.last_instruction_is(OpPop) {
+                    self.remove_last_pop();
+                }
+            }
+            Some(Statement::Return(_) | Statement::Break(_) | Statement::Continue(_)) => {}
+            _ => {
+                self.emit(OpNull, &[])?;
+            }
+        }
+        Ok(())
+    }
+
     pub fn add_instructions(&mut self, ins: &Instructions) -> usize {
         let pos = self.current_instruction().bytes.len();
         self.scopes[self.scope_index]
@@ -429,6 +1049,9 @@ impl Compiler {
             .instructions
             .bytes
             .truncate(last.position);
+        self.scopes[self.scope_index]
+            .mappings
+            .retain(|mapping| mapping.offset < last.position);
         self.scopes[self.scope_index].last_instruction = previous;
     }
 
@@ -450,7 +1073,21 @@ impl Compiler {
     fn change_operand(&mut self, pos: usize, operand: usize) -> Result<(), CompileError> {
         let op = cast_u8_to_opcode(self.current_instruction().bytes[pos])
             .map_err(CompileError::Opcode)?;
-        let ins = make_instructions(op, &[operand]);
+        if self.verbose.is_some() {
+            let def = definitions()
+                .get(&op)
+                .ok_or(CompileError::Opcode(OpCodeError::MissingDefinition(op)))?;
+            let (before, _) = read_operands(def, &self.current_instruction().bytes[pos + 1..])
+                .map_err(CompileError::Opcode)?;
+            log(
+                &mut self.verbose,
+                format_args!(
+                    "scope {}: patch {} at {:04}: {:04} -> {:04}",
+                    self.scope_index, def.name, pos, before[0], operand
+                ),
+            );
+        }
+        let ins = make(op, &[operand]).map_err(|err| operand_error(op, err))?;
         self.replace_instruction(pos, &ins);
         Ok(())
     }
@@ -462,34 +1099,137 @@ impl Compiler {
     fn enter_scope(&mut self) {
         self.scopes.push(CompilationScope::default());
         self.scope_index += 1;
+        log(
+            &mut self.verbose,
+            format_args!("enter scope {}", self.scope_index),
+        );
         self.symbol_table = SymbolTable::new_enclosed(Rc::new(self.symbol_table.clone()));
     }
 
-    fn leave_scope(&mut self) -> Result<Instructions, CompileError> {
+    fn leave_scope(&mut self) -> Result<(Instructions, Vec<Mapping>), CompileError> {
         if self.scope_index == 0 {
             return Err(CompileError::ScopeUnderflow);
         }
 
-        let instructions = self.current_instruction().clone();
-        self.scopes.pop();
+        let len = self.current_instruction().len();
+        log(
+            &mut self.verbose,
+            format_args!(
+                "leave scope {} ({} bytes, {} locals, {} free)",
+                self.scope_index,
+                len,
+                self.symbol_table.num_definitions(),
+                self.symbol_table.free_symbols().len()
+            ),
+        );
+        let scope = self.scopes.pop().ok_or(CompileError::ScopeUnderflow)?;
         self.scope_index -= 1;
         let Some(outer) = self.symbol_table.outer() else {
             return Err(CompileError::ScopeUnderflow);
         };
         self.symbol_table = outer.as_ref().clone();
-        Ok(instructions)
+        Ok((scope.instructions, scope.mappings))
     }
 
-    fn is_tail_recursive_call(&self, body: &BlockStatement, name: &str) -> bool {
-        if name.is_empty() {
-            return false;
+    pub fn warnings(&self) -> &[Warning] {
+        &self.warnings
+    }
+
+    fn check_shadowing(&mut self, name: &str, span: &Span) {
+        let Some(shadowed) = self.symbol_table.shadowed(name) else {
+            return;
+        };
+        if shadowed.scope != SymbolScope::Builtin && !self.warn_shadowing {
+            return;
         }
-        if let Some(Statement::Expr(Expression::FunctionCall(fc))) = body.body.last()
-            && let Expression::IDENTIFIER(id) = &*fc.callee
-        {
-            return id.name == name;
+        self.warnings.push(Warning {
+            kind: WarningKind::Shadowing {
+                name: name.to_string(),
+                shadowed: shadowed.scope.clone(),
+                previous: shadowed.span.clone(),
+            },
+            span: span.clone(),
+        });
+    }
+
+    // Only the first unreachable statement of a block is reported; nested
+    // blocks are checked when they are compiled.
+    fn check_reachable(&mut self, body: &[Statement]) {
+        let Some(position) = body.iter().position(|stmt| terminator(stmt).is_some()) else {
+            return;
+        };
+        let Some(unreachable) = body.get(position + 1) else {
+            return;
+        };
+        let (after, at) = terminator(&body[position]).unwrap();
+        self.warnings.push(Warning {
+            kind: WarningKind::UnreachableCode {
+                after,
+                at: at.clone(),
+            },
+            span: unreachable.span().clone(),
+        });
+    }
+
+    // Both engines insert pairs in source order, so a repeated key keeps
+    // its last value. Only literal keys can be compared before runtime.
+    // Keys that are array, hash or function literals could never be hashed
+    // and are errors; other unhashable keys are caught by the VM.
+    fn check_hash_keys(&mut self, hash: &parser::ast::Hash) -> Result<(), CompileError> {
+        let mut seen: HashMap<HashKey, Span> = HashMap::new();
+        for (key, _) in &hash.elements {
+            let unusable = match key {
+                Expression::LITERAL(Literal::Array(_)) => Some("ARRAY"),
+                Expression::LITERAL(Literal::Hash(_)) => Some("HASH"),
+                Expression::FUNCTION(_) => Some("FUNCTION"),
+                _ => None,
+            };
+            if let Some(type_name) = unusable {
+                return Err(CompileError::UnusableAsHashKey {
+                    type_name,
+                    span: key.span().clone(),
+                });
+            }
+            let Some(hash_key) =
+                literal_object(key).and_then(|object| HashKey::try_from(&*object).ok())
+            else {
+                continue;
+            };
+            match seen.get(&hash_key) {
+                Some(first) => self.warnings.push(Warning {
+                    kind: WarningKind::DuplicateHashKey {
+                        key: match &hash_key {
+                            HashKey::String(s) => format!("{:?}", s),
+                            HashKey::Integer(i) => i.to_string(),
+                            HashKey::Boolean(b) => b.to_string(),
+                        },
+                        first: first.clone(),
+                    },
+                    span: key.span().clone(),
+                }),
+                None => {
+                    seen.insert(hash_key, key.span().clone());
+                }
+            }
         }
-        false
+        Ok(())
+    }
+
+    // Only a function's own name resolves to `SymbolScope::Function`, so
+    // this is a direct call of the function being compiled. Calls to other
+    // functions, mutually recursive ones included, keep their frames.
+    fn calls_itself(&self, fc: &parser::ast::FunctionCall) -> bool {
+        let Expression::IDENTIFIER(callee) = &*fc.callee else {
+            return false;
+        };
+        let self_global = self.scopes[self.scope_index].self_global;
+        self.symbol_table
+            .resolve(&callee.name)
+            .is_some_and(|symbol| match symbol.scope {
+                SymbolScope::Function => true,
+                SymbolScope::Global => self_global == Some(symbol.index),
+                _ => false,
+            })
     }
 
     fn try_constant_fold_prefix(
@@ -498,25 +1238,67 @@ impl Compiler {
     ) -> Option<Result<(), CompileError>> {
         if prefix.op.kind == TokenKind::MINUS
             && let Expression::LITERAL(Literal::Integer(Integer { raw, .. })) = &*prefix.operand
+            && let Some(negated) = raw.checked_neg()
         {
-            let result = Object::Integer(-*raw);
-            let idx = self.add_constant(result);
-            self.emit(OpConst, &[idx]);
-            return Some(Ok(()));
+            return Some(self.emit_constant(Object::Integer(negated)));
         }
         if prefix.op.kind == TokenKind::BANG
             && let Expression::LITERAL(Literal::Boolean(b)) = &*prefix.operand
         {
-            if b.raw {
-                self.emit(OpFalse, &[]);
-            } else {
-                self.emit(OpTrue, &[]);
-            }
-            return Some(Ok(()));
+            let op = if b.raw { OpFalse } else { OpTrue };
+            return Some(self.emit(op, &[]).map(|_| ()));
+        }
+        if prefix.op.kind == TokenKind::BANG
+            && let Expression::LITERAL(Literal::Null(_)) = &*prefix.operand
+        {
+            return Some(self.emit(OpTrue, &[]).map(|_| ()));
         }
         None
     }
 
+    // `a && b` compiles to
+    //
+    //     a; OpJumpNotTruthy false; b; OpJumpNotTruthy false;
+    //     OpTrue; OpJump end; false: OpFalse; end:
+    //
+    // and `a || b` tests `a` first, jumping straight to `OpTrue` when it is
+    // truthy. `b` is skipped whenever `a` decides the result, and both
+    // operators leave a boolean rather than one of their operands.
+    fn compile_logical(
+        &mut self,
+        infix: &parser::ast::BinaryExpression,
+    ) -> Result<(), CompileError> {
+        self.compile_expr(&infix.left)?;
+        let left_jump = self.emit(OpJumpNotTruthy, &[Self::PLACEHOLDER_ADDRESS])?;
+        let left_true_jump = if infix.op.kind == TokenKind::OR {
+            let jump = self.emit(OpJump, &[Self::PLACEHOLDER_ADDRESS])?;
+            let right_start = self.current_instruction().bytes.len();
+            self.change_operand(left_jump, right_start)?;
+            Some(jump)
+        } else {
+            None
+        };
+        self.compile_expr(&infix.right)?;
+        let right_jump = self.emit(OpJumpNotTruthy, &[Self::PLACEHOLDER_ADDRESS])?;
+
+        if let Some(jump) = left_true_jump {
+            let true_start = self.current_instruction().bytes.len();
+            self.change_operand(jump, true_start)?;
+        }
+        self.emit(OpTrue, &[])?;
+        let jump_to_end = self.emit(OpJump, &[Self::PLACEHOLDER_ADDRESS])?;
+
+        let false_start = self.current_instruction().bytes.len();
+        if infix.op.kind == TokenKind::AND {
+            self.change_operand(left_jump, false_start)?;
+        }
+        self.change_operand(right_jump, false_start)?;
+        self.emit(OpFalse, &[])?;
+
+        let end = self.current_instruction().bytes.len();
+        self.change_operand(jump_to_end, end)
+    }
+
     fn try_constant_fold_infix(
         &mut self,
         infix: &parser::ast::BinaryExpression,
@@ -526,22 +1308,14 @@ impl Compiler {
             Expression::LITERAL(Literal::Integer(Integer { raw: right, .. })),
         ) = (&*infix.left, &*infix.right)
         {
+            // overflow and division by zero are left to the VM to report
             let result = match infix.op.kind {
-                TokenKind::PLUS => Object::Integer(left + right),
-                TokenKind::MINUS => Object::Integer(left - right),
-                TokenKind::ASTERISK => Object::Integer(left * right),
-                TokenKind::SLASH => {
-                    if *right == 0 {
-                        return None;
-                    }
-                    Object::Integer(left / right)
-                }
-                TokenKind::PERCENT => {
-                    if *right == 0 {
-                        return None;
-                    }
-                    Object::Integer(left % right)
-                }
+                TokenKind::PLUS => Object::Integer(left.checked_add(*right)?),
+                TokenKind::MINUS => Object::Integer(left.checked_sub(*right)?),
+                TokenKind::ASTERISK => Object::Integer(left.checked_mul(*right)?),
+                TokenKind::SLASH => Object::Integer(left.checked_div(*right)?),
+                TokenKind::DIV => Object::Integer(checked_floor_div(*left, *right)?),
+                TokenKind::PERCENT => Object::Integer(left.checked_rem(*right)?),
                 TokenKind::GT => Object::Boolean(left > right),
                 TokenKind::LT => Object::Boolean(left < right),
                 TokenKind::GTE => Object::Boolean(left >= right),
@@ -550,21 +1324,195 @@ impl Compiler {
                 TokenKind::NotEq => Object::Boolean(left != right),
                 _ => return None,
             };
-            match result {
-                Object::Integer(_) => {
-                    let idx = self.add_constant(result);
-                    self.emit(OpConst, &[idx]);
-                }
-                Object::Boolean(true) => {
-                    self.emit(OpTrue, &[]);
-                }
-                Object::Boolean(false) => {
-                    self.emit(OpFalse, &[]);
-                }
+            return Some(match result {
+                Object::Integer(_) => self.emit_constant(result),
+                Object::Boolean(true) => self.emit(OpTrue, &[]).map(|_| ()),
+                Object::Boolean(false) => self.emit(OpFalse, &[]).map(|_| ()),
                 _ => unreachable!(),
-            }
-            return Some(Ok(()));
+            });
         }
         None
     }
+
+    // Only fires when the callee still resolves to the builtin, so a user
+    // binding that shadows e.g. `len` is always called. Builtin errors and
+    // results that are not scalars are left to runtime.
+    fn try_constant_fold_call(
+        &mut self,
+        call: &parser::ast::FunctionCall,
+    ) -> Result<bool, CompileError> {
+        let Expression::IDENTIFIER(callee) = &*call.callee else {
+            return Ok(false);
+        };
+        if !PURE_BUILTINS.contains(&callee.name.as_str()) {
+            return Ok(false);
+        }
+        let Some(&(_, BuiltinFunc::Plain(func))) =
+            BuiltIns.iter().find(|(name, _)| *name == callee.name)
+        else {
+            return Ok(false);
+        };
+        // a registered builtin may hide the standard one
+        match self.symbol_table.resolve(&callee.name) {
+            Some(symbol)
+                if symbol.scope == SymbolScope::Builtin && symbol.index < BuiltIns.len() => {}
+            _ => return Ok(false),
+        }
+        let Some(args) = call
+            .arguments
+            .iter()
+            .map(literal_object)
+            .collect::<Option<Vec<_>>>()
+        else {
+            return Ok(false);
+        };
+
+        match &*func(args) {
+            Object::Integer(i) => self.emit_constant(Object::Integer(*i))?,
+            Object::String(s) => self.emit_constant(Object::String(s.clone()))?,
+            Object::Boolean(true) => {
+                self.emit(OpTrue, &[])?;
+            }
+            Object::Boolean(false) => {
+                self.emit(OpFalse, &[])?;
+            }
+            Object::Null => {
+                self.emit(OpNull, &[])?;
+            }
+            _ => return Ok(false),
+        }
+        Ok(true)
+    }
+}
+
+// A statement that leaves its block on every path.
+fn terminator(stmt: &Statement) -> Option<(Terminator, &Span)> {
+    match stmt {
+        Statement::Return(r) => Some((Terminator::Return, &r.span)),
+        Statement::Break(b) => Some((Terminator::Break, &b.span)),
+        Statement::Continue(c) => Some((Terminator::Continue, &c.span)),
+        Statement::Expr(Expression::IF(if_node)) => {
+            let alternate = if_node.alternate.as_ref()?;
+            let exit = |block: &BlockStatement| {
+                let (terminator, _) = block.body.iter().find_map(terminator)?;
+                Some(match terminator {
+                    Terminator::Return => Some(Exit::Return),
+                    Terminator::Break => Some(Exit::Break),
+                    Terminator::Continue => Some(Exit::Continue),
+                    Terminator::If(exit) => exit,
+                })
+            };
+            let (consequent, alternate) = (exit(&if_node.consequent)?, exit(alternate)?);
+            let shared = if consequent == alternate {
+                consequent
+            } else {
+                None
+            };
+            Some((Terminator::If(shared), &if_node.span))
+        }
+        _ => None,
+    }
+}
+
+// Names the limit a program ran into when an operand doesn't fit: index
+// operands count the slot they would need, so the 65537th constant is
+// reported as "too many constants (65537); limit is 65536".
+fn operand_error(op: Opcode, err: OpCodeError) -> CompileError {
+    let OpCodeError::OperandOutOfRange { operand, width } = err else {
+        return CompileError::Opcode(err);
+    };
+    let max = (1 << (8 * width)) - 1;
+    let (what, count, limit) = match (op, width) {
+        (OpJump | OpJumpNotTruthy, _) => return CompileError::JumpOutOfRange(operand),
+        (OpConst, _) | (OpClosure, 2) => ("constants", operand + 1, max + 1),
+        (OpGetGlobal | OpSetGlobal | OpTakeGlobal, _) => ("globals", operand + 1, max + 1),
+        (OpGetLocal | OpSetLocal | OpTakeLocal, _) => ("locals", operand + 1, max + 1),
+        (OpGetFree, _) => ("free variables", operand + 1, max + 1),
+        (OpClosure, _) => ("free variables", operand, max),
+        (OpArray, _) => ("array elements", operand, max),
+        (OpHash, _) => ("hash entries", operand / 2, max / 2),
+        (OpCall | OpTailCall, _) => ("arguments", operand, max),
+        _ => return CompileError::Opcode(err),
+    };
+    CompileError::TooMany { what, count, limit }
+}
+
+// Builtins with no side effects whose result depends only on their
+// arguments, and so can be evaluated at compile time. Builtins whose result
+// can be far larger than their arguments (`pad_start`, `pad_end`) are left
+// out so a literal call can't blow up compilation.
+const PURE_BUILTINS: &[&str] = &[
+    "len",
+    "first",
+    "last",
+    "rest",
+    "starts_with",
+    "ends_with",
+    "substr",
+    "parse_int",
+    "deep_equal",
+    "contains",
+    "type",
+    "split",
+    "join",
+    "trim",
+    "upper",
+    "lower",
+    "to_int",
+    "to_string",
+    "abs",
+    "min",
+    "max",
+    "pow",
+    "reverse",
+    "slice",
+];
+
+fn literal_object(expr: &Expression) -> Option<Rc<Object>> {
+    let object = match expr {
+        Expression::LITERAL(Literal::Integer(i)) => Object::Integer(i.raw),
+        Expression::LITERAL(Literal::Float(f)) => Object::Float(f.raw),
+        Expression::LITERAL(Literal::Boolean(b)) => Object::Boolean(b.raw),
+        Expression::LITERAL(Literal::String(s)) => Object::String(s.raw.as_str().into()),
+        Expression::LITERAL(Literal::Array(array)) => Object::Array(
+            array
+                .elements
+                .iter()
+                .map(literal_object)
+                .collect::<Option<Vec<_>>>()?,
+        ),
+        _ => return None,
+    };
+    Some(Rc::new(object))
+}
+
+// Conservative: only plain data built from literals and other names is
+// known not to read `name`; calls and function literals might.
+fn cannot_read(expr: &Expression, name: &str) -> bool {
+    match expr {
+        Expression::IDENTIFIER(identifier) => identifier.name != name,
+        Expression::LITERAL(Literal::Array(array)) => {
+            array.elements.iter().all(|e| cannot_read(e, name))
+        }
+        Expression::LITERAL(Literal::Hash(hash)) => hash
+            .elements
+            .iter()
+            .all(|(k, v)| cannot_read(k, name) && cannot_read(v, name)),
+        Expression::LITERAL(_) => true,
+        Expression::PREFIX(prefix) => cannot_read(&prefix.operand, name),
+        Expression::INFIX(infix) => {
+            cannot_read(&infix.left, name) && cannot_read(&infix.right, name)
+        }
+        Expression::Index(index) => {
+            cannot_read(&index.object, name) && cannot_read(&index.index, name)
+        }
+        _ => false,
+    }
+}
+
+fn log(verbose: &mut Option<Box<dyn Write>>, event: fmt::Arguments) {
+    if let Some(writer) = verbose {
+        // Logging is diagnostic only; a failed write must not fail the compile.
+        let _ = writeln!(writer, "{}", event);
+    }
 }
@@ -1,9 +1,11 @@
 #[cfg(test)]
 mod tests {
+    use crate::compiler::{CompileError, Compiler};
     use crate::compiler_test::{CompilerTestCase, run_compiler_test};
     use crate::op_code::Opcode::*;
-    use crate::op_code::{concat_instructions, make_instructions};
+    use crate::op_code::{Instructions, concat_instructions, make_instructions};
     use object::Object;
+    use parser::lexer::token::Span;
     use std::rc::Rc;
 
     #[test]
@@ -21,6 +23,7 @@ mod tests {
                         .bytes,
                         num_locals: 0,
                         num_parameters: 0,
+                        name: String::new(),
                     })),
                 ],
                 expected_instructions: vec![
@@ -40,6 +43,7 @@ mod tests {
                         .bytes,
                         num_locals: 0,
                         num_parameters: 0,
+                        name: String::new(),
                     })),
                 ],
                 expected_instructions: vec![
@@ -62,6 +66,7 @@ mod tests {
                         .bytes,
                         num_locals: 0,
                         num_parameters: 0,
+                        name: String::new(),
                     })),
                 ],
                 expected_instructions: vec![
@@ -82,6 +87,7 @@ mod tests {
                     instructions: concat_instructions(vec![make_instructions(OpReturn, &[])]).bytes,
                     num_locals: 0,
                     num_parameters: 0,
+                    name: String::new(),
                 },
             ))],
             expected_instructions: vec![
@@ -107,6 +113,7 @@ mod tests {
                         .bytes,
                         num_locals: 0,
                         num_parameters: 0,
+                        name: String::new(),
                     })),
                 ],
                 expected_instructions: vec![
@@ -127,6 +134,7 @@ mod tests {
                         .bytes,
                         num_locals: 0,
                         num_parameters: 0,
+                        name: "noArg".to_string(),
                     })),
                 ],
                 expected_instructions: vec![
@@ -148,6 +156,7 @@ mod tests {
                         .bytes,
                         num_locals: 1,
                         num_parameters: 1,
+                        name: "oneArg".to_string(),
                     })),
                     Object::Integer(24),
                 ],
@@ -175,6 +184,7 @@ mod tests {
                         .bytes,
                         num_locals: 3,
                         num_parameters: 3,
+                        name: "manyArg".to_string(),
                     })),
                     Object::Integer(24),
                     Object::Integer(25),
@@ -211,6 +221,7 @@ mod tests {
                         .bytes,
                         num_locals: 0,
                         num_parameters: 0,
+                        name: String::new(),
                     })),
                 ],
                 expected_instructions: vec![
@@ -234,6 +245,7 @@ mod tests {
                         .bytes,
                         num_locals: 1,
                         num_parameters: 0,
+                        name: String::new(),
                     })),
                 ],
                 expected_instructions: vec![
@@ -260,6 +272,7 @@ mod tests {
                         .bytes,
                         num_locals: 2,
                         num_parameters: 0,
+                        name: String::new(),
                     })),
                 ],
                 expected_instructions: vec![
@@ -276,32 +289,31 @@ mod tests {
         let tests = vec![
             CompilerTestCase {
                 input: "len([]); push([], 1);",
-                expected_constants: vec![Object::Integer(1)],
+                expected_constants: vec![Object::Integer(0), Object::Integer(1)],
                 expected_instructions: vec![
-                    make_instructions(OpGetBuiltin, &[0]),
-                    make_instructions(OpArray, &[0]),
-                    make_instructions(OpCall, &[1]),
+                    make_instructions(OpConst, &[0]),
                     make_instructions(OpPop, &[]),
                     make_instructions(OpGetBuiltin, &[5]),
                     make_instructions(OpArray, &[0]),
-                    make_instructions(OpConst, &[0]),
+                    make_instructions(OpConst, &[1]),
                     make_instructions(OpCall, &[2]),
                     make_instructions(OpPop, &[]),
                 ],
             },
             CompilerTestCase {
-                input: "fn() { len([]) }",
+                input: "fn(xs) { len(xs) }",
                 expected_constants: vec![Object::CompiledFunction(Rc::from(
                     object::CompiledFunction {
                         instructions: concat_instructions(vec![
                             make_instructions(OpGetBuiltin, &[0]),
-                            make_instructions(OpArray, &[0]),
+                            make_instructions(OpGetLocal, &[0]),
                             make_instructions(OpCall, &[1]),
                             make_instructions(OpReturnValue, &[]),
                         ])
                         .bytes,
-                        num_locals: 0,
-                        num_parameters: 0,
+                        num_locals: 1,
+                        num_parameters: 1,
+                        name: String::new(),
                     },
                 ))],
                 expected_instructions: vec![
@@ -312,4 +324,645 @@ mod tests {
         ];
         run_compiler_test(tests);
     }
+
+    #[test]
+    fn test_pure_builtin_calls_on_literals_are_folded() {
+        let tests = vec![
+            CompilerTestCase {
+                input: r#"len("hello")"#,
+                expected_constants: vec![Object::Integer(5)],
+                expected_instructions: vec![
+                    make_instructions(OpConst, &[0]),
+                    make_instructions(OpPop, &[]),
+                ],
+            },
+            CompilerTestCase {
+                input: "first([1, 2, 3]); last([]);",
+                expected_constants: vec![Object::Integer(1)],
+                expected_instructions: vec![
+                    make_instructions(OpConst, &[0]),
+                    make_instructions(OpPop, &[]),
+                    make_instructions(OpNull, &[]),
+                    make_instructions(OpPop, &[]),
+                ],
+            },
+            CompilerTestCase {
+                input: r#"starts_with("monkey", "mon"); ends_with("monkey", "mon")"#,
+                expected_constants: vec![],
+                expected_instructions: vec![
+                    make_instructions(OpTrue, &[]),
+                    make_instructions(OpPop, &[]),
+                    make_instructions(OpFalse, &[]),
+                    make_instructions(OpPop, &[]),
+                ],
+            },
+            CompilerTestCase {
+                input: "contains([1, 2], 3)",
+                expected_constants: vec![],
+                expected_instructions: vec![
+                    make_instructions(OpFalse, &[]),
+                    make_instructions(OpPop, &[]),
+                ],
+            },
+            CompilerTestCase {
+                input: r#"reverse("abc")"#,
+                expected_constants: vec![Object::String("cba".into())],
+                expected_instructions: vec![
+                    make_instructions(OpConst, &[0]),
+                    make_instructions(OpPop, &[]),
+                ],
+            },
+        ];
+        run_compiler_test(tests);
+    }
+
+    #[test]
+    fn test_builtin_calls_that_cannot_be_folded() {
+        let tests = vec![
+            // errors are reported at runtime
+            CompilerTestCase {
+                input: "len(1)",
+                expected_constants: vec![Object::Integer(1)],
+                expected_instructions: vec![
+                    make_instructions(OpGetBuiltin, &[0]),
+                    make_instructions(OpConst, &[0]),
+                    make_instructions(OpCall, &[1]),
+                    make_instructions(OpPop, &[]),
+                ],
+            },
+            // only scalar results become constants
+            CompilerTestCase {
+                input: "rest([1])",
+                expected_constants: vec![Object::Integer(1)],
+                expected_instructions: vec![
+                    make_instructions(OpGetBuiltin, &[4]),
+                    make_instructions(OpConst, &[0]),
+                    make_instructions(OpArray, &[1]),
+                    make_instructions(OpCall, &[1]),
+                    make_instructions(OpPop, &[]),
+                ],
+            },
+            // a result that can be much larger than the arguments would
+            // bloat the constant pool, even in code that never runs
+            CompilerTestCase {
+                input: r#"pad_start("7", 3, "0")"#,
+                expected_constants: vec![
+                    Object::String("7".into()),
+                    Object::Integer(3),
+                    Object::String("0".into()),
+                ],
+                expected_instructions: vec![
+                    make_instructions(OpGetBuiltin, &[10]),
+                    make_instructions(OpConst, &[0]),
+                    make_instructions(OpConst, &[1]),
+                    make_instructions(OpConst, &[2]),
+                    make_instructions(OpCall, &[3]),
+                    make_instructions(OpPop, &[]),
+                ],
+            },
+            // side effects must still happen
+            CompilerTestCase {
+                input: r#"puts("hi")"#,
+                expected_constants: vec![Object::String("hi".into())],
+                expected_instructions: vec![
+                    make_instructions(OpGetBuiltin, &[1]),
+                    make_instructions(OpConst, &[0]),
+                    make_instructions(OpCall, &[1]),
+                    make_instructions(OpPop, &[]),
+                ],
+            },
+        ];
+        run_compiler_test(tests);
+    }
+
+    #[test]
+    fn test_shadowed_builtin_calls_are_not_folded() {
+        let tests = vec![
+            CompilerTestCase {
+                input: "let len = fn(x) { 0 }; len([])",
+                expected_constants: vec![
+                    Object::Integer(0),
+                    Object::CompiledFunction(Rc::from(object::CompiledFunction {
+                        instructions: concat_instructions(vec![
+                            make_instructions(OpConst, &[0]),
+                            make_instructions(OpReturnValue, &[]),
+                        ])
+                        .bytes,
+                        num_locals: 1,
+                        num_parameters: 1,
+                        name: "len".to_string(),
+                    })),
+                ],
+                expected_instructions: vec![
+                    make_instructions(OpClosure, &[1, 0]),
+                    make_instructions(OpSetGlobal, &[0]),
+                    make_instructions(OpGetGlobal, &[0]),
+                    make_instructions(OpArray, &[0]),
+                    make_instructions(OpCall, &[1]),
+                    make_instructions(OpPop, &[]),
+                ],
+            },
+            CompilerTestCase {
+                input: r#"fn(len) { len("abc") }"#,
+                expected_constants: vec![
+                    Object::String("abc".into()),
+                    Object::CompiledFunction(Rc::from(object::CompiledFunction {
+                        instructions: concat_instructions(vec![
+                            make_instructions(OpGetLocal, &[0]),
+                            make_instructions(OpConst, &[0]),
+                            make_instructions(OpCall, &[1]),
+                            make_instructions(OpReturnValue, &[]),
+                        ])
+                        .bytes,
+                        num_locals: 1,
+                        num_parameters: 1,
+                        name: String::new(),
+                    })),
+                ],
+                expected_instructions: vec![
+                    make_instructions(OpClosure, &[1, 0]),
+                    make_instructions(OpPop, &[]),
+                ],
+            },
+        ];
+        run_compiler_test(tests);
+    }
+
+    #[test]
+    fn test_push_onto_own_binding_moves_the_array() {
+        let tests = vec![
+            CompilerTestCase {
+                input: "let xs = []; xs = push(xs, 1);",
+                expected_constants: vec![Object::Integer(1)],
+                expected_instructions: vec![
+                    make_instructions(OpArray, &[0]),
+                    make_instructions(OpSetGlobal, &[0]),
+                    make_instructions(OpGetBuiltin, &[5]),
+                    make_instructions(OpTakeGlobal, &[0]),
+                    make_instructions(OpConst, &[0]),
+                    make_instructions(OpCall, &[2]),
+                    make_instructions(OpSetGlobal, &[0]),
+                ],
+            },
+            CompilerTestCase {
+                input: "fn(xs) { xs = push(xs, 1); xs }",
+                expected_constants: vec![
+                    Object::Integer(1),
+                    Object::CompiledFunction(Rc::from(object::CompiledFunction {
+                        instructions: concat_instructions(vec![
+                            make_instructions(OpGetBuiltin, &[5]),
+                            make_instructions(OpTakeLocal, &[0]),
+                            make_instructions(OpConst, &[0]),
+                            make_instructions(OpCall, &[2]),
+                            make_instructions(OpSetLocal, &[0]),
+                            make_instructions(OpGetLocal, &[0]),
+                            make_instructions(OpReturnValue, &[]),
+                        ])
+                        .bytes,
+                        num_locals: 1,
+                        num_parameters: 1,
+                        name: String::new(),
+                    })),
+                ],
+                expected_instructions: vec![
+                    make_instructions(OpClosure, &[1, 0]),
+                    make_instructions(OpPop, &[]),
+                ],
+            },
+            // The pushed value reads `xs`, so it must still see the array.
+            CompilerTestCase {
+                input: "let xs = []; xs = push(xs, len(xs));",
+                expected_constants: vec![],
+                expected_instructions: vec![
+                    make_instructions(OpArray, &[0]),
+                    make_instructions(OpSetGlobal, &[0]),
+                    make_instructions(OpGetBuiltin, &[5]),
+                    make_instructions(OpGetGlobal, &[0]),
+                    make_instructions(OpGetBuiltin, &[0]),
+                    make_instructions(OpGetGlobal, &[0]),
+                    make_instructions(OpCall, &[1]),
+                    make_instructions(OpCall, &[2]),
+                    make_instructions(OpSetGlobal, &[0]),
+                ],
+            },
+        ];
+        run_compiler_test(tests);
+    }
+
+    #[test]
+    fn test_method_calls() {
+        let tests = vec![
+            CompilerTestCase {
+                input: "[].push(1).len();",
+                expected_constants: vec![Object::Integer(1)],
+                expected_instructions: vec![
+                    make_instructions(OpGetBuiltin, &[0]),
+                    make_instructions(OpGetBuiltin, &[5]),
+                    make_instructions(OpArray, &[0]),
+                    make_instructions(OpConst, &[0]),
+                    make_instructions(OpCall, &[2]),
+                    make_instructions(OpCall, &[1]),
+                    make_instructions(OpPop, &[]),
+                ],
+            },
+            CompilerTestCase {
+                input: "let double = fn(x) { x * 2 }; 3.double();",
+                expected_constants: vec![
+                    Object::Integer(2),
+                    Object::CompiledFunction(Rc::from(object::CompiledFunction {
+                        instructions: concat_instructions(vec![
+                            make_instructions(OpGetLocal, &[0]),
+                            make_instructions(OpConst, &[0]),
+                            make_instructions(OpMul, &[]),
+                            make_instructions(OpReturnValue, &[]),
+                        ])
+                        .bytes,
+                        num_locals: 1,
+                        num_parameters: 1,
+                        name: "double".to_string(),
+                    })),
+                    Object::Integer(3),
+                ],
+                expected_instructions: vec![
+                    make_instructions(OpClosure, &[1, 0]),
+                    make_instructions(OpSetGlobal, &[0]),
+                    make_instructions(OpGetGlobal, &[0]),
+                    make_instructions(OpConst, &[2]),
+                    make_instructions(OpCall, &[1]),
+                    make_instructions(OpPop, &[]),
+                ],
+            },
+        ];
+        run_compiler_test(tests);
+    }
+
+    fn function(
+        instructions: Vec<Instructions>,
+        num_locals: usize,
+        num_parameters: usize,
+    ) -> Object {
+        named_function("", instructions, num_locals, num_parameters)
+    }
+
+    fn named_function(
+        name: &str,
+        instructions: Vec<Instructions>,
+        num_locals: usize,
+        num_parameters: usize,
+    ) -> Object {
+        Object::CompiledFunction(Rc::from(object::CompiledFunction {
+            instructions: concat_instructions(instructions).bytes,
+            num_locals,
+            num_parameters,
+            name: name.to_string(),
+        }))
+    }
+
+    #[test]
+    fn test_tail_calls_in_if_branches() {
+        let tests = vec![CompilerTestCase {
+            input: "let countdown = fn(n) { if (n == 0) { 0 } else { countdown(n - 1) } };",
+            expected_constants: vec![
+                Object::Integer(0),
+                Object::Integer(1),
+                named_function(
+                    "countdown",
+                    vec![
+                        make_instructions(OpGetLocal, &[0]),
+                        make_instructions(OpConst, &[0]),
+                        make_instructions(OpEqual, &[]),
+                        make_instructions(OpJumpNotTruthy, &[15]),
+                        make_instructions(OpConst, &[0]),
+                        make_instructions(OpJump, &[26]),
+                        make_instructions(OpGetGlobal, &[0]),
+                        make_instructions(OpGetLocal, &[0]),
+                        make_instructions(OpConst, &[1]),
+                        make_instructions(OpSub, &[]),
+                        make_instructions(OpTailCall, &[1]),
+                        make_instructions(OpReturnValue, &[]),
+                    ],
+                    1,
+                    1,
+                ),
+            ],
+            expected_instructions: vec![
+                make_instructions(OpClosure, &[2, 0]),
+                make_instructions(OpSetGlobal, &[0]),
+            ],
+        }];
+        run_compiler_test(tests);
+    }
+
+    fn function_opcodes(input: &str, name: &str) -> String {
+        let program = parser::parse(input).unwrap();
+        let bytecode = Compiler::new().compile(&program).unwrap();
+        let function = bytecode
+            .constants
+            .iter()
+            .find_map(|constant| match &**constant {
+                Object::CompiledFunction(f) if f.name == name => Some(f.clone()),
+                _ => None,
+            })
+            .unwrap();
+        Instructions {
+            bytes: function.instructions.clone(),
+        }
+        .disassemble()
+        .unwrap()
+    }
+
+    #[test]
+    fn test_tail_call_positions() {
+        // `return` hands its value straight back, wherever it is
+        let listing = function_opcodes(
+            "let f = fn(n) { while (n > 0) { return f(n - 1); } 0 };",
+            "f",
+        );
+        assert!(listing.contains("OpTailCall 1"), "{}", listing);
+
+        // either branch of a conditional expression
+        let listing = function_opcodes("let f = fn(n) { n == 0 ? 0 : f(n - 1) };", "f");
+        assert!(listing.contains("OpTailCall 1"), "{}", listing);
+
+        for (input, name) in [
+            // the result is still needed
+            ("let f = fn(n) { 1 + f(n - 1) };", "f"),
+            ("let f = fn(n) { f(n - 1); 0 };", "f"),
+            ("let f = fn(n) { if (f(n - 1)) { 1 } };", "f"),
+            // another function
+            (
+                "let odd = fn(n) { n }; let even = fn(n) { odd(n - 1) };",
+                "even",
+            ),
+            // a nested function calling the one around it
+            ("let f = fn(n) { let g = fn() { f(n - 1) }; g };", "g"),
+        ] {
+            let listing = function_opcodes(input, name);
+            assert!(!listing.contains("OpTailCall"), "{}:\n{}", input, listing);
+        }
+    }
+
+    #[test]
+    fn test_closures() {
+        let tests = vec![
+            CompilerTestCase {
+                input: "fn(a) { fn(b) { a + b } }",
+                expected_constants: vec![
+                    function(
+                        vec![
+                            make_instructions(OpGetFree, &[0]),
+                            make_instructions(OpGetLocal, &[0]),
+                            make_instructions(OpAdd, &[]),
+                            make_instructions(OpReturnValue, &[]),
+                        ],
+                        1,
+                        1,
+                    ),
+                    function(
+                        vec![
+                            make_instructions(OpGetLocal, &[0]),
+                            make_instructions(OpClosure, &[0, 1]),
+                            make_instructions(OpReturnValue, &[]),
+                        ],
+                        1,
+                        1,
+                    ),
+                ],
+                expected_instructions: vec![
+                    make_instructions(OpClosure, &[1, 0]),
+                    make_instructions(OpPop, &[]),
+                ],
+            },
+            // `a` reaches the innermost function through the middle one,
+            // which captures it only to pass it on
+            CompilerTestCase {
+                input: "fn(a) { fn(b) { fn(c) { a + b + c } } }",
+                expected_constants: vec![
+                    function(
+                        vec![
+                            make_instructions(OpGetFree, &[0]),
+                            make_instructions(OpGetFree, &[1]),
+                            make_instructions(OpAdd, &[]),
+                            make_instructions(OpGetLocal, &[0]),
+                            make_instructions(OpAdd, &[]),
+                            make_instructions(OpReturnValue, &[]),
+                        ],
+                        1,
+                        1,
+                    ),
+                    function(
+                        vec![
+                            make_instructions(OpGetFree, &[0]),
+                            make_instructions(OpGetLocal, &[0]),
+                            make_instructions(OpClosure, &[0, 2]),
+                            make_instructions(OpReturnValue, &[]),
+                        ],
+                        1,
+                        1,
+                    ),
+                    function(
+                        vec![
+                            make_instructions(OpGetLocal, &[0]),
+                            make_instructions(OpClosure, &[1, 1]),
+                            make_instructions(OpReturnValue, &[]),
+                        ],
+                        1,
+                        1,
+                    ),
+                ],
+                expected_instructions: vec![
+                    make_instructions(OpClosure, &[2, 0]),
+                    make_instructions(OpPop, &[]),
+                ],
+            },
+            CompilerTestCase {
+                input: "let global = 55; \
+                        fn() { let a = 66; \
+                        fn() { let b = 77; \
+                        fn() { let c = 88; global + a + b + c } } }",
+                expected_constants: vec![
+                    Object::Integer(55),
+                    Object::Integer(66),
+                    Object::Integer(77),
+                    Object::Integer(88),
+                    function(
+                        vec![
+                            make_instructions(OpConst, &[3]),
+                            make_instructions(OpSetLocal, &[0]),
+                            make_instructions(OpGetGlobal, &[0]),
+                            make_instructions(OpGetFree, &[0]),
+                            make_instructions(OpAdd, &[]),
+                            make_instructions(OpGetFree, &[1]),
+                            make_instructions(OpAdd, &[]),
+                            make_instructions(OpGetLocal, &[0]),
+                            make_instructions(OpAdd, &[]),
+                            make_instructions(OpReturnValue, &[]),
+                        ],
+                        1,
+                        0,
+                    ),
+                    function(
+                        vec![
+                            make_instructions(OpConst, &[2]),
+                            make_instructions(OpSetLocal, &[0]),
+                            make_instructions(OpGetFree, &[0]),
+                            make_instructions(OpGetLocal, &[0]),
+                            make_instructions(OpClosure, &[4, 2]),
+                            make_instructions(OpReturnValue, &[]),
+                        ],
+                        1,
+                        0,
+                    ),
+                    function(
+                        vec![
+                            make_instructions(OpConst, &[1]),
+                            make_instructions(OpSetLocal, &[0]),
+                            make_instructions(OpGetLocal, &[0]),
+                            make_instructions(OpClosure, &[5, 1]),
+                            make_instructions(OpReturnValue, &[]),
+                        ],
+                        1,
+                        0,
+                    ),
+                ],
+                expected_instructions: vec![
+                    make_instructions(OpConst, &[0]),
+                    make_instructions(OpSetGlobal, &[0]),
+                    make_instructions(OpClosure, &[6, 0]),
+                    make_instructions(OpPop, &[]),
+                ],
+            },
+            // siblings number their captures independently
+            CompilerTestCase {
+                input: "fn(a, b) { [fn() { b }, fn() { a + b }] }",
+                expected_constants: vec![
+                    function(
+                        vec![
+                            make_instructions(OpGetFree, &[0]),
+                            make_instructions(OpReturnValue, &[]),
+                        ],
+                        0,
+                        0,
+                    ),
+                    function(
+                        vec![
+                            make_instructions(OpGetFree, &[0]),
+                            make_instructions(OpGetFree, &[1]),
+                            make_instructions(OpAdd, &[]),
+                            make_instructions(OpReturnValue, &[]),
+                        ],
+                        0,
+                        0,
+                    ),
+                    function(
+                        vec![
+                            make_instructions(OpGetLocal, &[1]),
+                            make_instructions(OpClosure, &[0, 1]),
+                            make_instructions(OpGetLocal, &[0]),
+                            make_instructions(OpGetLocal, &[1]),
+                            make_instructions(OpClosure, &[1, 2]),
+                            make_instructions(OpArray, &[2]),
+                            make_instructions(OpReturnValue, &[]),
+                        ],
+                        2,
+                        2,
+                    ),
+                ],
+                expected_instructions: vec![
+                    make_instructions(OpClosure, &[2, 0]),
+                    make_instructions(OpPop, &[]),
+                ],
+            },
+        ];
+        run_compiler_test(tests);
+    }
+
+    #[test]
+    fn test_recursive_functions() {
+        let count_down = |load_self| {
+            named_function(
+                "countDown",
+                vec![
+                    load_self,
+                    make_instructions(OpGetLocal, &[0]),
+                    make_instructions(OpConst, &[0]),
+                    make_instructions(OpSub, &[]),
+                    make_instructions(OpTailCall, &[1]),
+                    make_instructions(OpReturnValue, &[]),
+                ],
+                1,
+                1,
+            )
+        };
+        let tests = vec![
+            // a global function refers to itself through the global, which
+            // the program may reassign
+            CompilerTestCase {
+                input: "let countDown = fn(x) { countDown(x - 1); }; countDown(1);",
+                expected_constants: vec![
+                    Object::Integer(1),
+                    count_down(make_instructions(OpGetGlobal, &[0])),
+                ],
+                expected_instructions: vec![
+                    make_instructions(OpClosure, &[1, 0]),
+                    make_instructions(OpSetGlobal, &[0]),
+                    make_instructions(OpGetGlobal, &[0]),
+                    make_instructions(OpConst, &[0]),
+                    make_instructions(OpCall, &[1]),
+                    make_instructions(OpPop, &[]),
+                ],
+            },
+            // a local function refers to itself through the closure being
+            // run, since its slot is only set once the closure exists
+            CompilerTestCase {
+                input: "let wrapper = fn() { \
+                            let countDown = fn(x) { countDown(x - 1); }; \
+                            countDown(1); \
+                        }; \
+                        wrapper();",
+                expected_constants: vec![
+                    Object::Integer(1),
+                    count_down(make_instructions(OpCurrentClosure, &[])),
+                    named_function(
+                        "wrapper",
+                        vec![
+                            make_instructions(OpClosure, &[1, 0]),
+                            make_instructions(OpSetLocal, &[0]),
+                            make_instructions(OpGetLocal, &[0]),
+                            make_instructions(OpConst, &[0]),
+                            make_instructions(OpCall, &[1]),
+                            make_instructions(OpReturnValue, &[]),
+                        ],
+                        1,
+                        0,
+                    ),
+                ],
+                expected_instructions: vec![
+                    make_instructions(OpClosure, &[2, 0]),
+                    make_instructions(OpSetGlobal, &[0]),
+                    make_instructions(OpGetGlobal, &[0]),
+                    make_instructions(OpCall, &[0]),
+                    make_instructions(OpPop, &[]),
+                ],
+            },
+        ];
+        run_compiler_test(tests);
+    }
+
+    #[test]
+    fn test_method_call_to_unknown_name() {
+        let program = parser::parse("[1].nope()").unwrap();
+        let err = match Compiler::new().compile(&program) {
+            Ok(_) => panic!("expected compile error"),
+            Err(err) => err,
+        };
+        // reported at the dot, as a failed field access is
+        assert_eq!(
+            err,
+            CompileError::UndefinedVariable {
+                name: "nope".to_string(),
+                span: Span { start: 3, end: 4 },
+            }
+        );
+    }
 }
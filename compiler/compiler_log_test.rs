@@ -0,0 +1,58 @@
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::io::{self, Write};
+    use std::rc::Rc;
+
+    use parser::parse;
+
+    use crate::compiler::Compiler;
+
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn compile_log(input: &str) -> String {
+        let program = parse(input).unwrap();
+        let buffer = SharedBuffer::default();
+        let mut compiler = Compiler::new();
+        compiler.set_verbose(Box::new(buffer.clone()));
+        compiler.compile(&program).unwrap();
+        String::from_utf8(buffer.0.take()).unwrap()
+    }
+
+    #[test]
+    fn test_verbose_log_for_closure_fixture() {
+        let expected = "\
+scope 0: define make_adder as Global 0
+enter scope 1
+scope 1: define x as Local 0
+enter scope 2
+scope 2: define y as Local 0
+constant 0 = 0
+scope 2: patch OpJumpNotTruthy at 0006: 0000 -> 0017
+scope 2: patch OpJump at 0014: 0000 -> 0019
+leave scope 2 (20 bytes, 1 locals, 1 free)
+constant 1 = [compiled function]
+leave scope 1 (7 bytes, 1 locals, 0 free)
+constant 2 = [compiled function]
+constant 3 = 2
+scope 0: define add_two as Global 1
+constant 4 = 3
+";
+        assert_eq!(
+            compile_log(include_str!("tests/fixtures/closure.monkey")),
+            expected
+        );
+    }
+}
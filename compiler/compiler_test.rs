@@ -48,8 +48,15 @@ fn test_instructions(expected: &[Instructions], actual: &Instructions) {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::compiler::CompileError;
+    use crate::differential_test::run_interpreter;
     use crate::op_code::Opcode::*;
     use crate::op_code::make_instructions;
+    use crate::symbol_table::{SymbolScope, SymbolTable};
+    use object::EvalError;
+    use object::environment::Env;
+    use parser::lexer::token::{Span, TokenKind};
+    use std::cell::RefCell;
 
     #[test]
     fn integer_arithmetic() {
@@ -104,6 +111,14 @@ mod tests {
                     make_instructions(OpPop, &[]),
                 ],
             },
+            CompilerTestCase {
+                input: "+1",
+                expected_constants: vec![Object::Integer(1)],
+                expected_instructions: vec![
+                    make_instructions(OpConst, &[0]),
+                    make_instructions(OpPop, &[]),
+                ],
+            },
             CompilerTestCase {
                 input: "!true",
                 expected_constants: vec![],
@@ -236,6 +251,43 @@ mod tests {
         run_compiler_test(tests);
     }
 
+    #[test]
+    fn test_logical_operators() {
+        let tests = vec![
+            CompilerTestCase {
+                input: "true && false;",
+                expected_constants: vec![],
+                expected_instructions: vec![
+                    make_instructions(OpTrue, &[]),
+                    make_instructions(OpJumpNotTruthy, &[12]),
+                    make_instructions(OpFalse, &[]),
+                    make_instructions(OpJumpNotTruthy, &[12]),
+                    make_instructions(OpTrue, &[]),
+                    make_instructions(OpJump, &[13]),
+                    make_instructions(OpFalse, &[]),
+                    make_instructions(OpPop, &[]),
+                ],
+            },
+            CompilerTestCase {
+                input: "true || false;",
+                expected_constants: vec![],
+                expected_instructions: vec![
+                    make_instructions(OpTrue, &[]),
+                    make_instructions(OpJumpNotTruthy, &[7]),
+                    make_instructions(OpJump, &[11]),
+                    make_instructions(OpFalse, &[]),
+                    make_instructions(OpJumpNotTruthy, &[15]),
+                    make_instructions(OpTrue, &[]),
+                    make_instructions(OpJump, &[16]),
+                    make_instructions(OpFalse, &[]),
+                    make_instructions(OpPop, &[]),
+                ],
+            },
+        ];
+
+        run_compiler_test(tests);
+    }
+
     #[test]
     fn test_global_constants() {
         let tests = vec![
@@ -276,12 +328,495 @@ mod tests {
         run_compiler_test(tests);
     }
 
+    #[test]
+    fn test_float() {
+        let tests = vec![
+            CompilerTestCase {
+                input: "2.5 * 2",
+                expected_constants: vec![Object::Float(2.5), Object::Integer(2)],
+                expected_instructions: vec![
+                    make_instructions(OpConst, &[0]),
+                    make_instructions(OpConst, &[1]),
+                    make_instructions(OpMul, &[]),
+                    make_instructions(OpPop, &[]),
+                ],
+            },
+            CompilerTestCase {
+                input: "1 < 2.5",
+                expected_constants: vec![Object::Integer(1), Object::Float(2.5)],
+                expected_instructions: vec![
+                    make_instructions(OpConst, &[0]),
+                    make_instructions(OpConst, &[1]),
+                    make_instructions(OpLessThan, &[]),
+                    make_instructions(OpPop, &[]),
+                ],
+            },
+            CompilerTestCase {
+                input: "-0.5",
+                expected_constants: vec![Object::Float(0.5)],
+                expected_instructions: vec![
+                    make_instructions(OpConst, &[0]),
+                    make_instructions(OpMinus, &[]),
+                    make_instructions(OpPop, &[]),
+                ],
+            },
+        ];
+
+        run_compiler_test(tests);
+    }
+
+    #[test]
+    fn test_assignment() {
+        let tests = vec![
+            CompilerTestCase {
+                input: "let x = 1; x = 2;",
+                expected_constants: vec![Object::Integer(1), Object::Integer(2)],
+                expected_instructions: vec![
+                    make_instructions(OpConst, &[0]),
+                    make_instructions(OpSetGlobal, &[0]),
+                    make_instructions(OpConst, &[1]),
+                    make_instructions(OpSetGlobal, &[0]),
+                ],
+            },
+            // the existing slot is reused rather than defining a new symbol
+            CompilerTestCase {
+                input: "let x = 1; let y = 2; x = y; x",
+                expected_constants: vec![Object::Integer(1), Object::Integer(2)],
+                expected_instructions: vec![
+                    make_instructions(OpConst, &[0]),
+                    make_instructions(OpSetGlobal, &[0]),
+                    make_instructions(OpConst, &[1]),
+                    make_instructions(OpSetGlobal, &[1]),
+                    make_instructions(OpGetGlobal, &[1]),
+                    make_instructions(OpSetGlobal, &[0]),
+                    make_instructions(OpGetGlobal, &[0]),
+                    make_instructions(OpPop, &[]),
+                ],
+            },
+            CompilerTestCase {
+                input: "let xs = []; xs = push(xs, 1);",
+                expected_constants: vec![Object::Integer(1)],
+                expected_instructions: vec![
+                    make_instructions(OpArray, &[0]),
+                    make_instructions(OpSetGlobal, &[0]),
+                    make_instructions(OpGetBuiltin, &[5]),
+                    make_instructions(OpTakeGlobal, &[0]),
+                    make_instructions(OpConst, &[0]),
+                    make_instructions(OpCall, &[2]),
+                    make_instructions(OpSetGlobal, &[0]),
+                ],
+            },
+        ];
+
+        run_compiler_test(tests);
+    }
+
+    #[test]
+    fn test_index_assignment() {
+        let tests = vec![
+            CompilerTestCase {
+                input: "let xs = [1]; xs[0] = 2;",
+                expected_constants: vec![
+                    Object::Integer(1),
+                    Object::Integer(0),
+                    Object::Integer(2),
+                ],
+                expected_instructions: vec![
+                    make_instructions(OpConst, &[0]),
+                    make_instructions(OpArray, &[1]),
+                    make_instructions(OpSetGlobal, &[0]),
+                    make_instructions(OpTakeGlobal, &[0]),
+                    make_instructions(OpConst, &[1]),
+                    make_instructions(OpConst, &[2]),
+                    make_instructions(OpSetIndex, &[]),
+                    make_instructions(OpSetGlobal, &[0]),
+                ],
+            },
+            // the value reads `xs`, so it is loaded rather than taken
+            CompilerTestCase {
+                input: "let xs = [1]; xs[0] = xs;",
+                expected_constants: vec![Object::Integer(1), Object::Integer(0)],
+                expected_instructions: vec![
+                    make_instructions(OpConst, &[0]),
+                    make_instructions(OpArray, &[1]),
+                    make_instructions(OpSetGlobal, &[0]),
+                    make_instructions(OpGetGlobal, &[0]),
+                    make_instructions(OpConst, &[1]),
+                    make_instructions(OpGetGlobal, &[0]),
+                    make_instructions(OpSetIndex, &[]),
+                    make_instructions(OpSetGlobal, &[0]),
+                ],
+            },
+        ];
+
+        run_compiler_test(tests);
+    }
+
+    #[test]
+    fn test_block_scoped_slots() {
+        let tests = vec![
+            // inside a function, sibling blocks share a slot
+            CompilerTestCase {
+                input: "fn() { if (true) { let a = 1; a } else { let b = 2; b } }",
+                expected_constants: vec![
+                    Object::Integer(1),
+                    Object::Integer(2),
+                    Object::CompiledFunction(Rc::from(object::CompiledFunction {
+                        instructions: concat_instructions(vec![
+                            make_instructions(OpTrue, &[]),
+                            make_instructions(OpJumpNotTruthy, &[14]),
+                            make_instructions(OpConst, &[0]),
+                            make_instructions(OpSetLocal, &[0]),
+                            make_instructions(OpGetLocal, &[0]),
+                            make_instructions(OpJump, &[21]),
+                            make_instructions(OpConst, &[1]),
+                            make_instructions(OpSetLocal, &[0]),
+                            make_instructions(OpGetLocal, &[0]),
+                            make_instructions(OpReturnValue, &[]),
+                        ])
+                        .bytes,
+                        num_locals: 1,
+                        num_parameters: 0,
+                        name: String::new(),
+                    })),
+                ],
+                expected_instructions: vec![
+                    make_instructions(OpClosure, &[2, 0]),
+                    make_instructions(OpPop, &[]),
+                ],
+            },
+            // a block's `let x` gets a slot of its own rather than the
+            // outer `x`'s, and global slots are never reused
+            CompilerTestCase {
+                input: "let x = 1; if (true) { let x = 2; }; if (true) { let y = 3; }; x",
+                expected_constants: vec![
+                    Object::Integer(1),
+                    Object::Integer(2),
+                    Object::Integer(3),
+                ],
+                expected_instructions: vec![
+                    make_instructions(OpConst, &[0]),
+                    make_instructions(OpSetGlobal, &[0]),
+                    make_instructions(OpTrue, &[]),
+                    make_instructions(OpJumpNotTruthy, &[20]),
+                    make_instructions(OpConst, &[1]),
+                    make_instructions(OpSetGlobal, &[1]),
+                    make_instructions(OpNull, &[]),
+                    make_instructions(OpJump, &[21]),
+                    make_instructions(OpNull, &[]),
+                    make_instructions(OpPop, &[]),
+                    make_instructions(OpTrue, &[]),
+                    make_instructions(OpJumpNotTruthy, &[36]),
+                    make_instructions(OpConst, &[2]),
+                    make_instructions(OpSetGlobal, &[2]),
+                    make_instructions(OpNull, &[]),
+                    make_instructions(OpJump, &[37]),
+                    make_instructions(OpNull, &[]),
+                    make_instructions(OpPop, &[]),
+                    make_instructions(OpGetGlobal, &[0]),
+                    make_instructions(OpPop, &[]),
+                ],
+            },
+        ];
+
+        run_compiler_test(tests);
+    }
+
+    #[test]
+    fn test_duplicate_definitions() {
+        let tests = [
+            (
+                "let a = 1; let a = 2;",
+                CompileError::DuplicateDefinition {
+                    name: "a".to_string(),
+                    span: Span { start: 15, end: 16 },
+                    previous: Some(Span { start: 4, end: 5 }),
+                },
+            ),
+            (
+                "fn() { let b = 1; let b = b + 1; }",
+                CompileError::DuplicateDefinition {
+                    name: "b".to_string(),
+                    span: Span { start: 22, end: 23 },
+                    previous: Some(Span { start: 11, end: 12 }),
+                },
+            ),
+            // a parameter belongs to the function's own scope
+            (
+                "fn(c) { let c = 1; }",
+                CompileError::DuplicateDefinition {
+                    name: "c".to_string(),
+                    span: Span { start: 12, end: 13 },
+                    previous: Some(Span { start: 3, end: 4 }),
+                },
+            ),
+            (
+                "if (true) { let d = 1; let d = 2; }",
+                CompileError::DuplicateDefinition {
+                    name: "d".to_string(),
+                    span: Span { start: 27, end: 28 },
+                    previous: Some(Span { start: 16, end: 17 }),
+                },
+            ),
+        ];
+        for (input, expected) in &tests {
+            let program = parse(input).unwrap();
+            match Compiler::new().compile(&program) {
+                Ok(_) => panic!("expected {} to fail to compile", input),
+                Err(err) => assert_eq!(&err, expected, "{}", input),
+            }
+        }
+        assert_eq!(
+            tests[0].1.to_string(),
+            "'a' is already defined in this scope"
+        );
+
+        // shadowing from an inner scope is fine
+        for input in [
+            "let x = 1; fn(x) { x }",
+            "let x = 1; fn() { let x = 2; x }",
+            "let x = 1; if (true) { let x = 2; }",
+            "let f = fn() { let f = 1; f }",
+            "let len = 1;",
+            "for (i in [1]) { }; for (i in [2]) { }",
+        ] {
+            let program = parse(input).unwrap();
+            if let Err(err) = Compiler::new().compile(&program) {
+                panic!("{} failed to compile: {}", input, err);
+            }
+        }
+    }
+
+    #[test]
+    fn test_engines_agree_on_duplicate_definitions() {
+        for input in [
+            "let a = 1; let a = 2; a",
+            "let f = fn() { let b = 1; let b = b + 1; b }; f()",
+            "let f = fn(c) { let c = 1; c }; f(0)",
+            "if (true) { let d = 1; let d = 2; d }",
+            "for (i in [1]) { let e = i; let e = 2; }",
+        ] {
+            let compiled = Compiler::new().compile(&parse(input).unwrap());
+            let Err(compile_err @ CompileError::DuplicateDefinition { .. }) = compiled else {
+                panic!("expected {} to fail to compile", input);
+            };
+            match run_interpreter(input) {
+                Err(eval_err @ EvalError::DuplicateDefinition(_)) => {
+                    assert_eq!(eval_err.to_string(), compile_err.to_string(), "{}", input)
+                }
+                other => panic!("expected {} to fail to evaluate, got {:?}", input, other),
+            }
+        }
+
+        for input in [
+            "let x = 1; let f = fn(x) { x }; f(2)",
+            "let x = 1; let f = fn() { let x = 2; x }; f()",
+            "let x = 1; if (true) { let x = 2; x }",
+            "let f = fn() { let f = 1; f }; f()",
+            "let i = 0; while (i < 2) { let y = i; i = i + 1; }; i",
+            "for (i in [1]) { }; for (i in [2]) { let i = 3; i }",
+        ] {
+            assert!(run_interpreter(input).is_ok(), "{}", input);
+        }
+
+        // as in the REPL, where each input may redefine an earlier one's
+        let env: Env = Rc::new(RefCell::new(Default::default()));
+        interpreter::eval(parse("let x = 1;").unwrap(), &env).unwrap();
+        assert_eq!(
+            interpreter::eval(parse("let x = x + 1; x").unwrap(), &env)
+                .unwrap()
+                .to_string(),
+            "2"
+        );
+    }
+
+    #[test]
+    fn test_redefining_a_global_from_an_earlier_input() {
+        let mut compiler = Compiler::new();
+        compiler.compile(&parse("let x = 1;").unwrap()).unwrap();
+
+        let mut compiler = Compiler::new_with_state(compiler.symbol_table, compiler.constants);
+        let bytecode = compiler.compile(&parse("let x = x + 1;").unwrap()).unwrap();
+        // the new `x` takes over the old one's slot
+        assert!(
+            bytecode
+                .instructions
+                .string()
+                .unwrap()
+                .contains("OpSetGlobal 0")
+        );
+        assert!(matches!(
+            compiler.compile(&parse("let y = 1; let y = 2;").unwrap()),
+            Err(CompileError::DuplicateDefinition { name, .. }) if name == "y"
+        ));
+    }
+
+    #[test]
+    fn test_name_errors() {
+        let tests = [
+            (
+                "foo + 1",
+                CompileError::UndefinedVariable {
+                    name: "foo".to_string(),
+                    span: Span { start: 0, end: 3 },
+                },
+            ),
+            (
+                "let a = 1; b = a;",
+                CompileError::UndefinedVariable {
+                    name: "b".to_string(),
+                    span: Span { start: 11, end: 12 },
+                },
+            ),
+            (
+                "len = 1;",
+                CompileError::CannotAssign {
+                    name: "len".to_string(),
+                    scope: SymbolScope::Builtin,
+                    span: Span { start: 0, end: 3 },
+                },
+            ),
+        ];
+        for (input, expected) in tests {
+            let program = parse(input).unwrap();
+            match Compiler::new().compile(&program) {
+                Ok(_) => panic!("expected {} to fail to compile", input),
+                Err(err) => assert_eq!(err, expected, "{}", input),
+            }
+        }
+    }
+
+    #[test]
+    fn test_unusable_hash_keys() {
+        let tests = [
+            ("{[1, 2]: 3}", "ARRAY", Span { start: 1, end: 7 }),
+            (
+                "let h = {1: 2, {}: 3};",
+                "HASH",
+                Span { start: 15, end: 17 },
+            ),
+            ("{fn(x) { x }: 1}", "FUNCTION", Span { start: 1, end: 12 }),
+        ];
+        for (input, type_name, span) in tests {
+            let program = parse(input).unwrap();
+            match Compiler::new().compile(&program) {
+                Ok(_) => panic!("expected {} to fail to compile", input),
+                Err(err) => assert_eq!(
+                    err,
+                    CompileError::UnusableAsHashKey { type_name, span },
+                    "{}",
+                    input
+                ),
+            }
+        }
+        let Err(err) = Compiler::new().compile(&parse("{[1]: 2}").unwrap()) else {
+            panic!("expected an array key to fail to compile");
+        };
+        assert_eq!(err.to_string(), "unusable as hash key: ARRAY");
+
+        // only the VM can tell what a variable holds
+        let program = parse("let k = [1]; {k: 2}").unwrap();
+        Compiler::new().compile(&program).unwrap();
+    }
+
+    #[test]
+    fn test_assignment_errors() {
+        let tests = [
+            ("x = 1;", "undefined variable 'x'"),
+            ("len = 1;", "cannot assign to builtin 'len'"),
+            (
+                "let f = fn() { let v = 1; fn() { v = 2; } };",
+                "cannot assign to 'v', which belongs to an enclosing function",
+            ),
+            (
+                "let outer = fn() { let f = fn() { f = 1; }; };",
+                "cannot assign to 'f' inside its own body",
+            ),
+            ("h[0] = 1;", "undefined variable 'h'"),
+            ("if (true) { let y = 1; }; y = 2;", "undefined variable 'y'"),
+            (
+                "let f = fn() { let h = {}; fn() { h[0] = 2; } };",
+                "cannot assign to 'h', which belongs to an enclosing function",
+            ),
+        ];
+        for (input, expected) in tests {
+            let program = parse(input).unwrap();
+            match Compiler::new().compile(&program) {
+                Ok(_) => panic!("expected {} to fail to compile", input),
+                Err(err) => assert_eq!(err.to_string(), expected, "{}", input),
+            }
+        }
+    }
+
+    #[test]
+    fn test_compile_errors_render_with_their_source_line() {
+        let input = "let x = 1;\nlet y = x + nope;";
+        let Err(err) = Compiler::new().compile(&parse(input).unwrap()) else {
+            panic!("expected {} to fail to compile", input);
+        };
+        assert_eq!(
+            err.render(input),
+            "compile error at 2:13: undefined variable 'nope'\n\
+             \x20  2 | let y = x + nope;\n\
+             \x20    |             ^^^^"
+        );
+
+        // errors without a span keep to one line
+        assert_eq!(
+            CompileError::UnexpandedMacro.render(input),
+            format!("compile error: {}", CompileError::UnexpandedMacro)
+        );
+    }
+
+    #[test]
+    fn test_operand_limits() {
+        let numbered = |n: usize, item: &dyn Fn(usize) -> String, sep: &str| {
+            (0..n).map(item).collect::<Vec<_>>().join(sep)
+        };
+        let tests = [
+            (
+                numbered(70000, &|i| format!("{};", i), ""),
+                "too many constants (65537); limit is 65536",
+            ),
+            (
+                numbered(70000, &|i| format!("let v{} = true;", i), ""),
+                "too many globals (65537); limit is 65536",
+            ),
+            (
+                format!(
+                    "fn() {{ {} }}",
+                    numbered(300, &|i| format!("let v{} = true;", i), "")
+                ),
+                "too many locals (257); limit is 256",
+            ),
+            (
+                format!("[{}]", numbered(70000, &|_| "true".to_string(), ", ")),
+                "too many array elements (70000); limit is 65535",
+            ),
+            (
+                format!("len({})", numbered(300, &|_| "true".to_string(), ", ")),
+                "too many arguments (300); limit is 255",
+            ),
+            (
+                format!("if (true) {{ {} }}", "true;".repeat(70000)),
+                "jump target 140006 is out of range; limit is 65535",
+            ),
+        ];
+        for (input, expected) in tests {
+            let program = parse(&input).unwrap();
+            match Compiler::new().compile(&program) {
+                Ok(_) => panic!("expected {} to fail to compile", expected),
+                Err(err) => assert_eq!(err.to_string(), expected),
+            }
+        }
+    }
+
     #[test]
     fn test_string() {
         let tests = vec![
             CompilerTestCase {
                 input: "\"monkey\"",
-                expected_constants: vec![Object::String("monkey".to_string())],
+                expected_constants: vec![Object::String("monkey".into())],
                 expected_instructions: vec![
                     make_instructions(OpConst, &[0]),
                     make_instructions(OpPop, &[]),
@@ -290,8 +825,8 @@ mod tests {
             CompilerTestCase {
                 input: r#""mon" + "key""#,
                 expected_constants: vec![
-                    Object::String("mon".to_string()),
-                    Object::String("key".to_string()),
+                    Object::String("mon".into()),
+                    Object::String("key".into()),
                 ],
                 expected_instructions: vec![
                     make_instructions(OpConst, &[0]),
@@ -305,6 +840,42 @@ mod tests {
         run_compiler_test(tests);
     }
 
+    #[test]
+    fn test_null() {
+        let tests = vec![
+            CompilerTestCase {
+                input: "null",
+                expected_constants: vec![],
+                expected_instructions: vec![
+                    make_instructions(OpNull, &[]),
+                    make_instructions(OpPop, &[]),
+                ],
+            },
+            CompilerTestCase {
+                input: "!null",
+                expected_constants: vec![],
+                expected_instructions: vec![
+                    make_instructions(OpTrue, &[]),
+                    make_instructions(OpPop, &[]),
+                ],
+            },
+            CompilerTestCase {
+                input: "let x = null; x == null",
+                expected_constants: vec![],
+                expected_instructions: vec![
+                    make_instructions(OpNull, &[]),
+                    make_instructions(OpSetGlobal, &[0]),
+                    make_instructions(OpGetGlobal, &[0]),
+                    make_instructions(OpNull, &[]),
+                    make_instructions(OpEqual, &[]),
+                    make_instructions(OpPop, &[]),
+                ],
+            },
+        ];
+
+        run_compiler_test(tests);
+    }
+
     #[test]
     fn test_array() {
         let tests = vec![
@@ -414,30 +985,25 @@ mod tests {
                     Object::Integer(1),
                     Object::Integer(2),
                     Object::Integer(3),
-                    Object::Integer(2),
                 ],
                 expected_instructions: vec![
                     make_instructions(OpConst, &[0]),
                     make_instructions(OpConst, &[1]),
                     make_instructions(OpConst, &[2]),
                     make_instructions(OpArray, &[3]),
-                    make_instructions(OpConst, &[3]),
+                    make_instructions(OpConst, &[1]),
                     make_instructions(OpIndex, &[]),
                     make_instructions(OpPop, &[]),
                 ],
             },
             CompilerTestCase {
                 input: "{1: 2 }[2 -1]",
-                expected_constants: vec![
-                    Object::Integer(1),
-                    Object::Integer(2),
-                    Object::Integer(1),
-                ],
+                expected_constants: vec![Object::Integer(1), Object::Integer(2)],
                 expected_instructions: vec![
                     make_instructions(OpConst, &[0]),
                     make_instructions(OpConst, &[1]),
                     make_instructions(OpHash, &[2]),
-                    make_instructions(OpConst, &[2]),
+                    make_instructions(OpConst, &[0]),
                     make_instructions(OpIndex, &[]),
                     make_instructions(OpPop, &[]),
                 ],
@@ -447,6 +1013,52 @@ mod tests {
         run_compiler_test(tests);
     }
 
+    #[test]
+    fn test_constants_are_interned() {
+        let tests = vec![
+            CompilerTestCase {
+                input: "let a = 5; let b = 5;",
+                expected_constants: vec![Object::Integer(5)],
+                expected_instructions: vec![
+                    make_instructions(OpConst, &[0]),
+                    make_instructions(OpSetGlobal, &[0]),
+                    make_instructions(OpConst, &[0]),
+                    make_instructions(OpSetGlobal, &[1]),
+                ],
+            },
+            // equal-looking values of different types keep separate slots
+            CompilerTestCase {
+                input: r#"[1, "1", 1, "1"]"#,
+                expected_constants: vec![Object::Integer(1), Object::String("1".into())],
+                expected_instructions: vec![
+                    make_instructions(OpConst, &[0]),
+                    make_instructions(OpConst, &[1]),
+                    make_instructions(OpConst, &[0]),
+                    make_instructions(OpConst, &[1]),
+                    make_instructions(OpArray, &[4]),
+                    make_instructions(OpPop, &[]),
+                ],
+            },
+        ];
+
+        run_compiler_test(tests);
+
+        let program = parse("[fn() { 1 }, fn() { 1 }]").unwrap();
+        let bytecode = Compiler::new().compile(&program).unwrap();
+        let functions = bytecode
+            .constants
+            .iter()
+            .filter(|c| matches!(***c, Object::CompiledFunction(_)))
+            .count();
+        assert_eq!((bytecode.constants.len(), functions), (3, 2));
+
+        // a REPL line reuses constants from earlier lines
+        let earlier = vec![Rc::new(Object::String("x".into()))];
+        let mut compiler = Compiler::new_with_state(SymbolTable::new(), earlier);
+        let bytecode = compiler.compile(&parse(r#""x""#).unwrap()).unwrap();
+        assert_eq!(bytecode.constants.len(), 1);
+    }
+
     #[test]
     fn test_while_loop() {
         let tests = vec![
@@ -474,11 +1086,168 @@ mod tests {
                     make_instructions(OpNull, &[]),
                 ],
             },
+            // the back-jump targets the condition, not the start of the program
+            CompilerTestCase {
+                input: "let i = 0; while (i < 2) { i = i + 1; }",
+                expected_constants: vec![
+                    Object::Integer(0),
+                    Object::Integer(2),
+                    Object::Integer(1),
+                ],
+                expected_instructions: vec![
+                    make_instructions(OpConst, &[0]),
+                    make_instructions(OpSetGlobal, &[0]),
+                    make_instructions(OpGetGlobal, &[0]),
+                    make_instructions(OpConst, &[1]),
+                    make_instructions(OpLessThan, &[]),
+                    make_instructions(OpJumpNotTruthy, &[29]),
+                    make_instructions(OpGetGlobal, &[0]),
+                    make_instructions(OpConst, &[2]),
+                    make_instructions(OpAdd, &[]),
+                    make_instructions(OpSetGlobal, &[0]),
+                    make_instructions(OpJump, &[6]),
+                    make_instructions(OpNull, &[]),
+                ],
+            },
+        ];
+
+        run_compiler_test(tests);
+    }
+
+    #[test]
+    fn test_for_in_loop() {
+        let tests = vec![CompilerTestCase {
+            input: "for (x in [1]) { x }",
+            expected_constants: vec![Object::Integer(1), Object::Integer(-1)],
+            expected_instructions: vec![
+                make_instructions(OpConst, &[0]),
+                make_instructions(OpArray, &[1]),
+                make_instructions(OpIter, &[]),
+                make_instructions(OpSetGlobal, &[0]),
+                make_instructions(OpSetGlobal, &[1]),
+                make_instructions(OpConst, &[1]),
+                make_instructions(OpSetGlobal, &[2]),
+                // `continue` jumps here, to the increment
+                make_instructions(OpGetGlobal, &[2]),
+                make_instructions(OpConst, &[0]),
+                make_instructions(OpAdd, &[]),
+                make_instructions(OpSetGlobal, &[2]),
+                make_instructions(OpGetGlobal, &[0]),
+                make_instructions(OpGetGlobal, &[2]),
+                make_instructions(OpGreaterThan, &[]),
+                make_instructions(OpJumpNotTruthy, &[56]),
+                make_instructions(OpGetGlobal, &[1]),
+                make_instructions(OpGetGlobal, &[2]),
+                make_instructions(OpIndex, &[]),
+                make_instructions(OpSetGlobal, &[3]),
+                make_instructions(OpGetGlobal, &[3]),
+                make_instructions(OpPop, &[]),
+                make_instructions(OpJump, &[19]),
+                make_instructions(OpNull, &[]),
+            ],
+        }];
+
+        run_compiler_test(tests);
+    }
+
+    #[test]
+    fn test_break_and_continue() {
+        let tests = vec![
+            CompilerTestCase {
+                input: "while (true) { break; }",
+                expected_constants: vec![],
+                expected_instructions: vec![
+                    make_instructions(OpTrue, &[]),
+                    make_instructions(OpJumpNotTruthy, &[10]),
+                    make_instructions(OpJump, &[10]),
+                    make_instructions(OpJump, &[0]),
+                    make_instructions(OpNull, &[]),
+                ],
+            },
+            CompilerTestCase {
+                input: "while (true) { continue; }",
+                expected_constants: vec![],
+                expected_instructions: vec![
+                    make_instructions(OpTrue, &[]),
+                    make_instructions(OpJumpNotTruthy, &[10]),
+                    make_instructions(OpJump, &[0]),
+                    make_instructions(OpJump, &[0]),
+                    make_instructions(OpNull, &[]),
+                ],
+            },
+            // the inner `break` targets the end of the inner loop only
+            CompilerTestCase {
+                input: "while (true) { while (false) { break; }; break; }",
+                expected_constants: vec![],
+                expected_instructions: vec![
+                    make_instructions(OpTrue, &[]),
+                    make_instructions(OpJumpNotTruthy, &[22]),
+                    make_instructions(OpFalse, &[]),
+                    make_instructions(OpJumpNotTruthy, &[14]),
+                    make_instructions(OpJump, &[14]),
+                    make_instructions(OpJump, &[4]),
+                    make_instructions(OpNull, &[]),
+                    make_instructions(OpPop, &[]),
+                    make_instructions(OpJump, &[22]),
+                    make_instructions(OpJump, &[0]),
+                    make_instructions(OpNull, &[]),
+                    make_instructions(OpPop, &[]),
+                ],
+            },
         ];
 
         run_compiler_test(tests);
     }
 
+    #[test]
+    fn test_break_and_continue_errors() {
+        let tests = [
+            (
+                "break;",
+                CompileError::OutsideLoop {
+                    keyword: TokenKind::BREAK,
+                    span: Span { start: 0, end: 6 },
+                },
+            ),
+            (
+                "let f = fn() { continue };",
+                CompileError::OutsideLoop {
+                    keyword: TokenKind::CONTINUE,
+                    span: Span { start: 15, end: 23 },
+                },
+            ),
+            // a function body is not part of the loop it is defined in
+            (
+                "while (true) { fn() { break; } }",
+                CompileError::OutsideLoop {
+                    keyword: TokenKind::BREAK,
+                    span: Span { start: 22, end: 28 },
+                },
+            ),
+            (
+                "while (true) { puts(if (true) { break; }) }",
+                CompileError::InsideExpression {
+                    keyword: TokenKind::BREAK,
+                    span: Span { start: 32, end: 38 },
+                },
+            ),
+            (
+                "while (true) { 1 + if (true) { continue; } else { 2 } }",
+                CompileError::InsideExpression {
+                    keyword: TokenKind::CONTINUE,
+                    span: Span { start: 31, end: 40 },
+                },
+            ),
+        ];
+        for (input, expected) in tests {
+            let program = parse(input).unwrap();
+            match Compiler::new().compile(&program) {
+                Ok(_) => panic!("expected {} to fail to compile", input),
+                Err(err) => assert_eq!(err, expected, "{}", input),
+            }
+        }
+    }
+
     #[test]
     fn test_lte_gte_modulo_compilation() {
         let tests = vec![
@@ -488,8 +1257,7 @@ mod tests {
                 expected_instructions: vec![
                     make_instructions(OpTrue, &[]),
                     make_instructions(OpFalse, &[]),
-                    make_instructions(OpGreaterThan, &[]),
-                    make_instructions(OpBang, &[]),
+                    make_instructions(OpLessOrEqual, &[]),
                     make_instructions(OpPop, &[]),
                 ],
             },
@@ -497,10 +1265,21 @@ mod tests {
                 input: "true >= false",
                 expected_constants: vec![],
                 expected_instructions: vec![
-                    make_instructions(OpFalse, &[]),
                     make_instructions(OpTrue, &[]),
-                    make_instructions(OpGreaterThan, &[]),
-                    make_instructions(OpBang, &[]),
+                    make_instructions(OpFalse, &[]),
+                    make_instructions(OpGreaterOrEqual, &[]),
+                    make_instructions(OpPop, &[]),
+                ],
+            },
+            CompilerTestCase {
+                input: "let a = 1; a <= 2",
+                expected_constants: vec![Object::Integer(1), Object::Integer(2)],
+                expected_instructions: vec![
+                    make_instructions(OpConst, &[0]),
+                    make_instructions(OpSetGlobal, &[0]),
+                    make_instructions(OpGetGlobal, &[0]),
+                    make_instructions(OpConst, &[1]),
+                    make_instructions(OpLessOrEqual, &[]),
                     make_instructions(OpPop, &[]),
                 ],
             },
@@ -532,4 +1311,50 @@ mod tests {
 
         run_compiler_test(tests);
     }
+
+    #[test]
+    fn test_floor_division_compilation() {
+        let tests = vec![
+            CompilerTestCase {
+                input: "7 div -2",
+                expected_constants: vec![Object::Integer(7), Object::Integer(-2)],
+                expected_instructions: vec![
+                    make_instructions(OpConst, &[0]),
+                    make_instructions(OpConst, &[1]),
+                    make_instructions(OpFloorDiv, &[]),
+                    make_instructions(OpPop, &[]),
+                ],
+            },
+            CompilerTestCase {
+                input: "-7 div 2",
+                expected_constants: vec![Object::Integer(-7), Object::Integer(2)],
+                expected_instructions: vec![
+                    make_instructions(OpConst, &[0]),
+                    make_instructions(OpConst, &[1]),
+                    make_instructions(OpFloorDiv, &[]),
+                    make_instructions(OpPop, &[]),
+                ],
+            },
+            CompilerTestCase {
+                input: "7 div 2",
+                expected_constants: vec![Object::Integer(3)],
+                expected_instructions: vec![
+                    make_instructions(OpConst, &[0]),
+                    make_instructions(OpPop, &[]),
+                ],
+            },
+            CompilerTestCase {
+                input: "7 div 0",
+                expected_constants: vec![Object::Integer(7), Object::Integer(0)],
+                expected_instructions: vec![
+                    make_instructions(OpConst, &[0]),
+                    make_instructions(OpConst, &[1]),
+                    make_instructions(OpFloorDiv, &[]),
+                    make_instructions(OpPop, &[]),
+                ],
+            },
+        ];
+
+        run_compiler_test(tests);
+    }
 }
@@ -0,0 +1,70 @@
+use interpreter::eval;
+use object::environment::Env;
+use object::{EvalError, Object};
+use parser::parse;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+// The VM test helpers feed every input through the tree-walking interpreter
+// as well, so semantic drift between the two engines fails the VM suite.
+pub fn run_interpreter(input: &str) -> Result<Rc<Object>, EvalError> {
+    let program = parse(input).unwrap();
+    let env: Env = Rc::new(RefCell::new(Default::default()));
+    let evaluated = eval(program, &env)?;
+    match &*evaluated {
+        Object::ReturnValue(value) => Ok(Rc::clone(value)),
+        _ => Ok(evaluated),
+    }
+}
+
+pub fn assert_engines_agree(input: &str, vm_result: &Object) {
+    let evaluated =
+        run_interpreter(input).unwrap_or_else(|e| panic!("interpreter failed on {}: {}", input, e));
+
+    // functions have a different representation in each engine
+    if is_plain_data(vm_result) {
+        assert_eq!(
+            vm_result, &*evaluated,
+            "engines disagree on {}: vm={}, interpreter={}",
+            input, vm_result, evaluated
+        );
+    }
+}
+
+fn is_plain_data(obj: &Object) -> bool {
+    match obj {
+        Object::Integer(_) | Object::Boolean(_) | Object::String(_) | Object::Null => true,
+        Object::Array(elements) => elements.iter().all(|e| is_plain_data(e)),
+        Object::Hash(map) => map.values().all(|v| is_plain_data(v)),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::vm_test::{VmTestCase, run_vm_tests};
+    use object::Object;
+
+    #[test]
+    fn test_engines_agree_on_recursion_and_closures() {
+        let tests = vec![
+            VmTestCase {
+                input: "let fib = fn(n) { if (n < 2) { n } else { fib(n - 1) + fib(n - 2) } }; \
+                    fib(15)",
+                expected: Object::Integer(610),
+            },
+            VmTestCase {
+                input: "let counter = fn(start) { fn(step) { start + step } }; \
+                    let from_ten = counter(10); \
+                    from_ten(5)",
+                expected: Object::Integer(15),
+            },
+            VmTestCase {
+                input: "return 5; 6",
+                expected: Object::Integer(5),
+            },
+        ];
+
+        run_vm_tests(tests);
+    }
+}
@@ -1,17 +1,49 @@
+pub use session::{EvalSourceError, Session, eval_source};
+
+pub mod artifact;
+#[cfg(test)]
+mod artifact_test;
 pub mod compiler;
 #[cfg(test)]
 mod compiler_function_test;
 #[cfg(test)]
+mod compiler_log_test;
+#[cfg(test)]
 mod compiler_test;
+#[cfg(test)]
+mod differential_test;
 mod frame;
+#[cfg(test)]
+mod macro_test;
 pub mod op_code;
 #[cfg(test)]
 mod op_code_test;
+pub mod profile;
+#[cfg(test)]
+mod profile_test;
+pub mod session;
+#[cfg(test)]
+mod session_test;
+pub mod source_map;
+#[cfg(test)]
+mod source_map_test;
 pub mod symbol_table;
 #[cfg(test)]
 mod symbol_table_test;
+pub mod verifier;
+#[cfg(test)]
+mod verifier_test;
 pub mod vm;
 #[cfg(test)]
 mod vm_function_test;
 #[cfg(test)]
+mod vm_memory_test;
+#[cfg(test)]
+mod vm_safety_test;
+#[cfg(test)]
 mod vm_test;
+#[cfg(all(test, feature = "trace"))]
+mod vm_trace_test;
+pub mod warning;
+#[cfg(test)]
+mod warning_test;
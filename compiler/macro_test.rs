@@ -0,0 +1,51 @@
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    use interpreter::macro_expansion::{define_macros, expand_macros};
+    use object::environment::Env;
+    use parser::ast::Node;
+    use parser::parse;
+
+    use crate::compiler::{Bytecode, CompileError, Compiler};
+
+    fn compile_expanded(input: &str) -> Result<Bytecode, CompileError> {
+        let Node::Program(mut program) = parse(input).unwrap() else {
+            panic!("expected a program");
+        };
+        let env: Env = Rc::new(RefCell::new(Default::default()));
+        define_macros(&mut program, &env);
+        let program = expand_macros(program, &env).unwrap();
+        Compiler::new().compile(&Node::Program(program))
+    }
+
+    #[test]
+    fn test_expanded_macro_compiles_like_handwritten_code() {
+        let expanded = compile_expanded(
+            r#"let unless = macro(condition, consequence, alternative) {
+                quote(if (!(unquote(condition))) {
+                    unquote(consequence);
+                } else {
+                    unquote(alternative);
+                });
+            };
+            unless(10 > 5, "not greater", "greater");"#,
+        )
+        .unwrap();
+        let handwritten =
+            compile_expanded(r#"if (!(10 > 5)) { "not greater" } else { "greater" }"#).unwrap();
+
+        assert_eq!(expanded.instructions, handwritten.instructions);
+        assert_eq!(expanded.constants, handwritten.constants);
+    }
+
+    #[test]
+    fn test_unexpanded_macro_literal_is_rejected() {
+        let program = parse("fn() { macro(x) { x } }").unwrap();
+        assert!(matches!(
+            Compiler::new().compile(&program),
+            Err(CompileError::UnexpandedMacro)
+        ));
+    }
+}
@@ -1,59 +1,777 @@
-use compiler::compiler::Compiler;
+use compiler::artifact;
+use compiler::compiler::{Bytecode, Compiler};
+use compiler::session::{expand, render_parse_errors};
+use compiler::source_map::disassemble_with_lines;
+use compiler::symbol_table::SymbolScope;
 use compiler::vm::{VM, Value};
+use compiler::warning::Warning;
+use object::Object;
+use object::environment::Env;
+use object::format::{Limits, format_limited};
+use rustyline::DefaultEditor;
+use rustyline::error::ReadlineError;
+use std::cell::RefCell;
+use std::io::{self, BufRead, IsTerminal, Write};
+use std::path::PathBuf;
+use std::rc::Rc;
+use std::{env, fs, process, thread};
 
-use compiler::symbol_table::SymbolTable;
-use std::io::stdin;
-use std::io::{self, Write};
+use parser::ast::{Node, Statement};
+use parser::lexer::dump_tokens;
+use parser::{parse, parse_ast_json_string, parse_with_asi};
 
-use parser::parse;
+#[cfg(test)]
+mod repl_test;
+
+const REPL_COMMANDS: &str = "\
+commands:
+  :ast <code>              print the syntax tree of <code> as JSON
+  :bytecode <code>         print the instructions and new constants <code> compiles to
+  :symbols                 list the globals this session has defined
+  :tokens <code>           print the tokens of <code>
+  :mode [compile|interpret]
+                           show or switch the engine, starting a new session
+  :set [print_limit <n>]   show or change how many elements results print
+  :mem                     show the VM's memory use
+  :reset                   forget every binding
+  :quit                    leave the REPL (so does Ctrl-D)";
+
+const USAGE: &str = "usage: monkey-compiler [<file> [<arg>...] | --tokens <file> | --disassemble <file> | run [--interpret] [--warn-shadowing] [--asi] [--quiet] [--json] [--profile] <file> [-- <arg>...] | [--verbose] build [--warn-shadowing] [--asi] [--with-lines] [--output <file.mkc>] <file> | dasm <file.mkc> | exec [--quiet] [--no-verify] <file.mkc> [-- <arg>...]]";
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Mode {
+    Compile,
+    Interpret,
+}
+
+// Compile mode keeps the symbol table, constants and globals between inputs;
+// interpret mode keeps an environment. The two do not share bindings.
+// Macros are expanded before either engine sees the program.
+struct Session {
+    mode: Mode,
+    /// Whether scripts may use `read_file` and `write_file`.
+    io: bool,
+    compiled: compiler::Session,
+    env: Env,
+    macro_env: Env,
+    /// How many results have been bound to `_1`, `_2`, ...
+    history: usize,
+}
+
+impl Session {
+    fn new(mode: Mode) -> Self {
+        Session {
+            mode,
+            io: false,
+            compiled: compiler::Session::new(),
+            env: Rc::new(RefCell::new(Default::default())),
+            macro_env: Rc::new(RefCell::new(Default::default())),
+            history: 0,
+        }
+    }
+
+    fn enable_io(&mut self, enabled: bool) {
+        self.io = enabled;
+        self.compiled.enable_io(enabled);
+    }
+
+    fn eval(&mut self, source: &str, program: Node) -> Result<Option<Rc<Object>>, String> {
+        match self.mode {
+            Mode::Compile => {
+                let result = self.compiled.eval_program(program);
+                print_warnings(self.compiled.warnings(), source);
+                match result {
+                    Ok(value) => Ok(Some(value.into_rc_object())),
+                    Err(e) => Err(e.render(source)),
+                }
+            }
+            Mode::Interpret => {
+                let program = expand(program, &self.macro_env).map_err(|e| e.to_string())?;
+                interpreter::enable_io(self.io);
+                match interpreter::eval(program, &self.env) {
+                    Ok(evaluated) => Ok(Some(evaluated)),
+                    Err(e) => Err(e.to_string()),
+                }
+            }
+        }
+    }
+
+    /// The instructions `program` compiles to and the constants it adds,
+    /// without running it or keeping its definitions or macros.
+    fn bytecode(&self, source: &str, program: Node) -> Result<String, String> {
+        let bytecode = self
+            .compiled
+            .compile_only(program)
+            .map_err(|e| e.render(source))?;
+        let mut listing = bytecode
+            .instructions
+            .disassemble()
+            .map_err(|e| e.to_string())?;
+        for (index, constant) in bytecode
+            .constants
+            .iter()
+            .enumerate()
+            .skip(self.compiled.constants.len())
+        {
+            listing.push_str(&format!("constant {}: {}\n", index, constant));
+        }
+        Ok(listing)
+    }
+
+    /// Binds `value` to the next `_N` and to `_`, returning the new name.
+    fn remember(&mut self, value: &Rc<Object>) -> String {
+        self.history += 1;
+        let name = format!("_{}", self.history);
+        self.bind(&name, value);
+        self.bind("_", value);
+        name
+    }
+
+    fn bind(&mut self, name: &str, value: &Rc<Object>) {
+        match self.mode {
+            Mode::Compile => self
+                .compiled
+                .bind(name, Value::from_object(Rc::clone(value))),
+            Mode::Interpret => self
+                .env
+                .borrow_mut()
+                .set(name.to_string(), Rc::clone(value)),
+        }
+    }
+}
+
+// Only a trailing expression statement leaves a result worth remembering;
+// after a `let` the VM's last popped value is stale.
+fn ends_with_expression(program: &Node) -> bool {
+    match program {
+        Node::Program(program) => matches!(program.body.last(), Some(Statement::Expr(_))),
+        Node::Statement(statement) => matches!(statement, Statement::Expr(_)),
+        Node::Expression(_) => true,
+    }
+}
+
+// Disassembles the program and then each function, showing the source line
+// each run of instructions was compiled from.
+fn print_listing(compiler: &Compiler, bytecode: &Bytecode, source: &str) {
+    let source_map = compiler.source_map();
+    let mut sections = vec![(
+        "main".to_string(),
+        &bytecode.instructions.bytes,
+        &source_map.main,
+    )];
+    for (index, mappings) in &source_map.functions {
+        if let Object::CompiledFunction(function) = &*bytecode.constants[*index] {
+            sections.push((
+                format!("function (constant {})", index),
+                &function.instructions,
+                mappings,
+            ));
+        }
+    }
+    for (name, instructions, mappings) in sections {
+        match disassemble_with_lines(instructions, mappings, source) {
+            Ok(listing) => print!("\n{}:\n{}", name, listing),
+            Err(e) => {
+                eprintln!("{}", e);
+                process::exit(1);
+            }
+        }
+    }
+}
+
+fn print_warnings(warnings: &[Warning], source: &str) {
+    for warning in warnings {
+        eprintln!("warning: {}", warning.render(source));
+    }
+}
+
+// The compiler and `run --interpret` recurse on the native stack for every
+// level of an expression's tree, and the interpreter for every call too.
+// This is room for `parser::MAX_EXPRESSION_HEIGHT` levels and
+// `interpreter::MAX_FRAMES` calls without overflowing, even unoptimised.
+const STACK_SIZE: usize = 256 * 1024 * 1024;
 
 fn main() {
-    let mut constants = vec![];
-    let mut symbol_table = SymbolTable::new();
-    let mut globals: Vec<Value> = (0..compiler::vm::GLOBAL_SIZE)
-        .map(|_| Value::Null)
-        .collect();
-    loop {
-        print!(">> ");
-        io::stdout().flush().unwrap();
-        let mut input = String::new();
-        stdin().read_line(&mut input).unwrap();
+    let main = thread::Builder::new()
+        .stack_size(STACK_SIZE)
+        .spawn(run_main)
+        .expect("could not start the main thread");
+    if main.join().is_err() {
+        process::exit(101);
+    }
+}
+
+fn run_main() {
+    let mut args: Vec<String> = env::args().skip(1).collect();
+    let verbose = args.first().is_some_and(|arg| arg == "--verbose");
+    if verbose {
+        args.remove(0);
+    }
 
-        if input.trim_end().is_empty() {
-            std::process::exit(0);
+    match args.first().map(String::as_str) {
+        None if !verbose => repl(),
+        Some("run") if !verbose => run(&args[1..]),
+        Some("build") => build(&args[1..], verbose),
+        Some("--tokens") if !verbose => tokens(&args[1..]),
+        Some("--disassemble") if !verbose => disassemble(&args[1..]),
+        Some("dasm") if !verbose => dasm(&args[1..]),
+        Some("exec") if !verbose => exec(&args[1..]),
+        // `#!/usr/bin/env monkey-compiler` scripts are invoked with their path
+        // followed by the script's own arguments
+        Some(path) if !verbose && !path.starts_with('-') => {
+            object::builtins::set_script_args(args[1..].to_vec());
+            run(&args[..1])
         }
+        _ => usage_error(),
+    }
+}
 
-        let program = match parse(&input) {
-            Ok(x) => x,
+fn usage_error() -> ! {
+    eprintln!("{}", USAGE);
+    process::exit(2);
+}
+
+// Files need explicit semicolons unless `--asi` is given; the REPL always
+// lets a line break end a statement.
+fn read_program(path: &str, asi: bool) -> (String, Node) {
+    let source = match fs::read_to_string(path) {
+        Ok(source) => source,
+        Err(e) => {
+            eprintln!("cannot read {}: {}", path, e);
+            process::exit(1);
+        }
+    };
+
+    let parsed = if asi {
+        parse_with_asi(&source)
+    } else {
+        parse(&source)
+    };
+    match parsed {
+        Ok(program) => (source, program),
+        Err(errors) => {
+            eprintln!("{}", render_parse_errors(&errors, &source));
+            process::exit(1);
+        }
+    }
+}
+
+fn tokens(args: &[String]) {
+    let [path] = args else {
+        usage_error();
+    };
+    match fs::read_to_string(path) {
+        Ok(source) => println!("{}", dump_tokens(&source)),
+        Err(e) => {
+            eprintln!("cannot read {}: {}", path, e);
+            process::exit(1);
+        }
+    }
+}
+
+fn disassemble(args: &[String]) {
+    let [path] = args else {
+        usage_error();
+    };
+    let (source, program) = read_program(path, false);
+    let macro_env: Env = Rc::new(RefCell::new(Default::default()));
+    let program = match expand(program, &macro_env) {
+        Ok(program) => program,
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+    };
+
+    let mut compiler = Compiler::new();
+    let bytecode = match compiler.compile(&program) {
+        Ok(bytecode) => bytecode,
+        Err(e) => {
+            eprintln!("{}", e.render(&source));
+            process::exit(1);
+        }
+    };
+    print_warnings(compiler.warnings(), &source);
+    match artifact::disassemble(&bytecode) {
+        Ok(listing) => print!("{}", listing),
+        Err((section, e)) => {
+            eprintln!("cannot disassemble {} of {}: {}", section, path, e);
+            process::exit(1);
+        }
+    }
+}
+
+fn build(args: &[String], verbose: bool) {
+    let mut warn_shadowing = false;
+    let mut asi = false;
+    let mut with_lines = false;
+    let mut output = None;
+    let mut path = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--warn-shadowing" => warn_shadowing = true,
+            "--with-lines" => with_lines = true,
+            "--asi" => asi = true,
+            "--output" if output.is_none() => match args.next() {
+                Some(file) => output = Some(file),
+                None => usage_error(),
+            },
+            _ if path.is_none() && !arg.starts_with("--") => path = Some(arg),
+            _ => usage_error(),
+        }
+    }
+    let Some(path) = path else {
+        usage_error();
+    };
+
+    let (source, program) = read_program(path, asi);
+    let macro_env: Env = Rc::new(RefCell::new(Default::default()));
+    let program = match expand(program, &macro_env) {
+        Ok(program) => program,
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+    };
+
+    let mut compiler = Compiler::new();
+    compiler.set_warn_shadowing(warn_shadowing);
+    if verbose {
+        compiler.set_verbose(Box::new(io::stderr()));
+    }
+    match compiler.compile(&program) {
+        Ok(bytecode) => {
+            print_warnings(compiler.warnings(), &source);
+            println!(
+                "{}: {} bytes of instructions, {} constants",
+                path,
+                bytecode.instructions.len(),
+                bytecode.constants.len()
+            );
+            if with_lines {
+                print_listing(&compiler, &bytecode, &source);
+            }
+            if let Some(output) = output {
+                write_artifact(&bytecode, &source, output);
+            }
+        }
+        Err(e) => {
+            eprintln!("{}", e.render(&source));
+            process::exit(1);
+        }
+    }
+}
+
+fn write_artifact(bytecode: &Bytecode, source: &str, path: &str) {
+    let bytes = match artifact::encode(bytecode, source) {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            eprintln!("cannot save {}: {}", path, e);
+            process::exit(1);
+        }
+    };
+    if let Err(e) = fs::write(path, bytes) {
+        eprintln!("cannot write {}: {}", path, e);
+        process::exit(1);
+    }
+}
+
+// Decodes the artifact at `path`, checking its bytecode first if `verify`
+// is set.
+fn read_artifact(path: &str, verify: bool) -> artifact::Artifact {
+    let bytes = match fs::read(path) {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            eprintln!("cannot read {}: {}", path, e);
+            process::exit(1);
+        }
+    };
+    let loaded = if verify {
+        artifact::load(&bytes)
+    } else {
+        artifact::decode(&bytes).map_err(artifact::LoadError::Decode)
+    };
+    match loaded {
+        Ok(artifact) => artifact,
+        Err(artifact::LoadError::Decode(e)) => {
+            eprintln!("cannot decode {}: {}", path, e);
+            process::exit(1);
+        }
+        Err(artifact::LoadError::Verify(errors)) => {
+            for e in errors {
+                eprintln!("cannot verify {}: {}", path, e);
+            }
+            process::exit(1);
+        }
+    }
+}
+
+fn dasm(args: &[String]) {
+    let [path] = args else {
+        usage_error();
+    };
+    // a damaged artifact is worth looking at, so it isn't verified
+    let bytecode = read_artifact(path, false).bytecode;
+    match artifact::describe(&bytecode) {
+        Ok(listing) => print!("{}", listing),
+        Err((section, e)) => {
+            eprintln!("cannot disassemble {} of {}: {}", section, path, e);
+            process::exit(1);
+        }
+    }
+}
+
+// Runs a program saved by `build --output`, printing its last value as
+// `run` does. Runtime errors point into the source saved with it. The
+// bytecode is verified first unless `--no-verify` is given.
+fn exec(args: &[String]) {
+    let args = match args.iter().position(|arg| arg == "--") {
+        Some(split) => {
+            object::builtins::set_script_args(args[split + 1..].to_vec());
+            &args[..split]
+        }
+        None => args,
+    };
+    let mut quiet = false;
+    let mut verify = true;
+    let mut path = None;
+    for arg in args {
+        match arg.as_str() {
+            "--quiet" => quiet = true,
+            "--no-verify" => verify = false,
+            _ if path.is_none() && !arg.starts_with("--") => path = Some(arg),
+            _ => usage_error(),
+        }
+    }
+    let Some(path) = path else {
+        usage_error();
+    };
+
+    let artifact = read_artifact(path, verify);
+    let mut vm = VM::new(artifact.bytecode);
+    vm.enable_io(true);
+    if let Err(e) = vm.run() {
+        eprintln!("{}", e.render(&artifact.source));
+        process::exit(1);
+    }
+    if !quiet {
+        let value = vm.last_popped_stack_elm().unwrap_or(Value::Null);
+        print_result(&value.into_rc_object());
+    }
+}
+
+fn run(args: &[String]) {
+    // everything after `--` belongs to the script, flags included
+    let args = match args.iter().position(|arg| arg == "--") {
+        Some(split) => {
+            object::builtins::set_script_args(args[split + 1..].to_vec());
+            &args[..split]
+        }
+        None => args,
+    };
+    let mut mode = Mode::Compile;
+    let mut warn_shadowing = false;
+    let mut asi = false;
+    let mut quiet = false;
+    let mut json = false;
+    let mut profile = false;
+    let mut path = None;
+    for arg in args {
+        match arg.as_str() {
+            "--interpret" => mode = Mode::Interpret,
+            "--warn-shadowing" => warn_shadowing = true,
+            "--asi" => asi = true,
+            "--quiet" => quiet = true,
+            "--json" => json = true,
+            "--profile" => profile = true,
+            _ if path.is_none() && !arg.starts_with("--") => path = Some(arg),
+            _ => usage_error(),
+        }
+    }
+    let Some(path) = path else {
+        usage_error();
+    };
+    // only the VM keeps a profile
+    if profile && mode == Mode::Interpret {
+        usage_error();
+    }
+
+    let (source, program) = read_program(path, asi);
+    let mut session = Session::new(mode);
+    session.compiled.set_warn_shadowing(warn_shadowing);
+    session.compiled.set_profiling(profile);
+    // unlike the REPL, a script file is trusted like any other program
+    session.enable_io(true);
+    let result = session.eval(&source, program);
+    // on stderr, so it doesn't mix with what the script prints
+    if let Some(report) = session.compiled.profile_report() {
+        eprintln!("{}", report);
+    }
+    match result {
+        Ok(Some(value)) if !quiet && json => match serde_json::to_string(&*value) {
+            Ok(json) => print_result(&json),
+            Err(e) => {
+                eprintln!("cannot print the result as JSON: {}", e);
+                process::exit(1);
+            }
+        },
+        Ok(Some(value)) if !quiet => print_result(&value),
+        Ok(_) => {}
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+    }
+}
+
+// Like `println!`, but a closed stdout (`run script | head -1`) ends the
+// process quietly instead of panicking.
+fn print_result(result: &dyn std::fmt::Display) {
+    if let Err(e) = writeln!(io::stdout().lock(), "{}", result) {
+        if e.kind() != io::ErrorKind::BrokenPipe {
+            eprintln!("cannot print the result: {}", e);
+        }
+        process::exit(1);
+    }
+}
+
+const PROMPT: &str = ">> ";
+
+fn repl() {
+    if !io::stdin().is_terminal() {
+        if let Err(e) = run_repl(io::stdin().lock(), io::stdout()) {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+        return;
+    }
+
+    let mut editor = match DefaultEditor::new() {
+        Ok(editor) => editor,
+        Err(e) => {
+            eprintln!("cannot start the line editor: {}", e);
+            process::exit(1);
+        }
+    };
+    let history = history_path();
+    if let Some(path) = &history {
+        // there is no history yet on the first run
+        let _ = editor.load_history(path);
+    }
+
+    let mut repl = Repl::new();
+    let mut stdout = io::stdout();
+    loop {
+        let line = match editor.readline(PROMPT) {
+            Ok(line) => line,
+            // Ctrl-C throws away the line being typed, not the session
+            Err(ReadlineError::Interrupted) => continue,
+            Err(ReadlineError::Eof) => break,
             Err(e) => {
-                println!("{}", e[0]);
-                continue;
+                eprintln!("{}", e);
+                break;
             }
         };
+        if !line.trim().is_empty() {
+            let _ = editor.add_history_entry(line.as_str());
+        }
+        match repl.handle(&line, &mut stdout) {
+            Ok(true) => {}
+            Ok(false) => break,
+            Err(e) => {
+                eprintln!("{}", e);
+                break;
+            }
+        }
+    }
 
-        let mut compiler = Compiler::new_with_state(symbol_table, constants);
+    if let Some(path) = &history
+        && let Err(e) = editor.save_history(path)
+    {
+        eprintln!("cannot save history to {}: {}", path.display(), e);
+    }
+}
 
-        match compiler.compile(&program) {
-            Ok(bytecodes) => {
-                let mut vm = VM::new_with_global_store(bytecodes, globals);
-                match vm.run() {
-                    Ok(()) => {
-                        if let Some(value) = vm.last_popped_stack_elm() {
-                            println!("{}", value);
-                        }
+fn history_path() -> Option<PathBuf> {
+    env::var_os("HOME")
+        .or_else(|| env::var_os("USERPROFILE"))
+        .map(|home| PathBuf::from(home).join(".monkey_history"))
+}
+
+/// The REPL without a terminal: reads lines from `input` until it runs out
+/// or `:quit`, writing prompts and results to `output`.
+fn run_repl<R: BufRead, W: Write>(mut input: R, mut output: W) -> io::Result<()> {
+    let mut repl = Repl::new();
+    loop {
+        write!(output, "{}", PROMPT)?;
+        output.flush()?;
+        let mut line = String::new();
+        if input.read_line(&mut line)? == 0 || !repl.handle(&line, &mut output)? {
+            return Ok(());
+        }
+    }
+}
+
+struct Repl {
+    session: Session,
+    // outlives the session, so `:reset` and `:mode` keep it
+    limits: Limits,
+}
+
+impl Repl {
+    fn new() -> Self {
+        Repl {
+            session: Session::new(Mode::Compile),
+            limits: Limits::default(),
+        }
+    }
+
+    /// Runs one line of input or REPL command, returning false after `:quit`.
+    fn handle<W: Write>(&mut self, input: &str, output: &mut W) -> io::Result<bool> {
+        let session = &mut self.session;
+        let limits = &mut self.limits;
+
+        if input.trim().is_empty() {
+            return Ok(true);
+        }
+
+        if input.trim() == ":quit" {
+            return Ok(false);
+        }
+
+        if let Some(mode) = input.trim().strip_prefix(":mode") {
+            let mode = match mode.trim() {
+                "compile" => Mode::Compile,
+                "interpret" => Mode::Interpret,
+                "" => session.mode,
+                other => {
+                    writeln!(
+                        output,
+                        "unknown mode '{}', expected compile or interpret",
+                        other
+                    )?;
+                    return Ok(true);
+                }
+            };
+            if mode != session.mode {
+                *session = Session::new(mode);
+            }
+            writeln!(output, "mode: {:?}", session.mode)?;
+            return Ok(true);
+        }
+
+        if let Some(source) = input.trim_end().strip_prefix(":tokens") {
+            writeln!(output, "{}", dump_tokens(source.trim_start()))?;
+            return Ok(true);
+        }
+
+        if let Some(setting) = input.trim().strip_prefix(":set") {
+            match setting.split_whitespace().collect::<Vec<_>>()[..] {
+                [] => writeln!(output, "print_limit {}", limits.items)?,
+                ["print_limit", value] => match value.parse() {
+                    Ok(items) => {
+                        limits.items = items;
+                        writeln!(output, "print_limit {}", limits.items)?;
                     }
-                    Err(e) => {
-                        println!("VM error: {}", e);
+                    Err(_) => writeln!(
+                        output,
+                        "print_limit must be a whole number, got '{}'",
+                        value
+                    )?,
+                },
+                _ => writeln!(output, "usage: :set print_limit <n>")?,
+            }
+            return Ok(true);
+        }
+
+        if let Some(source) = input.trim_end().strip_prefix(":ast") {
+            let source = source.trim_start();
+            match parse_ast_json_string(source) {
+                Ok(json) => writeln!(output, "{}", json)?,
+                Err(e) => writeln!(output, "{}", render_parse_errors(&e, source))?,
+            }
+            return Ok(true);
+        }
+
+        if let Some(source) = input.trim_end().strip_prefix(":bytecode") {
+            let source = source.trim_start();
+            let listing = parse_with_asi(source)
+                .map_err(|e| render_parse_errors(&e, source))
+                .and_then(|program| session.bytecode(source, program));
+            match listing {
+                Ok(listing) => write!(output, "{}", listing)?,
+                Err(e) => writeln!(output, "{}", e)?,
+            }
+            return Ok(true);
+        }
+
+        if input.trim() == ":symbols" {
+            match session.mode {
+                Mode::Compile => {
+                    for symbol in session.compiled.symbol_table.symbols() {
+                        if symbol.scope != SymbolScope::Builtin {
+                            writeln!(
+                                output,
+                                "{:?} {} {}",
+                                symbol.scope, symbol.index, symbol.name
+                            )?;
+                        }
                     }
                 }
-                globals = vm.globals;
+                Mode::Interpret => writeln!(output, "symbols are only available in compile mode")?,
             }
+            return Ok(true);
+        }
+
+        if input.trim() == ":reset" {
+            *session = Session::new(session.mode);
+            writeln!(output, "session reset")?;
+            return Ok(true);
+        }
+
+        if input.trim() == ":mem" {
+            match session.compiled.memory_stats() {
+                Some(stats) => {
+                    writeln!(output, "{}", stats)?;
+                    writeln!(
+                        output,
+                        "constants reclaimed: {}",
+                        session.compiled.reclaimed_constants()
+                    )?;
+                }
+                None if session.mode == Mode::Interpret => {
+                    writeln!(output, "memory stats are only available in compile mode")?
+                }
+                None => writeln!(output, "nothing has run yet")?,
+            }
+            return Ok(true);
+        }
+
+        if let Some(command) = input.trim().strip_prefix(':') {
+            writeln!(output, "unknown command :{}\n{}", command, REPL_COMMANDS)?;
+            return Ok(true);
+        }
+
+        let program = match parse_with_asi(input) {
+            Ok(x) => x,
             Err(e) => {
-                println!("{}", e);
+                writeln!(output, "{}", render_parse_errors(&e, input))?;
+                return Ok(true);
             }
         };
 
-        symbol_table = compiler.symbol_table;
-        constants = compiler.constants;
+        let remember = ends_with_expression(&program);
+        match session.eval(input, program) {
+            // a builtin's error value is a diagnostic, not a result
+            Ok(Some(value)) if remember && !matches!(*value, Object::Null | Object::Error(_)) => {
+                let name = session.remember(&value);
+                writeln!(output, "{} = {}", name, format_limited(&value, limits))?;
+            }
+            Ok(Some(value)) => writeln!(output, "{}", format_limited(&value, limits))?,
+            Ok(None) => {}
+            Err(e) => writeln!(output, "{}", e)?,
+        }
+        Ok(true)
     }
 }
@@ -100,6 +100,22 @@ pub enum Opcode {
     OpCurrentClosure,
     OpModulo,
     OpTailCall,
+    OpTakeGlobal,
+    OpTakeLocal,
+    OpFloorDiv,
+    OpGreaterOrEqual,
+    OpSetIndex,
+    OpIter,
+    OpLessThan,
+    OpLessOrEqual,
+}
+
+static OPCODES: OnceLock<Vec<Opcode>> = OnceLock::new();
+
+// Every opcode, indexed by its byte, so decoding is a lookup rather than a
+// walk through `Opcode::iter()`.
+fn opcodes() -> &'static [Opcode] {
+    OPCODES.get_or_init(|| Opcode::iter().collect())
 }
 
 static DEFINITIONS: OnceLock<HashMap<Opcode, OpcodeDefinition>> = OnceLock::new();
@@ -139,6 +155,14 @@ pub fn definitions() -> &'static HashMap<Opcode, OpcodeDefinition> {
         insert_def(&mut m, Opcode::OpCurrentClosure, "OpCurrentClosure", &[]);
         insert_def(&mut m, Opcode::OpModulo, "OpModulo", &[]);
         insert_def(&mut m, Opcode::OpTailCall, "OpTailCall", &[1]);
+        insert_def(&mut m, Opcode::OpTakeGlobal, "OpTakeGlobal", &[2]);
+        insert_def(&mut m, Opcode::OpTakeLocal, "OpTakeLocal", &[1]);
+        insert_def(&mut m, Opcode::OpFloorDiv, "OpFloorDiv", &[]);
+        insert_def(&mut m, Opcode::OpGreaterOrEqual, "OpGreaterOrEqual", &[]);
+        insert_def(&mut m, Opcode::OpSetIndex, "OpSetIndex", &[]);
+        insert_def(&mut m, Opcode::OpIter, "OpIter", &[]);
+        insert_def(&mut m, Opcode::OpLessThan, "OpLessThan", &[]);
+        insert_def(&mut m, Opcode::OpLessOrEqual, "OpLessOrEqual", &[]);
         m
     })
 }
@@ -237,18 +261,9 @@ impl Instructions {
         let mut pos = 0;
 
         while pos < self.bytes.len() {
-            let op = self.bytes[pos];
-            let opcode = cast_u8_to_opcode_at(op, pos)?;
-
-            let def = definitions()
-                .get(&opcode)
-                .ok_or(OpCodeError::MissingDefinition(opcode))?;
-
-            let (operands, read) = read_operands(def, &self.bytes[pos + 1..])?;
-
-            output.push_str(&format!("{:04} {}\n", pos, def.display(&operands)));
-
-            pos += 1 + read;
+            let (text, width) = disassemble_instruction(&self.bytes, pos)?;
+            output.push_str(&format!("{:04} {}\n", pos, text));
+            pos += width;
         }
 
         Ok(output)
@@ -302,8 +317,9 @@ impl TryFrom<u8> for Opcode {
     type Error = OpCodeError;
 
     fn try_from(value: u8) -> Result<Self, Self::Error> {
-        Opcode::iter()
-            .nth(value as usize)
+        opcodes()
+            .get(value as usize)
+            .copied()
             .ok_or(OpCodeError::InvalidOpcodeByte {
                 byte: value,
                 position: None,
@@ -311,6 +327,21 @@ impl TryFrom<u8> for Opcode {
     }
 }
 
+/// Renders the single instruction at `pos` (e.g. `OpConst 1`) and returns it
+/// together with its width in bytes, opcode included.
+pub fn disassemble_instruction(bytes: &[u8], pos: usize) -> Result<(String, usize), OpCodeError> {
+    let byte = *bytes.get(pos).ok_or(OpCodeError::TruncatedOperands {
+        expected: 1,
+        available: 0,
+    })?;
+    let opcode = cast_u8_to_opcode_at(byte, pos)?;
+    let def = definitions()
+        .get(&opcode)
+        .ok_or(OpCodeError::MissingDefinition(opcode))?;
+    let (operands, read) = read_operands(def, &bytes[pos + 1..])?;
+    Ok((def.display(&operands), 1 + read))
+}
+
 pub fn cast_u8_to_opcode(byte: u8) -> Result<Opcode, OpCodeError> {
     Opcode::try_from(byte)
 }
@@ -323,9 +354,12 @@ pub fn concat_instructions(instructions: Vec<Instructions>) -> Instructions {
     Instructions::merge(instructions)
 }
 
-fn cast_u8_to_opcode_at(byte: u8, position: usize) -> Result<Opcode, OpCodeError> {
-    Opcode::iter()
-        .nth(byte as usize)
+/// Like [`cast_u8_to_opcode`], but an invalid byte is reported with the
+/// `position` it was read from.
+pub fn cast_u8_to_opcode_at(byte: u8, position: usize) -> Result<Opcode, OpCodeError> {
+    opcodes()
+        .get(byte as usize)
+        .copied()
         .ok_or(OpCodeError::InvalidOpcodeByte {
             byte,
             position: Some(position),
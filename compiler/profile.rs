@@ -0,0 +1,159 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use object::CompiledFunction;
+use strum::IntoEnumIterator;
+
+use crate::op_code::Opcode;
+
+/// What a profiled VM has run so far, from `VM::profile_report`.
+#[derive(Debug, Clone, Default)]
+pub struct ProfileReport {
+    /// Every opcode that ran and how often, most executed first.
+    pub opcodes: Vec<(Opcode, u64)>,
+    /// Every function that ran, the one that took longest first.
+    pub functions: Vec<FunctionProfile>,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct FunctionProfile {
+    /// The function's index in the constant pool; `None` for the main
+    /// program.
+    pub constant: Option<usize>,
+    /// Empty for anonymous functions.
+    pub name: String,
+    /// Calls, tail calls included.
+    pub calls: u64,
+    pub instructions: u64,
+    /// Wall-clock time spent on the function's own instructions, not on
+    /// the functions it called.
+    pub time: Duration,
+}
+
+impl FunctionProfile {
+    fn label(&self) -> &str {
+        match (self.constant, self.name.as_str()) {
+            (None, _) => "<main>",
+            (Some(_), "") => "<anonymous>",
+            (Some(_), name) => name,
+        }
+    }
+}
+
+impl fmt::Display for ProfileReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{:<24} {:>12}", "opcode", "count")?;
+        for (opcode, count) in &self.opcodes {
+            writeln!(f, "{:<24} {:>12}", format!("{:?}", opcode), count)?;
+        }
+        writeln!(f)?;
+        write!(
+            f,
+            "{:<24} {:>8} {:>12} {:>12} {:>12}",
+            "function", "constant", "calls", "instructions", "time"
+        )?;
+        for function in &self.functions {
+            let constant = function
+                .constant
+                .map_or("-".to_string(), |index| index.to_string());
+            write!(
+                f,
+                "\n{:<24} {:>8} {:>12} {:>12} {:>12}",
+                function.label(),
+                constant,
+                function.calls,
+                function.instructions,
+                format!("{:.3?}", function.time)
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Default)]
+struct Counts {
+    calls: u64,
+    instructions: u64,
+    time: Duration,
+}
+
+// Functions are keyed by address while the VM runs and only matched to
+// their constant index for the report. Each entry keeps its function alive,
+// so no address is reused.
+type FunctionKey = *const CompiledFunction;
+
+/// Counts what the VM dispatches. Each instruction's time is charged to the
+/// function that ran the one before it, when the next one starts.
+pub(crate) struct Profiler {
+    opcodes: [u64; 256],
+    functions: HashMap<FunctionKey, (Rc<CompiledFunction>, Counts)>,
+    last: Option<(FunctionKey, Instant)>,
+}
+
+impl Default for Profiler {
+    fn default() -> Self {
+        Profiler {
+            opcodes: [0; 256],
+            functions: HashMap::new(),
+            last: None,
+        }
+    }
+}
+
+impl Profiler {
+    fn counts(&mut self, func: &Rc<CompiledFunction>) -> &mut Counts {
+        &mut self
+            .functions
+            .entry(Rc::as_ptr(func))
+            .or_insert_with(|| (Rc::clone(func), Counts::default()))
+            .1
+    }
+
+    pub(crate) fn instruction(&mut self, opcode: Opcode, func: &Rc<CompiledFunction>) {
+        let now = Instant::now();
+        if let Some((key, since)) = self.last.replace((Rc::as_ptr(func), now))
+            && let Some((_, counts)) = self.functions.get_mut(&key)
+        {
+            counts.time += now - since;
+        }
+        self.opcodes[opcode as usize] += 1;
+        self.counts(func).instructions += 1;
+    }
+
+    pub(crate) fn call(&mut self, func: &Rc<CompiledFunction>) {
+        self.counts(func).calls += 1;
+    }
+
+    /// `constant_index` finds a function in the constant pool; functions it
+    /// can't find are taken to be the main program.
+    pub(crate) fn report(
+        &self,
+        constant_index: impl Fn(&Rc<CompiledFunction>) -> Option<usize>,
+    ) -> ProfileReport {
+        let mut opcodes: Vec<(Opcode, u64)> = Opcode::iter()
+            .map(|opcode| (opcode, self.opcodes[opcode as usize]))
+            .filter(|(_, count)| *count > 0)
+            .collect();
+        opcodes.sort_by_key(|(_, count)| std::cmp::Reverse(*count));
+
+        let mut functions: Vec<FunctionProfile> = self
+            .functions
+            .values()
+            .map(|(func, counts)| FunctionProfile {
+                constant: constant_index(func),
+                name: func.name.clone(),
+                calls: counts.calls,
+                instructions: counts.instructions,
+                time: counts.time,
+            })
+            .collect();
+        functions.sort_by(|a, b| {
+            b.time
+                .cmp(&a.time)
+                .then(b.instructions.cmp(&a.instructions))
+        });
+        ProfileReport { opcodes, functions }
+    }
+}
@@ -0,0 +1,75 @@
+#[cfg(test)]
+mod tests {
+    use parser::parse;
+
+    use crate::compiler::Compiler;
+    use crate::op_code::Opcode;
+    use crate::profile::ProfileReport;
+    use crate::vm::VM;
+
+    fn profile(input: &str) -> ProfileReport {
+        let program = parse(input).unwrap();
+        let bytecode = Compiler::new().compile(&program).unwrap();
+        let mut vm = VM::new(bytecode);
+        vm.enable_profiling();
+        vm.run().unwrap();
+        vm.profile_report().unwrap()
+    }
+
+    fn count(report: &ProfileReport, opcode: Opcode) -> u64 {
+        report
+            .opcodes
+            .iter()
+            .find(|(op, _)| *op == opcode)
+            .map_or(0, |(_, count)| *count)
+    }
+
+    #[test]
+    fn test_counts_each_opcode() {
+        let report = profile("let i = 0; while (i < 1000) { i = i + 1; }");
+        // one test per iteration and the one that ends the loop
+        assert_eq!(count(&report, Opcode::OpJumpNotTruthy), 1001);
+        assert_eq!(count(&report, Opcode::OpAdd), 1000);
+        assert!(report.opcodes.windows(2).all(|pair| pair[0].1 >= pair[1].1));
+        assert_eq!(report.functions.len(), 1);
+        assert_eq!(report.functions[0].constant, None);
+    }
+
+    #[test]
+    fn test_counts_calls_per_function() {
+        let report = profile(
+            "let fib = fn(n) { if (n < 2) { n } else { fib(n - 1) + fib(n - 2) } };
+             let twice = fn(f, x) { f(f(x)) };
+             fib(10);
+             twice(fn(x) { x + 1 }, 1);",
+        );
+        let fib = report
+            .functions
+            .iter()
+            .find(|function| function.name == "fib")
+            .unwrap();
+        assert_eq!(fib.calls, 177);
+        assert!(fib.constant.is_some());
+        let anonymous = report
+            .functions
+            .iter()
+            .find(|function| function.name.is_empty())
+            .unwrap();
+        assert_eq!(anonymous.calls, 2);
+        assert_eq!(report.functions.len(), 4);
+
+        let table = report.to_string();
+        assert!(table.starts_with("opcode"));
+        assert!(table.contains("\nfib "));
+        assert!(table.contains("\n<anonymous> "));
+        assert!(table.contains("\n<main> "));
+    }
+
+    #[test]
+    fn test_off_by_default() {
+        let program = parse("1 + 1").unwrap();
+        let mut vm = VM::new(Compiler::new().compile(&program).unwrap());
+        vm.run().unwrap();
+        assert!(vm.profile_report().is_none());
+    }
+}
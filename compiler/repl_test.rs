@@ -0,0 +1,22 @@
+use crate::run_repl;
+
+fn run(input: &str) -> String {
+    let mut output = Vec::new();
+    run_repl(input.as_bytes(), &mut output).unwrap();
+    String::from_utf8(output).unwrap()
+}
+
+#[test]
+fn test_run_repl_keeps_bindings_until_end_of_input() {
+    assert_eq!(run("let x = 40;\n\nx + 2\n"), ">> null\n>> >> _1 = 42\n>> ");
+}
+
+#[test]
+fn test_run_repl_stops_at_quit() {
+    assert_eq!(run("1\n:quit\n2\n"), ">> _1 = 1\n>> ");
+}
+
+#[test]
+fn test_run_repl_without_trailing_newline() {
+    assert_eq!(run("1 + 1"), ">> _1 = 2\n>> ");
+}
@@ -0,0 +1,289 @@
+use std::cell::RefCell;
+use std::fmt;
+use std::rc::Rc;
+use std::sync::Arc;
+use std::sync::atomic::AtomicBool;
+
+use interpreter::macro_expansion::{MacroError, define_macros, expand_macros};
+use object::environment::Env;
+use object::{BuiltinFunc, Object};
+use parser::ast::Node;
+use parser::lexer::token::Span;
+use parser::{ParseError, ParseErrors, parse};
+
+use crate::compiler::{Bytecode, CompileError, Compiler};
+use crate::profile::ProfileReport;
+use crate::source_map::line_column;
+use crate::symbol_table::SymbolTable;
+use crate::vm::{MemoryStats, VM, VMError, Value, compact_constants};
+use crate::warning::Warning;
+
+/// Why a source string could not be evaluated, by the stage that failed.
+#[derive(Debug)]
+pub enum EvalSourceError {
+    Parse(ParseErrors),
+    Macro(MacroError),
+    Compile(CompileError),
+    Runtime(VMError),
+}
+
+impl fmt::Display for EvalSourceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EvalSourceError::Parse(errors) => {
+                let messages: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+                write!(f, "{}", messages.join("\n"))
+            }
+            EvalSourceError::Macro(err) => write!(f, "{}", err),
+            EvalSourceError::Compile(err) => write!(f, "{}", err),
+            EvalSourceError::Runtime(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl EvalSourceError {
+    /// The error as the REPL and CLI print it, pointing into `source`.
+    pub fn render(&self, source: &str) -> String {
+        match self {
+            EvalSourceError::Parse(errors) => render_parse_errors(errors, source),
+            EvalSourceError::Macro(err) => err.to_string(),
+            EvalSourceError::Compile(err) => err.render(source),
+            EvalSourceError::Runtime(err) => err.render(source),
+        }
+    }
+}
+
+/// Every parse error, each prefixed with where it starts and followed by its
+/// source line, one after another.
+pub fn render_parse_errors(errors: &[ParseError], source: &str) -> String {
+    errors
+        .iter()
+        .map(|error| match error.span() {
+            Some(span) => {
+                let (line, column) = line_column(source, span.start);
+                format!(
+                    "parse error at {}:{}: {}",
+                    line,
+                    column,
+                    error.render(source)
+                )
+            }
+            None => format!("parse error: {}", error),
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Moves the program's macro definitions into `macro_env` and expands its
+/// macro calls.
+pub fn expand(program: Node, macro_env: &Env) -> Result<Node, MacroError> {
+    match program {
+        Node::Program(mut program) => {
+            define_macros(&mut program, macro_env);
+            Ok(Node::Program(expand_macros(program, macro_env)?))
+        }
+        other => Ok(other),
+    }
+}
+
+/// Parses, compiles and runs `source` in a fresh [`Session`], returning the
+/// value of its last expression.
+///
+/// ```
+/// use compiler::eval_source;
+/// use compiler::vm::Value;
+///
+/// let value = eval_source("let double = fn(x) { x * 2 }; double(21);").unwrap();
+/// assert!(matches!(value, Value::Integer(42)));
+/// ```
+pub fn eval_source(source: &str) -> Result<Value, EvalSourceError> {
+    Session::new().eval(source)
+}
+
+/// Compiles and runs one input after another, keeping the symbol table,
+/// constants, globals and macros between them, as the REPL does.
+pub struct Session {
+    pub symbol_table: SymbolTable,
+    pub constants: Vec<Rc<Object>>,
+    pub globals: Vec<Value>,
+    macro_env: Env,
+    warn_shadowing: bool,
+    io: bool,
+    warnings: Vec<Warning>,
+    memory: Option<MemoryStats>,
+    profile: Option<ProfileReport>,
+    profiling: bool,
+    cancel: Option<Arc<AtomicBool>>,
+    reclaimed_constants: usize,
+    host_builtins: Vec<(String, BuiltinFunc)>,
+    /// Set by the first input, after which no builtins can be added.
+    started: bool,
+}
+
+impl Default for Session {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Session {
+    pub fn new() -> Self {
+        Session {
+            symbol_table: SymbolTable::new(),
+            constants: vec![],
+            globals: vec![],
+            macro_env: Rc::new(RefCell::new(Default::default())),
+            warn_shadowing: false,
+            io: false,
+            warnings: vec![],
+            memory: None,
+            profile: None,
+            profiling: false,
+            cancel: None,
+            reclaimed_constants: 0,
+            host_builtins: vec![],
+            started: false,
+        }
+    }
+
+    pub fn set_warn_shadowing(&mut self, enabled: bool) {
+        self.warn_shadowing = enabled;
+    }
+
+    /// Whether scripts may use `read_file` and `write_file`; off by default.
+    pub fn enable_io(&mut self, enabled: bool) {
+        self.io = enabled;
+    }
+
+    /// Whether to profile each input, for `profile_report`; off by default.
+    pub fn set_profiling(&mut self, enabled: bool) {
+        self.profiling = enabled;
+    }
+
+    /// Stops each input once `token` is set, as `VM::set_cancel_token`
+    /// does.
+    pub fn set_cancel_token(&mut self, token: Option<Arc<AtomicBool>>) {
+        self.cancel = token;
+    }
+
+    /// Makes `func` callable from scripts as `name`, as
+    /// [`Compiler::register_builtin`] does. Must happen before the first
+    /// input.
+    pub fn register_builtin(&mut self, name: &str, func: BuiltinFunc) -> Result<(), CompileError> {
+        if self.started {
+            return Err(CompileError::BuiltinAfterCompile(name.to_string()));
+        }
+        self.host_builtins.push((name.to_string(), func));
+        Ok(())
+    }
+
+    /// Parses `source`, which needs explicit semicolons, and evaluates it.
+    pub fn eval(&mut self, source: &str) -> Result<Value, EvalSourceError> {
+        let program = parse(source).map_err(EvalSourceError::Parse)?;
+        self.eval_program(program)
+    }
+
+    /// Evaluates an already parsed `program`. An input that fails to compile
+    /// leaves no trace, not even the macros it defined.
+    pub fn eval_program(&mut self, program: Node) -> Result<Value, EvalSourceError> {
+        self.started = true;
+        let macro_env: Env = Rc::new(RefCell::new(self.macro_env.borrow().clone()));
+        let program = expand(program, &macro_env).map_err(EvalSourceError::Macro)?;
+
+        let symbol_table = std::mem::take(&mut self.symbol_table);
+        let constants = std::mem::take(&mut self.constants);
+        let saved_symbols = symbol_table.clone();
+        let saved_constants = constants.len();
+        let mut compiler = Compiler::new_with_state(symbol_table, constants);
+        compiler.set_warn_shadowing(self.warn_shadowing);
+
+        let result = match self
+            .register_host_builtins(&mut compiler)
+            .and_then(|()| compiler.compile(&program))
+        {
+            Ok(bytecodes) => {
+                self.macro_env = macro_env;
+                self.warnings = compiler.warnings().to_vec();
+                let globals = std::mem::take(&mut self.globals);
+                let mut vm = VM::new_with_global_store(bytecodes, globals);
+                vm.enable_io(self.io);
+                vm.set_cancel_token(self.cancel.clone());
+                if self.profiling {
+                    vm.enable_profiling();
+                }
+                let result = match vm.run() {
+                    Ok(()) => Ok(vm.last_popped_stack_elm().unwrap_or(Value::Null)),
+                    Err(e) => Err(EvalSourceError::Runtime(e)),
+                };
+                self.memory = Some(vm.memory_stats());
+                self.profile = vm.profile_report();
+                self.globals = vm.globals;
+                result
+            }
+            Err(e) => {
+                // names a failed input defined would resolve to slots it
+                // never wrote, so it leaves no trace
+                self.warnings.clear();
+                compiler.symbol_table = saved_symbols;
+                compiler.constants.truncate(saved_constants);
+                Err(EvalSourceError::Compile(e))
+            }
+        };
+
+        self.symbol_table = compiler.symbol_table;
+        self.constants = compiler.constants;
+        // constants only the finished input used would otherwise pile up
+        self.reclaimed_constants += compact_constants(&mut self.constants, &self.globals);
+        result
+    }
+
+    /// What `program` compiles to, without running it or keeping its
+    /// definitions or macros.
+    pub fn compile_only(&self, program: Node) -> Result<Bytecode, EvalSourceError> {
+        let macro_env: Env = Rc::new(RefCell::new(self.macro_env.borrow().clone()));
+        let program = expand(program, &macro_env).map_err(EvalSourceError::Macro)?;
+        let mut compiler =
+            Compiler::new_with_state(self.symbol_table.clone(), self.constants.clone());
+        self.register_host_builtins(&mut compiler)
+            .and_then(|()| compiler.compile(&program))
+            .map_err(EvalSourceError::Compile)
+    }
+
+    // Each input gets a new compiler, which has to learn the registered
+    // builtins again; their symbols keep the same indices every time.
+    fn register_host_builtins(&self, compiler: &mut Compiler) -> Result<(), CompileError> {
+        for (name, func) in &self.host_builtins {
+            compiler.register_builtin(name, *func)?;
+        }
+        Ok(())
+    }
+
+    /// Defines the global `name` holding `value`, as if by a `let`.
+    pub fn bind(&mut self, name: &str, value: Value) {
+        let symbol = self.symbol_table.define(name, Span { start: 0, end: 0 });
+        if self.globals.len() <= symbol.index {
+            self.globals.resize(symbol.index + 1, Value::Null);
+        }
+        self.globals[symbol.index] = value;
+    }
+
+    /// The warnings the last input that compiled raised.
+    pub fn warnings(&self) -> &[Warning] {
+        &self.warnings
+    }
+
+    /// The VM's memory use after the last input that ran.
+    pub fn memory_stats(&self) -> Option<&MemoryStats> {
+        self.memory.as_ref()
+    }
+
+    /// The profile of the last input that ran, when profiling is on.
+    pub fn profile_report(&self) -> Option<&ProfileReport> {
+        self.profile.as_ref()
+    }
+
+    /// How many constants no global could reach anymore and were dropped.
+    pub fn reclaimed_constants(&self) -> usize {
+        self.reclaimed_constants
+    }
+}
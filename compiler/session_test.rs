@@ -0,0 +1,158 @@
+#[cfg(test)]
+mod tests {
+    use crate::compiler::CompileError;
+    use crate::session::{EvalSourceError, Session, eval_source};
+    use crate::vm::{VMError, Value};
+    use object::{BuiltinFunc, Object};
+    use std::rc::Rc;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    #[test]
+    fn test_eval_source() {
+        assert!(matches!(eval_source("1 + 2 * 3;"), Ok(Value::Integer(7))));
+        assert!(matches!(eval_source("let x = 1;"), Ok(Value::Null)));
+        let Ok(Value::Object(value)) = eval_source("[1, \"a\"]") else {
+            panic!("expected an array");
+        };
+        assert_eq!(value.to_string(), "[1, a]");
+    }
+
+    #[test]
+    fn test_values_convert_to_rust() {
+        let value = eval_source("40 + 2;").unwrap();
+        assert_eq!(value.try_into_i64(), Ok(42));
+        assert_eq!(value.try_into_f64(), Ok(42.0));
+        assert_eq!(
+            value.try_into_string().unwrap_err().to_string(),
+            "expected STRING, got INTEGER"
+        );
+        let value = eval_source("\"a\" + \"b\";").unwrap();
+        assert_eq!(value.try_into_string(), Ok("ab".to_string()));
+        assert!(value.try_into_i64().is_err());
+        assert_eq!(eval_source("1 < 2;").unwrap().try_into_bool(), Ok(true));
+    }
+
+    #[test]
+    fn test_eval_source_errors_by_stage() {
+        assert!(matches!(
+            eval_source("let = 1;"),
+            Err(EvalSourceError::Parse(errors)) if errors.len() == 1
+        ));
+        assert!(matches!(
+            eval_source("missing;"),
+            Err(EvalSourceError::Compile(
+                CompileError::UndefinedVariable { .. }
+            ))
+        ));
+        let Err(EvalSourceError::Runtime(err)) = eval_source("1 + \"a\";") else {
+            panic!("expected a runtime error");
+        };
+        assert!(matches!(
+            err.without_location(),
+            VMError::StringConcatenation { .. }
+        ));
+
+        let source = "let y = 1;\nx;";
+        let err = eval_source(source).unwrap_err();
+        assert_eq!(
+            err.render(source),
+            "compile error at 2:1: undefined variable 'x'\n\
+             \x20  2 | x;\n\
+             \x20    | ^"
+        );
+    }
+
+    #[test]
+    fn test_session_keeps_state_between_inputs() {
+        let mut session = Session::new();
+        session.eval("let add = fn(a, b) { a + b };").unwrap();
+        session.eval("let x = 40;").unwrap();
+        assert!(matches!(session.eval("add(x, 2);"), Ok(Value::Integer(42))));
+
+        // macros carry over too
+        session
+            .eval("let unless = macro(c, a, b) { quote(if (!(unquote(c))) { unquote(a) } else { unquote(b) }) };")
+            .unwrap();
+        assert!(matches!(
+            session.eval("unless(false, 1, 2);"),
+            Ok(Value::Integer(1))
+        ));
+
+        session.bind("answer", Value::Integer(42));
+        assert!(matches!(
+            session.eval("answer + 1;"),
+            Ok(Value::Integer(43))
+        ));
+    }
+
+    #[test]
+    fn test_session_forgets_inputs_that_fail_to_compile() {
+        let mut session = Session::new();
+        assert!(session.eval("let f = fn() { let y = 1; nope };").is_err());
+        assert!(matches!(
+            session.eval("f;"),
+            Err(EvalSourceError::Compile(
+                CompileError::UndefinedVariable { .. }
+            ))
+        ));
+        assert!(matches!(session.eval("1;"), Ok(Value::Integer(1))));
+
+        // nor do the macros it defined
+        assert!(
+            session
+                .eval("let inc = macro(x) { quote(unquote(x) + 1) }; nope;")
+                .is_err()
+        );
+        assert!(matches!(
+            session.eval("inc(1);"),
+            Err(EvalSourceError::Compile(
+                CompileError::UndefinedVariable { name, .. }
+            )) if name == "inc"
+        ));
+    }
+
+    #[test]
+    fn test_session_cancel_token() {
+        let cancel = Arc::new(AtomicBool::new(true));
+        let mut session = Session::new();
+        session.set_cancel_token(Some(Arc::clone(&cancel)));
+        let Err(EvalSourceError::Runtime(e)) = session.eval("1 + 2;") else {
+            panic!("expected the input to be cancelled");
+        };
+        assert!(matches!(
+            e.without_location(),
+            VMError::Cancelled { executed: 0 }
+        ));
+        cancel.store(false, Ordering::Relaxed);
+        assert!(matches!(session.eval("1 + 2;"), Ok(Value::Integer(3))));
+    }
+
+    fn double(args: Vec<Rc<Object>>) -> Rc<Object> {
+        match args.first().map(|arg| &**arg) {
+            Some(Object::Integer(i)) => Rc::new(Object::Integer(i * 2)),
+            _ => Rc::new(Object::Null),
+        }
+    }
+
+    #[test]
+    fn test_session_registered_builtins() {
+        let mut session = Session::new();
+        session
+            .register_builtin("double", BuiltinFunc::Plain(double))
+            .unwrap();
+        assert!(matches!(
+            session.eval("double(21);"),
+            Ok(Value::Integer(42))
+        ));
+        // still there for later inputs
+        assert!(matches!(
+            session.eval("let f = fn(x) { double(x) }; f(4);"),
+            Ok(Value::Integer(8))
+        ));
+        assert!(matches!(
+            session.register_builtin("triple", BuiltinFunc::Plain(double)),
+            Err(CompileError::BuiltinAfterCompile(name)) if name == "triple"
+        ));
+    }
+}
@@ -0,0 +1,95 @@
+use std::collections::BTreeMap;
+
+use parser::lexer::token::Span;
+
+use crate::op_code::{OpCodeError, disassemble_instruction};
+use crate::warning::line_number;
+
+/// The instructions from `offset` up to the next mapping were compiled from
+/// the code at `span`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Mapping {
+    pub offset: usize,
+    pub span: Span,
+}
+
+/// Ties instruction offsets back to the source they were compiled from, for
+/// the main program and for each compiled function, which is keyed by its
+/// index in the constant pool. Spans are byte offsets, so turning them into
+/// lines and columns needs the source text.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct SourceMap {
+    pub main: Vec<Mapping>,
+    pub functions: BTreeMap<usize, Vec<Mapping>>,
+}
+
+/// The span the instruction at `offset` was compiled from.
+pub fn span_at(mappings: &[Mapping], offset: usize) -> Option<&Span> {
+    let index = mappings.partition_point(|mapping| mapping.offset <= offset);
+    Some(&mappings.get(index.checked_sub(1)?)?.span)
+}
+
+/// 1-based line and column of byte `offset`; the column counts characters.
+pub fn line_column(source: &str, offset: usize) -> (usize, usize) {
+    let mut offset = offset.min(source.len());
+    while !source.is_char_boundary(offset) {
+        offset -= 1;
+    }
+    let line_start = source[..offset]
+        .rfind('\n')
+        .map_or(0, |newline| newline + 1);
+    let column = source[line_start..offset].chars().count() + 1;
+    (line_number(source, offset), column)
+}
+
+/// The numbered source line holding `span` with the span underlined, in the
+/// layout parse errors use.
+pub fn excerpt(source: &str, span: &Span) -> String {
+    let mut start = span.start.min(source.len());
+    while !source.is_char_boundary(start) {
+        start -= 1;
+    }
+    let line_start = source[..start].rfind('\n').map_or(0, |newline| newline + 1);
+    let line_end = source[start..]
+        .find('\n')
+        .map_or(source.len(), |newline| start + newline);
+    let (line, column) = line_column(source, start);
+    let end = span.end.clamp(start, line_end);
+    let width = source
+        .get(start..end)
+        .map_or(0, |text| text.chars().count());
+    format!(
+        "{:>4} | {}\n     | {}{}",
+        line,
+        source[line_start..line_end].trim_end(),
+        " ".repeat(column - 1),
+        "^".repeat(width.max(1))
+    )
+}
+
+/// Disassembles `instructions`, printing each source line before the first
+/// instruction compiled from it.
+pub fn disassemble_with_lines(
+    instructions: &[u8],
+    mappings: &[Mapping],
+    source: &str,
+) -> Result<String, OpCodeError> {
+    let lines: Vec<&str> = source.lines().collect();
+    let mut output = String::new();
+    let mut current_line = None;
+    let mut pos = 0;
+    while pos < instructions.len() {
+        if let Some(span) = span_at(mappings, pos) {
+            let line = line_number(source, span.start);
+            if current_line != Some(line) {
+                let text = lines.get(line - 1).copied().unwrap_or_default();
+                output.push_str(&format!("{:>4} | {}\n", line, text.trim_end()));
+                current_line = Some(line);
+            }
+        }
+        let (text, width) = disassemble_instruction(instructions, pos)?;
+        output.push_str(&format!("{:04} {}\n", pos, text));
+        pos += width;
+    }
+    Ok(output)
+}
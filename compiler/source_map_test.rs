@@ -0,0 +1,129 @@
+#[cfg(test)]
+mod tests {
+    use crate::compiler::Compiler;
+    use crate::source_map::{Mapping, SourceMap, disassemble_with_lines, line_column, span_at};
+    use parser::lexer::token::Span;
+    use parser::parse;
+
+    fn compile(input: &str) -> (Compiler, crate::compiler::Bytecode) {
+        let program = parse(input).unwrap();
+        let mut compiler = Compiler::new();
+        let bytecode = compiler.compile(&program).unwrap();
+        (compiler, bytecode)
+    }
+
+    fn lines_of(input: &str, mappings: &[Mapping], offsets: &[usize]) -> Vec<(usize, usize)> {
+        offsets
+            .iter()
+            .map(|&offset| line_column(input, span_at(mappings, offset).unwrap().start))
+            .collect()
+    }
+
+    #[test]
+    fn test_span_at() {
+        let mappings = vec![
+            Mapping {
+                offset: 0,
+                span: Span { start: 0, end: 5 },
+            },
+            Mapping {
+                offset: 3,
+                span: Span { start: 7, end: 9 },
+            },
+        ];
+        assert_eq!(span_at(&mappings, 0), Some(&Span { start: 0, end: 5 }));
+        assert_eq!(span_at(&mappings, 2), Some(&Span { start: 0, end: 5 }));
+        assert_eq!(span_at(&mappings, 3), Some(&Span { start: 7, end: 9 }));
+        assert_eq!(span_at(&mappings, 10), Some(&Span { start: 7, end: 9 }));
+        assert_eq!(span_at(&[], 0), None);
+    }
+
+    #[test]
+    fn test_line_column() {
+        let source = "let a = 1;\n  let ü = \"é\"; b";
+        assert_eq!(line_column(source, 0), (1, 1));
+        assert_eq!(line_column(source, 4), (1, 5));
+        assert_eq!(line_column(source, 11), (2, 1));
+        assert_eq!(line_column(source, 13), (2, 3));
+        // columns count characters, not bytes
+        assert_eq!(line_column(source, source.len() - 1), (2, 16));
+    }
+
+    #[test]
+    fn test_main_program_mappings() {
+        let input = "let x = 1;\nlet y = x +\n    2;\ny";
+        let (compiler, _) = compile(input);
+        let map = compiler.source_map();
+        // 0000 OpConst 0
+        // 0003 OpSetGlobal 0
+        // 0006 OpGetGlobal 0
+        // 0009 OpConst 1
+        // 0012 OpAdd
+        // 0013 OpSetGlobal 1
+        // 0016 OpGetGlobal 1
+        // 0019 OpPop
+        assert_eq!(
+            lines_of(input, &map.main, &[0, 3, 6, 9, 12, 13, 16, 19]),
+            [
+                (1, 9),
+                (1, 1),
+                (2, 9),
+                (3, 5),
+                (2, 9),
+                (2, 1),
+                (4, 1),
+                (4, 1)
+            ]
+        );
+        assert!(map.functions.is_empty());
+    }
+
+    #[test]
+    fn test_function_mappings_are_keyed_by_constant() {
+        let input = "let f = fn(a) {\n  a + 1\n};\nf(2)";
+        let (compiler, bytecode) = compile(input);
+        let SourceMap { main, functions } = compiler.source_map();
+        assert_eq!(functions.keys().copied().collect::<Vec<_>>(), [1]);
+        assert!(matches!(
+            *bytecode.constants[1],
+            object::Object::CompiledFunction(_)
+        ));
+        // 0000 OpGetLocal 0
+        // 0002 OpConst 0
+        // 0005 OpAdd
+        // 0006 OpReturnValue
+        assert_eq!(
+            lines_of(input, &functions[&1], &[0, 2, 5, 6]),
+            [(2, 3), (2, 7), (2, 3), (2, 3)]
+        );
+        // 0000 OpClosure 1 0
+        assert_eq!(lines_of(input, &main, &[0]), [(1, 9)]);
+    }
+
+    #[test]
+    fn test_disassemble_with_lines() {
+        let input = "let a = 1;\n\nif (a > 0) { a } else { 2 };";
+        let (compiler, bytecode) = compile(input);
+        let listing = disassemble_with_lines(
+            &bytecode.instructions.bytes,
+            &compiler.source_map().main,
+            input,
+        )
+        .unwrap();
+        assert_eq!(
+            listing,
+            "   1 | let a = 1;\n\
+             0000 OpConst 0\n\
+             0003 OpSetGlobal 0\n\
+             \x20  3 | if (a > 0) { a } else { 2 };\n\
+             0006 OpGetGlobal 0\n\
+             0009 OpConst 1\n\
+             0012 OpGreaterThan\n\
+             0013 OpJumpNotTruthy 22\n\
+             0016 OpGetGlobal 0\n\
+             0019 OpJump 25\n\
+             0022 OpConst 2\n\
+             0025 OpPop\n"
+        );
+    }
+}
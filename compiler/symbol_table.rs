@@ -2,7 +2,9 @@ use std::cell::{Cell, RefCell};
 use std::collections::HashMap;
 use std::rc::Rc;
 
-#[derive(Clone, Debug, Eq, PartialEq)]
+use parser::lexer::token::Span;
+
+#[derive(Clone, Debug, Eq, PartialEq, Ord, PartialOrd)]
 pub enum SymbolScope {
     Local,
     Global,
@@ -13,17 +15,30 @@ pub enum SymbolScope {
 
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub struct Symbol {
-    pub name: String,
+    /// Shared with the table's key and with every free copy of the symbol.
+    pub name: Rc<str>,
     pub scope: SymbolScope,
     pub index: usize,
+    /// Where the name was bound; builtins have no definition site.
+    pub span: Option<Span>,
 }
 
 #[derive(Debug, Clone)]
 pub struct SymbolTable {
     outer: Option<Rc<SymbolTable>>,
-    symbols: RefCell<HashMap<String, Rc<Symbol>>>,
+    symbols: RefCell<HashMap<Rc<str>, Rc<Symbol>>>,
     free_symbols: RefCell<Vec<Rc<Symbol>>>,
+    /// How many slots the function (or the global scope) needs: the most
+    /// that were ever in use at once.
     num_definitions: Cell<usize>,
+    /// The slot the next definition takes. Ending a block inside a
+    /// function hands its slots back, so this can drop below
+    /// `num_definitions`.
+    next_index: Cell<usize>,
+    /// Set for the table of an `if`, `while` or `for` body, to the owner's
+    /// `next_index` when the block began. A block's names go away with it,
+    /// but its slots belong to the enclosing function or global scope.
+    block_start: Option<usize>,
 }
 
 impl Default for SymbolTable {
@@ -38,44 +53,97 @@ impl SymbolTable {
             symbols: RefCell::new(HashMap::new()),
             free_symbols: RefCell::new(Vec::new()),
             num_definitions: Cell::new(0),
+            next_index: Cell::new(0),
+            block_start: None,
             outer: None,
         }
     }
 
     pub fn new_enclosed(outer: Rc<Self>) -> Self {
         Self {
-            symbols: RefCell::new(HashMap::new()),
-            free_symbols: RefCell::new(Vec::new()),
-            num_definitions: Cell::new(0),
             outer: Some(outer),
+            ..Self::new()
         }
     }
 
-    pub fn define(&self, name: &str) -> Rc<Symbol> {
+    /// A table for a block inside `outer`'s function or global scope.
+    pub fn new_block(outer: Rc<Self>) -> Self {
+        let start = outer.owner().next_index.get();
+        Self {
+            outer: Some(outer),
+            block_start: Some(start),
+            ..Self::new()
+        }
+    }
+
+    /// Ends a block, returning the table it was opened in. Inside a
+    /// function the block's slots are reused by later definitions, since
+    /// closures copy what they capture. Global slots stay taken: functions
+    /// read globals by slot, and one defined in the block may outlive it.
+    pub fn end_block(&self) -> Option<Self> {
+        let start = self.block_start?;
+        let outer = self.outer.as_ref()?;
+        let owner = outer.owner();
+        if owner.outer.is_some() {
+            owner.next_index.set(start);
+        }
+        Some(outer.as_ref().clone())
+    }
+
+    // The function or global table whose slots this table's names use.
+    fn owner(&self) -> &Self {
+        match (&self.block_start, &self.outer) {
+            (Some(_), Some(outer)) => outer.owner(),
+            _ => self,
+        }
+    }
+
+    /// Whether `define` here binds a global rather than a local.
+    pub fn is_global_scope(&self) -> bool {
+        self.owner().outer.is_none()
+    }
+
+    pub fn define(&self, name: &str, span: Span) -> Rc<Symbol> {
         if let Some(existing) = self.symbols.borrow().get(name)
             && (existing.scope == SymbolScope::Global || existing.scope == SymbolScope::Local)
         {
             return Rc::clone(existing);
         }
 
-        let scope = if self.outer.is_some() {
+        let owner = self.owner();
+        let scope = if owner.outer.is_some() {
             SymbolScope::Local
         } else {
             SymbolScope::Global
         };
+        let index = owner.next_index.get();
+        owner.next_index.set(index + 1);
+        owner
+            .num_definitions
+            .set(owner.num_definitions.get().max(index + 1));
 
-        let name = name.to_string();
+        let name: Rc<str> = Rc::from(name);
         let symbol = Rc::new(Symbol {
-            name: name.clone(),
-            index: self.num_definitions.get(),
+            name: Rc::clone(&name),
+            index,
             scope,
+            span: Some(span),
         });
 
         self.symbols.borrow_mut().insert(name, Rc::clone(&symbol));
-        self.num_definitions.set(self.num_definitions.get() + 1);
         symbol
     }
 
+    /// The global or local this table already binds `name` to, whose slot
+    /// `define(name, ..)` would reuse rather than bind a new one.
+    pub fn defined_here(&self, name: &str) -> Option<Rc<Symbol>> {
+        self.symbols
+            .borrow()
+            .get(name)
+            .filter(|symbol| matches!(symbol.scope, SymbolScope::Global | SymbolScope::Local))
+            .cloned()
+    }
+
     pub fn resolve(&self, name: &str) -> Option<Rc<Symbol>> {
         if let Some(symbol) = self.symbols.borrow().get(name) {
             return Some(Rc::clone(symbol));
@@ -85,8 +153,14 @@ impl SymbolTable {
             let outer_symbol = outer.resolve(name);
 
             if let Some(symbol) = outer_symbol {
+                // a block runs in the same frame as the code around it
+                if self.block_start.is_some() {
+                    return Some(symbol);
+                }
+                // an enclosing function's own name is captured like any
+                // of its locals; `OpCurrentClosure` would load this one
                 match symbol.scope {
-                    SymbolScope::Local | SymbolScope::Free => {
+                    SymbolScope::Local | SymbolScope::Free | SymbolScope::Function => {
                         return Some(self.define_free_checked(symbol));
                     }
                     _ => return Some(symbol),
@@ -98,27 +172,50 @@ impl SymbolTable {
     }
 
     pub fn define_builtin(&self, index: usize, name: &str) -> Rc<Symbol> {
-        let name = name.to_string();
+        let name: Rc<str> = Rc::from(name);
         let symbol = Rc::new(Symbol {
-            name: name.clone(),
+            name: Rc::clone(&name),
             index,
             scope: SymbolScope::Builtin,
+            span: None,
         });
         self.symbols.borrow_mut().insert(name, Rc::clone(&symbol));
         symbol
     }
 
-    pub fn define_function_name(&self, name: &str) -> Rc<Symbol> {
-        let name = name.to_string();
+    pub fn define_function_name(&self, name: &str, span: Span) -> Rc<Symbol> {
+        let name: Rc<str> = Rc::from(name);
         let symbol = Rc::new(Symbol {
-            name: name.clone(),
+            name: Rc::clone(&name),
             index: 0,
             scope: SymbolScope::Function,
+            span: Some(span),
         });
         self.symbols.borrow_mut().insert(name, Rc::clone(&symbol));
         symbol
     }
 
+    /// The symbol that defining `name` in this table would hide, if any.
+    /// Re-defining a global or local of this table reuses its slot, so it
+    /// is not shadowing. Unlike `resolve`, this never captures free symbols.
+    pub fn shadowed(&self, name: &str) -> Option<Rc<Symbol>> {
+        if let Some(existing) = self.symbols.borrow().get(name) {
+            return match existing.scope {
+                SymbolScope::Global | SymbolScope::Local => None,
+                SymbolScope::Free => self.outer.as_ref()?.lookup(name),
+                SymbolScope::Builtin | SymbolScope::Function => Some(Rc::clone(existing)),
+            };
+        }
+        self.outer.as_ref()?.lookup(name)
+    }
+
+    fn lookup(&self, name: &str) -> Option<Rc<Symbol>> {
+        if let Some(symbol) = self.symbols.borrow().get(name) {
+            return Some(Rc::clone(symbol));
+        }
+        self.outer.as_ref()?.lookup(name)
+    }
+
     fn define_free_checked(&self, original: Rc<Symbol>) -> Rc<Symbol> {
         if let Some(existing) = self.symbols.borrow().get(&original.name) {
             return Rc::clone(existing);
@@ -129,13 +226,14 @@ impl SymbolTable {
     fn define_free(&self, original: Rc<Symbol>) -> Rc<Symbol> {
         self.free_symbols.borrow_mut().push(Rc::clone(&original));
         let symbol = Rc::new(Symbol {
-            name: original.name.clone(),
+            name: Rc::clone(&original.name),
             index: self.free_symbols.borrow().len() - 1,
             scope: SymbolScope::Free,
+            span: original.span.clone(),
         });
         self.symbols
             .borrow_mut()
-            .insert(original.name.clone(), Rc::clone(&symbol));
+            .insert(Rc::clone(&original.name), Rc::clone(&symbol));
         symbol
     }
 
@@ -148,6 +246,14 @@ impl SymbolTable {
         self.free_symbols.borrow().clone()
     }
 
+    /// The names defined in this table itself, not its outer tables, by
+    /// scope and then index.
+    pub fn symbols(&self) -> Vec<Rc<Symbol>> {
+        let mut symbols: Vec<_> = self.symbols.borrow().values().cloned().collect();
+        symbols.sort_by_key(|symbol| (symbol.scope.clone(), symbol.index));
+        symbols
+    }
+
     pub fn outer(&self) -> Option<&Rc<Self>> {
         self.outer.as_ref()
     }
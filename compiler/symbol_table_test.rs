@@ -1,19 +1,266 @@
 #[cfg(test)]
 mod tests {
     use crate::symbol_table::{SymbolScope, SymbolTable};
+    use parser::lexer::token::Span;
+    use std::rc::Rc;
+
+    fn span(start: usize) -> Span {
+        Span {
+            start,
+            end: start + 1,
+        }
+    }
+
+    fn assert_resolves(table: &SymbolTable, expected: &[(&str, SymbolScope, usize)]) {
+        for (name, scope, index) in expected {
+            let symbol = table
+                .resolve(name)
+                .unwrap_or_else(|| panic!("{} is not resolvable", name));
+            assert_eq!(
+                (&*symbol.name, &symbol.scope, symbol.index),
+                (*name, scope, *index),
+                "{}",
+                name
+            );
+        }
+    }
+
+    fn free_names(table: &SymbolTable) -> Vec<(String, SymbolScope, usize)> {
+        table
+            .free_symbols()
+            .iter()
+            .map(|symbol| (symbol.name.to_string(), symbol.scope.clone(), symbol.index))
+            .collect()
+    }
+
     #[test]
     fn test_define() {
         let symbol_table = SymbolTable::new();
-        let symbol = symbol_table.define("x");
-        assert_eq!(symbol.name, "x");
+        let symbol = symbol_table.define("x", Span { start: 4, end: 5 });
+        assert_eq!(&*symbol.name, "x");
         assert_eq!(symbol.scope, SymbolScope::Global);
         assert_eq!(symbol.index, 0);
     }
 
+    #[test]
+    fn test_symbols_lists_own_definitions_in_order() {
+        let global = Rc::new(SymbolTable::new());
+        global.define_builtin(0, "len");
+        global.define("b", span(0));
+        global.define("a", span(1));
+        let local = SymbolTable::new_enclosed(Rc::clone(&global));
+        local.define("c", span(2));
+
+        let listed = |table: &SymbolTable| {
+            table
+                .symbols()
+                .iter()
+                .map(|symbol| (symbol.name.to_string(), symbol.scope.clone(), symbol.index))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            listed(&global),
+            vec![
+                ("b".to_string(), SymbolScope::Global, 0),
+                ("a".to_string(), SymbolScope::Global, 1),
+                ("len".to_string(), SymbolScope::Builtin, 0),
+            ]
+        );
+        assert_eq!(
+            listed(&local),
+            vec![("c".to_string(), SymbolScope::Local, 0)]
+        );
+    }
+
     #[test]
     fn test_resolve() {
         let symbol_table = SymbolTable::new();
-        let symbol = symbol_table.define("x");
+        let symbol = symbol_table.define("x", Span { start: 4, end: 5 });
         assert_eq!(symbol_table.resolve("x"), Some(symbol));
     }
+
+    #[test]
+    fn test_resolve_nested_local() {
+        let global = Rc::new(SymbolTable::new());
+        global.define("a", span(0));
+        global.define("b", span(1));
+        let first = Rc::new(SymbolTable::new_enclosed(Rc::clone(&global)));
+        first.define("c", span(2));
+        first.define("d", span(3));
+        let second = SymbolTable::new_enclosed(Rc::clone(&first));
+        second.define("e", span(4));
+        second.define("f", span(5));
+
+        assert_resolves(
+            &first,
+            &[
+                ("a", SymbolScope::Global, 0),
+                ("b", SymbolScope::Global, 1),
+                ("c", SymbolScope::Local, 0),
+                ("d", SymbolScope::Local, 1),
+            ],
+        );
+        assert_resolves(
+            &second,
+            &[
+                ("a", SymbolScope::Global, 0),
+                ("b", SymbolScope::Global, 1),
+                ("c", SymbolScope::Free, 0),
+                ("d", SymbolScope::Free, 1),
+                ("e", SymbolScope::Local, 0),
+                ("f", SymbolScope::Local, 1),
+            ],
+        );
+        assert_eq!(
+            free_names(&second),
+            [
+                ("c".to_string(), SymbolScope::Local, 0),
+                ("d".to_string(), SymbolScope::Local, 1),
+            ]
+        );
+        assert!(first.free_symbols().is_empty());
+    }
+
+    #[test]
+    fn test_resolve_free_from_grandparent() {
+        let global = Rc::new(SymbolTable::new());
+        global.define("g", span(0));
+        let outer = Rc::new(SymbolTable::new_enclosed(Rc::clone(&global)));
+        outer.define("a", span(1));
+        let middle = Rc::new(SymbolTable::new_enclosed(Rc::clone(&outer)));
+        middle.define("b", span(2));
+        let inner = SymbolTable::new_enclosed(Rc::clone(&middle));
+        inner.define("c", span(3));
+
+        // resolving twice must not capture twice
+        for _ in 0..2 {
+            assert_resolves(
+                &inner,
+                &[
+                    ("g", SymbolScope::Global, 0),
+                    ("a", SymbolScope::Free, 0),
+                    ("b", SymbolScope::Free, 1),
+                    ("c", SymbolScope::Local, 0),
+                ],
+            );
+        }
+        // the middle function captures `a` so it can pass it on
+        assert_eq!(
+            free_names(&middle),
+            [("a".to_string(), SymbolScope::Local, 0)]
+        );
+        assert_eq!(
+            free_names(&inner),
+            [
+                ("a".to_string(), SymbolScope::Free, 0),
+                ("b".to_string(), SymbolScope::Local, 0),
+            ]
+        );
+        assert!(outer.free_symbols().is_empty());
+    }
+
+    #[test]
+    fn test_sibling_scopes_capture_independently() {
+        let global = Rc::new(SymbolTable::new());
+        let outer = Rc::new(SymbolTable::new_enclosed(Rc::clone(&global)));
+        outer.define("a", span(0));
+        outer.define("b", span(1));
+        let left = SymbolTable::new_enclosed(Rc::clone(&outer));
+        let right = SymbolTable::new_enclosed(Rc::clone(&outer));
+
+        assert_resolves(&left, &[("b", SymbolScope::Free, 0)]);
+        assert_resolves(
+            &right,
+            &[("a", SymbolScope::Free, 0), ("b", SymbolScope::Free, 1)],
+        );
+        assert_resolves(
+            &left,
+            &[("b", SymbolScope::Free, 0), ("a", SymbolScope::Free, 1)],
+        );
+        assert_eq!(
+            free_names(&left),
+            [
+                ("b".to_string(), SymbolScope::Local, 1),
+                ("a".to_string(), SymbolScope::Local, 0),
+            ]
+        );
+        assert!(outer.free_symbols().is_empty());
+    }
+
+    #[test]
+    fn test_resolve_unresolvable() {
+        let global = Rc::new(SymbolTable::new());
+        global.define("a", span(0));
+        let outer = Rc::new(SymbolTable::new_enclosed(Rc::clone(&global)));
+        outer.define("c", span(1));
+        let inner = SymbolTable::new_enclosed(Rc::clone(&outer));
+        inner.define("e", span(2));
+
+        assert_resolves(
+            &inner,
+            &[
+                ("a", SymbolScope::Global, 0),
+                ("c", SymbolScope::Free, 0),
+                ("e", SymbolScope::Local, 0),
+            ],
+        );
+        assert_eq!(inner.resolve("b"), None);
+        assert_eq!(inner.resolve("d"), None);
+        assert_eq!(inner.free_symbols().len(), 1);
+    }
+
+    #[test]
+    fn test_block_scopes() {
+        let global = Rc::new(SymbolTable::new());
+        global.define("x", span(0));
+        let block = SymbolTable::new_block(Rc::clone(&global));
+        assert_resolves(&block, &[("x", SymbolScope::Global, 0)]);
+        block.define("x", span(1));
+        assert_resolves(&block, &[("x", SymbolScope::Global, 1)]);
+        let global = block.end_block().unwrap();
+        assert_resolves(&global, &[("x", SymbolScope::Global, 0)]);
+        assert_eq!(global.num_definitions(), 2);
+
+        let function = Rc::new(SymbolTable::new_enclosed(Rc::new(global)));
+        function.define("a", span(2));
+        let first = Rc::new(SymbolTable::new_block(Rc::clone(&function)));
+        first.define("b", span(3));
+        let nested = SymbolTable::new_block(Rc::clone(&first));
+        nested.define("c", span(4));
+        // blocks share their function's frame, so nothing is captured
+        assert_resolves(
+            &nested,
+            &[
+                ("a", SymbolScope::Local, 0),
+                ("b", SymbolScope::Local, 1),
+                ("c", SymbolScope::Local, 2),
+            ],
+        );
+        assert!(nested.free_symbols().is_empty());
+        let first = nested.end_block().unwrap().end_block().unwrap();
+        assert_eq!(first.resolve("b"), None);
+
+        let second = SymbolTable::new_block(Rc::new(first));
+        assert_eq!(second.define("d", span(5)).index, 1);
+        let function = second.end_block().unwrap();
+        assert_eq!(function.num_definitions(), 3);
+        assert_eq!(function.end_block().map(|_| ()), None);
+    }
+
+    #[test]
+    fn test_shadowed() {
+        let global = SymbolTable::new();
+        global.define_builtin(0, "len");
+        let x = global.define("x", Span { start: 4, end: 5 });
+        assert_eq!(global.shadowed("x"), None);
+        assert_eq!(global.shadowed("len").unwrap().scope, SymbolScope::Builtin);
+
+        let local = SymbolTable::new_enclosed(Rc::new(global));
+        assert_eq!(local.shadowed("x"), Some(x));
+        assert_eq!(local.shadowed("y"), None);
+        assert!(local.free_symbols().is_empty());
+
+        local.define("x", Span { start: 20, end: 21 });
+        assert_eq!(local.shadowed("x"), None);
+    }
 }
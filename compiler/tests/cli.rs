@@ -0,0 +1,747 @@
+use std::env;
+use std::io::{BufRead, BufReader, Write};
+use std::path::Path;
+use std::process::{Command, Output, Stdio};
+
+const BIN: &str = env!("CARGO_BIN_EXE_monkey-compiler");
+
+fn fixture(name: &str) -> String {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures")
+        .join(name)
+        .display()
+        .to_string()
+}
+
+fn assert_ran_shebang_fixture(output: Output) {
+    assert!(output.status.success(), "{:?}", output);
+    assert!(String::from_utf8_lossy(&output.stdout).starts_with("42\n"));
+    // the shebang line still counts when numbering lines
+    assert_eq!(
+        String::from_utf8_lossy(&output.stderr),
+        "warning: line 2: `len` shadows the builtin `len`\n"
+    );
+}
+
+#[test]
+fn test_script_with_shebang_runs() {
+    let output = Command::new(BIN)
+        .args(["run", &fixture("shebang.monkey")])
+        .output()
+        .unwrap();
+    assert_ran_shebang_fixture(output);
+}
+
+#[cfg(unix)]
+#[test]
+fn test_script_with_shebang_runs_directly() {
+    let bin_dir = Path::new(BIN).parent().unwrap();
+    let path = env::join_paths(
+        std::iter::once(bin_dir.to_path_buf())
+            .chain(env::split_paths(&env::var_os("PATH").unwrap_or_default())),
+    )
+    .unwrap();
+
+    let output = Command::new(fixture("shebang.monkey"))
+        .env("PATH", path)
+        .output()
+        .unwrap();
+    assert_ran_shebang_fixture(output);
+}
+
+#[test]
+fn test_env_builtin_reads_the_process_environment() {
+    let output = Command::new(BIN)
+        .args(["run", &fixture("env.monkey")])
+        .env("MONKEY_GREETING", "hello there")
+        .env_remove("MONKEY_UNSET")
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        // the trailing null is the value of the last `puts`
+        "hello there\nnull\nhello there\nnull\n"
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn test_env_builtin_converts_non_utf8_lossily() {
+    use std::ffi::OsStr;
+    use std::os::unix::ffi::OsStrExt;
+
+    let output = Command::new(BIN)
+        .args(["run", &fixture("env.monkey")])
+        .env("MONKEY_GREETING", OsStr::from_bytes(b"caf\xe9"))
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{:?}", output);
+    assert!(String::from_utf8_lossy(&output.stdout).starts_with("caf\u{fffd}\n"));
+}
+
+#[test]
+fn test_run_prints_the_last_value() {
+    let path = fixture("sum.monkey");
+    assert_eq!(run_args_fixture(&["run", &path]), "hello\n15\n");
+    assert_eq!(
+        run_args_fixture(&["run", "--interpret", &path]),
+        "hello\n15\n"
+    );
+    // `puts` output is kept, only the final value is dropped
+    assert_eq!(run_args_fixture(&["run", "--quiet", &path]), "hello\n");
+}
+
+#[test]
+fn test_run_prints_the_last_value_as_json() {
+    let path = fixture("json.monkey");
+    for mode in [&["run", "--json"][..], &["run", "--json", "--interpret"]] {
+        assert_eq!(
+            run_args_fixture(&[mode, &[path.as_str()]].concat()),
+            "hi\n{\"name\":\"monkey\",\"xs\":[1,2.5,true],\"3\":\"three\"}\n"
+        );
+    }
+
+    let output = Command::new(BIN)
+        .args(["run", "--json", &fixture("json_closure.monkey")])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(1), "{:?}", output);
+    assert_eq!(
+        String::from_utf8_lossy(&output.stderr),
+        "cannot print the result as JSON: cannot serialize a CLOSURE\n"
+    );
+}
+
+#[test]
+fn test_run_stops_quietly_when_stdout_closes() {
+    for engine in [&[][..], &["--interpret"]] {
+        let mut child = Command::new(BIN)
+            .arg("run")
+            .args(engine)
+            .arg(fixture("many_lines.monkey"))
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .unwrap();
+        let mut first = String::new();
+        BufReader::new(child.stdout.take().unwrap())
+            .read_line(&mut first)
+            .unwrap();
+        assert_eq!(first, "0\n");
+        // `head -1` closes the pipe the same way
+        let output = child.wait_with_output().unwrap();
+        assert_ne!(output.status.code(), Some(101), "{:?}", output);
+        assert_eq!(String::from_utf8_lossy(&output.stderr), "");
+    }
+}
+
+#[test]
+fn test_run_profiles_on_stderr() {
+    let output = Command::new(BIN)
+        .args(["run", "--profile", &fixture("sum.monkey")])
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        run_args_fixture(&["run", &fixture("sum.monkey")])
+    );
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.starts_with("opcode "), "{}", stderr);
+    assert!(stderr.contains("\nfunction "), "{}", stderr);
+    assert!(stderr.contains("\n<main> "), "{}", stderr);
+
+    let output = Command::new(BIN)
+        .args(["run", "--profile", "--interpret", &fixture("sum.monkey")])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(2), "{:?}", output);
+}
+
+#[test]
+fn test_run_fails_on_errors() {
+    for (name, stderr) in [
+        (
+            "parse_errors.monkey",
+            "parse error at 1:7: expected token =, got start: 6, end: 7, kind: b\n\
+             \x20  1 | let a b 1;\n\
+             \x20    |       ^\n\
+             parse error at 2:9: no prefix function for token start: 19, end: 20, kind: ;\n\
+             \x20  2 | let c = ;\n\
+             \x20    |         ^\n",
+        ),
+        (
+            "undefined.monkey",
+            "compile error at 2:5: undefined variable 'missing'\n\
+             \x20  2 | x + missing;\n\
+             \x20    |     ^^^^^^^\n",
+        ),
+        (
+            "runtime_error.monkey",
+            "VM error at 1:22: type error: cannot add INTEGER and STRING; convert the other operand with to_string first\n\
+             \x20  1 | let add = fn(a, b) { a + b };\n\
+             \x20    |                      ^^^^^\n\
+             in add (offset 0004)\n\
+             in main (offset 0016)\n",
+        ),
+        (
+            "assertion.monkey",
+            "VM error at 2:1: assertion failed: expected one element\n\
+             \x20  2 | assert(len([]) == 1, \"expected one element\");\n\
+             \x20    | ^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^\n\
+             in main (offset 0021)\n",
+        ),
+    ] {
+        let output = Command::new(BIN)
+            .args(["run", &fixture(name)])
+            .output()
+            .unwrap();
+        assert_eq!(output.status.code(), Some(1), "{}: {:?}", name, output);
+        assert_eq!(String::from_utf8_lossy(&output.stdout), "", "{}", name);
+        assert_eq!(String::from_utf8_lossy(&output.stderr), stderr, "{}", name);
+    }
+}
+
+fn run_args_fixture(args: &[&str]) -> String {
+    let output = Command::new(BIN).args(args).output().unwrap();
+    assert!(output.status.success(), "{:?}", output);
+    String::from_utf8(output.stdout).unwrap()
+}
+
+#[test]
+fn test_args_builtin_gets_arguments_after_double_dash() {
+    let path = fixture("args.monkey");
+    assert_eq!(
+        run_args_fixture(&["run", "--asi", &path, "--", "a", "--interpret", "c"]),
+        "3\n<a>\n<--interpret>\n<c>\nnull\n"
+    );
+    assert_eq!(
+        run_args_fixture(&["run", "--interpret", &path, "--", "b"]),
+        "1\n<b>\nnull\n"
+    );
+}
+
+#[test]
+fn test_args_builtin_without_arguments() {
+    let path = fixture("args.monkey");
+    assert_eq!(run_args_fixture(&["run", &path]), "0\nnull\n");
+    assert_eq!(run_args_fixture(&["run", &path, "--"]), "0\nnull\n");
+}
+
+#[test]
+fn test_args_builtin_keeps_spaces_and_unicode() {
+    let path = fixture("args.monkey");
+    assert_eq!(
+        run_args_fixture(&["run", &path, "--", "two words", "héllo wörld", "🐒"]),
+        "3\n<two words>\n<héllo wörld>\n<🐒>\nnull\n"
+    );
+}
+
+#[test]
+fn test_args_builtin_when_run_by_path() {
+    let path = fixture("args.monkey");
+    assert_eq!(
+        run_args_fixture(&[&path, "x", "--y"]),
+        "2\n<x>\n<--y>\nnull\n"
+    );
+}
+
+// Feeds `input` to the REPL line by line and returns everything it printed.
+fn repl(input: &str) -> String {
+    let mut child = Command::new(BIN)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(input.as_bytes())
+        .unwrap();
+    let output = child.wait_with_output().unwrap();
+    assert!(output.status.success(), "{:?}", output);
+    String::from_utf8(output.stdout).unwrap()
+}
+
+#[test]
+fn test_file_builtins_round_trip() {
+    let path = env::temp_dir().join(format!("monkey-cli-{}-files.txt", std::process::id()));
+    let path = path.display().to_string();
+    for mode in [&["run"][..], &["run", "--interpret"]] {
+        let output = Command::new(BIN)
+            .args(mode)
+            .args([&fixture("files.monkey"), "--", &path])
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "{:?}", output);
+        assert_eq!(
+            String::from_utf8_lossy(&output.stdout),
+            "17\nline one\nline two\n",
+            "{:?}",
+            mode
+        );
+    }
+    std::fs::remove_file(&path).unwrap();
+
+    let output = Command::new(BIN)
+        .args([
+            "run",
+            &fixture("files.monkey"),
+            "--",
+            "/nonexistent/dir/file",
+        ])
+        .output()
+        .unwrap();
+    assert!(
+        String::from_utf8_lossy(&output.stdout)
+            .starts_with("builtin write_file cannot write /nonexistent/dir/file: "),
+        "{:?}",
+        output
+    );
+}
+
+#[test]
+fn test_repl_has_no_file_access() {
+    assert!(
+        repl("read_file(\"Cargo.toml\")\n")
+            .contains("builtin read_file is unavailable: file access disabled"),
+    );
+}
+
+#[test]
+fn test_repl_reclaims_throwaway_constants() {
+    let mut input = String::from("let add = fn(a, b) { a + b };\n");
+    for i in 0..1000 {
+        input.push_str(&format!("\"throwaway {}\"\n", i));
+    }
+    input.push_str("add(40, 2)\n:mem\n");
+
+    let stdout = repl(&input);
+
+    assert!(stdout.contains(">> _1001 = 42\n"), "{}", stdout);
+    // the last input only saw its own `40` and `2`; the function, every
+    // string and then those two were reclaimed
+    assert!(stdout.contains("constants: 2 "), "{}", stdout);
+    assert!(stdout.contains("constants reclaimed: 1003\n"), "{}", stdout);
+}
+
+#[test]
+fn test_build_with_lines_interleaves_source() {
+    let output = Command::new(BIN)
+        .args(["build", "--with-lines", &fixture("closure.monkey")])
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{:?}", output);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains(
+            "\nmain:\n   1 | let make_adder = fn(x) {\n0000 OpClosure 2 0\n0004 OpSetGlobal 0\n"
+        ),
+        "{}",
+        stdout
+    );
+    assert!(
+        stdout.contains(
+            "\nfunction (constant 2):\n   2 |     fn(y) { if (y > 0) { x + y } else { x } }\n0000 OpGetLocal 0\n"
+        ),
+        "{}",
+        stdout
+    );
+}
+
+// Builds `fixture` into an artifact in the temp directory and returns its path.
+fn build_artifact(fixture_name: &str) -> std::path::PathBuf {
+    let artifact = env::temp_dir().join(format!(
+        "monkey-cli-{}-{}.mkc",
+        std::process::id(),
+        fixture_name
+    ));
+    let output = Command::new(BIN)
+        .args(["build", "--output"])
+        .arg(&artifact)
+        .arg(fixture(fixture_name))
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{:?}", output);
+    artifact
+}
+
+fn dasm(artifact: &Path) -> Output {
+    Command::new(BIN)
+        .arg("dasm")
+        .arg(artifact)
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn test_dasm_lists_closures() {
+    let artifact = build_artifact("closure.monkey");
+    let output = dasm(&artifact);
+    std::fs::remove_file(&artifact).unwrap();
+
+    assert!(output.status.success(), "{:?}", output);
+    let expected = std::fs::read_to_string(fixture("closure.dasm")).unwrap();
+    assert_eq!(String::from_utf8_lossy(&output.stdout), expected);
+}
+
+#[test]
+fn test_disassemble_lists_main_and_functions() {
+    let output = Command::new(BIN)
+        .arg("--disassemble")
+        .arg(fixture("branch.monkey"))
+        .output()
+        .unwrap();
+
+    assert!(output.status.success(), "{:?}", output);
+    let expected = std::fs::read_to_string(fixture("branch.dasm")).unwrap();
+    assert_eq!(String::from_utf8_lossy(&output.stdout), expected);
+}
+
+#[test]
+fn test_dasm_lists_string_constants() {
+    let artifact = build_artifact("env.monkey");
+    let output = dasm(&artifact);
+    std::fs::remove_file(&artifact).unwrap();
+
+    assert!(output.status.success(), "{:?}", output);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains("   1: string \"MONKEY_UNSET\"\n"),
+        "{}",
+        stdout
+    );
+    assert!(
+        stdout.contains("0016 OpConst 1 ; \"MONKEY_UNSET\"\n"),
+        "{}",
+        stdout
+    );
+    assert!(
+        stdout.contains("0014 OpGetBuiltin 13 ; env\n"),
+        "{}",
+        stdout
+    );
+}
+
+fn exec(artifact: &Path) -> Output {
+    Command::new(BIN)
+        .arg("exec")
+        .arg(artifact)
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn test_exec_runs_an_artifact() {
+    let artifact = build_artifact("sum.monkey");
+    let output = exec(&artifact);
+    std::fs::remove_file(&artifact).unwrap();
+
+    let expected = Command::new(BIN)
+        .args(["run", &fixture("sum.monkey")])
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(output.stdout, expected.stdout);
+}
+
+#[test]
+fn test_exec_reports_runtime_errors_at_the_original_line() {
+    let artifact = build_artifact("runtime_error.monkey");
+    let output = exec(&artifact);
+    std::fs::remove_file(&artifact).unwrap();
+
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(
+        String::from_utf8_lossy(&output.stderr),
+        "VM error at 1:22: type error: cannot add INTEGER and STRING; convert the other operand with to_string first\n\
+         \x20  1 | let add = fn(a, b) { a + b };\n\
+         \x20    |                      ^^^^^\n\
+         in add (offset 0004)\n\
+         in main (offset 0016)\n"
+    );
+}
+
+#[test]
+fn test_exec_verifies_the_artifact() {
+    use compiler::artifact::encode;
+    use compiler::compiler::Bytecode;
+    use compiler::op_code::{Opcode, make_instructions};
+
+    // loads a constant the artifact doesn't have
+    let bytecode = Bytecode {
+        instructions: make_instructions(Opcode::OpConst, &[5]),
+        constants: vec![],
+        source_map: Default::default(),
+        host_builtins: Default::default(),
+        num_globals: 0,
+    };
+    let artifact =
+        env::temp_dir().join(format!("monkey-cli-{}-unverified.mkc", std::process::id()));
+    std::fs::write(&artifact, encode(&bytecode, "").unwrap()).unwrap();
+    let output = exec(&artifact);
+    std::fs::remove_file(&artifact).unwrap();
+
+    assert_eq!(output.status.code(), Some(1));
+    assert!(output.stdout.is_empty());
+    assert!(
+        String::from_utf8_lossy(&output.stderr).ends_with(
+            "-unverified.mkc: main program at 0000: constant index 5 is out of range for 0 constant(s)\n"
+        ),
+        "{:?}",
+        output
+    );
+}
+
+#[test]
+fn test_exec_without_verifying() {
+    let artifact = build_artifact("sum.monkey");
+    let output = Command::new(BIN)
+        .args(["exec", "--no-verify"])
+        .arg(&artifact)
+        .output()
+        .unwrap();
+    std::fs::remove_file(&artifact).unwrap();
+
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "hello\n15\n");
+}
+
+#[test]
+fn test_dasm_reports_corrupted_sections() {
+    let artifact = build_artifact("closure.monkey");
+    let bytes = std::fs::read(&artifact).unwrap();
+    std::fs::write(&artifact, &bytes[..30]).unwrap();
+    let truncated = dasm(&artifact);
+    std::fs::write(&artifact, b"#!/bin/sh\n").unwrap();
+    let not_an_artifact = dasm(&artifact);
+    std::fs::remove_file(&artifact).unwrap();
+
+    assert_eq!(truncated.status.code(), Some(1));
+    assert!(truncated.stdout.is_empty());
+    assert!(
+        String::from_utf8_lossy(&truncated.stderr)
+            .ends_with(".mkc: constant 1: unexpected end of file\n"),
+        "{:?}",
+        truncated
+    );
+    assert_eq!(not_an_artifact.status.code(), Some(1));
+    assert!(
+        String::from_utf8_lossy(&not_an_artifact.stderr)
+            .ends_with(".mkc: header: not a compiled monkey program\n"),
+        "{:?}",
+        not_an_artifact
+    );
+}
+
+#[test]
+fn test_repl_result_history() {
+    let stdout = repl("40\n2\n_1 + _2\n_\nlet x = 5;\nx * 2\n_ + _4\nputs(\"hi\")\n_\n:reset\n_\n");
+    assert_eq!(
+        stdout,
+        ">> _1 = 40\n\
+         >> _2 = 2\n\
+         >> _3 = 42\n\
+         >> _4 = 42\n\
+         >> null\n\
+         >> _5 = 10\n\
+         >> _6 = 52\n\
+         >> hi\nnull\n\
+         >> _7 = 52\n\
+         >> session reset\n\
+         >> compile error at 1:1: undefined variable '_'\n   \
+         1 | _\n     \
+         | ^\n\
+         >> "
+    );
+}
+
+#[test]
+fn test_repl_result_history_skips_builtin_errors() {
+    let error = "builtin len expected argument 1 to be STRING, ARRAY, BYTES or HASH, got INTEGER";
+    for mode in ["compile", "interpret"] {
+        // `_` still holds the 1, so the error never got a name
+        let stdout = repl(&format!(":mode {}\n1\nlen(1)\n_\n", mode));
+        assert!(
+            stdout.ends_with(&format!(">> _1 = 1\n>> {}\n>> _2 = 1\n>> ", error)),
+            "{}",
+            stdout
+        );
+    }
+}
+
+#[test]
+fn test_repl_result_history_when_interpreting() {
+    let stdout = repl(":mode interpret\n\"a\"\n_ + \"b\"\n_1 + _2\n");
+    assert_eq!(
+        stdout,
+        ">> mode: Interpret\n>> _1 = a\n>> _2 = ab\n>> _3 = aab\n>> "
+    );
+}
+
+#[test]
+fn test_repl_set_print_limit() {
+    let stdout = repl(
+        ":set\n:set print_limit 3\nnew_array(100000, 7)\nputs(new_array(5, 1))\n:set print_limit x\n:set width 3\n:reset\nnew_array(4, 0)\n",
+    );
+    assert_eq!(
+        stdout,
+        ">> print_limit 100\n\
+         >> print_limit 3\n\
+         >> _1 = [7, 7, 7, ... (99997 more)]\n\
+         >> [1, 1, 1, 1, 1]\n\
+         null\n\
+         >> print_limit must be a whole number, got 'x'\n\
+         >> usage: :set print_limit <n>\n\
+         >> session reset\n\
+         >> _1 = [0, 0, 0, ... (1 more)]\n\
+         >> "
+    );
+}
+
+#[test]
+fn test_repl_underlines_out_of_range_integers() {
+    let stdout = repl("1 + 99999999999999999999\n-9223372036854775808\n");
+    assert_eq!(
+        stdout,
+        ">> parse error at 1:5: integer literal out of range for 64-bit integer\n   \
+         1 | 1 + 99999999999999999999\n     \
+         |     ^^^^^^^^^^^^^^^^^^^^\n\
+         >> _1 = -9223372036854775808\n\
+         >> "
+    );
+}
+
+#[test]
+fn test_repl_floats() {
+    let stdout = repl("let pi = 3.14159; pi * 2\n1 + 2.5 > 3\n1.2.3\n");
+    assert_eq!(
+        stdout,
+        ">> _1 = 6.28318\n\
+         >> _2 = true\n\
+         >> parse error at 1:1: invalid number literal 1.2.3: a number has at most one decimal point\n   \
+         1 | 1.2.3\n     \
+         | ^^^^^\n\
+         >> "
+    );
+}
+
+#[test]
+fn test_repl_ast_command() {
+    let stdout = repl(":ast !x\n:ast 1 +\n");
+    let (ast, error) = stdout
+        .strip_prefix(">> ")
+        .and_then(|rest| rest.split_once("\n>> "))
+        .unwrap();
+    assert!(ast.starts_with("{\n  \"Program\": {"), "{}", stdout);
+    assert!(ast.contains("\"type\": \"BANG\""), "{}", stdout);
+    assert!(ast.contains("\"name\": \"x\""), "{}", stdout);
+    assert_eq!(
+        error,
+        "parse error at 1:4: no prefix function for token start: 3, end: 3, kind: EOF\n   \
+         1 | 1 +\n     \
+         |    ^\n\
+         >> "
+    );
+}
+
+#[test]
+fn test_repl_bytecode_command() {
+    let stdout = repl("let x = 1\n:bytecode x + 41\n:bytecode let y = 2\ny\n:bytecode z\n");
+    assert_eq!(
+        stdout,
+        ">> null\n\
+         >> 0000 OpGetGlobal 0\n\
+         0003 OpConst 0\n\
+         0006 OpAdd\n\
+         0007 OpPop\n\
+         constant 0: 41\n\
+         >> 0000 OpConst 0\n\
+         0003 OpSetGlobal 1\n\
+         constant 0: 2\n\
+         >> compile error at 1:1: undefined variable 'y'\n   \
+         1 | y\n     \
+         | ^\n\
+         >> compile error at 1:1: undefined variable 'z'\n   \
+         1 | z\n     \
+         | ^\n\
+         >> "
+    );
+}
+
+#[test]
+fn test_repl_symbols_command() {
+    let stdout =
+        repl(":symbols\nlet x = 1\nlet f = fn() { x }\n3\n:symbols\n:mode interpret\n:symbols\n");
+    assert_eq!(
+        stdout,
+        ">> >> null\n\
+         >> null\n\
+         >> _1 = 3\n\
+         >> Global 0 x\n\
+         Global 1 f\n\
+         Global 2 _1\n\
+         Global 3 _\n\
+         >> mode: Interpret\n\
+         >> symbols are only available in compile mode\n\
+         >> "
+    );
+}
+
+#[test]
+fn test_repl_quit_and_unknown_commands() {
+    let stdout = repl(":frobnicate\n:quit\n1 + 1\n");
+    assert!(
+        stdout.starts_with(">> unknown command :frobnicate\ncommands:\n"),
+        "{}",
+        stdout
+    );
+    assert!(stdout.contains("\n  :ast <code>"), "{}", stdout);
+    assert!(stdout.contains("\n  :quit "), "{}", stdout);
+    // nothing after `:quit` runs
+    assert!(stdout.ends_with("(so does Ctrl-D)\n>> "), "{}", stdout);
+}
+
+#[test]
+fn test_repl_reports_every_error_with_its_position() {
+    let stdout = repl("let a b 1; let = 2\nlet x = 1; x + missing\n");
+    assert_eq!(
+        stdout,
+        ">> parse error at 1:7: expected token =, got start: 6, end: 7, kind: b\n   \
+         1 | let a b 1; let = 2\n     \
+         |       ^\n\
+         parse error at 1:16: expected identifier, got start: 15, end: 16, kind: =\n   \
+         1 | let a b 1; let = 2\n     \
+         |                ^\n\
+         >> compile error at 1:16: undefined variable 'missing'\n   \
+         1 | let x = 1; x + missing\n     \
+         |                ^^^^^^^\n\
+         >> "
+    );
+}
+
+#[test]
+fn test_repl_forgets_definitions_from_inputs_that_fail_to_compile() {
+    let stdout = repl("let x = undefinedVar;\nx\nlet f = fn() { let y = 1; nope }\ny\nf\n");
+    assert_eq!(
+        stdout,
+        ">> compile error at 1:9: undefined variable 'undefinedVar'\n   \
+         1 | let x = undefinedVar;\n     \
+         |         ^^^^^^^^^^^^\n\
+         >> compile error at 1:1: undefined variable 'x'\n   \
+         1 | x\n     \
+         | ^\n\
+         >> compile error at 1:27: undefined variable 'nope'\n   \
+         1 | let f = fn() { let y = 1; nope }\n     \
+         |                           ^^^^\n\
+         >> compile error at 1:1: undefined variable 'y'\n   \
+         1 | y\n     \
+         | ^\n\
+         >> compile error at 1:1: undefined variable 'f'\n   \
+         1 | f\n     \
+         | ^\n\
+         >> "
+    );
+}
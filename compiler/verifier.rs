@@ -0,0 +1,397 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::{self, Display, Formatter};
+use std::rc::Rc;
+
+use object::builtins::BuiltIns;
+use object::{CompiledFunction, Object};
+
+use crate::compiler::Bytecode;
+use crate::op_code::{OpCodeError, Opcode, cast_u8_to_opcode, definitions, read_operands};
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Location {
+    Main {
+        offset: usize,
+    },
+    /// A compiled function stored in the constant pool at `constant`.
+    Function {
+        constant: usize,
+        offset: usize,
+    },
+}
+
+impl Display for Location {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Location::Main { offset } => write!(f, "main program at {:04}", offset),
+            Location::Function { constant, offset } => {
+                write!(f, "function (constant {}) at {:04}", constant, offset)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum VerifyErrorKind {
+    Opcode(OpCodeError),
+    JumpOutOfBounds { target: usize, len: usize },
+    JumpIntoInstruction { target: usize },
+    ConstantOutOfRange { index: usize, len: usize },
+    NotAFunction { index: usize },
+    BuiltinOutOfRange { index: usize, len: usize },
+    LocalOutOfRange { index: usize, num_locals: usize },
+    OddHashOperand(usize),
+    StackUnderflow { needed: usize, depth: usize },
+    InconsistentStackDepth { expected: usize, got: usize },
+    MissingReturn,
+}
+
+impl Display for VerifyErrorKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            VerifyErrorKind::Opcode(err) => write!(f, "{}", err),
+            VerifyErrorKind::JumpOutOfBounds { target, len } => write!(
+                f,
+                "jump target {} is outside the {} byte(s) of instructions",
+                target, len
+            ),
+            VerifyErrorKind::JumpIntoInstruction { target } => {
+                write!(f, "jump target {} is not an instruction boundary", target)
+            }
+            VerifyErrorKind::ConstantOutOfRange { index, len } => write!(
+                f,
+                "constant index {} is out of range for {} constant(s)",
+                index, len
+            ),
+            VerifyErrorKind::NotAFunction { index } => {
+                write!(f, "constant {} is not a compiled function", index)
+            }
+            VerifyErrorKind::BuiltinOutOfRange { index, len } => write!(
+                f,
+                "builtin index {} is out of range for {} builtin(s)",
+                index, len
+            ),
+            VerifyErrorKind::LocalOutOfRange { index, num_locals } => write!(
+                f,
+                "local index {} is out of range for {} local(s)",
+                index, num_locals
+            ),
+            VerifyErrorKind::OddHashOperand(count) => {
+                write!(f, "hash operand must be even, got {}", count)
+            }
+            VerifyErrorKind::StackUnderflow { needed, depth } => write!(
+                f,
+                "stack underflow: needs {} value(s), only {} on the stack",
+                needed, depth
+            ),
+            VerifyErrorKind::InconsistentStackDepth { expected, got } => write!(
+                f,
+                "stack depth {} differs from {} on another path here",
+                got, expected
+            ),
+            VerifyErrorKind::MissingReturn => write!(f, "function ends without returning"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct VerifyError {
+    pub location: Location,
+    pub kind: VerifyErrorKind,
+}
+
+impl Display for VerifyError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.location, self.kind)
+    }
+}
+
+/// Checks that `bytecode` is safe for the VM to run: every instruction
+/// decodes, every operand indexes something that exists, every jump lands on
+/// an instruction in the same function, and no path pops more values than it
+/// pushed. The main program and each compiled function in the constant pool
+/// are checked separately.
+pub fn verify(bytecode: &Bytecode) -> Result<(), Vec<VerifyError>> {
+    let constants = &bytecode.constants;
+    let builtins = BuiltIns.len() + bytecode.host_builtins.len();
+    let mut errors = Vec::new();
+    FunctionVerifier {
+        instructions: &bytecode.instructions.bytes,
+        num_locals: 0,
+        constant: None,
+        constants,
+        builtins,
+        errors: &mut errors,
+    }
+    .verify();
+    for (index, constant) in constants.iter().enumerate() {
+        if let Object::CompiledFunction(function) = &**constant {
+            let CompiledFunction {
+                instructions,
+                num_locals,
+                ..
+            } = &**function;
+            FunctionVerifier {
+                instructions,
+                num_locals: *num_locals,
+                constant: Some(index),
+                constants,
+                builtins,
+                errors: &mut errors,
+            }
+            .verify();
+        }
+    }
+
+    match errors.is_empty() {
+        true => Ok(()),
+        false => Err(errors),
+    }
+}
+
+struct Instruction {
+    opcode: Opcode,
+    operands: Vec<usize>,
+    next: usize,
+}
+
+struct FunctionVerifier<'a> {
+    instructions: &'a [u8],
+    num_locals: usize,
+    /// `None` for the main program.
+    constant: Option<usize>,
+    constants: &'a [Rc<Object>],
+    /// How many builtins `OpGetBuiltin` can index, registered ones included.
+    builtins: usize,
+    errors: &'a mut Vec<VerifyError>,
+}
+
+impl FunctionVerifier<'_> {
+    fn verify(mut self) {
+        let Some(decoded) = self.decode() else {
+            return;
+        };
+        for (&offset, instruction) in &decoded {
+            self.check_operands(&decoded, offset, instruction);
+        }
+        self.check_stack(&decoded);
+    }
+
+    fn error(&mut self, offset: usize, kind: VerifyErrorKind) {
+        let location = match self.constant {
+            None => Location::Main { offset },
+            Some(constant) => Location::Function { constant, offset },
+        };
+        self.errors.push(VerifyError { location, kind });
+    }
+
+    // Without instruction boundaries nothing else can be checked, so decoding
+    // stops at the first bad instruction.
+    fn decode(&mut self) -> Option<BTreeMap<usize, Instruction>> {
+        let mut decoded = BTreeMap::new();
+        let mut offset = 0;
+        while offset < self.instructions.len() {
+            let instruction = cast_u8_to_opcode(self.instructions[offset]).and_then(|opcode| {
+                let def = definitions()
+                    .get(&opcode)
+                    .ok_or(OpCodeError::MissingDefinition(opcode))?;
+                let (operands, read) = read_operands(def, &self.instructions[offset + 1..])?;
+                Ok(Instruction {
+                    opcode,
+                    operands,
+                    next: offset + 1 + read,
+                })
+            });
+            match instruction {
+                Ok(instruction) => {
+                    let next = instruction.next;
+                    decoded.insert(offset, instruction);
+                    offset = next;
+                }
+                Err(OpCodeError::InvalidOpcodeByte { byte, .. }) => {
+                    let err = OpCodeError::InvalidOpcodeByte {
+                        byte,
+                        position: Some(offset),
+                    };
+                    self.error(offset, VerifyErrorKind::Opcode(err));
+                    return None;
+                }
+                Err(err) => {
+                    self.error(offset, VerifyErrorKind::Opcode(err));
+                    return None;
+                }
+            }
+        }
+        Some(decoded)
+    }
+
+    fn check_operands(
+        &mut self,
+        decoded: &BTreeMap<usize, Instruction>,
+        offset: usize,
+        instruction: &Instruction,
+    ) {
+        let operand = instruction.operands.first().copied().unwrap_or_default();
+        match instruction.opcode {
+            Opcode::OpJump | Opcode::OpJumpNotTruthy => {
+                let len = self.instructions.len();
+                if operand > len {
+                    self.error(
+                        offset,
+                        VerifyErrorKind::JumpOutOfBounds {
+                            target: operand,
+                            len,
+                        },
+                    );
+                } else if operand < len && !decoded.contains_key(&operand) {
+                    self.error(
+                        offset,
+                        VerifyErrorKind::JumpIntoInstruction { target: operand },
+                    );
+                }
+            }
+            Opcode::OpConst | Opcode::OpClosure => {
+                let len = self.constants.len();
+                match self.constants.get(operand) {
+                    None => self.error(
+                        offset,
+                        VerifyErrorKind::ConstantOutOfRange {
+                            index: operand,
+                            len,
+                        },
+                    ),
+                    Some(constant)
+                        if instruction.opcode == Opcode::OpClosure
+                            && !matches!(**constant, Object::CompiledFunction(_)) =>
+                    {
+                        self.error(offset, VerifyErrorKind::NotAFunction { index: operand })
+                    }
+                    Some(_) => {}
+                }
+            }
+            Opcode::OpGetBuiltin if operand >= self.builtins => self.error(
+                offset,
+                VerifyErrorKind::BuiltinOutOfRange {
+                    index: operand,
+                    len: self.builtins,
+                },
+            ),
+            Opcode::OpGetLocal | Opcode::OpSetLocal | Opcode::OpTakeLocal
+                if operand >= self.num_locals =>
+            {
+                self.error(
+                    offset,
+                    VerifyErrorKind::LocalOutOfRange {
+                        index: operand,
+                        num_locals: self.num_locals,
+                    },
+                )
+            }
+            Opcode::OpHash if operand % 2 != 0 => {
+                self.error(offset, VerifyErrorKind::OddHashOperand(operand))
+            }
+            _ => {}
+        }
+    }
+
+    // Follows every path from the entry point, tracking how many values are
+    // on the stack. Paths that meet must agree on the depth.
+    fn check_stack(&mut self, decoded: &BTreeMap<usize, Instruction>) {
+        let len = self.instructions.len();
+        let mut depths: HashMap<usize, usize> = HashMap::new();
+        let mut pending = vec![(0, 0)];
+        while let Some((offset, depth)) = pending.pop() {
+            if let Some(&expected) = depths.get(&offset) {
+                if expected != depth {
+                    self.error(
+                        offset,
+                        VerifyErrorKind::InconsistentStackDepth {
+                            expected,
+                            got: depth,
+                        },
+                    );
+                }
+                continue;
+            }
+            depths.insert(offset, depth);
+
+            let Some(instruction) = decoded.get(&offset) else {
+                // running off the end only finishes the main program
+                if offset == len && self.constant.is_some() {
+                    self.error(offset, VerifyErrorKind::MissingReturn);
+                }
+                continue;
+            };
+            let (pops, pushes) = stack_effect(instruction);
+            if depth < pops {
+                self.error(
+                    offset,
+                    VerifyErrorKind::StackUnderflow {
+                        needed: pops,
+                        depth,
+                    },
+                );
+                continue;
+            }
+            let depth = depth - pops + pushes;
+
+            let target = instruction.operands.first().copied().unwrap_or_default();
+            match instruction.opcode {
+                Opcode::OpReturn | Opcode::OpReturnValue => {}
+                // bad targets were already reported
+                Opcode::OpJump if target <= len => pending.push((target, depth)),
+                Opcode::OpJumpNotTruthy if target <= len => {
+                    pending.push((target, depth));
+                    pending.push((instruction.next, depth));
+                }
+                Opcode::OpJump | Opcode::OpJumpNotTruthy => {}
+                _ => pending.push((instruction.next, depth)),
+            }
+        }
+    }
+}
+
+/// How many values `instruction` pops and then pushes.
+fn stack_effect(instruction: &Instruction) -> (usize, usize) {
+    let operand = instruction.operands.first().copied().unwrap_or_default();
+    match instruction.opcode {
+        Opcode::OpConst
+        | Opcode::OpTrue
+        | Opcode::OpFalse
+        | Opcode::OpNull
+        | Opcode::OpGetGlobal
+        | Opcode::OpTakeGlobal
+        | Opcode::OpGetLocal
+        | Opcode::OpTakeLocal
+        | Opcode::OpGetBuiltin
+        | Opcode::OpGetFree
+        | Opcode::OpCurrentClosure => (0, 1),
+        Opcode::OpAdd
+        | Opcode::OpSub
+        | Opcode::OpMul
+        | Opcode::OpDiv
+        | Opcode::OpFloorDiv
+        | Opcode::OpModulo
+        | Opcode::OpEqual
+        | Opcode::OpNotEqual
+        | Opcode::OpGreaterThan
+        | Opcode::OpGreaterOrEqual
+        | Opcode::OpLessThan
+        | Opcode::OpLessOrEqual
+        | Opcode::OpIndex => (2, 1),
+        Opcode::OpSetIndex => (3, 1),
+        Opcode::OpIter => (1, 2),
+        Opcode::OpMinus | Opcode::OpBang => (1, 1),
+        Opcode::OpPop
+        | Opcode::OpSetGlobal
+        | Opcode::OpSetLocal
+        | Opcode::OpJumpNotTruthy
+        | Opcode::OpReturnValue => (1, 0),
+        Opcode::OpJump | Opcode::OpReturn => (0, 0),
+        Opcode::OpArray | Opcode::OpHash => (operand, 1),
+        // the callee sits below its arguments
+        // a tail call to another function returns here like any call
+        Opcode::OpCall | Opcode::OpTailCall => (operand + 1, 1),
+        Opcode::OpClosure => (instruction.operands[1], 1),
+    }
+}
@@ -0,0 +1,340 @@
+#[cfg(test)]
+mod tests {
+    use crate::compiler::{Bytecode, Compiler};
+    use crate::op_code::{
+        Instructions, OpCodeError, Opcode::*, concat_instructions, make_instructions,
+    };
+    use crate::verifier::{Location, VerifyError, VerifyErrorKind, verify};
+    use object::{CompiledFunction, Object};
+    use parser::parse;
+    use std::rc::Rc;
+
+    fn main_program(instructions: Vec<Instructions>, constants: Vec<Object>) -> Bytecode {
+        Bytecode {
+            instructions: concat_instructions(instructions),
+            constants: constants.into_iter().map(Rc::new).collect(),
+            source_map: Default::default(),
+            host_builtins: Default::default(),
+            num_globals: 0,
+        }
+    }
+
+    fn function(instructions: Vec<Instructions>, num_locals: usize) -> Object {
+        Object::CompiledFunction(Rc::new(CompiledFunction {
+            instructions: concat_instructions(instructions).bytes,
+            num_locals,
+            num_parameters: 0,
+            name: String::new(),
+        }))
+    }
+
+    fn at_main(offset: usize, kind: VerifyErrorKind) -> VerifyError {
+        VerifyError {
+            location: Location::Main { offset },
+            kind,
+        }
+    }
+
+    fn assert_rejected(bytecode: Bytecode, expected: Vec<VerifyError>) {
+        assert_eq!(verify(&bytecode), Err(expected));
+    }
+
+    #[test]
+    fn test_compiled_programs_pass() {
+        let inputs = [
+            "1 + 2; 3 * 4",
+            "let x = if (1 > 2) { 10 } else { 20 }; x",
+            "if (true) { 1 }; [1, 2][0]; {\"a\": 1}[\"a\"]",
+            "let x = 0; while (x < 5) { x = x + 1; }; x",
+            "let fib = fn(n) { if (n < 2) { return n; } fib(n - 1) + fib(n - 2) }; fib(10)",
+            "let count = fn(n) { if (n == 0) { 0 } else { count(n - 1) } }; count(10)",
+            "let adder = fn(a) { fn(b) { a + b } }; adder(1)(2)",
+            "let f = fn() { }; f(); len(\"abc\"); puts(1)",
+            "return 5; 6",
+        ];
+        for input in inputs {
+            let program = parse(input).unwrap();
+            let bytecode = Compiler::new().compile(&program).unwrap();
+            assert_eq!(verify(&bytecode), Ok(()), "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_invalid_opcode_byte() {
+        let mut bytecode = main_program(vec![make_instructions(OpTrue, &[])], vec![]);
+        bytecode.instructions.bytes.push(0xff);
+        assert_rejected(
+            bytecode,
+            vec![at_main(
+                1,
+                VerifyErrorKind::Opcode(OpCodeError::InvalidOpcodeByte {
+                    byte: 0xff,
+                    position: Some(1),
+                }),
+            )],
+        );
+    }
+
+    #[test]
+    fn test_truncated_operand() {
+        let mut bytecode = main_program(vec![make_instructions(OpConst, &[0])], vec![]);
+        bytecode.instructions.bytes.pop();
+        assert_rejected(
+            bytecode,
+            vec![at_main(
+                0,
+                VerifyErrorKind::Opcode(OpCodeError::TruncatedOperands {
+                    expected: 2,
+                    available: 1,
+                }),
+            )],
+        );
+    }
+
+    #[test]
+    fn test_jump_into_instruction() {
+        let bytecode = main_program(
+            vec![
+                make_instructions(OpJump, &[4]),
+                make_instructions(OpConst, &[0]),
+                make_instructions(OpPop, &[]),
+            ],
+            vec![Object::Integer(1)],
+        );
+        assert_rejected(
+            bytecode,
+            vec![at_main(
+                0,
+                VerifyErrorKind::JumpIntoInstruction { target: 4 },
+            )],
+        );
+    }
+
+    #[test]
+    fn test_jump_out_of_bounds() {
+        let bytecode = main_program(
+            vec![
+                make_instructions(OpTrue, &[]),
+                make_instructions(OpJumpNotTruthy, &[100]),
+            ],
+            vec![],
+        );
+        assert_rejected(
+            bytecode,
+            vec![at_main(
+                1,
+                VerifyErrorKind::JumpOutOfBounds {
+                    target: 100,
+                    len: 4,
+                },
+            )],
+        );
+    }
+
+    #[test]
+    fn test_constant_out_of_range() {
+        let bytecode = main_program(
+            vec![
+                make_instructions(OpConst, &[1]),
+                make_instructions(OpPop, &[]),
+            ],
+            vec![Object::Integer(1)],
+        );
+        assert_rejected(
+            bytecode,
+            vec![at_main(
+                0,
+                VerifyErrorKind::ConstantOutOfRange { index: 1, len: 1 },
+            )],
+        );
+    }
+
+    #[test]
+    fn test_closure_over_non_function() {
+        let bytecode = main_program(
+            vec![
+                make_instructions(OpClosure, &[0, 0]),
+                make_instructions(OpPop, &[]),
+            ],
+            vec![Object::Integer(1)],
+        );
+        assert_rejected(
+            bytecode,
+            vec![at_main(0, VerifyErrorKind::NotAFunction { index: 0 })],
+        );
+    }
+
+    #[test]
+    fn test_builtin_out_of_range() {
+        let bytecode = main_program(
+            vec![
+                make_instructions(OpGetBuiltin, &[200]),
+                make_instructions(OpPop, &[]),
+            ],
+            vec![],
+        );
+        let len = object::builtins::BuiltIns.len();
+        assert_rejected(
+            bytecode,
+            vec![at_main(
+                0,
+                VerifyErrorKind::BuiltinOutOfRange { index: 200, len },
+            )],
+        );
+    }
+
+    #[test]
+    fn test_local_out_of_range() {
+        let inner = function(
+            vec![
+                make_instructions(OpGetLocal, &[0]),
+                make_instructions(OpSetLocal, &[1]),
+                make_instructions(OpReturn, &[]),
+            ],
+            1,
+        );
+        let bytecode = main_program(vec![make_instructions(OpGetLocal, &[0])], vec![inner]);
+        assert_rejected(
+            bytecode,
+            vec![
+                at_main(
+                    0,
+                    VerifyErrorKind::LocalOutOfRange {
+                        index: 0,
+                        num_locals: 0,
+                    },
+                ),
+                VerifyError {
+                    location: Location::Function {
+                        constant: 0,
+                        offset: 2,
+                    },
+                    kind: VerifyErrorKind::LocalOutOfRange {
+                        index: 1,
+                        num_locals: 1,
+                    },
+                },
+            ],
+        );
+    }
+
+    #[test]
+    fn test_odd_hash_operand() {
+        let bytecode = main_program(
+            vec![
+                make_instructions(OpTrue, &[]),
+                make_instructions(OpHash, &[1]),
+            ],
+            vec![],
+        );
+        assert_rejected(
+            bytecode,
+            vec![at_main(1, VerifyErrorKind::OddHashOperand(1))],
+        );
+    }
+
+    #[test]
+    fn test_stack_underflow() {
+        let bytecode = main_program(
+            vec![
+                make_instructions(OpTrue, &[]),
+                make_instructions(OpAdd, &[]),
+            ],
+            vec![],
+        );
+        assert_rejected(
+            bytecode,
+            vec![at_main(
+                1,
+                VerifyErrorKind::StackUnderflow {
+                    needed: 2,
+                    depth: 1,
+                },
+            )],
+        );
+    }
+
+    #[test]
+    fn test_stack_underflow_on_one_branch() {
+        // 0000 OpTrue
+        // 0001 OpJumpNotTruthy 8
+        // 0004 OpTrue
+        // 0005 OpJump 9
+        // 0008 OpPop
+        let bytecode = main_program(
+            vec![
+                make_instructions(OpTrue, &[]),
+                make_instructions(OpJumpNotTruthy, &[8]),
+                make_instructions(OpTrue, &[]),
+                make_instructions(OpJump, &[9]),
+                make_instructions(OpPop, &[]),
+            ],
+            vec![],
+        );
+        assert_rejected(
+            bytecode,
+            vec![at_main(
+                8,
+                VerifyErrorKind::StackUnderflow {
+                    needed: 1,
+                    depth: 0,
+                },
+            )],
+        );
+    }
+
+    #[test]
+    fn test_inconsistent_stack_depth() {
+        // 0000 OpTrue
+        // 0001 OpJumpNotTruthy 5
+        // 0004 OpTrue
+        // 0005 OpNull
+        let bytecode = main_program(
+            vec![
+                make_instructions(OpTrue, &[]),
+                make_instructions(OpJumpNotTruthy, &[5]),
+                make_instructions(OpTrue, &[]),
+                make_instructions(OpNull, &[]),
+            ],
+            vec![],
+        );
+        let errors = verify(&bytecode).unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].location, Location::Main { offset: 5 });
+        assert!(matches!(
+            errors[0].kind,
+            VerifyErrorKind::InconsistentStackDepth { .. }
+        ));
+    }
+
+    #[test]
+    fn test_function_missing_return() {
+        let inner = function(vec![make_instructions(OpTrue, &[])], 0);
+        let bytecode = main_program(vec![], vec![inner]);
+        assert_rejected(
+            bytecode,
+            vec![VerifyError {
+                location: Location::Function {
+                    constant: 0,
+                    offset: 1,
+                },
+                kind: VerifyErrorKind::MissingReturn,
+            }],
+        );
+    }
+
+    #[test]
+    fn test_error_display() {
+        let error = VerifyError {
+            location: Location::Function {
+                constant: 3,
+                offset: 7,
+            },
+            kind: VerifyErrorKind::JumpIntoInstruction { target: 9 },
+        };
+        assert_eq!(
+            error.to_string(),
+            "function (constant 3) at 0007: jump target 9 is not an instruction boundary"
+        );
+    }
+}
@@ -1,23 +1,50 @@
-use std::collections::HashMap;
+use std::collections::HashSet;
 use std::fmt;
+#[cfg(feature = "trace")]
+use std::io::{self, Write};
+use std::mem::size_of;
 use std::rc::Rc;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
 
 use byteorder::{BigEndian, ByteOrder};
 use object::builtins::BuiltIns;
+use object::convert::ConversionError;
 
-use object::{Closure, HashKey, Object};
+use object::ordered_map::OrderedMap;
+use object::random::Random;
+use object::{
+    BuiltinFunc, Closure, Engine, Halt, HashKey, Object, checked_floor_div, utf8_error_field,
+    values_equal,
+};
+use parser::lexer::token::Span;
 
 use crate::compiler::Bytecode;
 use crate::frame::Frame;
-use crate::op_code::{OpCodeError, Opcode, cast_u8_to_opcode};
+#[cfg(feature = "trace")]
+use crate::op_code::disassemble_instruction;
+use crate::op_code::{
+    OpCodeError, Opcode, cast_u8_to_opcode, cast_u8_to_opcode_at, definitions, read_operands,
+};
+use crate::profile::{ProfileReport, Profiler};
+use crate::source_map::{SourceMap, excerpt, line_column, span_at};
 
+// the stack starts this big and doubles as needed, up to `max_stack`
 const STACK_SIZE: usize = 2048;
+/// The default for `VM::set_max_stack`.
+pub const MAX_STACK: usize = 1 << 20;
+/// How many globals a program can have. The VM only allocates the slots
+/// the bytecode uses, and more as `OpSetGlobal` reaches them.
 pub const GLOBAL_SIZE: usize = 65536;
-const MAX_FRAMES: usize = 1024;
+/// The default for `VM::set_max_frames`.
+pub const MAX_FRAMES: usize = 1024;
+// frames are allocated as calls first get this deep
+const INITIAL_FRAMES: usize = 64;
 
 #[derive(Debug, Clone)]
 pub enum Value {
     Integer(i64),
+    Float(f64),
     Boolean(bool),
     Null,
     Object(Rc<Object>),
@@ -27,6 +54,7 @@ impl Value {
     pub fn from_object(obj: Rc<Object>) -> Value {
         match &*obj {
             Object::Integer(i) => Value::Integer(*i),
+            Object::Float(x) => Value::Float(*x),
             Object::Boolean(b) => Value::Boolean(*b),
             Object::Null => Value::Null,
             _ => Value::Object(obj),
@@ -36,12 +64,40 @@ impl Value {
     pub fn into_rc_object(&self) -> Rc<Object> {
         match self {
             Value::Integer(i) => Rc::new(Object::Integer(*i)),
-            Value::Boolean(b) => Rc::new(Object::Boolean(*b)),
-            Value::Null => Rc::new(Object::Null),
+            Value::Float(x) => Rc::new(Object::Float(*x)),
+            Value::Boolean(b) => object::boolean(*b),
+            Value::Null => object::null(),
             Value::Object(o) => Rc::clone(o),
         }
     }
 
+    pub fn try_into_i64(&self) -> Result<i64, ConversionError> {
+        match self {
+            Value::Integer(i) => Ok(*i),
+            other => i64::try_from(&*other.into_rc_object()),
+        }
+    }
+
+    /// Integers convert too, as they do in arithmetic with a float.
+    pub fn try_into_f64(&self) -> Result<f64, ConversionError> {
+        match self {
+            Value::Float(x) => Ok(*x),
+            Value::Integer(i) => Ok(*i as f64),
+            other => f64::try_from(&*other.into_rc_object()),
+        }
+    }
+
+    pub fn try_into_bool(&self) -> Result<bool, ConversionError> {
+        match self {
+            Value::Boolean(b) => Ok(*b),
+            other => bool::try_from(&*other.into_rc_object()),
+        }
+    }
+
+    pub fn try_into_string(&self) -> Result<String, ConversionError> {
+        String::try_from(&*self.into_rc_object())
+    }
+
     fn is_truthy(&self) -> bool {
         match self {
             Value::Boolean(b) => *b,
@@ -53,22 +109,10 @@ impl Value {
     fn type_name(&self) -> &'static str {
         match self {
             Value::Integer(_) => "INTEGER",
+            Value::Float(_) => "FLOAT",
             Value::Boolean(_) => "BOOLEAN",
             Value::Null => "NULL",
-            Value::Object(o) => match &**o {
-                Object::String(_) => "STRING",
-                Object::Array(_) => "ARRAY",
-                Object::Hash(_) => "HASH",
-                Object::Function(_, _, _) => "FUNCTION",
-                Object::ClosureObj(_) => "CLOSURE",
-                Object::Builtin(_) => "BUILTIN",
-                Object::CompiledFunction(_) => "COMPILED_FUNCTION",
-                Object::ReturnValue(_) => "RETURN_VALUE",
-                Object::Error(_) => "ERROR",
-                Object::Integer(_) => "INTEGER",
-                Object::Boolean(_) => "BOOLEAN",
-                Object::Null => "NULL",
-            },
+            Value::Object(o) => o.type_name(),
         }
     }
 }
@@ -77,6 +121,7 @@ impl fmt::Display for Value {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             Value::Integer(i) => write!(f, "{}", i),
+            Value::Float(x) => write!(f, "{:?}", x),
             Value::Boolean(b) => write!(f, "{}", b),
             Value::Null => write!(f, "null"),
             Value::Object(o) => write!(f, "{}", o),
@@ -87,15 +132,29 @@ impl fmt::Display for Value {
 #[derive(Debug)]
 pub enum VMError {
     StackOverflow,
-    FrameOverflow,
+    StackUnderflow,
+    /// A call would have made more than `depth` frames active; see
+    /// `VM::set_max_frames`.
+    MaxFramesExceeded {
+        depth: usize,
+    },
+    InvalidBytecode(String),
+    DivisionByZero,
+    IntegerOverflow(Opcode),
     Opcode(OpCodeError),
     UnknownBuiltinIndex(usize),
     UnknownIntegerOperator(Opcode),
+    UnknownFloatOperator(Opcode),
     UnsupportedBinaryOperation {
         op: Opcode,
         left: &'static str,
         right: &'static str,
     },
+    /// `+` with a string on only one side.
+    StringConcatenation {
+        left: &'static str,
+        right: &'static str,
+    },
     UnknownComparisonOperator(Opcode),
     UnknownBooleanComparisonOperator(Opcode),
     UnsupportedComparison {
@@ -109,15 +168,141 @@ pub enum VMError {
     },
     NotCallable(&'static str),
     UnsupportedIndexOperator(&'static str),
-    UnusableAsHashKey(String),
+    UnusableAsHashKey(&'static str),
+    /// An array element assignment past either end of the array.
+    IndexError {
+        index: i64,
+        len: usize,
+    },
     ExpectedCompiledFunction(&'static str),
+    /// A `for` loop over something other than an array or hash.
+    NotIterable(&'static str),
+    /// `error`, from calling the function passed to a higher-order builtin
+    /// on the element at `index`.
+    CallbackFailed {
+        builtin: &'static str,
+        index: usize,
+        error: Box<VMError>,
+    },
+    /// `assert` on a falsy condition. Empty when no message was given.
+    AssertionFailed(String),
+    /// The budget from `set_fuel` ran out after `executed` instructions.
+    OutOfFuel {
+        executed: u64,
+    },
+    /// The token from `set_cancel_token` was set after `executed`
+    /// instructions.
+    Cancelled {
+        executed: u64,
+    },
+    /// `run` after the program stopped inside a function a builtin such
+    /// as `map` was calling, whose frames are gone.
+    CannotResume,
+    /// `error`, raised by an instruction compiled from `span` while the
+    /// calls in `trace` were active, innermost first.
+    Located {
+        error: Box<VMError>,
+        span: Option<Span>,
+        trace: Vec<TraceFrame>,
+    },
+}
+
+/// A call that was active when a runtime error happened.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct TraceFrame {
+    /// Empty for anonymous functions.
+    pub function: String,
+    /// The start of the instruction the function was executing: the failing
+    /// instruction for the innermost call, the pending call for the others.
+    pub offset: usize,
+}
+
+impl fmt::Display for TraceFrame {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let function = if self.function.is_empty() {
+            "<anonymous>"
+        } else {
+            &self.function
+        };
+        write!(f, "in {} (offset {:04})", function, self.offset)
+    }
+}
+
+impl VMError {
+    /// Where the failing instruction came from, if the bytecode had a
+    /// source map.
+    pub fn span(&self) -> Option<&Span> {
+        match self {
+            VMError::Located { span, .. } => span.as_ref(),
+            _ => None,
+        }
+    }
+
+    /// The calls that were active when the error happened, innermost first.
+    pub fn trace(&self) -> &[TraceFrame] {
+        match self {
+            VMError::Located { trace, .. } => trace,
+            _ => &[],
+        }
+    }
+
+    /// The error without its location and trace.
+    pub fn without_location(self) -> VMError {
+        match self {
+            VMError::Located { error, .. } => *error,
+            error => error,
+        }
+    }
+
+    /// The error as the REPL and CLI print it: prefixed with the line and
+    /// column it happened at and followed by the offending source line,
+    /// when it has a location, then by the call trace. A run of identical
+    /// frames, as deep recursion leaves, is printed once.
+    pub fn render(&self, source: &str) -> String {
+        let mut rendered = match self.span() {
+            Some(span) => {
+                let (line, column) = line_column(source, span.start);
+                format!(
+                    "VM error at {}:{}: {}\n{}",
+                    line,
+                    column,
+                    self,
+                    excerpt(source, span)
+                )
+            }
+            None => format!("VM error: {}", self),
+        };
+        let trace = self.trace();
+        let mut i = 0;
+        while i < trace.len() {
+            let repeats = trace[i..]
+                .iter()
+                .take_while(|frame| **frame == trace[i])
+                .count();
+            rendered.push_str(&format!("\n{}", trace[i]));
+            if repeats > 1 {
+                rendered.push_str(&format!(
+                    "\n[previous frame repeated {} more times]",
+                    repeats - 1
+                ));
+            }
+            i += repeats;
+        }
+        rendered
+    }
 }
 
 impl fmt::Display for VMError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             VMError::StackOverflow => write!(f, "stack overflow"),
-            VMError::FrameOverflow => write!(f, "frame overflow"),
+            VMError::StackUnderflow => write!(f, "stack underflow"),
+            VMError::MaxFramesExceeded { depth } => {
+                write!(f, "maximum call depth of {} frames exceeded", depth)
+            }
+            VMError::InvalidBytecode(reason) => write!(f, "invalid bytecode: {}", reason),
+            VMError::DivisionByZero => write!(f, "division by zero"),
+            VMError::IntegerOverflow(op) => write!(f, "integer overflow in {:?}", op),
             VMError::Opcode(err) => write!(f, "opcode error: {}", err),
             VMError::UnknownBuiltinIndex(index) => {
                 write!(f, "type error: unknown builtin index {}", index)
@@ -125,11 +310,19 @@ impl fmt::Display for VMError {
             VMError::UnknownIntegerOperator(op) => {
                 write!(f, "type error: unknown integer operator: {:?}", op)
             }
+            VMError::UnknownFloatOperator(op) => {
+                write!(f, "type error: unknown float operator: {:?}", op)
+            }
             VMError::UnsupportedBinaryOperation { op, left, right } => write!(
                 f,
                 "type error: unsupported binary operation {:?} for {} and {}",
                 op, left, right
             ),
+            VMError::StringConcatenation { left, right } => write!(
+                f,
+                "type error: cannot add {} and {}; convert the other operand with to_string first",
+                left, right
+            ),
             VMError::UnknownComparisonOperator(op) => {
                 write!(f, "type error: unknown comparison operator: {:?}", op)
             }
@@ -161,54 +354,176 @@ impl fmt::Display for VMError {
                     value_type
                 )
             }
-            VMError::UnusableAsHashKey(value) => {
-                write!(f, "index error: unusable as hash key: {}", value)
+            VMError::UnusableAsHashKey(value_type) => {
+                write!(f, "index error: unusable as hash key: {}", value_type)
             }
+            VMError::IndexError { index, len } => write!(
+                f,
+                "index error: index {} out of range for array of length {}",
+                index, len
+            ),
             VMError::ExpectedCompiledFunction(value_type) => {
                 write!(f, "type error: not a function: {}", value_type)
             }
+            VMError::NotIterable(value_type) => {
+                write!(f, "type error: cannot iterate over {}", value_type)
+            }
+            VMError::CallbackFailed {
+                builtin,
+                index,
+                error,
+            } => write!(
+                f,
+                "{} (calling the function passed to {} on element {})",
+                error, builtin, index
+            ),
+            VMError::AssertionFailed(message) if message.is_empty() => {
+                write!(f, "assertion failed")
+            }
+            VMError::AssertionFailed(message) => write!(f, "assertion failed: {}", message),
+            VMError::OutOfFuel { executed } => {
+                write!(f, "out of fuel after {} instructions", executed)
+            }
+            VMError::Cancelled { executed } => {
+                write!(f, "cancelled after {} instructions", executed)
+            }
+            VMError::CannotResume => write!(
+                f,
+                "cannot resume: the program stopped inside a function called by a builtin"
+            ),
+            VMError::Located { error, .. } => write!(f, "{}", error),
         }
     }
 }
 
+/// The VM as seen by a builtin. Keeps the error of the call that failed,
+/// if one did.
+struct VmEngine<'a> {
+    vm: &'a mut VM,
+    error: Option<VMError>,
+}
+
+impl Engine for VmEngine<'_> {
+    fn call(&mut self, function: &Rc<Object>, args: Vec<Rc<Object>>) -> Option<Rc<Object>> {
+        self.vm
+            .call_function(function, args)
+            .map_err(|error| self.error = Some(error))
+            .ok()
+    }
+
+    fn seed_random(&mut self, seed: u64) {
+        self.vm.random = Random::new(seed);
+    }
+
+    fn next_random(&mut self) -> u64 {
+        self.vm.random.next_u64()
+    }
+
+    fn io_enabled(&self) -> bool {
+        self.vm.io
+    }
+
+    fn cancelled(&self) -> bool {
+        self.vm.cancelled()
+    }
+}
+
+/// A snapshot of what a VM is holding on to. Byte counts are estimates:
+/// they follow the shape of each value, count a value shared through
+/// several `Rc`s once, and ignore allocator overhead.
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct MemoryStats {
+    pub constants: usize,
+    pub constant_bytes: usize,
+    pub live_globals: usize,
+    pub stack_depth: usize,
+    pub peak_stack_depth: usize,
+    pub peak_frame_depth: usize,
+    pub heap_bytes: usize,
+}
+
+impl fmt::Display for MemoryStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "constants: {} (~{} bytes)",
+            self.constants, self.constant_bytes
+        )?;
+        writeln!(f, "live globals: {}", self.live_globals)?;
+        writeln!(
+            f,
+            "stack depth: {} (peak {})",
+            self.stack_depth, self.peak_stack_depth
+        )?;
+        writeln!(f, "peak frame depth: {}", self.peak_frame_depth)?;
+        write!(f, "heap: ~{} bytes", self.heap_bytes)
+    }
+}
+
 pub struct VM {
     constants: Vec<Value>,
 
     stack: Vec<Value>,
+    /// How many values the stack may grow to hold.
+    max_stack: usize,
     sp: usize,
+    peak_sp: usize,
 
     pub globals: Vec<Value>,
 
     frames: Vec<Frame>,
     frame_index: usize,
+    peak_frame_index: usize,
+    /// How many frames, the main program's included, may be active.
+    max_frames: usize,
+
+    source_map: SourceMap,
+    /// What `rand` draws from; `seed` replaces it.
+    random: Random,
+    /// Off unless the host calls `enable_io`.
+    io: bool,
+    /// What `OpGetBuiltin` finds past the standard builtins.
+    host_builtins: Rc<Vec<(String, BuiltinFunc)>>,
+    /// How many more instructions may run; `None` for no limit.
+    fuel: Option<u64>,
+    /// Instructions dispatched since the VM was created.
+    executed: u64,
+    /// Stops `run` once another thread sets it.
+    cancel: Option<Arc<AtomicBool>>,
+    /// Cleared when an error unwinds a call from a builtin, after which
+    /// `run` refuses to carry on.
+    resumable: bool,
+    /// Boxed so a VM that isn't profiling stays small.
+    profiler: Option<Box<Profiler>>,
+
+    #[cfg(feature = "trace")]
+    trace: Option<Box<dyn Write>>,
 }
 
 impl VM {
     pub fn new(bytecode: Bytecode) -> VM {
-        let empty_frame = Frame::new(
-            Closure {
-                func: Rc::from(object::CompiledFunction {
-                    instructions: vec![],
-                    num_locals: 0,
-                    num_parameters: 0,
-                }),
-                free: vec![],
-            },
-            0,
-        );
+        VM::new_with_global_store(bytecode, vec![])
+    }
 
+    /// A VM whose globals start out as `globals`, as an earlier VM left
+    /// them, padded with nulls to the slots `bytecode` uses.
+    pub fn new_with_global_store(bytecode: Bytecode, mut globals: Vec<Value>) -> VM {
+        if globals.len() < bytecode.num_globals {
+            globals.resize(bytecode.num_globals, Value::Null);
+        }
         let main_fn = Rc::from(object::CompiledFunction {
             instructions: bytecode.instructions.bytes,
             num_locals: 0,
             num_parameters: 0,
+            name: "main".to_string(),
         });
         let main_closure = Closure {
             func: main_fn,
             free: vec![],
         };
         let main_frame = Frame::new(main_closure, 0);
-        let mut frames = vec![empty_frame; MAX_FRAMES];
-        frames[0] = main_frame;
+        let mut frames = Vec::with_capacity(INITIAL_FRAMES);
+        frames.push(main_frame);
 
         let constants = bytecode
             .constants
@@ -219,41 +534,215 @@ impl VM {
         VM {
             constants,
             stack: (0..STACK_SIZE).map(|_| Value::Null).collect(),
+            max_stack: MAX_STACK,
             sp: 0,
-            globals: (0..GLOBAL_SIZE).map(|_| Value::Null).collect(),
+            peak_sp: 0,
+            globals,
             frames,
             frame_index: 1,
+            peak_frame_index: 1,
+            max_frames: MAX_FRAMES,
+            source_map: bytecode.source_map,
+            random: Random::from_clock(),
+            io: false,
+            host_builtins: bytecode.host_builtins,
+            fuel: None,
+            executed: 0,
+            cancel: None,
+            resumable: true,
+            profiler: None,
+            #[cfg(feature = "trace")]
+            trace: None,
+        }
+    }
+
+    /// Logs every dispatched instruction to stderr while enabled.
+    #[cfg(feature = "trace")]
+    pub fn set_trace(&mut self, enabled: bool) {
+        self.trace = match enabled {
+            true => Some(Box::new(io::stderr())),
+            false => None,
+        };
+    }
+
+    /// Logs every dispatched instruction to `writer`.
+    #[cfg(feature = "trace")]
+    pub fn enable_trace(&mut self, writer: Box<dyn Write>) {
+        self.trace = Some(writer);
+    }
+
+    /// Lets `read_file` and `write_file` touch the file system. Hosts that
+    /// run code they don't trust should leave this off.
+    pub fn enable_io(&mut self, enabled: bool) {
+        self.io = enabled;
+    }
+
+    /// Lets the stack grow to hold `limit` values instead of `MAX_STACK`
+    /// before a push fails with `VMError::StackOverflow`. It starts smaller
+    /// and doubles as needed.
+    pub fn set_max_stack(&mut self, limit: usize) {
+        self.max_stack = limit;
+        // a push only checks the limit once it runs out of slots
+        self.stack.truncate(limit.max(self.sp));
+    }
+
+    /// Lets calls nest until `limit` frames are active, counting the main
+    /// program's, instead of `MAX_FRAMES`. Frames are allocated as they are
+    /// first needed, so a high limit costs nothing until it is used.
+    pub fn set_max_frames(&mut self, limit: usize) {
+        self.max_frames = limit;
+    }
+
+    /// Lets `run` execute at most `limit` more instructions before failing
+    /// with `VMError::OutOfFuel`. The VM is left on the next instruction, so
+    /// after `add_fuel` another `run` carries on from there. If the fuel ran
+    /// out inside a function a builtin such as `map` was calling, the
+    /// builtin can't be resumed and later runs fail with
+    /// `VMError::CannotResume`.
+    pub fn set_fuel(&mut self, limit: u64) {
+        self.fuel = Some(limit);
+    }
+
+    /// Once `token` is set, from any thread, `run` fails with
+    /// `VMError::Cancelled` before its next instruction, and a running
+    /// `sleep` wakes up within one `SLEEP_SLICE` with an error. As with
+    /// fuel, clearing the token lets a later `run` carry on.
+    pub fn set_cancel_token(&mut self, token: Option<Arc<AtomicBool>>) {
+        self.cancel = token;
+    }
+
+    fn cancelled(&self) -> bool {
+        self.cancel
+            .as_ref()
+            .is_some_and(|token| token.load(Ordering::Relaxed))
+    }
+
+    /// Tops up the budget from `set_fuel`; without one there is no limit to
+    /// raise.
+    pub fn add_fuel(&mut self, amount: u64) {
+        if let Some(fuel) = &mut self.fuel {
+            *fuel = fuel.saturating_add(amount);
         }
     }
 
-    pub fn new_with_global_store(bytecode: Bytecode, globals: Vec<Value>) -> VM {
-        let mut vm = VM::new(bytecode);
-        vm.globals = globals;
-        vm
+    /// The fuel left, or `None` when there is no limit.
+    pub fn fuel(&self) -> Option<u64> {
+        self.fuel
+    }
+
+    /// Counts every instruction and call from now on, and times each
+    /// function, for `profile_report`.
+    pub fn enable_profiling(&mut self) {
+        self.profiler.get_or_insert_default();
+    }
+
+    /// What has run since `enable_profiling`, or `None` when profiling is
+    /// off.
+    pub fn profile_report(&self) -> Option<ProfileReport> {
+        let main = &self.frames[0].closure.func;
+        let report = self.profiler.as_ref()?.report(|func| {
+            if Rc::ptr_eq(func, main) {
+                return None;
+            }
+            self.constants.iter().position(|constant| {
+                matches!(constant, Value::Object(o)
+                    if matches!(&**o, Object::CompiledFunction(f) if Rc::ptr_eq(f, func)))
+            })
+        });
+        Some(report)
     }
 
+    /// Runs the program. Errors carry the active calls and, when the
+    /// bytecode has a source map, the span of the failing instruction.
     pub fn run(&mut self) -> Result<(), VMError> {
+        if !self.resumable {
+            return Err(VMError::CannotResume);
+        }
+        self.dispatch(0).map_err(|error| VMError::Located {
+            error: Box::new(error),
+            span: self.failing_span(),
+            trace: self.call_trace(),
+        })
+    }
+
+    fn call_trace(&self) -> Vec<TraceFrame> {
+        self.frames[..self.frame_index]
+            .iter()
+            .rev()
+            .map(|frame| TraceFrame {
+                function: frame.closure.func.name.clone(),
+                offset: instruction_start(
+                    frame.instructions(),
+                    usize::try_from(frame.ip).unwrap_or(0),
+                ),
+            })
+            .collect()
+    }
+
+    // The frame that failed is still on top, so its instruction pointer is
+    // inside the failing instruction. A function's mappings are keyed by its
+    // constant index, which the closure doesn't record, so it is looked up.
+    fn failing_span(&self) -> Option<Span> {
+        let frame = &self.frames[self.frame_index - 1];
+        let mappings = if self.frame_index == 1 {
+            &self.source_map.main
+        } else {
+            let index = self.constants.iter().position(|constant| {
+                matches!(constant, Value::Object(o)
+                    if matches!(&**o, Object::CompiledFunction(f) if Rc::ptr_eq(f, &frame.closure.func)))
+            })?;
+            self.source_map.functions.get(&index)?
+        };
+        span_at(mappings, usize::try_from(frame.ip).ok()?).cloned()
+    }
+
+    // Runs until the program halts or, when a builtin has called back into
+    // a Monkey function, until a return brings the frame count down to
+    // `depth`. The main frame never returns, so `run` passes 0.
+    fn dispatch(&mut self, depth: usize) -> Result<(), VMError> {
         while self.current_frame().ip < self.current_instruction_len() as i32 - 1 {
+            // checked before moving on, so a later `run` resumes here
+            if let Some(fuel) = &mut self.fuel {
+                if *fuel == 0 {
+                    return Err(VMError::OutOfFuel {
+                        executed: self.executed,
+                    });
+                }
+                *fuel -= 1;
+            }
+            if self.cancelled() {
+                return Err(VMError::Cancelled {
+                    executed: self.executed,
+                });
+            }
+            self.executed += 1;
             self.current_frame().ip += 1;
-            let opcode =
-                cast_u8_to_opcode(self.current_instruction_byte(0)).map_err(VMError::Opcode)?;
+            let position = self.current_frame().ip as usize;
+            let opcode = cast_u8_to_opcode_at(self.current_instruction_byte(0)?, position)
+                .map_err(VMError::Opcode)?;
+            #[cfg(feature = "trace")]
+            self.trace_instruction()?;
+            if let Some(profiler) = &mut self.profiler {
+                profiler.instruction(opcode, &self.frames[self.frame_index - 1].closure.func);
+            }
 
             match opcode {
                 Opcode::OpConst => {
-                    let const_index = self.read_u16_operand(1);
+                    let const_index = self.read_u16_operand(1)?;
                     self.current_frame().ip += 2;
-                    let val = self.constants[const_index].clone();
+                    let val = self.constant(const_index)?;
                     self.push(val)?;
                 }
                 Opcode::OpAdd
                 | Opcode::OpSub
                 | Opcode::OpMul
                 | Opcode::OpDiv
+                | Opcode::OpFloorDiv
                 | Opcode::OpModulo => {
                     self.execute_binary_operation(opcode)?;
                 }
                 Opcode::OpPop => {
-                    self.pop();
+                    self.discard()?;
                 }
                 Opcode::OpTrue => {
                     self.push(Value::Boolean(true))?;
@@ -261,7 +750,12 @@ impl VM {
                 Opcode::OpFalse => {
                     self.push(Value::Boolean(false))?;
                 }
-                Opcode::OpEqual | Opcode::OpNotEqual | Opcode::OpGreaterThan => {
+                Opcode::OpEqual
+                | Opcode::OpNotEqual
+                | Opcode::OpGreaterThan
+                | Opcode::OpGreaterOrEqual
+                | Opcode::OpLessThan
+                | Opcode::OpLessOrEqual => {
                     self.execute_comparison(opcode)?;
                 }
                 Opcode::OpMinus => {
@@ -271,13 +765,13 @@ impl VM {
                     self.execute_bang_operation()?;
                 }
                 Opcode::OpJump => {
-                    let pos = self.read_u16_operand(1);
+                    let pos = self.read_u16_operand(1)?;
                     self.current_frame().ip = pos as i32 - 1;
                 }
                 Opcode::OpJumpNotTruthy => {
-                    let pos = self.read_u16_operand(1);
+                    let pos = self.read_u16_operand(1)?;
                     self.current_frame().ip += 2;
-                    let condition = self.pop();
+                    let condition = self.pop()?;
                     if !condition.is_truthy() {
                         self.current_frame().ip = pos as i32 - 1;
                     }
@@ -286,96 +780,170 @@ impl VM {
                     self.push(Value::Null)?;
                 }
                 Opcode::OpGetGlobal => {
-                    let global_index = self.read_u16_operand(1);
+                    let global_index = self.read_u16_operand(1)?;
+                    self.current_frame().ip += 2;
+                    let val = self
+                        .globals
+                        .get(global_index)
+                        .cloned()
+                        .ok_or_else(|| invalid_index("global", global_index))?;
+                    self.push(val)?;
+                }
+                Opcode::OpTakeGlobal => {
+                    let global_index = self.read_u16_operand(1)?;
                     self.current_frame().ip += 2;
-                    let val = self.globals[global_index].clone();
+                    let slot = self
+                        .globals
+                        .get_mut(global_index)
+                        .ok_or_else(|| invalid_index("global", global_index))?;
+                    let val = std::mem::replace(slot, Value::Null);
                     self.push(val)?;
                 }
                 Opcode::OpSetGlobal => {
-                    let global_index = self.read_u16_operand(1);
+                    let global_index = self.read_u16_operand(1)?;
                     self.current_frame().ip += 2;
-                    self.globals[global_index] = self.pop();
+                    let val = self.pop()?;
+                    if global_index >= self.globals.len() {
+                        if global_index >= GLOBAL_SIZE {
+                            return Err(invalid_index("global", global_index));
+                        }
+                        self.globals.resize(global_index + 1, Value::Null);
+                    }
+                    self.globals[global_index] = val;
                 }
                 Opcode::OpArray => {
-                    let count = self.read_u16_operand(1);
+                    let count = self.read_u16_operand(1)?;
                     self.current_frame().ip += 2;
-                    let elements = self.build_array(self.sp - count, self.sp);
-                    self.sp -= count;
+                    let start = self.stack_start(count)?;
+                    let elements = self.build_array(start, self.sp);
+                    self.sp = start;
                     self.push(Value::Object(Rc::new(Object::Array(elements))))?;
                 }
                 Opcode::OpHash => {
-                    let count = self.read_u16_operand(1);
+                    let count = self.read_u16_operand(1)?;
                     self.current_frame().ip += 2;
-                    let elements = self.build_hash(self.sp - count, self.sp)?;
-                    self.sp -= count;
+                    if count % 2 != 0 {
+                        return Err(VMError::InvalidBytecode(format!(
+                            "hash operand must be even, got {}",
+                            count
+                        )));
+                    }
+                    let start = self.stack_start(count)?;
+                    let elements = self.build_hash(start, self.sp)?;
+                    self.sp = start;
                     self.push(Value::Object(Rc::new(Object::Hash(elements))))?;
                 }
                 Opcode::OpIndex => {
-                    let index = self.pop();
-                    let left = self.pop();
+                    let index = self.pop()?;
+                    let left = self.pop()?;
                     self.execute_index_operation(left, index)?;
                 }
+                Opcode::OpSetIndex => {
+                    let value = self.pop()?;
+                    let index = self.pop()?;
+                    let collection = self.pop()?;
+                    let updated = set_index(collection, index, value)?;
+                    self.push(updated)?;
+                }
+                Opcode::OpIter => {
+                    let (sequence, len) = iteration_sequence(self.pop()?)?;
+                    self.push(sequence)?;
+                    self.push(Value::Integer(len as i64))?;
+                }
                 Opcode::OpReturnValue => {
-                    let return_value = self.pop();
+                    if self.frame_index == 1 {
+                        // a top-level `return` halts the program; the value
+                        // stays visible through `last_popped_stack_elm`
+                        return self.discard();
+                    }
+                    let return_value = self.pop()?;
                     let frame = self.pop_frame();
                     self.sp = frame.base_pointer - 1;
                     self.push(return_value)?;
+                    if self.frame_index == depth {
+                        return Ok(());
+                    }
                 }
                 Opcode::OpReturn => {
+                    if self.frame_index == 1 {
+                        return Ok(());
+                    }
                     let frame = self.pop_frame();
                     self.sp = frame.base_pointer - 1;
                     self.push(Value::Null)?;
+                    if self.frame_index == depth {
+                        return Ok(());
+                    }
                 }
                 Opcode::OpCall => {
-                    let num_args = self.read_u8_operand(1) as usize;
+                    let num_args = self.read_u8_operand(1)? as usize;
                     self.current_frame().ip += 1;
                     self.execute_call(num_args)?;
                 }
                 Opcode::OpTailCall => {
-                    let num_args = self.read_u8_operand(1) as usize;
+                    let num_args = self.read_u8_operand(1)? as usize;
                     self.current_frame().ip += 1;
-                    let base = self.current_frame().base_pointer;
-                    let num_locals = self.current_frame().closure.func.num_locals;
-                    for i in 0..num_args {
-                        self.stack[base + i] = self.stack[self.sp - num_args + i].clone();
+                    // a function calling itself through a global finds
+                    // whatever the global holds now, which may be another
+                    // function; that one gets a frame of its own
+                    if self.calls_current_closure(num_args)? {
+                        self.tail_call(num_args)?;
+                    } else {
+                        self.execute_call(num_args)?;
                     }
-                    self.sp = base + num_locals;
-                    self.current_frame().ip = -1;
                 }
                 Opcode::OpSetLocal => {
-                    let local_index = self.read_u8_operand(1) as usize;
+                    let local_index = self.read_u8_operand(1)? as usize;
                     self.current_frame().ip += 1;
-                    let base = self.current_frame().base_pointer;
-                    self.stack[base + local_index] = self.pop();
+                    let slot = self.local_slot(local_index)?;
+                    let val = self.pop()?;
+                    *self.stack_slot_mut(slot)? = val;
                 }
                 Opcode::OpGetLocal => {
-                    let local_index = self.read_u8_operand(1) as usize;
+                    let local_index = self.read_u8_operand(1)? as usize;
                     self.current_frame().ip += 1;
-                    let base = self.current_frame().base_pointer;
-                    let val = self.stack[base + local_index].clone();
+                    let slot = self.local_slot(local_index)?;
+                    let val = self.stack_slot(slot)?.clone();
+                    self.push(val)?;
+                }
+                Opcode::OpTakeLocal => {
+                    let local_index = self.read_u8_operand(1)? as usize;
+                    self.current_frame().ip += 1;
+                    let slot = self.local_slot(local_index)?;
+                    let val = std::mem::replace(self.stack_slot_mut(slot)?, Value::Null);
                     self.push(val)?;
                 }
                 Opcode::OpGetBuiltin => {
-                    let built_index = self.read_u8_operand(1) as usize;
+                    let built_index = self.read_u8_operand(1)? as usize;
                     self.current_frame().ip += 1;
-                    let definition = BuiltIns
-                        .get(built_index)
-                        .ok_or(VMError::UnknownBuiltinIndex(built_index))?
-                        .1;
+                    let definition = match built_index.checked_sub(BuiltIns.len()) {
+                        None => BuiltIns[built_index].1,
+                        Some(host_index) => {
+                            self.host_builtins
+                                .get(host_index)
+                                .ok_or(VMError::UnknownBuiltinIndex(built_index))?
+                                .1
+                        }
+                    };
                     self.push(Value::Object(Rc::new(Object::Builtin(definition))))?;
                 }
                 Opcode::OpClosure => {
-                    let const_index = self.read_u16_operand(1);
-                    let num_free = self.read_u8_operand(3) as usize;
+                    let const_index = self.read_u16_operand(1)?;
+                    let num_free = self.read_u8_operand(3)? as usize;
                     self.current_frame().ip += 3;
                     self.push_closure(const_index, num_free)?;
                 }
                 Opcode::OpGetFree => {
-                    let free_index = self.read_u8_operand(1) as usize;
+                    let free_index = self.read_u8_operand(1)? as usize;
                     self.current_frame().ip += 1;
-                    let current_closure = self.current_frame().closure.clone();
-                    let val = Value::from_object(current_closure.free[free_index].clone());
-                    self.push(val)?;
+                    let free = self
+                        .current_frame()
+                        .closure
+                        .free
+                        .get(free_index)
+                        .cloned()
+                        .ok_or_else(|| invalid_index("free variable", free_index))?;
+                    self.push(Value::from_object(free))?;
                 }
                 Opcode::OpCurrentClosure => {
                     let current_closure = self.current_frame().closure.clone();
@@ -387,50 +955,98 @@ impl VM {
     }
 
     fn execute_binary_operation(&mut self, opcode: Opcode) -> Result<(), VMError> {
-        let right = self.pop();
-        let left = self.pop();
+        let right = self.pop()?;
+        let left = self.pop()?;
         match (&left, &right) {
             (Value::Integer(l), Value::Integer(r)) => {
+                let result = match opcode {
+                    Opcode::OpAdd => l.checked_add(*r),
+                    Opcode::OpSub => l.checked_sub(*r),
+                    Opcode::OpMul => l.checked_mul(*r),
+                    Opcode::OpDiv | Opcode::OpFloorDiv | Opcode::OpModulo if *r == 0 => {
+                        return Err(VMError::DivisionByZero);
+                    }
+                    Opcode::OpDiv => l.checked_div(*r),
+                    Opcode::OpFloorDiv => checked_floor_div(*l, *r),
+                    Opcode::OpModulo => l.checked_rem(*r),
+                    _ => return Err(VMError::UnknownIntegerOperator(opcode)),
+                };
+                let result = result.ok_or(VMError::IntegerOverflow(opcode))?;
+                self.push(Value::Integer(result))
+            }
+            (Value::Float(_) | Value::Integer(_), Value::Float(_) | Value::Integer(_)) => {
+                let (l, r) = (as_float(&left), as_float(&right));
+                // IEEE semantics: dividing by zero gives an infinity or NaN
                 let result = match opcode {
                     Opcode::OpAdd => l + r,
                     Opcode::OpSub => l - r,
                     Opcode::OpMul => l * r,
                     Opcode::OpDiv => l / r,
+                    Opcode::OpFloorDiv => (l / r).floor(),
                     Opcode::OpModulo => l % r,
-                    _ => return Err(VMError::UnknownIntegerOperator(opcode)),
+                    _ => return Err(VMError::UnknownFloatOperator(opcode)),
                 };
-                self.push(Value::Integer(result))
+                self.push(Value::Float(result))
             }
             (Value::Object(l), Value::Object(r)) => {
                 if let (Object::String(ls), Object::String(rs)) = (&**l, &**r)
                     && opcode == Opcode::OpAdd
                 {
-                    let result = ls.to_string() + rs;
-                    return self.push(Value::Object(Rc::new(Object::String(result))));
+                    let mut result = String::with_capacity(ls.len() + rs.len());
+                    result.push_str(ls);
+                    result.push_str(rs);
+                    return self.push(Value::Object(Rc::new(Object::String(result.into()))));
                 }
-                Err(VMError::UnsupportedBinaryOperation {
-                    op: opcode,
-                    left: left.type_name(),
-                    right: right.type_name(),
-                })
+                Err(binary_type_error(opcode, &left, &right))
             }
-            _ => Err(VMError::UnsupportedBinaryOperation {
-                op: opcode,
-                left: left.type_name(),
-                right: right.type_name(),
-            }),
+            _ => Err(binary_type_error(opcode, &left, &right)),
         }
     }
 
     fn execute_comparison(&mut self, opcode: Opcode) -> Result<(), VMError> {
-        let right = self.pop();
-        let left = self.pop();
+        let right = self.pop()?;
+        let left = self.pop()?;
+        // strings order by code point, not by any locale's collation
+        if let (Value::Object(l), Value::Object(r)) = (&left, &right)
+            && let (Object::String(l), Object::String(r)) = (&**l, &**r)
+            && matches!(
+                opcode,
+                Opcode::OpGreaterThan
+                    | Opcode::OpGreaterOrEqual
+                    | Opcode::OpLessThan
+                    | Opcode::OpLessOrEqual
+            )
+        {
+            let result = match opcode {
+                Opcode::OpGreaterThan => l > r,
+                Opcode::OpGreaterOrEqual => l >= r,
+                Opcode::OpLessThan => l < r,
+                _ => l <= r,
+            };
+            return self.push(Value::Boolean(result));
+        }
         match (&left, &right) {
             (Value::Integer(l), Value::Integer(r)) => {
                 let result = match opcode {
                     Opcode::OpEqual => l == r,
                     Opcode::OpNotEqual => l != r,
                     Opcode::OpGreaterThan => l > r,
+                    Opcode::OpGreaterOrEqual => l >= r,
+                    Opcode::OpLessThan => l < r,
+                    Opcode::OpLessOrEqual => l <= r,
+                    _ => return Err(VMError::UnknownComparisonOperator(opcode)),
+                };
+                self.push(Value::Boolean(result))
+            }
+            (Value::Float(_) | Value::Integer(_), Value::Float(_) | Value::Integer(_)) => {
+                let (l, r) = (as_float(&left), as_float(&right));
+                let result = match opcode {
+                    Opcode::OpEqual => l == r,
+                    Opcode::OpNotEqual => l != r,
+                    Opcode::OpGreaterThan => l > r,
+                    Opcode::OpGreaterOrEqual => l >= r,
+                    Opcode::OpLessThan => l < r,
+                    Opcode::OpLessOrEqual => l <= r,
                     _ => return Err(VMError::UnknownComparisonOperator(opcode)),
                 };
                 self.push(Value::Boolean(result))
@@ -443,6 +1059,13 @@ impl VM {
                 };
                 self.push(Value::Boolean(result))
             }
+            // everything else is equal only to a value of the same type with
+            // the same contents, compared all the way down, except that
+            // functions are only equal to themselves
+            _ if matches!(opcode, Opcode::OpEqual | Opcode::OpNotEqual) => {
+                let equal = values_equal(&left.into_rc_object(), &right.into_rc_object());
+                self.push(Value::Boolean(equal == (opcode == Opcode::OpEqual)))
+            }
             _ => Err(VMError::UnsupportedComparison {
                 left: left.type_name(),
                 right: right.type_name(),
@@ -451,18 +1074,52 @@ impl VM {
     }
 
     fn execute_minus_operation(&mut self) -> Result<(), VMError> {
-        let operand = self.pop();
+        let operand = self.pop()?;
         match &operand {
-            Value::Integer(l) => self.push(Value::Integer(-*l)),
+            Value::Integer(l) => {
+                let negated = l
+                    .checked_neg()
+                    .ok_or(VMError::IntegerOverflow(Opcode::OpMinus))?;
+                self.push(Value::Integer(negated))
+            }
+            Value::Float(x) => self.push(Value::Float(-x)),
             _ => Err(VMError::UnsupportedNegation(operand.type_name())),
         }
     }
 
     fn execute_bang_operation(&mut self) -> Result<(), VMError> {
-        let operand = self.pop();
-        match &operand {
-            Value::Boolean(l) => self.push(Value::Boolean(!*l)),
-            _ => self.push(Value::Boolean(false)),
+        let operand = self.pop()?;
+        self.push(Value::Boolean(!operand.is_truthy()))
+    }
+
+    pub fn memory_stats(&self) -> MemoryStats {
+        let live_globals: Vec<&Value> = self
+            .globals
+            .iter()
+            .filter(|value| !matches!(value, Value::Null))
+            .collect();
+        let mut seen = SeenAllocations::default();
+        let constant_bytes = self
+            .constants
+            .iter()
+            .map(|value| value_size(value, &mut seen))
+            .sum();
+        let mut seen = SeenAllocations::default();
+        let heap_bytes = live_globals
+            .iter()
+            .copied()
+            .chain(&self.stack[..self.sp])
+            .map(|value| value_size(value, &mut seen))
+            .sum();
+
+        MemoryStats {
+            constants: self.constants.len(),
+            constant_bytes,
+            live_globals: live_globals.len(),
+            stack_depth: self.sp,
+            peak_stack_depth: self.peak_sp,
+            peak_frame_depth: self.peak_frame_index,
+            heap_bytes,
         }
     }
 
@@ -470,39 +1127,101 @@ impl VM {
         self.stack.get(self.sp).cloned()
     }
 
-    fn pop(&mut self) -> Value {
-        let o = self.stack[self.sp - 1].clone();
+    // Moves the value out, so a popped array is not still referenced from
+    // the stack when a builtin wants to append to it in place.
+    fn pop(&mut self) -> Result<Value, VMError> {
+        if self.sp == 0 {
+            return Err(VMError::StackUnderflow);
+        }
         self.sp -= 1;
-        o
+        Ok(std::mem::replace(&mut self.stack[self.sp], Value::Null))
+    }
+
+    // `OpPop` leaves the value in its slot for `last_popped_stack_elm`.
+    fn discard(&mut self) -> Result<(), VMError> {
+        if self.sp == 0 {
+            return Err(VMError::StackUnderflow);
+        }
+        self.sp -= 1;
+        Ok(())
     }
 
     fn push(&mut self, v: Value) -> Result<(), VMError> {
-        if self.sp >= STACK_SIZE {
-            return Err(VMError::StackOverflow);
+        if self.sp >= self.stack.len() {
+            self.reserve_stack(self.sp + 1)?;
         }
         self.stack[self.sp] = v;
         self.sp += 1;
+        self.peak_sp = self.peak_sp.max(self.sp);
         Ok(())
     }
 
-    fn build_array(&self, start: usize, end: usize) -> Vec<Rc<Object>> {
-        let mut elements = Vec::with_capacity(end - start);
-        for i in start..end {
-            elements.push(self.stack[i].into_rc_object());
+    // Makes room for `len` values, doubling the stack until it fits.
+    #[cold]
+    fn reserve_stack(&mut self, len: usize) -> Result<(), VMError> {
+        if len <= self.stack.len() {
+            return Ok(());
         }
-        elements
+        if len > self.max_stack {
+            return Err(VMError::StackOverflow);
+        }
+        let mut size = self.stack.len().max(1);
+        while size < len {
+            size *= 2;
+        }
+        self.stack.resize(size.min(self.max_stack), Value::Null);
+        Ok(())
+    }
+
+    fn stack_start(&self, count: usize) -> Result<usize, VMError> {
+        self.sp.checked_sub(count).ok_or(VMError::StackUnderflow)
+    }
+
+    fn local_slot(&mut self, local_index: usize) -> Result<usize, VMError> {
+        let frame = self.current_frame();
+        if local_index >= frame.closure.func.num_locals {
+            return Err(invalid_index("local", local_index));
+        }
+        Ok(frame.base_pointer + local_index)
+    }
+
+    fn stack_slot(&self, index: usize) -> Result<&Value, VMError> {
+        self.stack
+            .get(index)
+            .ok_or_else(|| invalid_index("stack slot", index))
+    }
+
+    fn stack_slot_mut(&mut self, index: usize) -> Result<&mut Value, VMError> {
+        self.stack
+            .get_mut(index)
+            .ok_or_else(|| invalid_index("stack slot", index))
+    }
+
+    fn constant(&self, index: usize) -> Result<Value, VMError> {
+        self.constants
+            .get(index)
+            .cloned()
+            .ok_or_else(|| invalid_index("constant", index))
+    }
+
+    // Callers drop `start..end` from the stack, so the values are moved.
+    fn build_array(&mut self, start: usize, end: usize) -> Vec<Rc<Object>> {
+        self.stack[start..end]
+            .iter_mut()
+            .map(|v| std::mem::replace(v, Value::Null).into_rc_object())
+            .collect()
     }
 
     fn build_hash(
         &self,
         start: usize,
         end: usize,
-    ) -> Result<HashMap<HashKey, Rc<Object>>, VMError> {
-        let mut elements = HashMap::new();
+    ) -> Result<OrderedMap<HashKey, Rc<Object>>, VMError> {
+        let mut elements = OrderedMap::with_capacity((end - start) / 2);
         for i in (start..end).step_by(2) {
             let key = self.stack[i].into_rc_object();
             let hash_key = HashKey::try_from(key.as_ref())
-                .map_err(|()| VMError::UnusableAsHashKey(key.to_string()))?;
+                .map_err(|()| VMError::UnusableAsHashKey(key.type_name()))?;
             let value = self.stack[i + 1].into_rc_object();
             elements.insert(hash_key, value);
         }
@@ -514,10 +1233,14 @@ impl VM {
             (Value::Object(o), Value::Integer(i)) => match &**o {
                 Object::Array(arr) => self.execute_array_index(arr, *i),
                 Object::Hash(hash) => self.execute_hash_index(hash, index.into_rc_object()),
+                Object::String(s) => self.execute_string_index(s, *i),
                 _ => Err(VMError::UnsupportedIndexOperator(left.type_name())),
             },
             (Value::Object(o), _) => match &**o {
                 Object::Hash(hash) => self.execute_hash_index(hash, index.into_rc_object()),
+                Object::Utf8Error { offset } => self.push(Value::from_object(Rc::new(
+                    utf8_error_field(*offset, &index.into_rc_object()),
+                ))),
                 _ => Err(VMError::UnsupportedIndexOperator(left.type_name())),
             },
             _ => Err(VMError::UnsupportedIndexOperator(left.type_name())),
@@ -532,9 +1255,19 @@ impl VM {
         }
     }
 
+    // Indexes by char, so `"héllo"[1]` is "é" rather than half of it.
+    fn execute_string_index(&mut self, s: &str, index: i64) -> Result<(), VMError> {
+        match usize::try_from(index).ok().and_then(|i| s.chars().nth(i)) {
+            Some(c) => self.push(Value::from_object(Rc::new(Object::String(
+                c.to_string().into(),
+            )))),
+            None => self.push(Value::Null),
+        }
+    }
+
     fn execute_hash_index(
         &mut self,
-        hash: &HashMap<HashKey, Rc<Object>>,
+        hash: &OrderedMap<HashKey, Rc<Object>>,
         index: Rc<Object>,
     ) -> Result<(), VMError> {
         match HashKey::try_from(index.as_ref()) {
@@ -542,7 +1275,7 @@ impl VM {
                 Some(el) => self.push(Value::from_object(Rc::clone(el))),
                 None => self.push(Value::Null),
             },
-            Err(()) => Err(VMError::UnusableAsHashKey(index.to_string())),
+            Err(()) => Err(VMError::UnusableAsHashKey(index.type_name())),
         }
     }
 
@@ -551,12 +1284,19 @@ impl VM {
     }
 
     fn push_frame(&mut self, frame: Frame) -> Result<(), VMError> {
-        if self.frame_index >= MAX_FRAMES {
-            return Err(VMError::FrameOverflow);
+        if self.frame_index >= self.max_frames {
+            return Err(VMError::MaxFramesExceeded {
+                depth: self.frame_index,
+            });
         }
 
-        self.frames[self.frame_index] = frame;
+        // slots above `frame_index` hold returned frames, reused here
+        match self.frames.get_mut(self.frame_index) {
+            Some(slot) => *slot = frame,
+            None => self.frames.push(frame),
+        }
         self.frame_index += 1;
+        self.peak_frame_index = self.peak_frame_index.max(self.frame_index);
         Ok(())
     }
 
@@ -565,8 +1305,53 @@ impl VM {
         self.frames[self.frame_index].clone()
     }
 
+    fn calls_current_closure(&self, num_args: usize) -> Result<bool, VMError> {
+        let callee_index = self
+            .sp
+            .checked_sub(num_args + 1)
+            .ok_or(VMError::StackUnderflow)?;
+        let current = &self.frames[self.frame_index - 1].closure;
+        Ok(match &self.stack[callee_index] {
+            Value::Object(o) => matches!(&**o, Object::ClosureObj(callee)
+                if Rc::ptr_eq(&callee.func, &current.func)
+                    && callee.free.len() == current.free.len()
+                    && callee.free.iter().zip(&current.free).all(|(a, b)| Rc::ptr_eq(a, b))),
+            _ => false,
+        })
+    }
+
+    // Restarts the current function with the arguments on top of the stack,
+    // reusing its frame.
+    fn tail_call(&mut self, num_args: usize) -> Result<(), VMError> {
+        let base = self.current_frame().base_pointer;
+        let num_locals = self.current_frame().closure.func.num_locals;
+        let args_start = self.stack_start(num_args)?;
+        let num_parameters = self.current_frame().closure.func.num_parameters;
+        if num_args != num_parameters {
+            return Err(VMError::WrongArity {
+                expected: num_parameters,
+                got: num_args,
+            });
+        }
+        for i in 0..num_args {
+            let arg = std::mem::replace(self.stack_slot_mut(args_start + i)?, Value::Null);
+            *self.stack_slot_mut(base + i)? = arg;
+        }
+        self.reserve_stack(base + num_locals)?;
+        self.sp = base + num_locals;
+        self.current_frame().ip = -1;
+        if let Some(profiler) = &mut self.profiler {
+            profiler.call(&self.frames[self.frame_index - 1].closure.func);
+        }
+        Ok(())
+    }
+
     fn execute_call(&mut self, num_args: usize) -> Result<(), VMError> {
-        let callee = self.stack[self.sp - 1 - num_args].clone();
+        let callee_index = self
+            .sp
+            .checked_sub(num_args + 1)
+            .ok_or(VMError::StackUnderflow)?;
+        let callee = self.stack[callee_index].clone();
         match &callee {
             Value::Object(o) => match &**o {
                 Object::ClosureObj(cf) => self.call_closure(cf.clone(), num_args),
@@ -586,30 +1371,92 @@ impl VM {
         }
 
         let frame = Frame::new(cl.clone(), self.sp - num_args);
+        self.reserve_stack(frame.base_pointer + cl.func.num_locals)?;
         self.sp = frame.base_pointer + cl.func.num_locals;
+        self.peak_sp = self.peak_sp.max(self.sp);
+        if let Some(profiler) = &mut self.profiler {
+            profiler.call(&cl.func);
+        }
         self.push_frame(frame)
     }
 
-    fn call_builtin(&mut self, bt: object::BuiltinFunc, num_args: usize) -> Result<(), VMError> {
+    fn call_builtin(&mut self, bt: BuiltinFunc, num_args: usize) -> Result<(), VMError> {
+        // Arguments are moved off the stack so a builtin holding the only
+        // reference to a value (see `push`) can reuse it instead of copying.
         let args: Vec<Rc<Object>> = self.stack[self.sp - num_args..self.sp]
-            .iter()
-            .map(|v| v.into_rc_object())
+            .iter_mut()
+            .map(|v| std::mem::replace(v, Value::Null).into_rc_object())
             .collect();
-        let result = bt(args);
+        let result = match bt {
+            BuiltinFunc::Plain(bt) => bt(args),
+            BuiltinFunc::WithEngine(bt) => {
+                let mut engine = VmEngine {
+                    vm: self,
+                    error: None,
+                };
+                bt(args, &mut engine).map_err(|halt| match halt {
+                    Halt::CallFailed { builtin, index } => VMError::CallbackFailed {
+                        builtin,
+                        index,
+                        error: Box::new(
+                            engine.error.take().expect("a failed call keeps its error"),
+                        ),
+                    },
+                    Halt::AssertionFailed(message) => VMError::AssertionFailed(message),
+                })?
+            }
+        };
         self.sp = self.sp - num_args - 1;
         self.push(Value::from_object(result))
     }
 
+    // Calls `function` from inside a builtin: the call goes on the stack
+    // above the builtin's own arguments and, for a closure, runs until its
+    // frame returns. If the call fails, its frames and stack are dropped and
+    // the VM stops being resumable: the builtin that made the call is gone,
+    // so nothing could take the callback's result.
+    fn call_function(
+        &mut self,
+        function: &Rc<Object>,
+        args: Vec<Rc<Object>>,
+    ) -> Result<Rc<Object>, VMError> {
+        let depth = self.frame_index;
+        let sp = self.sp;
+        let result = self.run_callback(function, args, depth);
+        if result.is_err() {
+            self.frame_index = depth;
+            self.sp = sp;
+            self.resumable = false;
+        }
+        result
+    }
+
+    fn run_callback(
+        &mut self,
+        function: &Rc<Object>,
+        args: Vec<Rc<Object>>,
+        depth: usize,
+    ) -> Result<Rc<Object>, VMError> {
+        let num_args = args.len();
+        self.push(Value::from_object(Rc::clone(function)))?;
+        for arg in args {
+            self.push(Value::from_object(arg))?;
+        }
+        self.execute_call(num_args)?;
+        if self.frame_index > depth {
+            self.dispatch(depth)?;
+        }
+        Ok(self.pop()?.into_rc_object())
+    }
+
     fn push_closure(&mut self, const_index: usize, num_free: usize) -> Result<(), VMError> {
-        let constant = self.constants[const_index].clone();
+        let constant = self.constant(const_index)?;
         match &constant {
             Value::Object(o) => match &**o {
                 Object::CompiledFunction(f) => {
-                    let mut free = Vec::with_capacity(num_free);
-                    for i in 0..num_free {
-                        free.push(self.stack[self.sp - num_free + i].into_rc_object());
-                    }
-                    self.sp -= num_free;
+                    let start = self.stack_start(num_free)?;
+                    let free = self.build_array(start, self.sp);
+                    self.sp = start;
                     let closure = Object::ClosureObj(Closure {
                         func: f.clone(),
                         free,
@@ -622,22 +1469,286 @@ impl VM {
         }
     }
 
+    // One line per instruction: the same `ip opcode operands` text as the
+    // disassembler, then the index of the running frame (0 for the main
+    // program) and up to three values from the top of the stack, topmost
+    // last.
+    #[cfg(feature = "trace")]
+    fn trace_instruction(&mut self) -> Result<(), VMError> {
+        if self.trace.is_none() {
+            return Ok(());
+        }
+
+        let ip = self.current_frame().ip as usize;
+        let (instruction, _) = disassemble_instruction(self.current_frame().instructions(), ip)
+            .map_err(VMError::Opcode)?;
+        let stack = self.stack[self.sp.saturating_sub(3)..self.sp]
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+            .join(", ");
+        let frame = self.frame_index - 1;
+
+        if let Some(trace) = self.trace.as_mut() {
+            // A broken trace sink should not abort the program being traced.
+            let _ = writeln!(
+                trace,
+                "{:04} {:<20} frame={} stack=[{}]",
+                ip, instruction, frame, stack
+            );
+        }
+        Ok(())
+    }
+
     fn current_instruction_len(&mut self) -> usize {
         self.current_frame().instructions().len()
     }
 
-    fn current_instruction_byte(&mut self, offset: usize) -> u8 {
-        let ip = self.current_frame().ip as usize;
-        self.current_frame().instructions()[ip + offset]
+    fn current_instruction_byte(&mut self, offset: usize) -> Result<u8, VMError> {
+        let position = self.current_frame().ip as usize + offset;
+        self.current_frame()
+            .instructions()
+            .get(position)
+            .copied()
+            .ok_or_else(|| truncated_instruction(position))
     }
 
-    fn read_u8_operand(&mut self, offset: usize) -> u8 {
+    fn read_u8_operand(&mut self, offset: usize) -> Result<u8, VMError> {
         self.current_instruction_byte(offset)
     }
 
-    fn read_u16_operand(&mut self, offset: usize) -> usize {
-        let ip = self.current_frame().ip as usize;
-        let frame = self.current_frame();
-        BigEndian::read_u16(&frame.instructions()[ip + offset..ip + offset + 2]) as usize
+    fn read_u16_operand(&mut self, offset: usize) -> Result<usize, VMError> {
+        let position = self.current_frame().ip as usize + offset;
+        let bytes = self
+            .current_frame()
+            .instructions()
+            .get(position..position + 2)
+            .ok_or_else(|| truncated_instruction(position))?;
+        Ok(BigEndian::read_u16(bytes) as usize)
+    }
+}
+
+// Copy-on-write like the `push` builtin: a collection nothing else
+// references, such as one just moved out of its variable by OpTakeGlobal or
+// OpTakeLocal, is updated in place, and a shared one is copied.
+fn set_index(collection: Value, index: Value, value: Value) -> Result<Value, VMError> {
+    let mut collection = match collection {
+        Value::Object(obj) if matches!(&*obj, Object::Array(_) | Object::Hash(_)) => obj,
+        other => return Err(VMError::UnsupportedIndexOperator(other.type_name())),
+    };
+    match (Rc::make_mut(&mut collection), index) {
+        (Object::Array(elements), Value::Integer(i)) => {
+            let len = elements.len();
+            let slot = usize::try_from(i)
+                .ok()
+                .and_then(|i| elements.get_mut(i))
+                .ok_or(VMError::IndexError { index: i, len })?;
+            *slot = value.into_rc_object();
+        }
+        (Object::Hash(map), key) => {
+            let key = key.into_rc_object();
+            let hash_key = HashKey::try_from(key.as_ref())
+                .map_err(|()| VMError::UnusableAsHashKey(key.type_name()))?;
+            map.insert(hash_key, value.into_rc_object());
+        }
+        (array, _) => return Err(VMError::UnsupportedIndexOperator(array.type_name())),
+    }
+    Ok(Value::Object(collection))
+}
+
+// What a `for` loop walks by index: an array as it is, or a hash's keys in
+// insertion order, along with its length.
+fn iteration_sequence(iterable: Value) -> Result<(Value, usize), VMError> {
+    if let Value::Object(obj) = &iterable {
+        match &**obj {
+            Object::Array(elements) => {
+                let len = elements.len();
+                return Ok((iterable, len));
+            }
+            Object::Hash(map) => {
+                let keys: Vec<_> = map.keys().map(|key| Rc::new(Object::from(key))).collect();
+                let len = keys.len();
+                return Ok((Value::Object(Rc::new(Object::Array(keys))), len));
+            }
+            _ => {}
+        }
+    }
+    Err(VMError::NotIterable(iterable.type_name()))
+}
+
+// Only called with integers and floats; an integer is promoted to the
+// nearest float.
+fn as_float(value: &Value) -> f64 {
+    match value {
+        Value::Integer(i) => *i as f64,
+        Value::Float(x) => *x,
+        _ => unreachable!("as_float called with {}", value.type_name()),
+    }
+}
+
+fn value_size(value: &Value, seen: &mut SeenAllocations) -> usize {
+    match value {
+        Value::Object(obj) => object_size(obj, seen),
+        _ => 0,
+    }
+}
+
+// The objects and functions already counted, so a value shared through
+// several `Rc`s adds its bytes once; every reference still costs a slot.
+#[derive(Default)]
+struct SeenAllocations {
+    objects: HashSet<*const Object>,
+    functions: HashSet<*const object::CompiledFunction>,
+}
+
+impl SeenAllocations {
+    fn function_size(&mut self, func: &Rc<object::CompiledFunction>) -> usize {
+        match self.functions.insert(Rc::as_ptr(func)) {
+            true => func.instructions.capacity(),
+            false => 0,
+        }
+    }
+}
+
+// Values are acyclic, so the recursion always terminates.
+fn object_size(obj: &Rc<Object>, seen: &mut SeenAllocations) -> usize {
+    if !seen.objects.insert(Rc::as_ptr(obj)) {
+        return 0;
+    }
+    let reference = size_of::<Rc<Object>>();
+    size_of::<Object>()
+        + match &**obj {
+            Object::String(s) => s.len(),
+            Object::Error(s) => s.capacity(),
+            Object::Array(elements) => {
+                elements.capacity() * reference
+                    + elements.iter().map(|e| object_size(e, seen)).sum::<usize>()
+            }
+            Object::Hash(map) => {
+                map.capacity() * (size_of::<HashKey>() + reference)
+                    + map
+                        .iter()
+                        .map(|(key, value)| {
+                            let key_bytes = match key {
+                                HashKey::String(s) => s.len(),
+                                _ => 0,
+                            };
+                            key_bytes + object_size(value, seen)
+                        })
+                        .sum::<usize>()
+            }
+            Object::ReturnValue(inner) => object_size(inner, seen),
+            Object::CompiledFunction(func) => seen.function_size(func),
+            Object::ClosureObj(closure) => {
+                seen.function_size(&closure.func)
+                    + closure.free.capacity() * reference
+                    + closure
+                        .free
+                        .iter()
+                        .map(|f| object_size(f, seen))
+                        .sum::<usize>()
+            }
+            _ => 0,
+        }
+}
+
+/// Shrinks a REPL session's constant pool to what its live `globals` can
+/// still reach, returning how many constants were dropped.
+///
+/// Functions already compiled keep referring to constants by index, so only
+/// the unreferenced tail of the pool can go; the next compilation then hands
+/// out those indices again. A constant is referenced if an `OpConst` or
+/// `OpClosure` in any function reachable from a global, directly or through
+/// arrays, hashes, free variables and nested closures, names it.
+pub fn compact_constants(constants: &mut Vec<Rc<Object>>, globals: &[Value]) -> usize {
+    let mut objects: Vec<Rc<Object>> = globals
+        .iter()
+        .filter_map(|value| match value {
+            Value::Object(obj) => Some(Rc::clone(obj)),
+            _ => None,
+        })
+        .collect();
+    let mut seen_objects = HashSet::new();
+    let mut functions = vec![];
+    while let Some(obj) = objects.pop() {
+        if !seen_objects.insert(Rc::as_ptr(&obj)) {
+            continue;
+        }
+        match &*obj {
+            Object::Array(elements) => objects.extend(elements.iter().cloned()),
+            Object::Hash(map) => objects.extend(map.values().cloned()),
+            Object::ReturnValue(inner) => objects.push(Rc::clone(inner)),
+            Object::CompiledFunction(func) => functions.push(Rc::clone(func)),
+            Object::ClosureObj(closure) => {
+                functions.push(Rc::clone(&closure.func));
+                objects.extend(closure.free.iter().cloned());
+            }
+            _ => {}
+        }
+    }
+
+    let mut seen_functions = HashSet::new();
+    let mut live = 0;
+    while let Some(func) = functions.pop() {
+        if !seen_functions.insert(Rc::as_ptr(&func)) {
+            continue;
+        }
+        let mut pos = 0;
+        while pos < func.instructions.len() {
+            let Some((opcode, operands, width)) = decode_instruction(&func.instructions, pos)
+            else {
+                // can't tell what a malformed function refers to; keep it all
+                return 0;
+            };
+            if matches!(opcode, Opcode::OpConst | Opcode::OpClosure) {
+                live = live.max(operands[0] + 1);
+                if let Some(constant) = constants.get(operands[0])
+                    && let Object::CompiledFunction(inner) = &**constant
+                {
+                    functions.push(Rc::clone(inner));
+                }
+            }
+            pos += width;
+        }
     }
+
+    let reclaimed = constants.len().saturating_sub(live);
+    constants.truncate(live);
+    reclaimed
+}
+
+fn decode_instruction(bytes: &[u8], pos: usize) -> Option<(Opcode, Vec<usize>, usize)> {
+    let opcode = cast_u8_to_opcode(bytes[pos]).ok()?;
+    let def = definitions().get(&opcode)?;
+    let (operands, read) = read_operands(def, &bytes[pos + 1..]).ok()?;
+    Some((opcode, operands, 1 + read))
+}
+
+fn binary_type_error(op: Opcode, left: &Value, right: &Value) -> VMError {
+    let (left, right) = (left.type_name(), right.type_name());
+    if op == Opcode::OpAdd && (left == "STRING" || right == "STRING") {
+        VMError::StringConcatenation { left, right }
+    } else {
+        VMError::UnsupportedBinaryOperation { op, left, right }
+    }
+}
+
+// The offset of the instruction that `ip` points into.
+fn instruction_start(bytes: &[u8], ip: usize) -> usize {
+    let mut pos = 0;
+    while pos < bytes.len()
+        && let Some((_, _, width)) = decode_instruction(bytes, pos)
+        && pos + width <= ip
+    {
+        pos += width;
+    }
+    pos
+}
+
+fn invalid_index(kind: &str, index: usize) -> VMError {
+    VMError::InvalidBytecode(format!("{} index {} out of range", kind, index))
+}
+
+fn truncated_instruction(position: usize) -> VMError {
+    VMError::InvalidBytecode(format!("truncated instruction at position {}", position))
 }
@@ -1,8 +1,16 @@
 #[cfg(test)]
 mod tests {
+    use crate::compiler::Compiler;
+    use crate::differential_test::run_interpreter;
+    use crate::vm::{VM, VMError};
     use crate::vm_test::{VmTestCase, run_vm_tests};
-    use object::Object;
+    use object::{HashKey, Object};
+    use parser::parse;
+    use std::cell::{Cell, RefCell};
     use std::rc::Rc;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::time::Duration;
 
     #[test]
     fn test_function_without_arguments() {
@@ -144,6 +152,102 @@ mod tests {
         run_vm_tests(tests);
     }
 
+    #[test]
+    fn test_closures() {
+        let tests = vec![
+            VmTestCase {
+                input: "let newClosure = fn(a) { fn() { a; }; }; \
+                    let closure = newClosure(99); \
+                    closure();",
+                expected: Object::Integer(99),
+            },
+            VmTestCase {
+                input: "let newAdderOuter = fn(a, b) { \
+                        let c = a + b; \
+                        fn(d) { let e = d + c; fn(f) { e + f; }; }; \
+                    }; \
+                    let newAdderInner = newAdderOuter(1, 2); \
+                    let adder = newAdderInner(3); \
+                    adder(8);",
+                expected: Object::Integer(14),
+            },
+            VmTestCase {
+                input: "let a = 1; \
+                    let newAdderOuter = fn(b) { fn(c) { fn(d) { a + b + c + d }; }; }; \
+                    let newAdderInner = newAdderOuter(2); \
+                    let adder = newAdderInner(3); \
+                    adder(8);",
+                expected: Object::Integer(14),
+            },
+            VmTestCase {
+                input: "let newClosures = fn(a, b) { \
+                        let one = fn() { a; }; \
+                        let two = fn() { b; }; \
+                        fn() { one() + two(); }; \
+                    }; \
+                    let closure = newClosures(9, 90); \
+                    closure();",
+                expected: Object::Integer(99),
+            },
+        ];
+
+        run_vm_tests(tests);
+    }
+
+    #[test]
+    fn test_recursive_closures() {
+        let tests = vec![
+            VmTestCase {
+                input: "let countDown = fn(x) { if (x == 0) { return 0; } else { countDown(x - 1); } }; \
+                    countDown(1);",
+                expected: Object::Integer(0),
+            },
+            VmTestCase {
+                input: "let wrapper = fn() { \
+                        let countDown = fn(x) { if (x == 0) { 0 } else { countDown(x - 1) } }; \
+                        countDown(2) \
+                    }; \
+                    wrapper()",
+                expected: Object::Integer(0),
+            },
+            VmTestCase {
+                input: "let fibonacci = fn(x) { \
+                        if (x < 2) { x } else { fibonacci(x - 1) + fibonacci(x - 2) } \
+                    }; \
+                    fibonacci(15);",
+                expected: Object::Integer(610),
+            },
+        ];
+
+        run_vm_tests(tests);
+    }
+
+    #[test]
+    fn test_recursion_through_a_reassigned_global() {
+        // a global function calls whatever its name holds at the time, in
+        // both engines
+        let tests = vec![
+            VmTestCase {
+                input: "let r = fn(n) { if (n == 0) { 1 } else { 0 + r(n - 1) } }; \
+                    let g = r; \
+                    r = fn(n) { 42 }; \
+                    g(3)",
+                expected: Object::Integer(42),
+            },
+            // a call in tail position can't reuse the frame for another
+            // function
+            VmTestCase {
+                input: "let r = fn(n) { if (n == 0) { 1 } else { r(n - 1) } }; \
+                    let g = r; \
+                    r = fn(n) { n * 10 }; \
+                    g(3)",
+                expected: Object::Integer(20),
+            },
+        ];
+
+        run_vm_tests(tests);
+    }
+
     #[test]
     fn test_builtins() {
         let tests = vec![
@@ -159,10 +263,39 @@ mod tests {
                 input: "len(\"hello world\");",
                 expected: Object::Integer(11),
             },
-            // VmTestCase {
-            //     input: "len(1);",
-            //     expected: Object::Error("argument to `len` not supported, got INTEGER".to_string()),
-            // },
+            // chars rather than bytes, so the last index is `len(s) - 1`
+            VmTestCase {
+                input: "let s = \"héllo ✓\"; [len(s), s[len(s) - 1], len(encode(s))]",
+                expected: Object::Array(vec![
+                    Rc::new(Object::Integer(7)),
+                    Rc::new(Object::String("✓".into())),
+                    Rc::new(Object::Integer(10)),
+                ]),
+            },
+            VmTestCase {
+                input: "len(1);",
+                expected: Object::Error("builtin len expected argument 1 to be STRING, ARRAY, BYTES or HASH, got INTEGER".to_string()),
+            },
+            VmTestCase {
+                input: "len({});",
+                expected: Object::Integer(0),
+            },
+            VmTestCase {
+                input: "len({1: 2});",
+                expected: Object::Integer(1),
+            },
+            VmTestCase {
+                input: "len({1: 2, \"a\": 3, true: 4, 1: 5});",
+                expected: Object::Integer(3),
+            },
+            VmTestCase {
+                input: "len(fn(x) { x });",
+                expected: Object::Error("builtin len expected argument 1 to be STRING, ARRAY, BYTES or HASH, got CLOSURE".to_string()),
+            },
+            VmTestCase {
+                input: "len();",
+                expected: Object::Error("builtin len expected 1 argument, got 0".to_string()),
+            },
             VmTestCase {
                 input: "len(\"one\", \"two\");",
                 expected: Object::Error("builtin len expected 1 argument, got 2".to_string()),
@@ -183,10 +316,14 @@ mod tests {
                 input: "first([]);",
                 expected: Object::Null,
             },
-            // VmTestCase {
-            //     input: "first(1);",
-            //     expected: Object::Error("argument to `first` must be ARRAY, got INTEGER".to_string()),
-            // },
+            VmTestCase {
+                input: "first(1);",
+                expected: Object::Error("builtin first expected argument 1 to be ARRAY, got INTEGER".to_string()),
+            },
+            VmTestCase {
+                input: "first([1], [2]);",
+                expected: Object::Error("builtin first expected 1 argument, got 2".to_string()),
+            },
             VmTestCase {
                 input: "last([1, 2, 3]);",
                 expected: Object::Integer(3),
@@ -195,6 +332,14 @@ mod tests {
                 input: "last([]);",
                 expected: Object::Null,
             },
+            VmTestCase {
+                input: r#"last("abc");"#,
+                expected: Object::Error("builtin last expected argument 1 to be ARRAY, got STRING".to_string()),
+            },
+            VmTestCase {
+                input: "last();",
+                expected: Object::Error("builtin last expected 1 argument, got 0".to_string()),
+            },
             VmTestCase {
                 input: "rest([1, 2, 3]);",
                 expected: Object::Array(vec![
@@ -206,6 +351,30 @@ mod tests {
                 input: "rest([]);",
                 expected: Object::Null,
             },
+            VmTestCase {
+                input: "rest(rest([1, 2, 3]));",
+                expected: Object::Array(vec![Rc::from(Object::Integer(3))]),
+            },
+            VmTestCase {
+                input: "rest(rest(rest([1, 2, 3])));",
+                expected: Object::Array(vec![]),
+            },
+            VmTestCase {
+                input: "rest(rest(rest(rest([1, 2, 3]))));",
+                expected: Object::Null,
+            },
+            VmTestCase {
+                input: "first(rest([1, 2, 3])) + last(rest([1, 2, 3]));",
+                expected: Object::Integer(5),
+            },
+            VmTestCase {
+                input: "rest({});",
+                expected: Object::Error("builtin rest expected argument 1 to be ARRAY, got HASH".to_string()),
+            },
+            VmTestCase {
+                input: "rest([1], 2);",
+                expected: Object::Error("builtin rest expected 1 argument, got 2".to_string()),
+            },
             VmTestCase {
                 input: "push([], 1);",
                 expected: Object::Array(vec![Rc::from(Object::Integer(1))]),
@@ -213,4 +382,1655 @@ mod tests {
         ];
         run_vm_tests(tests);
     }
+
+    #[test]
+    fn test_starts_with_and_ends_with() {
+        let tests = vec![
+            VmTestCase {
+                input: r#"starts_with("monkey", "mon");"#,
+                expected: Object::Boolean(true),
+            },
+            VmTestCase {
+                input: r#"starts_with("monkey", "key");"#,
+                expected: Object::Boolean(false),
+            },
+            VmTestCase {
+                input: r#"ends_with("monkey", "key");"#,
+                expected: Object::Boolean(true),
+            },
+            VmTestCase {
+                input: r#"[starts_with("abc", ""), ends_with("", "")];"#,
+                expected: Object::Array(vec![Rc::from(Object::Boolean(true)); 2]),
+            },
+            VmTestCase {
+                input: r#"[starts_with("abc", "abc"), ends_with("abc", "abc")];"#,
+                expected: Object::Array(vec![Rc::from(Object::Boolean(true)); 2]),
+            },
+            VmTestCase {
+                input: r#"[starts_with("ab", "abc"), ends_with("bc", "abc")];"#,
+                expected: Object::Array(vec![Rc::from(Object::Boolean(false)); 2]),
+            },
+            VmTestCase {
+                input: r#"[starts_with("été", "é"), ends_with("naïve", "ïve"), starts_with("été", "e")];"#,
+                expected: Object::Array(vec![
+                    Rc::from(Object::Boolean(true)),
+                    Rc::from(Object::Boolean(true)),
+                    Rc::from(Object::Boolean(false)),
+                ]),
+            },
+            VmTestCase {
+                input: r#"starts_with(1, "a");"#,
+                expected: Object::Error(
+                    "builtin starts_with expected argument 1 to be STRING, got INTEGER".to_string(),
+                ),
+            },
+            VmTestCase {
+                input: r#"ends_with("a", [1]);"#,
+                expected: Object::Error(
+                    "builtin ends_with expected argument 2 to be STRING, got ARRAY".to_string(),
+                ),
+            },
+        ];
+        run_vm_tests(tests);
+    }
+
+    #[test]
+    fn test_substr() {
+        let string = |s: &str| Object::String(s.into());
+        let tests = vec![
+            VmTestCase {
+                input: r#"substr("monkey", 1, 3);"#,
+                expected: string("onk"),
+            },
+            VmTestCase {
+                input: r#"substr("héllo", 1, 2);"#,
+                expected: string("él"),
+            },
+            VmTestCase {
+                input: r#"substr("abc", 1, 10);"#,
+                expected: string("bc"),
+            },
+            VmTestCase {
+                input: r#"substr("abc", 5, 1);"#,
+                expected: string(""),
+            },
+            VmTestCase {
+                input: r#"substr("abc", -1, 1);"#,
+                expected: Object::Error(
+                    "builtin substr start must not be negative, got -1".to_string(),
+                ),
+            },
+            VmTestCase {
+                input: r#"substr("abc", 0, -2);"#,
+                expected: Object::Error(
+                    "builtin substr length must not be negative, got -2".to_string(),
+                ),
+            },
+            VmTestCase {
+                input: r#"substr([1], 0, 1);"#,
+                expected: Object::Error(
+                    "builtin substr expected argument 1 to be STRING, got ARRAY".to_string(),
+                ),
+            },
+            VmTestCase {
+                input: r#"substr("abc", 0);"#,
+                expected: Object::Error("builtin substr expected 3 arguments, got 2".to_string()),
+            },
+        ];
+        run_vm_tests(tests);
+    }
+
+    #[test]
+    fn test_pad_start_and_pad_end() {
+        let string = |s: &str| Object::String(s.into());
+        let tests = vec![
+            VmTestCase {
+                input: r#"pad_start("7", 3, "0");"#,
+                expected: string("007"),
+            },
+            VmTestCase {
+                input: r#"pad_end("ab", 4);"#,
+                expected: string("ab  "),
+            },
+            VmTestCase {
+                input: r#"pad_start("7", 6, "ab");"#,
+                expected: string("ababa7"),
+            },
+            VmTestCase {
+                input: r#"pad_end("né", 4, "·");"#,
+                expected: string("né··"),
+            },
+            VmTestCase {
+                input: r#"pad_start("héllo", 5, "-");"#,
+                expected: string("héllo"),
+            },
+            VmTestCase {
+                input: r#"pad_end("hello", 2, "-");"#,
+                expected: string("hello"),
+            },
+            VmTestCase {
+                input: r#"pad_start("x", 3, "");"#,
+                expected: Object::Error(
+                    "builtin pad_start fill string must not be empty".to_string(),
+                ),
+            },
+            VmTestCase {
+                input: r#"pad_end("x", "3");"#,
+                expected: Object::Error(
+                    "builtin pad_end expected argument 2 to be INTEGER, got STRING".to_string(),
+                ),
+            },
+            VmTestCase {
+                input: r#"if (false) { pad_start("a", 10000000) } else { 1 }"#,
+                expected: Object::Integer(1),
+            },
+            VmTestCase {
+                input: r#"pad_start("x", 100000000000);"#,
+                expected: Object::Error(format!(
+                    "builtin pad_start width 100000000000 exceeds the limit of {}",
+                    object::builtins::DEFAULT_MAX_ARRAY_LENGTH
+                )),
+            },
+            VmTestCase {
+                input: r#"pad_end("x");"#,
+                expected: Object::Error(
+                    "builtin pad_end expected 2 or 3 arguments, got 1".to_string(),
+                ),
+            },
+        ];
+        run_vm_tests(tests);
+    }
+
+    #[test]
+    fn test_parse_int() {
+        let error = |message: &str| Object::Error(message.to_string());
+        let tests = vec![
+            VmTestCase {
+                input: r#"parse_int("42");"#,
+                expected: Object::Integer(42),
+            },
+            VmTestCase {
+                input: r#"parse_int("  -17\n");"#,
+                expected: Object::Integer(-17),
+            },
+            VmTestCase {
+                input: r#"parse_int("+8");"#,
+                expected: Object::Integer(8),
+            },
+            VmTestCase {
+                input: r#"[parse_int("1011", 2), parse_int("777", 8), parse_int("ff", 16), parse_int("Zz", 36)];"#,
+                expected: Object::Array(vec![
+                    Rc::from(Object::Integer(11)),
+                    Rc::from(Object::Integer(511)),
+                    Rc::from(Object::Integer(255)),
+                    Rc::from(Object::Integer(1295)),
+                ]),
+            },
+            VmTestCase {
+                input: r#"parse_int("-9223372036854775808");"#,
+                expected: Object::Integer(i64::MIN),
+            },
+            VmTestCase {
+                input: r#"parse_int("9223372036854775808");"#,
+                expected: error(
+                    r#"builtin parse_int "9223372036854775808" does not fit in a 64-bit integer"#,
+                ),
+            },
+            VmTestCase {
+                input: r#"parse_int("12", 2);"#,
+                expected: error(r#"builtin parse_int cannot parse "12" in radix 2"#),
+            },
+            VmTestCase {
+                input: r#"parse_int("ff");"#,
+                expected: error(r#"builtin parse_int cannot parse "ff" in radix 10"#),
+            },
+            VmTestCase {
+                input: r#"parse_int("  ");"#,
+                expected: error("builtin parse_int cannot parse an empty string"),
+            },
+            VmTestCase {
+                input: r#"parse_int("1", 37);"#,
+                expected: error("builtin parse_int radix must be between 2 and 36, got 37"),
+            },
+        ];
+        run_vm_tests(tests);
+    }
+
+    #[test]
+    fn test_new_array() {
+        let tests = vec![
+            VmTestCase {
+                input: "new_array(0, 1);",
+                expected: Object::Array(vec![]),
+            },
+            VmTestCase {
+                input: "new_array(3, 0);",
+                expected: Object::Array(vec![Rc::from(Object::Integer(0)); 3]),
+            },
+            VmTestCase {
+                input: "len(new_array(1000, []));",
+                expected: Object::Integer(1000),
+            },
+            VmTestCase {
+                input: r#"let row = new_array(3, {"a": 1}); row[2]["a"] + len(row);"#,
+                expected: Object::Integer(4),
+            },
+            VmTestCase {
+                input: "new_array(10000000000, 0);",
+                expected: Object::Error(format!(
+                    "builtin new_array length 10000000000 exceeds the limit of {}",
+                    object::builtins::DEFAULT_MAX_ARRAY_LENGTH
+                )),
+            },
+            VmTestCase {
+                input: "new_array(-1, 0);",
+                expected: Object::Error(
+                    "builtin new_array length must not be negative, got -1".to_string(),
+                ),
+            },
+        ];
+        run_vm_tests(tests);
+    }
+
+    #[test]
+    fn test_max_array_length() {
+        object::builtins::set_max_array_length(4);
+        let tests = vec![
+            VmTestCase {
+                input: "len(new_array(4, 0))",
+                expected: Object::Integer(4),
+            },
+            VmTestCase {
+                input: "new_array(5, 0)",
+                expected: Object::Error(
+                    "builtin new_array length 5 exceeds the limit of 4".to_string(),
+                ),
+            },
+            VmTestCase {
+                input: "range(0, 5)",
+                expected: Object::Error(
+                    "builtin range length 5 exceeds the limit of 4".to_string(),
+                ),
+            },
+        ];
+        run_vm_tests(tests);
+        // other threads keep the default
+        let elsewhere = std::thread::spawn(object::builtins::max_array_length)
+            .join()
+            .unwrap();
+        assert_eq!(elsewhere, object::builtins::DEFAULT_MAX_ARRAY_LENGTH);
+        object::builtins::set_max_array_length(object::builtins::DEFAULT_MAX_ARRAY_LENGTH);
+    }
+
+    #[test]
+    fn test_push_keeps_value_semantics() {
+        let tests = vec![
+            VmTestCase {
+                input: "let a = [1]; let b = a; a = push(a, 2); [len(a), len(b)];",
+                expected: Object::Array(vec![
+                    Rc::from(Object::Integer(2)),
+                    Rc::from(Object::Integer(1)),
+                ]),
+            },
+            VmTestCase {
+                input: "let f = fn() { let xs = []; let ys = xs; xs = push(xs, 1); \
+                    [len(xs), len(ys)] }; f();",
+                expected: Object::Array(vec![
+                    Rc::from(Object::Integer(1)),
+                    Rc::from(Object::Integer(0)),
+                ]),
+            },
+            VmTestCase {
+                input: "let xs = [1]; let keep = fn() { xs }; xs = push(xs, len(xs)); \
+                    [xs, keep()];",
+                expected: Object::Array(vec![
+                    Rc::from(Object::Array(vec![
+                        Rc::from(Object::Integer(1)),
+                        Rc::from(Object::Integer(1)),
+                    ])),
+                    Rc::from(Object::Array(vec![
+                        Rc::from(Object::Integer(1)),
+                        Rc::from(Object::Integer(1)),
+                    ])),
+                ]),
+            },
+            VmTestCase {
+                input: "let xs = []; let i = 0; \
+                    while (i < 1000) { xs = push(xs, i); i = i + 1; }; \
+                    [len(xs), xs[999]];",
+                expected: Object::Array(vec![
+                    Rc::from(Object::Integer(1000)),
+                    Rc::from(Object::Integer(999)),
+                ]),
+            },
+        ];
+        run_vm_tests(tests);
+    }
+
+    #[test]
+    fn test_method_calls() {
+        let tests = vec![
+            VmTestCase {
+                input: "[1, 2, 3].len();",
+                expected: Object::Integer(3),
+            },
+            VmTestCase {
+                input: "[1, 2, 3].rest().first();",
+                expected: Object::Integer(2),
+            },
+            VmTestCase {
+                input: "[].push(1).push(2).last();",
+                expected: Object::Integer(2),
+            },
+            VmTestCase {
+                input: "let add = fn(a, b) { a + b }; 1.add(2).add(3);",
+                expected: Object::Integer(6),
+            },
+            VmTestCase {
+                input: "let xs = [[1, 2], [3]]; xs[0].len() + xs.last().len();",
+                expected: Object::Integer(3),
+            },
+            VmTestCase {
+                input: "let wrap = fn(f) { fn(x) { x.f() } }; wrap(len)(\"abc\");",
+                expected: Object::Integer(3),
+            },
+        ];
+        run_vm_tests(tests);
+    }
+
+    #[test]
+    fn test_env() {
+        // cargo sets this for the test process
+        let dir = match std::env::var("CARGO_MANIFEST_DIR") {
+            Ok(dir) => Object::String(dir.into()),
+            Err(_) => Object::Null,
+        };
+        let tests = vec![
+            VmTestCase {
+                input: r#"env("CARGO_MANIFEST_DIR")"#,
+                expected: dir.clone(),
+            },
+            VmTestCase {
+                input: r#"env()["CARGO_MANIFEST_DIR"]"#,
+                expected: dir,
+            },
+            VmTestCase {
+                input: r#"env("MONKEY_SURELY_UNSET_VARIABLE")"#,
+                expected: Object::Null,
+            },
+            VmTestCase {
+                input: r#"env("")"#,
+                expected: Object::Null,
+            },
+            VmTestCase {
+                input: "env(1)",
+                expected: Object::Error(
+                    "builtin env expected argument 1 to be STRING, got INTEGER".to_string(),
+                ),
+            },
+            VmTestCase {
+                input: r#"env("A", "B")"#,
+                expected: Object::Error("builtin env expected 0 or 1 arguments, got 2".to_string()),
+            },
+        ];
+        run_vm_tests(tests);
+    }
+
+    #[test]
+    fn test_env_disabled() {
+        let disabled =
+            Object::Error("builtin env is unavailable: environment access disabled".to_string());
+        object::builtins::set_allow_env(false);
+        let tests = vec![
+            VmTestCase {
+                input: r#"env("CARGO_MANIFEST_DIR")"#,
+                expected: disabled.clone(),
+            },
+            VmTestCase {
+                input: "env()",
+                expected: disabled,
+            },
+        ];
+        run_vm_tests(tests);
+        object::builtins::set_allow_env(true);
+    }
+
+    #[test]
+    fn test_args() {
+        let tests = vec![
+            VmTestCase {
+                input: "args()",
+                expected: Object::Array(vec![]),
+            },
+            VmTestCase {
+                input: "args(1)",
+                expected: Object::Error("builtin args expected 0 arguments, got 1".to_string()),
+            },
+        ];
+        run_vm_tests(tests);
+
+        object::builtins::set_script_args(vec!["a".to_string(), "b c".to_string()]);
+        let tests = vec![
+            VmTestCase {
+                input: "len(args())",
+                expected: Object::Integer(2),
+            },
+            VmTestCase {
+                input: "args()[1]",
+                expected: Object::String("b c".into()),
+            },
+        ];
+        run_vm_tests(tests);
+        object::builtins::set_script_args(vec![]);
+    }
+
+    #[test]
+    fn test_puts() {
+        let output = Rc::new(RefCell::new(Vec::<u8>::new()));
+        object::builtins::set_output(Some(output.clone()));
+        let tests = vec![
+            VmTestCase {
+                input: r#"puts("hello", 1 + 2)"#,
+                expected: Object::Null,
+            },
+            VmTestCase {
+                input: "print([1, 2])",
+                expected: Object::Null,
+            },
+        ];
+        run_vm_tests(tests);
+        object::builtins::set_output(None);
+
+        // each case runs on the VM and then on the interpreter
+        assert_eq!(
+            String::from_utf8(output.borrow().clone()).unwrap(),
+            "hello\n3\nhello\n3\n[1, 2]\n[1, 2]\n"
+        );
+    }
+
+    #[test]
+    fn test_sleep() {
+        let slept = Rc::new(RefCell::new(vec![]));
+        let recorder = Rc::clone(&slept);
+        object::builtins::set_sleeper(Some(Rc::new(move |slice| {
+            recorder.borrow_mut().push(slice)
+        })));
+        let tests = vec![
+            VmTestCase {
+                input: "sleep(25)",
+                expected: Object::Null,
+            },
+            VmTestCase {
+                input: "sleep(0)",
+                expected: Object::Null,
+            },
+            VmTestCase {
+                input: "sleep(-1)",
+                expected: Object::Error(
+                    "builtin sleep duration must not be negative, got -1".to_string(),
+                ),
+            },
+            VmTestCase {
+                input: r#"sleep("1")"#,
+                expected: Object::Error(
+                    "builtin sleep expected argument 1 to be INTEGER, got STRING".to_string(),
+                ),
+            },
+        ];
+        run_vm_tests(tests);
+        object::builtins::set_sleeper(None);
+
+        // the VM and then the interpreter each sleep 25ms
+        let ms = Duration::from_millis;
+        let slices = [ms(10), ms(10), ms(5)];
+        assert_eq!(*slept.borrow(), [slices, slices].concat());
+    }
+
+    #[test]
+    fn test_sleep_wakes_up_when_cancelled() {
+        let cancel = Arc::new(AtomicBool::new(false));
+        let slices = Rc::new(Cell::new(0));
+        let (flag, counter) = (Arc::clone(&cancel), Rc::clone(&slices));
+        object::builtins::set_sleeper(Some(Rc::new(move |_| {
+            counter.set(counter.get() + 1);
+            if counter.get() == 3 {
+                flag.store(true, Ordering::Relaxed);
+            }
+        })));
+
+        // the VM stops the script as soon as `sleep` returns
+        let program = parse("sleep(60000); 1").unwrap();
+        let mut vm = VM::new(Compiler::new().compile(&program).unwrap());
+        vm.set_cancel_token(Some(Arc::clone(&cancel)));
+        let err = vm.run().unwrap_err().without_location();
+        assert!(matches!(err, VMError::Cancelled { .. }), "got {:?}", err);
+        assert_eq!(slices.get(), 3);
+
+        // the interpreter only checks in `sleep`, which returns the error
+        cancel.store(false, Ordering::Relaxed);
+        slices.set(0);
+        interpreter::set_cancel_token(Some(Arc::clone(&cancel)));
+        let evaluated = run_interpreter("sleep(60000)").unwrap();
+        interpreter::set_cancel_token(None);
+        object::builtins::set_sleeper(None);
+        assert_eq!(
+            *evaluated,
+            Object::Error("builtin sleep interrupted".to_string())
+        );
+        assert_eq!(slices.get(), 3);
+    }
+
+    #[test]
+    fn test_deep_equal() {
+        let tests = vec![
+            VmTestCase {
+                input: r#"deep_equal([1, [2, {"a": [3]}]], [1, [2, {"a": [3]}]])"#,
+                expected: Object::Boolean(true),
+            },
+            VmTestCase {
+                input: r#"deep_equal([1, [2, {"a": [3]}]], [1, [2, {"a": [4]}]])"#,
+                expected: Object::Boolean(false),
+            },
+            VmTestCase {
+                input: r#"deep_equal({"a": 1, "b": 2}, {"b": 2, "a": 1})"#,
+                expected: Object::Boolean(true),
+            },
+            VmTestCase {
+                input: r#"deep_equal({"a": 1}, {"a": 1, "b": 2})"#,
+                expected: Object::Boolean(false),
+            },
+            VmTestCase {
+                input: r#"deep_equal([1], 1)"#,
+                expected: Object::Boolean(false),
+            },
+            VmTestCase {
+                input: r#"deep_equal("a", "a")"#,
+                expected: Object::Boolean(true),
+            },
+            VmTestCase {
+                input: "let nest = fn(n) { let a = []; let i = 0; \
+                    while (i < n) { a = [a]; i = i + 1; }; a }; \
+                    deep_equal(nest(2000), nest(2000))",
+                expected: Object::Boolean(true),
+            },
+        ];
+        run_vm_tests(tests);
+    }
+
+    #[test]
+    fn test_clone() {
+        let tests = vec![
+            VmTestCase {
+                input: r#"let a = [1, {"b": [2]}]; let c = clone(a); deep_equal(a, c)"#,
+                expected: Object::Boolean(true),
+            },
+            VmTestCase {
+                input: r#"let a = [1, [2]]; let c = push(clone(a), 3); [len(a), len(c)]"#,
+                expected: Object::Array(vec![
+                    Rc::new(Object::Integer(2)),
+                    Rc::new(Object::Integer(3)),
+                ]),
+            },
+            VmTestCase {
+                input: "clone(5)",
+                expected: Object::Integer(5),
+            },
+            VmTestCase {
+                input: "let a = []; let i = 0; \
+                    while (i < 1024) { a = [a]; i = i + 1; }; clone(a)",
+                expected: Object::Error(
+                    "builtin clone nesting exceeds the limit of 1024".to_string(),
+                ),
+            },
+            VmTestCase {
+                input: "clone()",
+                expected: Object::Error("builtin clone expected 1 argument, got 0".to_string()),
+            },
+        ];
+        run_vm_tests(tests);
+    }
+
+    fn array(elements: Vec<Object>) -> Object {
+        Object::Array(elements.into_iter().map(Rc::new).collect())
+    }
+
+    fn string(s: &str) -> Object {
+        Object::String(s.into())
+    }
+
+    #[test]
+    fn test_zip() {
+        let tests = vec![
+            VmTestCase {
+                input: r#"zip([1, 2, 3], ["a", "b"])"#,
+                expected: array(vec![
+                    array(vec![Object::Integer(1), string("a")]),
+                    array(vec![Object::Integer(2), string("b")]),
+                ]),
+            },
+            VmTestCase {
+                input: r#"zip([1, 2], ["a", "b", "c"], [true, false])"#,
+                expected: array(vec![
+                    array(vec![Object::Integer(1), string("a"), Object::Boolean(true)]),
+                    array(vec![
+                        Object::Integer(2),
+                        string("b"),
+                        Object::Boolean(false),
+                    ]),
+                ]),
+            },
+            VmTestCase {
+                input: "zip([], [1, 2])",
+                expected: array(vec![]),
+            },
+            VmTestCase {
+                input: "zip([], [])",
+                expected: array(vec![]),
+            },
+            VmTestCase {
+                input: "zip([1, 2])",
+                expected: array(vec![
+                    array(vec![Object::Integer(1)]),
+                    array(vec![Object::Integer(2)]),
+                ]),
+            },
+            VmTestCase {
+                input: r#"zip([1], "ab")"#,
+                expected: Object::Error(
+                    "builtin zip expected argument 2 to be ARRAY, got STRING".to_string(),
+                ),
+            },
+            VmTestCase {
+                input: "zip()",
+                expected: Object::Error(
+                    "builtin zip expected at least 1 argument, got 0".to_string(),
+                ),
+            },
+        ];
+        run_vm_tests(tests);
+    }
+
+    fn ints(values: &[i64]) -> Object {
+        array(values.iter().copied().map(Object::Integer).collect())
+    }
+
+    #[test]
+    fn test_hash_builtins() {
+        let tests = vec![
+            VmTestCase {
+                input: r#"keys({2: "b", 1: "a"})"#,
+                expected: array(vec![Object::Integer(1), Object::Integer(2)]),
+            },
+            VmTestCase {
+                input: r#"values({2: "b", 1: "a"})"#,
+                expected: array(vec![string("a"), string("b")]),
+            },
+            VmTestCase {
+                input: r#"keys({"b": 1, true: 2, -3: 3, "a": 4, false: 5})"#,
+                expected: array(vec![
+                    Object::Integer(-3),
+                    Object::Boolean(false),
+                    Object::Boolean(true),
+                    string("a"),
+                    string("b"),
+                ]),
+            },
+            VmTestCase {
+                input: "keys({})",
+                expected: array(vec![]),
+            },
+            VmTestCase {
+                input: r#"has_key({"a": 1}, "a")"#,
+                expected: Object::Boolean(true),
+            },
+            VmTestCase {
+                input: r#"has_key({"a": 1}, 1)"#,
+                expected: Object::Boolean(false),
+            },
+            VmTestCase {
+                input: "keys([1])",
+                expected: Object::Error(
+                    "builtin keys expected argument 1 to be HASH, got ARRAY".to_string(),
+                ),
+            },
+            VmTestCase {
+                input: "values({}, {})",
+                expected: Object::Error("builtin values expected 1 argument, got 2".to_string()),
+            },
+            VmTestCase {
+                input: "has_key({})",
+                expected: Object::Error("builtin has_key expected 2 arguments, got 1".to_string()),
+            },
+            VmTestCase {
+                input: "has_key({}, [1])",
+                expected: Object::Error(
+                    "builtin has_key expected argument 2 to be INTEGER, BOOLEAN or STRING, got ARRAY"
+                        .to_string(),
+                ),
+            },
+        ];
+        run_vm_tests(tests);
+    }
+
+    #[test]
+    fn test_delete_and_contains() {
+        let hash = |entries: Vec<(i64, Object)>| {
+            Object::Hash(
+                entries
+                    .into_iter()
+                    .map(|(key, value)| (HashKey::Integer(key), Rc::new(value)))
+                    .collect(),
+            )
+        };
+        let tests = vec![
+            VmTestCase {
+                input: r#"delete({1: "a", 2: "b"}, 1)"#,
+                expected: hash(vec![(2, string("b"))]),
+            },
+            VmTestCase {
+                input: r#"delete({1: "a"}, 3)"#,
+                expected: hash(vec![(1, string("a"))]),
+            },
+            // the original is left alone
+            VmTestCase {
+                input: r#"let h = {1: "a"}; let g = delete(h, 1); [len(keys(g)), h[1]]"#,
+                expected: array(vec![Object::Integer(0), string("a")]),
+            },
+            VmTestCase {
+                input: "delete({}, [1])",
+                expected: Object::Error(
+                    "builtin delete expected argument 2 to be INTEGER, BOOLEAN or STRING, got ARRAY"
+                        .to_string(),
+                ),
+            },
+            VmTestCase {
+                input: "delete([1], 0)",
+                expected: Object::Error(
+                    "builtin delete expected argument 1 to be HASH, got ARRAY".to_string(),
+                ),
+            },
+            VmTestCase {
+                input: "delete({})",
+                expected: Object::Error("builtin delete expected 2 arguments, got 1".to_string()),
+            },
+            VmTestCase {
+                input: r#"contains([1, "two", [3]], [3])"#,
+                expected: Object::Boolean(true),
+            },
+            VmTestCase {
+                input: r#"contains([1, "two"], "three")"#,
+                expected: Object::Boolean(false),
+            },
+            VmTestCase {
+                input: "contains([], 1)",
+                expected: Object::Boolean(false),
+            },
+            VmTestCase {
+                input: "let xs = [1, 2]; contains(xs, 1 + 1)",
+                expected: Object::Boolean(true),
+            },
+            VmTestCase {
+                input: r#"contains("abc", "a")"#,
+                expected: Object::Error(
+                    "builtin contains expected argument 1 to be ARRAY, got STRING".to_string(),
+                ),
+            },
+        ];
+        run_vm_tests(tests);
+    }
+
+    #[test]
+    fn test_type() {
+        let tests = [
+            ("type(1)", "INTEGER"),
+            ("type(1.5)", "FLOAT"),
+            ("type(true)", "BOOLEAN"),
+            (r#"type("a")"#, "STRING"),
+            (r#"type(encode("a"))"#, "BYTES"),
+            ("type([])", "ARRAY"),
+            ("type({})", "HASH"),
+            ("type(if (false) { 1 })", "NULL"),
+            ("type(fn() {})", "CLOSURE"),
+            ("let f = fn(x) { x }; type(f)", "CLOSURE"),
+            ("type(len)", "BUILTIN"),
+            ("type(type(1))", "STRING"),
+        ]
+        .into_iter()
+        .map(|(input, name)| VmTestCase {
+            input,
+            expected: string(name),
+        })
+        .chain([
+            VmTestCase {
+                input: "type()",
+                expected: Object::Error("builtin type expected 1 argument, got 0".to_string()),
+            },
+            VmTestCase {
+                input: "type(1, 2)",
+                expected: Object::Error("builtin type expected 1 argument, got 2".to_string()),
+            },
+        ])
+        .collect();
+        run_vm_tests(tests);
+    }
+
+    #[test]
+    fn test_string_builtins() {
+        let strings = |parts: &[&str]| array(parts.iter().map(|part| string(part)).collect());
+        let tests = vec![
+            VmTestCase {
+                input: r#"split("a,b,c", ",")"#,
+                expected: strings(&["a", "b", "c"]),
+            },
+            VmTestCase {
+                input: r#"join(split("a,b,c", ","), ",")"#,
+                expected: string("a,b,c"),
+            },
+            VmTestCase {
+                input: r#"split("a--b--", "--")"#,
+                expected: strings(&["a", "b", ""]),
+            },
+            VmTestCase {
+                input: r#"split("héllo", "")"#,
+                expected: strings(&["h", "é", "l", "l", "o"]),
+            },
+            VmTestCase {
+                input: r#"len(split("héllo", ""))"#,
+                expected: Object::Integer(5),
+            },
+            VmTestCase {
+                input: r#"split("", "")"#,
+                expected: array(vec![]),
+            },
+            VmTestCase {
+                input: r#"join([], "-")"#,
+                expected: string(""),
+            },
+            VmTestCase {
+                input: r#"join(["a", 1], "-")"#,
+                expected: Object::Error(
+                    "builtin join expected element 1 to be STRING, got INTEGER".to_string(),
+                ),
+            },
+            VmTestCase {
+                input: r#"join("ab", "")"#,
+                expected: Object::Error(
+                    "builtin join expected argument 1 to be ARRAY, got STRING".to_string(),
+                ),
+            },
+            VmTestCase {
+                input: r#"split("a,b", 1)"#,
+                expected: Object::Error(
+                    "builtin split expected argument 2 to be STRING, got INTEGER".to_string(),
+                ),
+            },
+            VmTestCase {
+                input: r#"trim("  hi there \n")"#,
+                expected: string("hi there"),
+            },
+            VmTestCase {
+                input: r#"upper("héllo")"#,
+                expected: string("HÉLLO"),
+            },
+            VmTestCase {
+                input: r#"lower("HÉLLO")"#,
+                expected: string("héllo"),
+            },
+            VmTestCase {
+                input: "upper(1)",
+                expected: Object::Error(
+                    "builtin upper expected argument 1 to be STRING, got INTEGER".to_string(),
+                ),
+            },
+            VmTestCase {
+                input: r#"trim("a", "b")"#,
+                expected: Object::Error("builtin trim expected 1 argument, got 2".to_string()),
+            },
+        ];
+        run_vm_tests(tests);
+    }
+
+    #[test]
+    fn test_conversions() {
+        let tests = vec![
+            VmTestCase {
+                input: r#"to_int("42")"#,
+                expected: Object::Integer(42),
+            },
+            VmTestCase {
+                input: r#"to_int("  -17 ")"#,
+                expected: Object::Integer(-17),
+            },
+            VmTestCase {
+                input: "to_int(-5)",
+                expected: Object::Integer(-5),
+            },
+            VmTestCase {
+                input: "[to_int(true), to_int(false)]",
+                expected: array(vec![Object::Integer(1), Object::Integer(0)]),
+            },
+            VmTestCase {
+                input: r#"to_int("4 2")"#,
+                expected: Object::Null,
+            },
+            VmTestCase {
+                input: r#"to_int("")"#,
+                expected: Object::Null,
+            },
+            VmTestCase {
+                input: r#"to_int("99999999999999999999")"#,
+                expected: Object::Null,
+            },
+            VmTestCase {
+                input: "to_int([1])",
+                expected: Object::Error(
+                    "builtin to_int expected argument 1 to be STRING, INTEGER or BOOLEAN, got ARRAY"
+                        .to_string(),
+                ),
+            },
+            VmTestCase {
+                input: r#""n = " + to_string(-12)"#,
+                expected: string("n = -12"),
+            },
+            VmTestCase {
+                input: r#"to_string([1, "a", true])"#,
+                expected: string("[1, a, true]"),
+            },
+            VmTestCase {
+                input: "to_int(to_string(-123)) == -123",
+                expected: Object::Boolean(true),
+            },
+            VmTestCase {
+                input: r#"to_string(to_int("0042"))"#,
+                expected: string("42"),
+            },
+            VmTestCase {
+                input: "to_string()",
+                expected: Object::Error("builtin to_string expected 1 argument, got 0".to_string()),
+            },
+        ];
+        run_vm_tests(tests);
+    }
+
+    #[test]
+    fn test_math_builtins() {
+        let error = |message: &str| Object::Error(message.to_string());
+        let tests = vec![
+            VmTestCase {
+                input: "[abs(-3), abs(0), abs(4)]",
+                expected: array(vec![
+                    Object::Integer(3),
+                    Object::Integer(0),
+                    Object::Integer(4),
+                ]),
+            },
+            VmTestCase {
+                input: "abs(-9223372036854775807 - 1)",
+                expected: error("builtin abs overflowed"),
+            },
+            VmTestCase {
+                input: "[min(3, -1, 2), max(3, -1, 2), min(5, 5)]",
+                expected: array(vec![
+                    Object::Integer(-1),
+                    Object::Integer(3),
+                    Object::Integer(5),
+                ]),
+            },
+            VmTestCase {
+                input: "min(1)",
+                expected: error("builtin min expected at least 2 arguments, got 1"),
+            },
+            VmTestCase {
+                input: r#"max(1, "2")"#,
+                expected: error("builtin max expected argument 2 to be INTEGER, got STRING"),
+            },
+            VmTestCase {
+                input: "pow(2, 62)",
+                expected: Object::Integer(1 << 62),
+            },
+            VmTestCase {
+                input: "[pow(7, 0), pow(0, 0), pow(-2, 3), pow(-1, 1000001)]",
+                expected: array(vec![
+                    Object::Integer(1),
+                    Object::Integer(1),
+                    Object::Integer(-8),
+                    Object::Integer(-1),
+                ]),
+            },
+            VmTestCase {
+                input: "pow(2, 63)",
+                expected: error("builtin pow overflowed"),
+            },
+            VmTestCase {
+                input: "pow(2, 64)",
+                expected: error("builtin pow overflowed"),
+            },
+            VmTestCase {
+                input: "pow(-2, 63)",
+                expected: Object::Integer(i64::MIN),
+            },
+            VmTestCase {
+                input: "pow(2, -1)",
+                expected: error("builtin pow exponent must not be negative, got -1"),
+            },
+            VmTestCase {
+                input: "pow(2)",
+                expected: error("builtin pow expected 2 arguments, got 1"),
+            },
+        ];
+        run_vm_tests(tests);
+    }
+
+    #[test]
+    fn test_higher_order_builtins() {
+        let error = |message: &str| Object::Error(message.to_string());
+        let tests = vec![
+            VmTestCase {
+                input: "map([1, 2, 3], fn(x) { x * 2 })",
+                expected: array(vec![
+                    Object::Integer(2),
+                    Object::Integer(4),
+                    Object::Integer(6),
+                ]),
+            },
+            VmTestCase {
+                input: "reduce([1, 2, 3, 4], 0, fn(sum, x) { sum + x })",
+                expected: Object::Integer(10),
+            },
+            VmTestCase {
+                input: "reduce([], 7, fn(sum, x) { sum + x })",
+                expected: Object::Integer(7),
+            },
+            VmTestCase {
+                input: "filter([1, 2, 3, 4, 5], fn(x) { x % 2 == 1 })",
+                expected: array(vec![
+                    Object::Integer(1),
+                    Object::Integer(3),
+                    Object::Integer(5),
+                ]),
+            },
+            VmTestCase {
+                input: "filter([1, 2, 3], fn(x) { if (x != 2) { x } })",
+                expected: array(vec![Object::Integer(1), Object::Integer(3)]),
+            },
+            VmTestCase {
+                input: r#"map(["a", "bb"], len)"#,
+                expected: array(vec![Object::Integer(1), Object::Integer(2)]),
+            },
+            VmTestCase {
+                // closures keep their free variables when called back
+                input: "let scale = fn(k) { fn(x) { x * k } }; map([1, 2], scale(10))",
+                expected: array(vec![Object::Integer(10), Object::Integer(20)]),
+            },
+            VmTestCase {
+                // and can call higher-order builtins themselves
+                input: "map([[1, 2], [3]], fn(row) { reduce(row, 0, fn(a, b) { a + b }) })",
+                expected: array(vec![Object::Integer(3), Object::Integer(3)]),
+            },
+            VmTestCase {
+                input: "let f = fn(x) { return x + 1; }; let y = map([1], f)[0]; y * 10",
+                expected: Object::Integer(20),
+            },
+            VmTestCase {
+                input: r#"map([1, "a"], len)"#,
+                expected: error(
+                    "builtin len expected argument 1 to be STRING, ARRAY, BYTES or HASH, got INTEGER",
+                ),
+            },
+            VmTestCase {
+                input: "map(1, len)",
+                expected: error("builtin map expected argument 1 to be ARRAY, got INTEGER"),
+            },
+            VmTestCase {
+                input: "filter([1], 2)",
+                expected: error("builtin filter expected argument 2 to be FUNCTION, got INTEGER"),
+            },
+            VmTestCase {
+                input: "reduce([1], fn(a, b) { a })",
+                expected: error("builtin reduce expected 3 arguments, got 2"),
+            },
+        ];
+        run_vm_tests(tests);
+    }
+
+    #[test]
+    fn test_range() {
+        let error = |message: &str| Object::Error(message.to_string());
+        let tests = vec![
+            VmTestCase {
+                input: "range(0, 5)",
+                expected: array(vec![
+                    Object::Integer(0),
+                    Object::Integer(1),
+                    Object::Integer(2),
+                    Object::Integer(3),
+                    Object::Integer(4),
+                ]),
+            },
+            VmTestCase {
+                input: "range(1, 10, 4)",
+                expected: array(vec![
+                    Object::Integer(1),
+                    Object::Integer(5),
+                    Object::Integer(9),
+                ]),
+            },
+            VmTestCase {
+                input: "range(5, 1, -1)",
+                expected: array(vec![
+                    Object::Integer(5),
+                    Object::Integer(4),
+                    Object::Integer(3),
+                    Object::Integer(2),
+                ]),
+            },
+            VmTestCase {
+                input: "range(10, -2, -5)",
+                expected: array(vec![
+                    Object::Integer(10),
+                    Object::Integer(5),
+                    Object::Integer(0),
+                ]),
+            },
+            VmTestCase {
+                input: "range(5, 1)",
+                expected: array(vec![]),
+            },
+            VmTestCase {
+                input: "range(3, 3)",
+                expected: array(vec![]),
+            },
+            VmTestCase {
+                input: "range(1, 5, -1)",
+                expected: array(vec![]),
+            },
+            VmTestCase {
+                input: "range(9223372036854775806, 9223372036854775807, 9223372036854775807)",
+                expected: array(vec![Object::Integer(9223372036854775806)]),
+            },
+            VmTestCase {
+                input: "range(1, 5, 0)",
+                expected: error("builtin range step must not be 0"),
+            },
+            VmTestCase {
+                input: "range(0, 100000000)",
+                expected: error("builtin range length 100000000 exceeds the limit of 16777216"),
+            },
+            VmTestCase {
+                input: r#"range(0, "5")"#,
+                expected: error("builtin range expected argument 2 to be INTEGER, got STRING"),
+            },
+            VmTestCase {
+                input: "range(5)",
+                expected: error("builtin range expected 2 or 3 arguments, got 1"),
+            },
+        ];
+        run_vm_tests(tests);
+    }
+
+    #[test]
+    fn test_assert() {
+        let error = |message: &str| Object::Error(message.to_string());
+        let tests = vec![
+            VmTestCase {
+                input: r#"assert(1 < 2, "ordering")"#,
+                expected: Object::Null,
+            },
+            VmTestCase {
+                input: "assert([])",
+                expected: Object::Null,
+            },
+            VmTestCase {
+                input: "assert(false, 1)",
+                expected: error("builtin assert expected argument 2 to be STRING, got INTEGER"),
+            },
+            VmTestCase {
+                input: "assert()",
+                expected: error("builtin assert expected 1 or 2 arguments, got 0"),
+            },
+        ];
+        run_vm_tests(tests);
+    }
+
+    #[test]
+    fn test_clock_and_random() {
+        let error = |message: &str| Object::Error(message.to_string());
+        let tests = vec![
+            VmTestCase {
+                input: "clock() > 1600000000000",
+                expected: Object::Boolean(true),
+            },
+            VmTestCase {
+                input: "seed(42); let a = [rand(100), rand(100), rand(100)]; \
+                        seed(42); deep_equal(a, [rand(100), rand(100), rand(100)])",
+                expected: Object::Boolean(true),
+            },
+            VmTestCase {
+                // both engines draw the same sequence
+                input: "seed(7); [rand(1000), rand(1000), rand(1000)]",
+                expected: array(vec![
+                    Object::Integer(748),
+                    Object::Integer(684),
+                    Object::Integer(997),
+                ]),
+            },
+            VmTestCase {
+                input: "seed(-3); let xs = map(range(0, 200), fn(i) { rand(6) }); \
+                        [contains(xs, 0), contains(xs, 5), filter(xs, fn(x) { x < 0 || x > 5 })]",
+                expected: array(vec![
+                    Object::Boolean(true),
+                    Object::Boolean(true),
+                    array(vec![]),
+                ]),
+            },
+            VmTestCase {
+                input: "rand(0)",
+                expected: error("builtin rand bound must be positive, got 0"),
+            },
+            VmTestCase {
+                input: r#"seed("x")"#,
+                expected: error("builtin seed expected argument 1 to be INTEGER, got STRING"),
+            },
+            VmTestCase {
+                input: "clock(1)",
+                expected: error("builtin clock expected 0 arguments, got 1"),
+            },
+        ];
+        run_vm_tests(tests);
+    }
+
+    #[test]
+    fn test_file_builtins_need_permission() {
+        let error = |message: &str| Object::Error(message.to_string());
+        let tests = vec![
+            VmTestCase {
+                input: r#"read_file("Cargo.toml")"#,
+                expected: error("builtin read_file is unavailable: file access disabled"),
+            },
+            VmTestCase {
+                input: r#"write_file("out.txt", "x")"#,
+                expected: error("builtin write_file is unavailable: file access disabled"),
+            },
+            VmTestCase {
+                input: "read_file()",
+                expected: error("builtin read_file expected 1 argument, got 0"),
+            },
+        ];
+        run_vm_tests(tests);
+    }
+
+    #[test]
+    fn test_sort_and_reverse() {
+        let error = |message: &str| Object::Error(message.to_string());
+        let tests = vec![
+            VmTestCase {
+                input: "sort([3, 1, 2])",
+                expected: array(vec![
+                    Object::Integer(1),
+                    Object::Integer(2),
+                    Object::Integer(3),
+                ]),
+            },
+            VmTestCase {
+                input: "sort([1, 2, 3, 4])",
+                expected: array(vec![
+                    Object::Integer(1),
+                    Object::Integer(2),
+                    Object::Integer(3),
+                    Object::Integer(4),
+                ]),
+            },
+            VmTestCase {
+                input: "sort([2, -1, 2, 0, -1])",
+                expected: array(vec![
+                    Object::Integer(-1),
+                    Object::Integer(-1),
+                    Object::Integer(0),
+                    Object::Integer(2),
+                    Object::Integer(2),
+                ]),
+            },
+            VmTestCase {
+                input: "sort([])",
+                expected: array(vec![]),
+            },
+            VmTestCase {
+                input: r#"sort(["pear", "apple", "Zebra", "fig"])"#,
+                expected: array(vec![
+                    string("Zebra"),
+                    string("apple"),
+                    string("fig"),
+                    string("pear"),
+                ]),
+            },
+            VmTestCase {
+                // the original is left alone
+                input: "let xs = [2, 1]; let ys = sort(xs); [xs, ys]",
+                expected: array(vec![
+                    array(vec![Object::Integer(2), Object::Integer(1)]),
+                    array(vec![Object::Integer(1), Object::Integer(2)]),
+                ]),
+            },
+            VmTestCase {
+                input: "sort([5, 1, 4, 2, 3], fn(a, b) { b - a })",
+                expected: array(vec![
+                    Object::Integer(5),
+                    Object::Integer(4),
+                    Object::Integer(3),
+                    Object::Integer(2),
+                    Object::Integer(1),
+                ]),
+            },
+            VmTestCase {
+                // stable: pairs with equal keys keep their order
+                input: "let byFirst = fn(a, b) { a[0] - b[0] }; \
+                        map(sort([[2, 1], [1, 2], [2, 3], [1, 4]], byFirst), fn(p) { p[1] })",
+                expected: array(vec![
+                    Object::Integer(2),
+                    Object::Integer(4),
+                    Object::Integer(1),
+                    Object::Integer(3),
+                ]),
+            },
+            VmTestCase {
+                // an inconsistent comparator still gives some order
+                input: "len(sort([3, 1, 2, 5, 4], fn(a, b) { 1 }))",
+                expected: Object::Integer(5),
+            },
+            VmTestCase {
+                input: r#"sort([1, "a"])"#,
+                expected: error("builtin sort cannot compare INTEGER with STRING"),
+            },
+            VmTestCase {
+                input: "sort([true, false])",
+                expected: error("builtin sort cannot order BOOLEAN elements without a comparator"),
+            },
+            VmTestCase {
+                input: r#"sort([2, 1], fn(a, b) { "x" })"#,
+                expected: error("builtin sort comparator must return INTEGER, got STRING"),
+            },
+            VmTestCase {
+                input: "sort(1)",
+                expected: error("builtin sort expected argument 1 to be ARRAY, got INTEGER"),
+            },
+            VmTestCase {
+                input: "reverse([1, 2, 3])",
+                expected: array(vec![
+                    Object::Integer(3),
+                    Object::Integer(2),
+                    Object::Integer(1),
+                ]),
+            },
+            VmTestCase {
+                input: r#"reverse("héllo, 世界")"#,
+                expected: string("界世 ,olléh"),
+            },
+            VmTestCase {
+                input: "reverse({})",
+                expected: error(
+                    "builtin reverse expected argument 1 to be ARRAY or STRING, got HASH",
+                ),
+            },
+        ];
+        run_vm_tests(tests);
+    }
+
+    #[test]
+    fn test_slice() {
+        let error = |message: &str| Object::Error(message.to_string());
+        let tests = vec![
+            VmTestCase {
+                input: "slice([1, 2, 3, 4, 5], 1, 3)",
+                expected: array(vec![Object::Integer(2), Object::Integer(3)]),
+            },
+            VmTestCase {
+                input: "let a = [1, 2, 3]; slice(a, 0, len(a))",
+                expected: array(vec![
+                    Object::Integer(1),
+                    Object::Integer(2),
+                    Object::Integer(3),
+                ]),
+            },
+            VmTestCase {
+                input: "slice([1, 2, 3, 4, 5], -2, 5)",
+                expected: array(vec![Object::Integer(4), Object::Integer(5)]),
+            },
+            VmTestCase {
+                input: "slice([1, 2, 3, 4, 5], 1, -1)",
+                expected: array(vec![
+                    Object::Integer(2),
+                    Object::Integer(3),
+                    Object::Integer(4),
+                ]),
+            },
+            VmTestCase {
+                input: "slice([1, 2, 3], -10, 10)",
+                expected: array(vec![
+                    Object::Integer(1),
+                    Object::Integer(2),
+                    Object::Integer(3),
+                ]),
+            },
+            VmTestCase {
+                input: "slice([1, 2, 3], 2, 1)",
+                expected: array(vec![]),
+            },
+            VmTestCase {
+                input: "slice([1, 2, 3], -1, -2)",
+                expected: array(vec![]),
+            },
+            VmTestCase {
+                input: "slice([1, 2, 3], 5, 9)",
+                expected: array(vec![]),
+            },
+            VmTestCase {
+                input: r#"slice("héllo wörld", 1, 4)"#,
+                expected: string("éll"),
+            },
+            VmTestCase {
+                input: r#"slice("日本語テキスト", -4, -1)"#,
+                expected: string("テキス"),
+            },
+            VmTestCase {
+                input: r#"slice("abc", 3, 3)"#,
+                expected: string(""),
+            },
+            VmTestCase {
+                input: "slice([1], -9223372036854775807 - 1, 9223372036854775807)",
+                expected: array(vec![Object::Integer(1)]),
+            },
+            VmTestCase {
+                input: r#"slice([1, 2], "0", 1)"#,
+                expected: error("builtin slice expected argument 2 to be INTEGER, got STRING"),
+            },
+            VmTestCase {
+                input: "slice({}, 0, 1)",
+                expected: error(
+                    "builtin slice expected argument 1 to be ARRAY or STRING, got HASH",
+                ),
+            },
+            VmTestCase {
+                input: "slice([1], 0)",
+                expected: error("builtin slice expected 3 arguments, got 2"),
+            },
+        ];
+        run_vm_tests(tests);
+    }
+
+    #[test]
+    fn test_flatten() {
+        let tests = vec![
+            VmTestCase {
+                input: "flatten([[1, 2], [3], [4, [5]]])",
+                expected: array(vec![
+                    Object::Integer(1),
+                    Object::Integer(2),
+                    Object::Integer(3),
+                    Object::Integer(4),
+                    ints(&[5]),
+                ]),
+            },
+            VmTestCase {
+                input: "flatten([1, [2, [3, [4]]]], 2)",
+                expected: array(vec![
+                    Object::Integer(1),
+                    Object::Integer(2),
+                    Object::Integer(3),
+                    ints(&[4]),
+                ]),
+            },
+            VmTestCase {
+                input: "flatten([1, [2, [3, [4]]]], -1)",
+                expected: ints(&[1, 2, 3, 4]),
+            },
+            VmTestCase {
+                input: "flatten([1, [2, [3, [4]]]], 1000000)",
+                expected: ints(&[1, 2, 3, 4]),
+            },
+            VmTestCase {
+                input: "flatten([[1, 2], [3]], 0)",
+                expected: array(vec![ints(&[1, 2]), ints(&[3])]),
+            },
+            VmTestCase {
+                input: r#"flatten([1, "a", true])"#,
+                expected: array(vec![
+                    Object::Integer(1),
+                    Object::String("a".into()),
+                    Object::Boolean(true),
+                ]),
+            },
+            VmTestCase {
+                input: "flatten([])",
+                expected: ints(&[]),
+            },
+            VmTestCase {
+                input: "flatten([[], [1, []], [[[]]]])",
+                expected: array(vec![Object::Integer(1), ints(&[]), array(vec![ints(&[])])]),
+            },
+            VmTestCase {
+                input: "flatten([[], [1, []], [[[]]]], -1)",
+                expected: ints(&[1]),
+            },
+            VmTestCase {
+                input: "let a = [1]; let i = 0; \
+                    while (i < 2000) { a = [a]; i = i + 1; }; flatten(a, -1)",
+                expected: ints(&[1]),
+            },
+            VmTestCase {
+                input: "flatten([1], -2)",
+                expected: Object::Error(
+                    "builtin flatten depth must be -1 or at least 0, got -2".to_string(),
+                ),
+            },
+            VmTestCase {
+                input: r#"flatten("ab")"#,
+                expected: Object::Error(
+                    "builtin flatten expected argument 1 to be ARRAY, got STRING".to_string(),
+                ),
+            },
+            VmTestCase {
+                input: "flatten()",
+                expected: Object::Error(
+                    "builtin flatten expected 1 or 2 arguments, got 0".to_string(),
+                ),
+            },
+        ];
+        run_vm_tests(tests);
+    }
+
+    fn bytes(values: &[u8]) -> Object {
+        Object::Bytes(values.to_vec())
+    }
+
+    #[test]
+    fn test_bytes_conversions() {
+        let tests = vec![
+            VmTestCase {
+                input: r#"encode("hé")"#,
+                expected: bytes(&[0x68, 0xc3, 0xa9]),
+            },
+            VmTestCase {
+                input: r#"len(encode("hé"))"#,
+                expected: Object::Integer(3),
+            },
+            VmTestCase {
+                input: r#"decode(encode("hé"))"#,
+                expected: Object::String("hé".into()),
+            },
+            VmTestCase {
+                input: r#"hex(encode("hé"))"#,
+                expected: Object::String("68c3a9".into()),
+            },
+            VmTestCase {
+                input: r#"from_hex("68C3a9")"#,
+                expected: bytes(&[0x68, 0xc3, 0xa9]),
+            },
+            VmTestCase {
+                input: r#"decode(from_hex(hex(encode("round trip"))))"#,
+                expected: Object::String("round trip".into()),
+            },
+            VmTestCase {
+                input: r#"[encode(""), decode(encode("")), hex(from_hex(""))]"#,
+                expected: array(vec![
+                    bytes(&[]),
+                    Object::String("".into()),
+                    Object::String("".into()),
+                ]),
+            },
+            VmTestCase {
+                input: r#"decode(from_hex("68ff69"))"#,
+                expected: Object::Utf8Error { offset: 1 },
+            },
+            VmTestCase {
+                input: r#"decode(from_hex("68c3"))"#,
+                expected: Object::Utf8Error { offset: 1 },
+            },
+            // the script gets the error as a value and reads where it failed
+            VmTestCase {
+                input: r#"let r = decode(from_hex("6869ff"));
+                    if (type(r) == "UTF8_ERROR") { [r.offset, r["offset"], r.other, to_string(r)] }"#,
+                expected: Object::Array(vec![
+                    Rc::new(Object::Integer(2)),
+                    Rc::new(Object::Integer(2)),
+                    Rc::new(Object::Null),
+                    Rc::new(Object::String(
+                        "builtin decode found invalid UTF-8 at byte 2".into(),
+                    )),
+                ]),
+            },
+            VmTestCase {
+                input: r#"decode_lossy(from_hex("68ff69c3"))"#,
+                expected: Object::String("h\u{fffd}i\u{fffd}".into()),
+            },
+            VmTestCase {
+                input: r#"decode_lossy(from_hex(""))"#,
+                expected: Object::String("".into()),
+            },
+            VmTestCase {
+                input: r#"from_hex("abc")"#,
+                expected: Object::Error(
+                    "builtin from_hex expected an even number of digits, got 3".to_string(),
+                ),
+            },
+            VmTestCase {
+                input: r#"from_hex("ab+f")"#,
+                expected: Object::Error(
+                    "builtin from_hex found an invalid digit at offset 2".to_string(),
+                ),
+            },
+            VmTestCase {
+                input: r#"decode("text")"#,
+                expected: Object::Error(
+                    "builtin decode expected argument 1 to be BYTES, got STRING".to_string(),
+                ),
+            },
+            VmTestCase {
+                input: "encode(1)",
+                expected: Object::Error(
+                    "builtin encode expected argument 1 to be STRING, got INTEGER".to_string(),
+                ),
+            },
+        ];
+        run_vm_tests(tests);
+    }
 }
@@ -0,0 +1,200 @@
+#[cfg(test)]
+mod tests {
+    use parser::parse;
+
+    use crate::compiler::{Bytecode, Compiler};
+    use crate::op_code::Opcode::{OpGetGlobal, OpPop, OpSetGlobal, OpTrue};
+    use crate::op_code::{concat_instructions, make_instructions};
+    use crate::symbol_table::SymbolTable;
+    use crate::vm::{MemoryStats, VM, Value, compact_constants};
+    use object::Object;
+    use std::rc::Rc;
+
+    fn run(input: &str) -> MemoryStats {
+        let program = parse(input).unwrap();
+        let bytecode = Compiler::new().compile(&program).unwrap();
+        let mut vm = VM::new(bytecode);
+        vm.run().unwrap();
+        vm.memory_stats()
+    }
+
+    #[test]
+    fn test_peak_depths_track_recursion() {
+        let shallow = run("let f = fn(n) { if (n == 0) { 0 } else { 1 + f(n - 1) } }; f(1);");
+        let deep = run("let f = fn(n) { if (n == 0) { 0 } else { 1 + f(n - 1) } }; f(10);");
+
+        assert_eq!(shallow.peak_frame_depth, 3);
+        assert_eq!(deep.peak_frame_depth, 12);
+        assert!(deep.peak_stack_depth > shallow.peak_stack_depth);
+        assert_eq!(deep.stack_depth, 0);
+    }
+
+    #[test]
+    fn test_globals_and_heap_estimate() {
+        let small = run("let xs = new_array(10, 0); let s = \"abc\";");
+        let large = run("let xs = new_array(10000, 0); let s = \"abc\";");
+
+        assert_eq!(small.live_globals, 2);
+        assert_eq!(large.live_globals, 2);
+        assert!(large.heap_bytes >= small.heap_bytes + 9990 * size_of::<usize>());
+    }
+
+    #[test]
+    fn test_shared_values_are_counted_once() {
+        let one = run("let xs = new_array(10000, 0); let ys = [xs];");
+        let many = run("let xs = new_array(10000, 0); let ys = [xs, xs, xs, xs];");
+        // three more slots in `ys`, not three more copies of `xs`
+        assert!(many.heap_bytes < one.heap_bytes + 10000);
+    }
+
+    #[test]
+    fn test_constants_are_counted() {
+        let stats = run("let f = fn() { \"hello\" }; 1 + 2;");
+        assert_eq!(stats.constants, 3);
+        assert!(stats.constant_bytes > "hello".len());
+    }
+
+    // Runs inputs the way the REPL does, compacting the pool after each one.
+    struct Session {
+        symbol_table: SymbolTable,
+        constants: Vec<Rc<Object>>,
+        globals: Vec<Value>,
+        reclaimed: usize,
+    }
+
+    impl Session {
+        fn new() -> Self {
+            Session {
+                symbol_table: SymbolTable::new(),
+                constants: vec![],
+                globals: vec![],
+                reclaimed: 0,
+            }
+        }
+
+        fn eval(&mut self, input: &str) -> Rc<Object> {
+            let program = parse(input).unwrap();
+            let symbol_table = std::mem::take(&mut self.symbol_table);
+            let constants = std::mem::take(&mut self.constants);
+            let mut compiler = Compiler::new_with_state(symbol_table, constants);
+            let bytecode = compiler.compile(&program).unwrap();
+            let globals = std::mem::take(&mut self.globals);
+            let mut vm = VM::new_with_global_store(bytecode, globals);
+            vm.run().unwrap();
+            let result = vm.last_popped_stack_elm().unwrap().into_rc_object();
+            self.globals = vm.globals;
+            self.symbol_table = compiler.symbol_table;
+            self.constants = compiler.constants;
+            self.reclaimed += compact_constants(&mut self.constants, &self.globals);
+            result
+        }
+    }
+
+    #[test]
+    fn test_compaction_keeps_the_pool_bounded() {
+        let mut session = Session::new();
+        session.eval("let add = fn(a, b) { a + b + 0 };");
+        session.eval("let greeter = fn(name) { fn() { \"hi \" + name } };");
+        session.eval("let hi = greeter(\"monkey\");");
+        let baseline = session.constants.len();
+
+        for i in 0..1000 {
+            let input = format!("\"throwaway\" + \"{}\"; fn() {{ {} }}", i, i);
+            session.eval(&input);
+            assert!(
+                session.constants.len() <= baseline,
+                "{} constants",
+                session.constants.len()
+            );
+        }
+        assert!(session.reclaimed >= 3000);
+
+        assert_eq!(*session.eval("add(40, 2)"), Object::Integer(42));
+        assert_eq!(*session.eval("hi()"), Object::String("hi monkey".into()));
+        assert_eq!(
+            *session.eval("greeter(\"there\")()"),
+            Object::String("hi there".into())
+        );
+    }
+
+    #[test]
+    fn test_compaction_keeps_constants_of_nested_values() {
+        let mut session = Session::new();
+        session.eval("let fs = [1, {\"f\": fn() { \"deep\" }}];");
+        session.eval("let g = fn() { 99 };");
+        session.eval("\"throwaway\";");
+        assert_eq!(
+            *session.eval("fs[1][\"f\"]()"),
+            Object::String("deep".into())
+        );
+        assert_eq!(*session.eval("g()"), Object::Integer(99));
+    }
+
+    #[test]
+    fn test_globals_only_take_the_slots_in_use() {
+        let program = parse("let a = 1; let b = 2; if (true) { let c = 3; }; a + b").unwrap();
+        let bytecode = Compiler::new().compile(&program).unwrap();
+        assert_eq!(bytecode.num_globals, 3);
+        let mut vm = VM::new(bytecode);
+        assert_eq!(vm.globals.len(), 3);
+        vm.run().unwrap();
+        assert_eq!(vm.globals.len(), 3);
+    }
+
+    #[test]
+    fn test_globals_grow_between_inputs() {
+        let mut session = Session::new();
+        assert!(session.globals.is_empty());
+        session.eval("let a = 1;");
+        assert_eq!(session.globals.len(), 1);
+        session.eval("let b = a + 1; let c = b + 1;");
+        assert_eq!(session.globals.len(), 3);
+        assert_eq!(*session.eval("a + b + c"), Object::Integer(6));
+        assert_eq!(session.globals.len(), 3);
+    }
+
+    #[test]
+    fn test_set_global_extends_the_store() {
+        // bytecode that doesn't say how many globals it needs
+        let bytecode = Bytecode {
+            instructions: concat_instructions(vec![
+                make_instructions(OpTrue, &[]),
+                make_instructions(OpSetGlobal, &[4]),
+                make_instructions(OpGetGlobal, &[4]),
+                make_instructions(OpPop, &[]),
+            ]),
+            constants: vec![],
+            source_map: Default::default(),
+            host_builtins: Default::default(),
+            num_globals: 0,
+        };
+        let mut vm = VM::new(bytecode);
+        vm.run().unwrap();
+        assert_eq!(vm.globals.len(), 5);
+        assert!(matches!(vm.globals[4], Value::Boolean(true)));
+        assert!(matches!(vm.globals[0], Value::Null));
+    }
+
+    #[test]
+    fn test_booleans_and_null_are_shared() {
+        let program =
+            parse("let t = 1 < 2; [t, !false, 1 == 2, 2 < 1, first([]), if (false) { 1 }]")
+                .unwrap();
+        let mut vm = VM::new(Compiler::new().compile(&program).unwrap());
+        vm.run().unwrap();
+        let result = vm.last_popped_stack_elm().unwrap().into_rc_object();
+        let Object::Array(elements) = &*result else {
+            panic!("expected an array, got {}", result);
+        };
+        assert!(Rc::ptr_eq(&elements[0], &elements[1]));
+        assert!(Rc::ptr_eq(&elements[0], &object::boolean(true)));
+        assert!(Rc::ptr_eq(&elements[2], &elements[3]));
+        assert!(Rc::ptr_eq(&elements[2], &object::boolean(false)));
+        assert!(Rc::ptr_eq(&elements[4], &elements[5]));
+        assert!(Rc::ptr_eq(&elements[4], &object::null()));
+        assert!(Rc::ptr_eq(
+            &Value::Boolean(true).into_rc_object(),
+            &object::boolean(true)
+        ));
+    }
+}
@@ -0,0 +1,462 @@
+#[cfg(test)]
+mod tests {
+    use crate::compiler::{Bytecode, Compiler};
+    use crate::differential_test::run_interpreter;
+    use crate::op_code::Opcode::*;
+    use crate::op_code::{Instructions, OpCodeError, concat_instructions, make_instructions};
+    use crate::vm::{VM, VMError};
+    use crate::vm_test::run_vm_error_test;
+    use object::{EvalError, Object};
+    use parser::parse;
+    use std::rc::Rc;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::thread;
+    use std::time::Duration;
+
+    fn run_bytecode(instructions: Instructions, constants: Vec<Object>) -> VMError {
+        let mut vm = VM::new(Bytecode {
+            instructions,
+            constants: constants.into_iter().map(Rc::new).collect(),
+            source_map: Default::default(),
+            host_builtins: Default::default(),
+            num_globals: 0,
+        });
+        vm.run().unwrap_err().without_location()
+    }
+
+    fn assert_invalid_bytecode(instructions: Instructions, constants: Vec<Object>) {
+        let err = run_bytecode(instructions, constants);
+        assert!(
+            matches!(err, VMError::InvalidBytecode(_)),
+            "expected invalid bytecode, got {:?}",
+            err
+        );
+    }
+
+    fn assert_stack_underflow(instructions: Instructions) {
+        let err = run_bytecode(instructions, vec![]);
+        assert!(
+            matches!(err, VMError::StackUnderflow),
+            "expected stack underflow, got {:?}",
+            err
+        );
+    }
+
+    #[test]
+    fn test_truncated_operands() {
+        for op in [OpConst, OpJump, OpGetGlobal, OpCall, OpClosure] {
+            assert_invalid_bytecode(
+                Instructions {
+                    bytes: vec![op as u8],
+                },
+                vec![],
+            );
+        }
+        assert_invalid_bytecode(
+            Instructions {
+                bytes: vec![OpClosure as u8, 0, 0],
+            },
+            vec![],
+        );
+    }
+
+    #[test]
+    fn test_invalid_opcode_byte() {
+        let mut instructions = concat_instructions(vec![
+            make_instructions(OpConst, &[0]),
+            make_instructions(OpPop, &[]),
+        ]);
+        instructions.bytes.push(0x2a);
+        let err = run_bytecode(instructions, vec![Object::Integer(1)]);
+        assert!(
+            matches!(
+                err,
+                VMError::Opcode(OpCodeError::InvalidOpcodeByte {
+                    byte: 0x2a,
+                    position: Some(4),
+                })
+            ),
+            "expected invalid opcode, got {:?}",
+            err
+        );
+        assert_eq!(
+            err.to_string(),
+            "opcode error: invalid opcode byte: 0x2a at position 4"
+        );
+    }
+
+    #[test]
+    fn test_out_of_range_indices() {
+        assert_invalid_bytecode(make_instructions(OpConst, &[5]), vec![]);
+        assert_invalid_bytecode(make_instructions(OpClosure, &[1, 0]), vec![]);
+        assert_invalid_bytecode(make_instructions(OpGetLocal, &[0]), vec![]);
+        assert_invalid_bytecode(
+            concat_instructions(vec![
+                make_instructions(OpTrue, &[]),
+                make_instructions(OpSetLocal, &[3]),
+            ]),
+            vec![],
+        );
+        assert_invalid_bytecode(make_instructions(OpGetFree, &[0]), vec![]);
+        assert_invalid_bytecode(make_instructions(OpHash, &[1]), vec![]);
+
+        let mut short_globals_vm = VM::new_with_global_store(
+            Bytecode {
+                instructions: make_instructions(OpGetGlobal, &[3]),
+                constants: vec![],
+                source_map: Default::default(),
+                host_builtins: Default::default(),
+                num_globals: 0,
+            },
+            vec![],
+        );
+        assert!(matches!(
+            short_globals_vm.run().unwrap_err().without_location(),
+            VMError::InvalidBytecode(_)
+        ));
+    }
+
+    #[test]
+    fn test_stack_underflow() {
+        assert_stack_underflow(make_instructions(OpPop, &[]));
+        assert_stack_underflow(make_instructions(OpAdd, &[]));
+        assert_stack_underflow(make_instructions(OpBang, &[]));
+        assert_stack_underflow(make_instructions(OpIndex, &[]));
+        assert_stack_underflow(make_instructions(OpJumpNotTruthy, &[0]));
+        assert_stack_underflow(make_instructions(OpSetGlobal, &[0]));
+        assert_stack_underflow(make_instructions(OpArray, &[4]));
+        assert_stack_underflow(make_instructions(OpHash, &[4]));
+        assert_stack_underflow(make_instructions(OpCall, &[2]));
+        assert_stack_underflow(make_instructions(OpTailCall, &[2]));
+        assert_stack_underflow(make_instructions(OpReturnValue, &[]));
+    }
+
+    #[test]
+    fn test_checked_integer_arithmetic() {
+        let err = run_bytecode(
+            concat_instructions(vec![
+                make_instructions(OpConst, &[0]),
+                make_instructions(OpConst, &[1]),
+                make_instructions(OpAdd, &[]),
+            ]),
+            vec![Object::Integer(i64::MAX), Object::Integer(1)],
+        );
+        assert!(matches!(err, VMError::IntegerOverflow(OpAdd)));
+
+        let err = run_bytecode(
+            concat_instructions(vec![
+                make_instructions(OpConst, &[0]),
+                make_instructions(OpMinus, &[]),
+            ]),
+            vec![Object::Integer(i64::MIN)],
+        );
+        assert!(matches!(err, VMError::IntegerOverflow(OpMinus)));
+
+        assert!(matches!(
+            run_vm_error_test("1 / 0"),
+            VMError::DivisionByZero
+        ));
+        assert!(matches!(
+            run_vm_error_test("let x = 0; 1 % x"),
+            VMError::DivisionByZero
+        ));
+        assert!(matches!(
+            run_vm_error_test("10 % 0"),
+            VMError::DivisionByZero
+        ));
+        assert!(matches!(
+            run_vm_error_test("let min = -9223372036854775807 - 1; min % -1"),
+            VMError::IntegerOverflow(_)
+        ));
+        assert!(matches!(
+            run_vm_error_test("1 div 0"),
+            VMError::DivisionByZero
+        ));
+        assert!(matches!(
+            run_vm_error_test("let min = -9223372036854775807 - 1; min div -1"),
+            VMError::IntegerOverflow(_)
+        ));
+        assert!(matches!(
+            run_vm_error_test("9223372036854775807 * 2"),
+            VMError::IntegerOverflow(OpMul)
+        ));
+    }
+
+    #[test]
+    fn test_top_level_return_halts() {
+        let program = parser::parse("return 5; 6").unwrap();
+        let mut compiler = crate::compiler::Compiler::new();
+        let mut vm = VM::new(compiler.compile(&program).unwrap());
+        vm.run().unwrap();
+        assert_eq!(
+            *vm.last_popped_stack_elm().unwrap().into_rc_object(),
+            Object::Integer(5)
+        );
+    }
+
+    fn fueled_vm(input: &str, fuel: u64) -> VM {
+        let bytecode = Compiler::new().compile(&parse(input).unwrap()).unwrap();
+        let mut vm = VM::new(bytecode);
+        vm.set_fuel(fuel);
+        vm
+    }
+
+    #[test]
+    fn test_fuel_stops_infinite_loops() {
+        let mut vm = fueled_vm("let n = 0; while (true) { n = n + 1; }", 10_000);
+        let err = vm.run().unwrap_err();
+        assert_eq!(err.to_string(), "out of fuel after 10000 instructions");
+        assert!(matches!(
+            err.without_location(),
+            VMError::OutOfFuel { executed: 10_000 }
+        ));
+        assert_eq!(vm.fuel(), Some(0));
+        let count = |vm: &VM| match vm.globals[0] {
+            crate::vm::Value::Integer(n) => n,
+            ref other => panic!("expected an integer, got {:?}", other),
+        };
+        let before = count(&vm);
+        assert!(before > 0);
+
+        // the loop picks up where it stopped
+        vm.add_fuel(10_000);
+        assert!(matches!(
+            vm.run().unwrap_err().without_location(),
+            VMError::OutOfFuel { executed: 20_000 }
+        ));
+        assert!(count(&vm) > before);
+    }
+
+    #[test]
+    fn test_cancel_token_stops_a_running_script() {
+        let program = parse("let n = 0; while (true) { n = n + 1; }").unwrap();
+        let mut vm = VM::new(Compiler::new().compile(&program).unwrap());
+        let cancel = Arc::new(AtomicBool::new(false));
+        vm.set_cancel_token(Some(Arc::clone(&cancel)));
+        let token = Arc::clone(&cancel);
+        let canceller = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(20));
+            token.store(true, Ordering::Relaxed);
+        });
+        let err = vm.run().unwrap_err();
+        canceller.join().unwrap();
+        assert!(err.to_string().starts_with("cancelled after "), "{}", err);
+        assert!(matches!(
+            err.without_location(),
+            VMError::Cancelled { executed } if executed > 0
+        ));
+
+        // once the token is cleared the loop carries on
+        cancel.store(false, Ordering::Relaxed);
+        vm.set_fuel(1000);
+        assert!(matches!(
+            vm.run().unwrap_err().without_location(),
+            VMError::OutOfFuel { .. }
+        ));
+    }
+
+    #[test]
+    fn test_fuel_resumes_inside_functions() {
+        let input = "let f = fn(n) { let total = 0; for (i in range(0, n)) { total = total + i; }; total }; f(100)";
+        let mut vm = fueled_vm(input, 50);
+        let mut runs = 1;
+        while let Err(err) = vm.run() {
+            assert!(matches!(err.without_location(), VMError::OutOfFuel { .. }));
+            vm.add_fuel(50);
+            runs += 1;
+        }
+        assert!(runs > 2, "finished in {} runs", runs);
+        assert!(matches!(
+            vm.last_popped_stack_elm(),
+            Some(crate::vm::Value::Integer(4950))
+        ));
+    }
+
+    #[test]
+    fn test_fuel_running_out_in_a_callback_is_final() {
+        let input = "let r = map(range(0, 50), fn(x) { let i = 0; while (i < 10) { i = i + 1; } x * 2 }); r";
+        let mut vm = fueled_vm(input, 500);
+        let err = vm.run().unwrap_err().without_location();
+        assert!(
+            matches!(&err, VMError::CallbackFailed { builtin: "map", error, .. }
+                if matches!(**error, VMError::OutOfFuel { .. })),
+            "expected fuel to run out in map, got {:?}",
+            err
+        );
+
+        // the callback's frames are gone, so carrying on would be wrong
+        vm.add_fuel(100_000);
+        for _ in 0..2 {
+            let err = vm.run().unwrap_err();
+            assert!(matches!(err, VMError::CannotResume), "got {:?}", err);
+        }
+        assert!(matches!(vm.globals[0], crate::vm::Value::Null));
+
+        // with enough fuel the same program finishes
+        let mut vm = fueled_vm(input, 100_000);
+        vm.run().unwrap();
+        assert_eq!(
+            vm.last_popped_stack_elm()
+                .unwrap()
+                .into_rc_object()
+                .to_string(),
+            format!(
+                "[{}]",
+                (0..50)
+                    .map(|x| (x * 2).to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            )
+        );
+    }
+
+    #[test]
+    fn test_no_fuel_limit_by_default() {
+        let bytecode = Compiler::new()
+            .compile(&parse("let n = 0; while (n < 100000) { n = n + 1; }; n").unwrap())
+            .unwrap();
+        let mut vm = VM::new(bytecode);
+        assert_eq!(vm.fuel(), None);
+        vm.add_fuel(1);
+        assert_eq!(vm.fuel(), None);
+        vm.run().unwrap();
+    }
+
+    // each call only leaves its callee on the stack, so frames run out first
+    const DEEP_RECURSION: &str =
+        "let n = 0; let f = fn() { n = n + 1; if (n < 2000) { f(); } n }; f()";
+
+    fn deep_recursion(max_frames: Option<usize>) -> VM {
+        let bytecode = Compiler::new()
+            .compile(&parse(DEEP_RECURSION).unwrap())
+            .unwrap();
+        let mut vm = VM::new(bytecode);
+        if let Some(limit) = max_frames {
+            vm.set_max_frames(limit);
+        }
+        vm
+    }
+
+    #[test]
+    fn test_deep_recursion_is_an_error() {
+        let err = deep_recursion(None).run().unwrap_err();
+        assert!(err.span().is_some());
+        let err = err.without_location();
+        assert!(
+            matches!(err, VMError::MaxFramesExceeded { depth: 1024 }),
+            "expected too many frames, got {:?}",
+            err
+        );
+        assert_eq!(
+            err.to_string(),
+            "maximum call depth of 1024 frames exceeded"
+        );
+    }
+
+    #[test]
+    fn test_engines_agree_on_the_call_depth_limit() {
+        // not `run_vm_error_test`: an unoptimised interpreter needs more
+        // native stack than a test thread has to get this deep
+        let deep = |input: &'static str| {
+            thread::Builder::new()
+                .stack_size(256 * 1024 * 1024)
+                .spawn(move || run_interpreter(input).map(|value| value.to_string()))
+                .unwrap()
+                .join()
+                .unwrap()
+        };
+
+        for input in [
+            DEEP_RECURSION,
+            "let f = fn(n) { if (n == 0) { 0 } else { 1 + f(n - 1) } }; f(2000);",
+        ] {
+            let vm_err = Compiler::new()
+                .compile(&parse(input).unwrap())
+                .map(VM::new)
+                .unwrap()
+                .run()
+                .unwrap_err()
+                .without_location();
+            let eval_err = deep(input).unwrap_err();
+            assert!(matches!(vm_err, VMError::MaxFramesExceeded { depth: 1024 }));
+            assert!(matches!(
+                eval_err,
+                EvalError::MaxFramesExceeded { depth: 1024 }
+            ));
+            assert_eq!(vm_err.to_string(), eval_err.to_string());
+        }
+
+        // the main program and 1023 calls fill every frame
+        let input = "let f = fn(n) { if (n == 0) { 0 } else { 1 + f(n - 1) } }; f(1022)";
+        let mut vm = VM::new(Compiler::new().compile(&parse(input).unwrap()).unwrap());
+        vm.run().unwrap();
+        assert!(matches!(
+            vm.last_popped_stack_elm(),
+            Some(crate::vm::Value::Integer(1022))
+        ));
+        assert_eq!(deep(input), Ok("1022".to_string()));
+    }
+
+    #[test]
+    fn test_max_frames_is_configurable() {
+        let mut vm = deep_recursion(Some(4096));
+        vm.run().unwrap();
+        assert!(matches!(
+            vm.last_popped_stack_elm(),
+            Some(crate::vm::Value::Integer(2000))
+        ));
+        assert_eq!(vm.memory_stats().peak_frame_depth, 2001);
+
+        assert!(matches!(
+            deep_recursion(Some(10))
+                .run()
+                .unwrap_err()
+                .without_location(),
+            VMError::MaxFramesExceeded { depth: 10 }
+        ));
+    }
+
+    // a variable, so the literal isn't folded into a constant
+    fn array_literal(len: usize) -> String {
+        format!("let z = 0; len([{}])", vec!["z"; len].join(", "))
+    }
+
+    #[test]
+    fn test_stack_grows_past_its_initial_size() {
+        let bytecode = Compiler::new()
+            .compile(&parse(&array_literal(5000)).unwrap())
+            .unwrap();
+        let mut vm = VM::new(bytecode);
+        vm.run().unwrap();
+        assert!(matches!(
+            vm.last_popped_stack_elm(),
+            Some(crate::vm::Value::Integer(5000))
+        ));
+        assert!(vm.memory_stats().peak_stack_depth >= 5000);
+    }
+
+    #[test]
+    fn test_max_stack_is_configurable() {
+        let bytecode = Compiler::new()
+            .compile(&parse(&array_literal(100)).unwrap())
+            .unwrap();
+        let mut vm = VM::new(bytecode);
+        vm.set_max_stack(50);
+        assert!(matches!(
+            vm.run().unwrap_err().without_location(),
+            VMError::StackOverflow
+        ));
+
+        // locals need room too
+        let bytecode = Compiler::new()
+            .compile(&parse("let f = fn(a) { let b = a; b }; f(1)").unwrap())
+            .unwrap();
+        let mut vm = VM::new(bytecode);
+        vm.set_max_stack(2);
+        assert!(matches!(
+            vm.run().unwrap_err().without_location(),
+            VMError::StackOverflow
+        ));
+    }
+}
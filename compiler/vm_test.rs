@@ -1,5 +1,7 @@
 use crate::compiler::Compiler;
 use crate::compiler_test::test_constants;
+use crate::differential_test::{assert_engines_agree, run_interpreter};
+use crate::verifier::verify;
 use crate::vm::{VM, VMError};
 use object::Object;
 use parser::parse;
@@ -14,6 +16,10 @@ pub fn run_vm_tests(tests: Vec<VmTestCase>) {
         let program = parse(t.input).unwrap();
         let mut compiler = Compiler::new();
         let bytecodes = compiler.compile(&program).unwrap();
+        // everything the compiler emits must pass verification
+        if let Err(errors) = verify(&bytecodes) {
+            panic!("{} failed verification: {:?}", t.input, errors);
+        }
         println!(
             "ins {} for input {}",
             bytecodes.instructions.string().unwrap(),
@@ -22,6 +28,7 @@ pub fn run_vm_tests(tests: Vec<VmTestCase>) {
         let mut vm = VM::new(bytecodes);
         vm.run().unwrap();
         let got = vm.last_popped_stack_elm().unwrap().into_rc_object();
+        assert_engines_agree(t.input, &got);
         let expected_argument = t.expected;
         test_constants(&[expected_argument], &[got]);
     }
@@ -31,21 +38,66 @@ pub fn run_vm_error_test(input: &str) -> VMError {
     let program = parse(input).unwrap();
     let mut compiler = Compiler::new();
     let bytecodes = compiler.compile(&program).unwrap();
+    if let Err(errors) = verify(&bytecodes) {
+        panic!("{} failed verification: {:?}", input, errors);
+    }
     let mut vm = VM::new(bytecodes);
-    vm.run().unwrap_err()
+    let err = vm.run().unwrap_err();
+    assert!(
+        run_interpreter(input).is_err(),
+        "interpreter accepted {} but the VM failed with {}",
+        input,
+        err
+    );
+    // errors from compiled source always know where they came from
+    assert!(err.span().is_some(), "{} failed without a span", input);
+    err.without_location()
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::compiler::Bytecode;
-    use crate::op_code::Opcode::{OpClosure, OpGetBuiltin, OpGreaterThan, OpSub};
+    use crate::compiler::{Bytecode, CompileError, Compiler};
+    use crate::differential_test::run_interpreter;
+    use crate::op_code::Opcode::{OpClosure, OpGetBuiltin, OpGreaterThan, OpLessOrEqual, OpSub};
     use crate::op_code::{Instructions, OpCodeError, make_instructions};
+    use crate::verifier::verify;
     use crate::vm::{VM, VMError};
     use crate::vm_test::{VmTestCase, run_vm_error_test, run_vm_tests};
-    use object::{HashKey, Object};
-    use std::collections::HashMap;
+    use object::ordered_map::OrderedMap;
+    use object::{BuiltinFunc, HashKey, Object};
+    use parser::parse;
+    use std::cell::RefCell;
     use std::rc::Rc;
 
+    #[test]
+    fn test_division_sign_semantics() {
+        let tests = [
+            ("7 / 2", 3),
+            ("-7 / 2", -3),
+            ("7 / -2", -3),
+            ("-7 / -2", 3),
+            ("7 div 2", 3),
+            ("-7 div 2", -4),
+            ("7 div -2", -4),
+            ("-7 div -2", 3),
+            ("7 % 2", 1),
+            ("-7 % 2", -1),
+            ("7 % -2", 1),
+            ("-7 % -2", -1),
+            ("-8 div 2", -4),
+            ("let a = -7; let b = 2; a / b * b + a % b", -7),
+        ];
+        run_vm_tests(
+            tests
+                .into_iter()
+                .map(|(input, expected)| VmTestCase {
+                    input,
+                    expected: Object::Integer(expected),
+                })
+                .collect(),
+        );
+    }
+
     #[test]
     fn test_integer_arithmetic() {
         let tests: Vec<VmTestCase> = vec![
@@ -57,6 +109,14 @@ mod tests {
                 input: "2",
                 expected: Object::Integer(2),
             },
+            VmTestCase {
+                input: "-9223372036854775808",
+                expected: Object::Integer(i64::MIN),
+            },
+            VmTestCase {
+                input: "9223372036854775807",
+                expected: Object::Integer(i64::MAX),
+            },
             VmTestCase {
                 input: "1 + 2",
                 expected: Object::Integer(3),
@@ -114,6 +174,454 @@ mod tests {
         run_vm_tests(tests);
     }
 
+    #[test]
+    fn test_float_arithmetic() {
+        let tests: Vec<VmTestCase> = vec![
+            VmTestCase {
+                input: "let rate = 1.25; rate * 2",
+                expected: Object::Float(2.5),
+            },
+            VmTestCase {
+                input: "0.5 + 0.25",
+                expected: Object::Float(0.75),
+            },
+            // an integer on either side promotes the other to float
+            VmTestCase {
+                input: "1 + 0.5",
+                expected: Object::Float(1.5),
+            },
+            VmTestCase {
+                input: "0.5 - 1",
+                expected: Object::Float(-0.5),
+            },
+            VmTestCase {
+                input: "7 / 2.0",
+                expected: Object::Float(3.5),
+            },
+            VmTestCase {
+                input: "7.5 div 2",
+                expected: Object::Float(3.0),
+            },
+            VmTestCase {
+                input: "-7.5 div 2",
+                expected: Object::Float(-4.0),
+            },
+            VmTestCase {
+                input: "7.5 % 2",
+                expected: Object::Float(1.5),
+            },
+            VmTestCase {
+                input: "-2.5",
+                expected: Object::Float(-2.5),
+            },
+            VmTestCase {
+                input: "1 / 0.0",
+                expected: Object::Float(f64::INFINITY),
+            },
+            VmTestCase {
+                input: "2.0 * 3",
+                expected: Object::Float(6.0),
+            },
+        ];
+
+        run_vm_tests(tests);
+    }
+
+    #[test]
+    fn test_float_comparison() {
+        let tests: Vec<VmTestCase> = vec![
+            VmTestCase {
+                input: "1.5 < 2",
+                expected: Object::Boolean(true),
+            },
+            VmTestCase {
+                input: "2 > 1.5",
+                expected: Object::Boolean(true),
+            },
+            VmTestCase {
+                input: "2.5 > 2.5",
+                expected: Object::Boolean(false),
+            },
+            VmTestCase {
+                input: "2.5 >= 2.5",
+                expected: Object::Boolean(true),
+            },
+            VmTestCase {
+                input: "3 <= 2.5",
+                expected: Object::Boolean(false),
+            },
+            VmTestCase {
+                input: "2 == 2.0",
+                expected: Object::Boolean(true),
+            },
+            VmTestCase {
+                input: "0.1 + 0.2 == 0.3",
+                expected: Object::Boolean(false),
+            },
+            VmTestCase {
+                input: "1.0 != 1",
+                expected: Object::Boolean(false),
+            },
+            VmTestCase {
+                input: "0.0 == -0.0",
+                expected: Object::Boolean(true),
+            },
+            VmTestCase {
+                input: "let nan = 0.0 / 0.0; nan == nan",
+                expected: Object::Boolean(false),
+            },
+        ];
+
+        run_vm_tests(tests);
+    }
+
+    #[test]
+    fn test_assignment() {
+        let tests: Vec<VmTestCase> = vec![
+            VmTestCase {
+                input: "let x = 0; while (x < 5) { x = x + 1; }; x",
+                expected: Object::Integer(5),
+            },
+            VmTestCase {
+                input: "let count = 0; let bump = fn() { count = count + 1; }; bump(); bump(); count",
+                expected: Object::Integer(2),
+            },
+            VmTestCase {
+                input: "let sum = fn(n) { let total = 0; while (n > 0) { total = total + n; n = n - 1; }; total }; sum(4)",
+                expected: Object::Integer(10),
+            },
+            VmTestCase {
+                input: "let adder = fn(a) { fn(b) { b = b + a; b } }; adder(2)(3)",
+                expected: Object::Integer(5),
+            },
+            VmTestCase {
+                input: "let xs = []; let i = 0; while (i < 3) { xs = push(xs, i); i = i + 1; }; xs",
+                expected: Object::Array(vec![
+                    Rc::new(Object::Integer(0)),
+                    Rc::new(Object::Integer(1)),
+                    Rc::new(Object::Integer(2)),
+                ]),
+            },
+        ];
+
+        run_vm_tests(tests);
+    }
+
+    #[test]
+    fn test_index_assignment() {
+        let tests: Vec<VmTestCase> = vec![
+            VmTestCase {
+                input: "let xs = [1, 2, 3]; xs[1] = 5; xs",
+                expected: Object::Array(vec![
+                    Rc::new(Object::Integer(1)),
+                    Rc::new(Object::Integer(5)),
+                    Rc::new(Object::Integer(3)),
+                ]),
+            },
+            VmTestCase {
+                input: "let h = {\"a\": 1}; h[\"a\"] = 2; h[\"b\"] = 3; [h[\"a\"], h[\"b\"]]",
+                expected: Object::Array(vec![
+                    Rc::new(Object::Integer(2)),
+                    Rc::new(Object::Integer(3)),
+                ]),
+            },
+            // other bindings of the same collection are left unchanged
+            VmTestCase {
+                input: "let a = [1, 2]; let b = a; a[0] = 9; [a[0], b[0]]",
+                expected: Object::Array(vec![
+                    Rc::new(Object::Integer(9)),
+                    Rc::new(Object::Integer(1)),
+                ]),
+            },
+            VmTestCase {
+                input: "let f = fn() { let xs = [0, 0]; let i = 0; while (i < 2) { xs[i] = i + 1; i = i + 1; }; xs }; f()",
+                expected: Object::Array(vec![
+                    Rc::new(Object::Integer(1)),
+                    Rc::new(Object::Integer(2)),
+                ]),
+            },
+            VmTestCase {
+                input: "let xs = [1, 2]; xs[0] = xs[1] + xs[0]; xs[0]",
+                expected: Object::Integer(3),
+            },
+        ];
+
+        run_vm_tests(tests);
+    }
+
+    #[test]
+    fn test_logical_operators() {
+        let tests: Vec<VmTestCase> = vec![
+            VmTestCase {
+                input: "true && true",
+                expected: Object::Boolean(true),
+            },
+            VmTestCase {
+                input: "true && false",
+                expected: Object::Boolean(false),
+            },
+            VmTestCase {
+                input: "false || true",
+                expected: Object::Boolean(true),
+            },
+            VmTestCase {
+                input: "false || false",
+                expected: Object::Boolean(false),
+            },
+            // truthiness, not just booleans, and the result is always a boolean
+            VmTestCase {
+                input: "0 && \"\"",
+                expected: Object::Boolean(true),
+            },
+            VmTestCase {
+                input: "if (false) { 1 } || [1][5]",
+                expected: Object::Boolean(false),
+            },
+            VmTestCase {
+                input: "let a = 1; let b = 2; if (a > 0 && b > 0) { 10 } else { 20 }",
+                expected: Object::Integer(10),
+            },
+            // `&&` binds tighter than `||`, and both looser than `==`
+            VmTestCase {
+                input: "true || false && false",
+                expected: Object::Boolean(true),
+            },
+            VmTestCase {
+                input: "1 == 2 || 3 == 3",
+                expected: Object::Boolean(true),
+            },
+            VmTestCase {
+                input: "let crash = fn() { 1 + true }; false && crash()",
+                expected: Object::Boolean(false),
+            },
+            VmTestCase {
+                input: "let crash = fn() { 1 + true }; true || crash()",
+                expected: Object::Boolean(true),
+            },
+            VmTestCase {
+                input: "let calls = 0; let call = fn() { calls = calls + 1; true }; \
+                        false && call(); true || call(); true && call(); false || call(); calls",
+                expected: Object::Integer(2),
+            },
+        ];
+
+        run_vm_tests(tests);
+    }
+
+    #[test]
+    fn test_chained_prefix_operators() {
+        let tests = vec![
+            VmTestCase {
+                input: "+5",
+                expected: Object::Integer(5),
+            },
+            VmTestCase {
+                input: "-+5",
+                expected: Object::Integer(-5),
+            },
+            VmTestCase {
+                input: "--5",
+                expected: Object::Integer(5),
+            },
+            VmTestCase {
+                input: "- -5",
+                expected: Object::Integer(5),
+            },
+            VmTestCase {
+                input: "-(-5)",
+                expected: Object::Integer(5),
+            },
+            VmTestCase {
+                input: "let x = 2; +x * 3",
+                expected: Object::Integer(6),
+            },
+            VmTestCase {
+                input: "+2.5",
+                expected: Object::Float(2.5),
+            },
+            VmTestCase {
+                input: "!!true",
+                expected: Object::Boolean(true),
+            },
+            VmTestCase {
+                input: "!!0",
+                expected: Object::Boolean(true),
+            },
+        ];
+
+        run_vm_tests(tests);
+    }
+
+    #[test]
+    fn test_long_operator_chain() {
+        // longer than the parser's nesting limit, but only one level deep;
+        // the tree is still 1000 levels tall, more than an unoptimised
+        // compiler can walk on a test thread's stack
+        std::thread::Builder::new()
+            .stack_size(256 * 1024 * 1024)
+            .spawn(|| {
+                let input = vec!["1"; 1000].join(" + ");
+                run_vm_tests(vec![VmTestCase {
+                    input: &input,
+                    expected: Object::Integer(1000),
+                }]);
+            })
+            .unwrap()
+            .join()
+            .unwrap();
+    }
+
+    #[test]
+    fn test_structural_equality() {
+        let tests = vec![
+            VmTestCase {
+                input: "[1, 2] == [1, 2]",
+                expected: Object::Boolean(true),
+            },
+            VmTestCase {
+                input: "[1, 2] != [1, 2]",
+                expected: Object::Boolean(false),
+            },
+            VmTestCase {
+                input: "[1, 2] == [2, 1]",
+                expected: Object::Boolean(false),
+            },
+            VmTestCase {
+                input: "[1, [2, [3]]] == [1, [2, [3]]]",
+                expected: Object::Boolean(true),
+            },
+            VmTestCase {
+                input: "[1, [2, [3]]] == [1, [2, [4]]]",
+                expected: Object::Boolean(false),
+            },
+            VmTestCase {
+                input: "\"ab\" == \"a\" + \"b\"",
+                expected: Object::Boolean(true),
+            },
+            VmTestCase {
+                input: "\"a\" != \"b\"",
+                expected: Object::Boolean(true),
+            },
+            VmTestCase {
+                input: "{\"a\": [1], 2: true} == {2: true, \"a\": [1]}",
+                expected: Object::Boolean(true),
+            },
+            VmTestCase {
+                input: "{\"a\": 1} == {\"a\": 2}",
+                expected: Object::Boolean(false),
+            },
+            VmTestCase {
+                input: "{\"a\": {\"b\": [1, 2]}} == {\"a\": {\"b\": [1, 2]}}",
+                expected: Object::Boolean(true),
+            },
+            VmTestCase {
+                input: "let a = if (false) { 1 }; let b = if (false) { 2 }; a == b",
+                expected: Object::Boolean(true),
+            },
+            VmTestCase {
+                input: "let n = if (false) { 1 }; n == 0",
+                expected: Object::Boolean(false),
+            },
+            VmTestCase {
+                input: "1 == true",
+                expected: Object::Boolean(false),
+            },
+            VmTestCase {
+                input: "1 != true",
+                expected: Object::Boolean(true),
+            },
+            VmTestCase {
+                input: "\"1\" == 1",
+                expected: Object::Boolean(false),
+            },
+            VmTestCase {
+                input: "[1] == 1",
+                expected: Object::Boolean(false),
+            },
+            // functions are only equal to themselves, however alike
+            VmTestCase {
+                input: "fn() {} == fn() {}",
+                expected: Object::Boolean(false),
+            },
+            VmTestCase {
+                input: "let f = fn(x) { x }; let g = f; [f == g, f != f, [f] == [g]]",
+                expected: Object::Array(vec![
+                    Rc::new(Object::Boolean(true)),
+                    Rc::new(Object::Boolean(false)),
+                    Rc::new(Object::Boolean(true)),
+                ]),
+            },
+            VmTestCase {
+                input: "let make = fn() { fn(x) { x } }; [make() == make(), {\"f\": make()} == {\"f\": make()}]",
+                expected: Object::Array(vec![
+                    Rc::new(Object::Boolean(false)),
+                    Rc::new(Object::Boolean(false)),
+                ]),
+            },
+            VmTestCase {
+                input: "len == len",
+                expected: Object::Boolean(true),
+            },
+            VmTestCase {
+                input: "[] == {}",
+                expected: Object::Boolean(false),
+            },
+            VmTestCase {
+                input: "let f = fn(x) { x }; f == f",
+                expected: Object::Boolean(true),
+            },
+            VmTestCase {
+                input: "len == len",
+                expected: Object::Boolean(true),
+            },
+            VmTestCase {
+                input: "len == first",
+                expected: Object::Boolean(false),
+            },
+        ];
+        run_vm_tests(tests);
+    }
+
+    #[test]
+    fn test_string_ordering() {
+        // code point order: uppercase before lowercase, accents after both
+        let tests = vec![
+            VmTestCase {
+                input: "\"a\" < \"b\"",
+                expected: Object::Boolean(true),
+            },
+            VmTestCase {
+                input: "\"abc\" > \"ab\"",
+                expected: Object::Boolean(true),
+            },
+            VmTestCase {
+                input: "\"ab\" > \"abc\"",
+                expected: Object::Boolean(false),
+            },
+            VmTestCase {
+                input: "\"b\" <= \"b\"",
+                expected: Object::Boolean(true),
+            },
+            VmTestCase {
+                input: "\"b\" >= \"c\"",
+                expected: Object::Boolean(false),
+            },
+            VmTestCase {
+                input: "\"Z\" < \"a\"",
+                expected: Object::Boolean(true),
+            },
+            VmTestCase {
+                input: "\"é\" > \"z\"",
+                expected: Object::Boolean(true),
+            },
+            VmTestCase {
+                input: "\"\" < \"a\"",
+                expected: Object::Boolean(true),
+            },
+        ];
+        run_vm_tests(tests);
+    }
+
     #[test]
     fn test_boolean_expressions() {
         let tests: Vec<VmTestCase> = vec![
@@ -230,8 +738,125 @@ mod tests {
         run_vm_tests(tests);
     }
 
+    #[test]
+    fn test_runtime_errors_report_the_failing_span() {
+        let input = "let inner = fn(x) {\n  x - true\n};\n\
+                     let middle = fn(x) { inner(x) };\n\
+                     let outer = fn() { middle(1) };\n\
+                     outer();";
+        let program = parse(input).unwrap();
+        let bytecode = Compiler::new().compile(&program).unwrap();
+        let err = VM::new(bytecode).run().unwrap_err();
+
+        let span = err.span().unwrap();
+        assert_eq!(&input[span.start..span.end], "x - true");
+        assert_eq!(
+            err.render(input),
+            "VM error at 2:3: type error: unsupported binary operation OpSub for INTEGER and BOOLEAN\n\
+             \x20  2 |   x - true\n\
+             \x20    |   ^^^^^^^^\n\
+             in inner (offset 0003)\n\
+             in middle (offset 0005)\n\
+             in outer (offset 0006)\n\
+             in main (offset 0024)"
+        );
+
+        // an arity mismatch is the caller's fault, so it points at the call
+        let input = "let f = fn(a) { a };\nlet g = fn() { f() };\ng();";
+        let program = parse(input).unwrap();
+        let bytecode = Compiler::new().compile(&program).unwrap();
+        let err = VM::new(bytecode).run().unwrap_err();
+        let span = err.span().unwrap();
+        assert_eq!(&input[span.start..span.end], "f()");
+    }
+
+    #[test]
+    fn test_runtime_errors_carry_a_call_trace() {
+        let input = "let add = fn(a, b) { a + b };
+                     let twice = fn(x) { add(x) };
+                     let run = fn() { fn() { twice(1) }() };
+                     run();";
+        let program = parse(input).unwrap();
+        let bytecode = Compiler::new().compile(&program).unwrap();
+        let err = VM::new(bytecode).run().unwrap_err();
+
+        let functions: Vec<&str> = err
+            .trace()
+            .iter()
+            .map(|frame| frame.function.as_str())
+            .collect();
+        assert_eq!(functions, ["twice", "", "run", "main"]);
+        let rendered = err.render(input);
+        let trace: Vec<&str> = rendered
+            .lines()
+            .filter(|line| line.starts_with("in "))
+            .collect();
+        assert_eq!(
+            trace,
+            [
+                "in twice (offset 0005)",
+                "in <anonymous> (offset 0006)",
+                "in run (offset 0004)",
+                "in main (offset 0024)",
+            ]
+        );
+        assert!(matches!(
+            err.without_location(),
+            VMError::WrongArity {
+                expected: 2,
+                got: 1
+            }
+        ));
+
+        // runaway recursion prints the repeated frame once
+        let input = "let f = fn(n) { f(n) + 1 }; f(1);";
+        let program = parse(input).unwrap();
+        let bytecode = Compiler::new().compile(&program).unwrap();
+        let rendered = VM::new(bytecode).run().unwrap_err().render(input);
+        let trace: Vec<&str> = rendered.lines().skip(3).collect();
+        assert_eq!(trace.len(), 3, "{}", rendered);
+        assert!(
+            trace[1].starts_with("[previous frame repeated "),
+            "{}",
+            rendered
+        );
+    }
+
+    #[test]
+    fn test_failed_assertion_stops_the_program() {
+        let output = Rc::new(RefCell::new(Vec::<u8>::new()));
+        object::builtins::set_output(Some(output.clone()));
+        let program =
+            parse("let xs = [1]; puts(1); assert(len(xs) == 2, \"one short\"); puts(2);").unwrap();
+        let bytecode = Compiler::new().compile(&program).unwrap();
+        let err = VM::new(bytecode).run().unwrap_err();
+        object::builtins::set_output(None);
+
+        assert_eq!(err.to_string(), "assertion failed: one short");
+        // nothing after the assertion ran
+        assert_eq!(String::from_utf8(output.borrow().clone()).unwrap(), "1\n");
+    }
+
     #[test]
     fn test_runtime_error_variants_from_source() {
+        assert!(matches!(
+            run_vm_error_test(r#""n = " + 1"#),
+            VMError::StringConcatenation {
+                left: "STRING",
+                right: "INTEGER",
+            }
+        ));
+        assert!(matches!(
+            run_vm_error_test(r#"[1] + "s""#),
+            VMError::StringConcatenation {
+                left: "ARRAY",
+                right: "STRING",
+            }
+        ));
+        assert_eq!(
+            run_vm_error_test(r#"1 + "s""#).to_string(),
+            "type error: cannot add INTEGER and STRING; convert the other operand with to_string first"
+        );
         assert!(matches!(
             run_vm_error_test("fn(a) { a }();"),
             VMError::WrongArity {
@@ -239,6 +864,42 @@ mod tests {
                 got: 0,
             }
         ));
+        let err = run_vm_error_test("map([1, 2], fn(a, b) { a })");
+        assert_eq!(
+            err.to_string(),
+            "wrong number of arguments: want=2, got=1 (calling the function passed to map on element 0)"
+        );
+        assert!(matches!(
+            err,
+            VMError::CallbackFailed {
+                builtin: "map",
+                index: 0,
+                ..
+            }
+        ));
+        assert!(matches!(
+            run_vm_error_test("reduce([1, 2], 0, fn(a, b) { b / a })"),
+            VMError::CallbackFailed {
+                builtin: "reduce",
+                index: 0,
+                ..
+            }
+        ));
+        assert_eq!(
+            run_vm_error_test(r#"assert(1 > 2, "out of order")"#).to_string(),
+            "assertion failed: out of order"
+        );
+        assert!(matches!(
+            run_vm_error_test("let xs = []; assert(len(xs) > 0); puts(xs);"),
+            VMError::AssertionFailed(message) if message.is_empty()
+        ));
+        assert!(matches!(
+            run_vm_error_test("sort([2, 1], fn(a) { 0 })"),
+            VMError::CallbackFailed {
+                builtin: "sort",
+                ..
+            }
+        ));
         assert!(matches!(
             run_vm_error_test("1();"),
             VMError::NotCallable("INTEGER")
@@ -255,8 +916,31 @@ mod tests {
                 right: "BOOLEAN",
             }
         ));
+        assert_eq!(
+            run_vm_error_test(r#""a" > 1;"#).to_string(),
+            "type error: unsupported comparison for STRING and INTEGER"
+        );
+        // both engines name the operands in source order, `<` included
+        for (input, vm_message, interpreter_message) in [
+            (
+                r#""a" < 1;"#,
+                "type error: unsupported comparison for STRING and INTEGER",
+                "eval infix error for op: <, left: STRING, right: INTEGER",
+            ),
+            (
+                r#"1 <= "a";"#,
+                "type error: unsupported comparison for INTEGER and STRING",
+                "eval infix error for op: <=, left: INTEGER, right: STRING",
+            ),
+        ] {
+            assert_eq!(run_vm_error_test(input).to_string(), vm_message);
+            assert_eq!(
+                run_interpreter(input).unwrap_err().to_string(),
+                interpreter_message
+            );
+        }
         assert!(matches!(
-            run_vm_error_test("1 == true;"),
+            run_vm_error_test("1 > true;"),
             VMError::UnsupportedComparison {
                 left: "INTEGER",
                 right: "BOOLEAN",
@@ -266,13 +950,37 @@ mod tests {
             run_vm_error_test("true > false;"),
             VMError::UnknownBooleanComparisonOperator(OpGreaterThan)
         ));
+        assert!(matches!(
+            run_vm_error_test("true <= false;"),
+            VMError::UnknownBooleanComparisonOperator(OpLessOrEqual)
+        ));
         assert!(matches!(
             run_vm_error_test("1[0];"),
             VMError::UnsupportedIndexOperator("INTEGER")
         ));
+        assert_eq!(
+            run_vm_error_test("let k = [1]; {k: 2};").to_string(),
+            "index error: unusable as hash key: ARRAY"
+        );
         assert!(matches!(
-            run_vm_error_test("{[1]: 2};"),
-            VMError::UnusableAsHashKey(key) if key == "[1]"
+            run_vm_error_test("let xs = [1, 2, 3]; xs[3] = 1;"),
+            VMError::IndexError { index: 3, len: 3 }
+        ));
+        assert!(matches!(
+            run_vm_error_test("let xs = [1]; xs[-1] = 1;"),
+            VMError::IndexError { index: -1, len: 1 }
+        ));
+        assert!(matches!(
+            run_vm_error_test("let h = {}; h[[1]] = 1;"),
+            VMError::UnusableAsHashKey("ARRAY")
+        ));
+        assert!(matches!(
+            run_vm_error_test("for (x in 5) { x }"),
+            VMError::NotIterable("INTEGER")
+        ));
+        assert!(matches!(
+            run_vm_error_test("let n = 1; n[0] = 1;"),
+            VMError::UnsupportedIndexOperator("INTEGER")
         ));
     }
 
@@ -281,34 +989,97 @@ mod tests {
         let mut invalid_opcode_vm = VM::new(Bytecode {
             instructions: Instructions { bytes: vec![255] },
             constants: vec![],
+            source_map: Default::default(),
+            host_builtins: Default::default(),
+            num_globals: 0,
         });
         assert!(matches!(
-            invalid_opcode_vm.run().unwrap_err(),
+            invalid_opcode_vm.run().unwrap_err().without_location(),
             VMError::Opcode(OpCodeError::InvalidOpcodeByte {
                 byte: 255,
-                position: None,
+                position: Some(0),
             })
         ));
 
         let mut invalid_builtin_vm = VM::new(Bytecode {
             instructions: make_instructions(OpGetBuiltin, &[255]),
             constants: vec![],
+            source_map: Default::default(),
+            host_builtins: Default::default(),
+            num_globals: 0,
         });
         assert!(matches!(
-            invalid_builtin_vm.run().unwrap_err(),
+            invalid_builtin_vm.run().unwrap_err().without_location(),
             VMError::UnknownBuiltinIndex(255)
         ));
 
         let mut invalid_closure_vm = VM::new(Bytecode {
             instructions: make_instructions(OpClosure, &[0, 0]),
             constants: vec![Rc::new(Object::Integer(1))],
+            source_map: Default::default(),
+            host_builtins: Default::default(),
+            num_globals: 0,
         });
         assert!(matches!(
-            invalid_closure_vm.run().unwrap_err(),
+            invalid_closure_vm.run().unwrap_err().without_location(),
             VMError::ExpectedCompiledFunction("INTEGER")
         ));
     }
 
+    fn double(args: Vec<Rc<Object>>) -> Rc<Object> {
+        match args.as_slice() {
+            [arg] => match &**arg {
+                Object::Integer(i) => Rc::new(Object::Integer(i * 2)),
+                _ => Rc::new(Object::Null),
+            },
+            _ => Rc::new(Object::Null),
+        }
+    }
+
+    fn run_with_builtins(input: &str, builtins: &[(&str, BuiltinFunc)]) -> Rc<Object> {
+        let mut compiler = Compiler::new();
+        for (name, func) in builtins {
+            compiler.register_builtin(name, *func).unwrap();
+        }
+        let bytecode = compiler.compile(&parse(input).unwrap()).unwrap();
+        verify(&bytecode).unwrap();
+        let mut vm = VM::new(bytecode);
+        vm.run().unwrap();
+        vm.last_popped_stack_elm().unwrap().into_rc_object()
+    }
+
+    #[test]
+    fn test_registered_builtins() {
+        let double = BuiltinFunc::Plain(double);
+        assert_eq!(
+            *run_with_builtins("double(21)", &[("double", double)]),
+            Object::Integer(42)
+        );
+        assert_eq!(
+            *run_with_builtins(
+                "let f = fn(x) { double(x) + 1 }; map([1, 2], f)",
+                &[("double", double)]
+            ),
+            Object::Array(vec![
+                Rc::new(Object::Integer(3)),
+                Rc::new(Object::Integer(5))
+            ])
+        );
+        // a registered builtin hides the standard one, even when the call
+        // could have been folded at compile time
+        assert_eq!(
+            *run_with_builtins("len(21)", &[("len", double)]),
+            Object::Integer(42)
+        );
+
+        let mut compiler = Compiler::new();
+        compiler.compile(&parse("1").unwrap()).unwrap();
+        assert_eq!(
+            compiler.register_builtin("double", double),
+            Err(CompileError::BuiltinAfterCompile("double".to_string()))
+        );
+    }
+
     #[test]
     fn test_conditionals() {
         let tests = vec![
@@ -329,28 +1100,73 @@ mod tests {
                 expected: Object::Integer(10),
             },
             VmTestCase {
-                input: "if (1 < 2) { 10 }",
-                expected: Object::Integer(10),
+                input: "if (1 < 2) { 10 }",
+                expected: Object::Integer(10),
+            },
+            VmTestCase {
+                input: "if (1 < 2) { 10 } else { 20 }",
+                expected: Object::Integer(10),
+            },
+            VmTestCase {
+                input: "if (1 > 2) { 10 } else { 20 }",
+                expected: Object::Integer(20),
+            },
+            VmTestCase {
+                input: "if (1 > 2) { 10 }",
+                expected: Object::Null,
+            },
+            VmTestCase {
+                input: "if (false) { 10 }",
+                expected: Object::Null,
+            },
+            VmTestCase {
+                input: "if ((if (false) { 10 })) { 10 } else { 20 }",
+                expected: Object::Integer(20),
+            },
+        ];
+
+        run_vm_tests(tests);
+    }
+
+    #[test]
+    fn test_conditional_expressions() {
+        let tests = vec![
+            VmTestCase {
+                input: "let x = 1; x < 2 ? 10 : 20",
+                expected: Object::Integer(10),
+            },
+            VmTestCase {
+                input: "let x = 1; x > 2 ? 10 : 20",
+                expected: Object::Integer(20),
+            },
+            VmTestCase {
+                input: "null ? 1 : 2",
+                expected: Object::Integer(2),
             },
             VmTestCase {
-                input: "if (1 < 2) { 10 } else { 20 }",
-                expected: Object::Integer(10),
+                input: "let sign = fn(n) { n < 0 ? -1 : n == 0 ? 0 : 1 }; [sign(-5), sign(0), sign(5)]",
+                expected: Object::Array(vec![
+                    Rc::new(Object::Integer(-1)),
+                    Rc::new(Object::Integer(0)),
+                    Rc::new(Object::Integer(1)),
+                ]),
             },
             VmTestCase {
-                input: "if (1 > 2) { 10 } else { 20 }",
-                expected: Object::Integer(20),
+                input: "let a = true; let b = false; a ? b ? 1 : 2 : 3",
+                expected: Object::Integer(2),
             },
             VmTestCase {
-                input: "if (1 > 2) { 10 }",
-                expected: Object::Null,
+                input: "1 + (true ? 2 : 3) * 4",
+                expected: Object::Integer(9),
             },
             VmTestCase {
-                input: "if (false) { 10 }",
-                expected: Object::Null,
+                input: "let h = {\"k\": false ? 1 : 2}; h[\"k\"]",
+                expected: Object::Integer(2),
             },
+            // only the chosen branch runs
             VmTestCase {
-                input: "if ((if (false) { 10 })) { 10 } else { 20 }",
-                expected: Object::Integer(20),
+                input: "let zero = 0; zero == 0 ? 1 : 1 / zero",
+                expected: Object::Integer(1),
             },
         ];
 
@@ -382,19 +1198,63 @@ mod tests {
         let tests = vec![
             VmTestCase {
                 input: "\"monkey\"",
-                expected: Object::String("monkey".to_string()),
+                expected: Object::String("monkey".into()),
             },
             VmTestCase {
                 input: "\"mon\" + \"key\"",
-                expected: Object::String("monkey".to_string()),
+                expected: Object::String("monkey".into()),
             },
             VmTestCase {
                 input: "\"mon\" + \"key\" + \"banana\"",
-                expected: Object::String("monkeybanana".to_string()),
+                expected: Object::String("monkeybanana".into()),
+            },
+        ];
+
+        run_vm_tests(tests);
+    }
+
+    #[test]
+    fn test_null() {
+        let tests = vec![
+            VmTestCase {
+                input: "null",
+                expected: Object::Null,
+            },
+            VmTestCase {
+                input: "!null",
+                expected: Object::Boolean(true),
+            },
+            VmTestCase {
+                input: "let x = null; !x",
+                expected: Object::Boolean(true),
+            },
+            VmTestCase {
+                input: "let x = null; x == null",
+                expected: Object::Boolean(true),
+            },
+            VmTestCase {
+                input: "null == if (false) { 1 }",
+                expected: Object::Boolean(true),
+            },
+            VmTestCase {
+                input: "null != 0",
+                expected: Object::Boolean(true),
+            },
+            VmTestCase {
+                input: "if (null) { 1 } else { 2 }",
+                expected: Object::Integer(2),
+            },
+            VmTestCase {
+                input: "[1, null][1]",
+                expected: Object::Null,
             },
         ];
 
         run_vm_tests(tests);
+        assert!(matches!(
+            run_vm_error_test(r#"let h = {"a": 1}; h[null]"#),
+            VMError::UnusableAsHashKey("NULL")
+        ));
     }
 
     #[test]
@@ -427,7 +1287,7 @@ mod tests {
     #[test]
     fn test_hash() {
         fn map_vec_to_object(vec: Vec<(i64, i64)>) -> Object {
-            let hash = vec.iter().fold(HashMap::new(), |mut acc, (k, v)| {
+            let hash = vec.iter().fold(OrderedMap::new(), |mut acc, (k, v)| {
                 acc.insert(HashKey::Integer(*k), Rc::new(Object::Integer(*v)));
                 acc
             });
@@ -436,7 +1296,7 @@ mod tests {
         let tests = vec![
             VmTestCase {
                 input: "{}",
-                expected: Object::Hash(HashMap::new()),
+                expected: Object::Hash(OrderedMap::new()),
             },
             VmTestCase {
                 input: "{1: 2, 2: 3}",
@@ -451,6 +1311,49 @@ mod tests {
         run_vm_tests(tests);
     }
 
+    #[test]
+    fn test_duplicate_hash_keys_keep_the_last_value() {
+        let tests = vec![
+            VmTestCase {
+                input: r#"{1: "a", 1: "b"}[1]"#,
+                expected: Object::String("b".into()),
+            },
+            VmTestCase {
+                input: r#"let h = {"k": 1, "j": 2, "k": 3}; [h["k"], len(h)]"#,
+                expected: Object::Array(vec![
+                    Rc::new(Object::Integer(3)),
+                    Rc::new(Object::Integer(2)),
+                ]),
+            },
+            VmTestCase {
+                // keys computed at runtime follow the same rule
+                input: r#"let one = fn() { 1 }; {one(): "a", 2 - 1: "b", one(): "c"}[1]"#,
+                expected: Object::String("c".into()),
+            },
+        ];
+        run_vm_tests(tests);
+    }
+
+    #[test]
+    fn test_hash_display_follows_literal_order() {
+        let tests = [
+            (r#"{"b": 1, "a": 2, "c": 3}"#, "[b: 1, a: 2, c: 3]"),
+            (
+                "{3: true, 1: false, 2: true}",
+                "[3: true, 1: false, 2: true]",
+            ),
+            (r#"{"x": 1, "y": 2, "x": 3}"#, "[x: 3, y: 2]"),
+        ];
+        for (input, expected) in tests {
+            let program = parse(input).unwrap();
+            let bytecodes = Compiler::new().compile(&program).unwrap();
+            let mut vm = VM::new(bytecodes);
+            vm.run().unwrap();
+            let got = vm.last_popped_stack_elm().unwrap().into_rc_object();
+            assert_eq!(got.to_string(), expected, "for input {}", input);
+        }
+    }
+
     #[test]
     fn test_index() {
         let tests = vec![
@@ -503,15 +1406,154 @@ mod tests {
         run_vm_tests(tests);
     }
 
+    #[test]
+    fn test_string_index() {
+        let string = |s: &str| Object::String(s.into());
+        let tests = vec![
+            VmTestCase {
+                input: r#""hello"[1]"#,
+                expected: string("e"),
+            },
+            VmTestCase {
+                input: r#""hello"[4]"#,
+                expected: string("o"),
+            },
+            VmTestCase {
+                input: r#""héllo"[1]"#,
+                expected: string("é"),
+            },
+            VmTestCase {
+                input: r#""héllo"[2]"#,
+                expected: string("l"),
+            },
+            VmTestCase {
+                input: r#""hello"[-1]"#,
+                expected: Object::Null,
+            },
+            VmTestCase {
+                input: r#""hello"[5]"#,
+                expected: Object::Null,
+            },
+            VmTestCase {
+                input: r#""héllo"[5]"#,
+                expected: Object::Null,
+            },
+            VmTestCase {
+                input: r#"""[0]"#,
+                expected: Object::Null,
+            },
+        ];
+
+        run_vm_tests(tests);
+    }
+
+    #[test]
+    fn test_dot_access() {
+        let tests = vec![
+            VmTestCase {
+                input: r#"let person = {"name": "Ada"}; person.name"#,
+                expected: Object::String("Ada".into()),
+            },
+            VmTestCase {
+                input: r#"let p = {"address": {"city": "London"}}; p.address.city"#,
+                expected: Object::String("London".into()),
+            },
+            VmTestCase {
+                input: r#"let h = {"items": [{"id": 7}]}; h.items[0].id"#,
+                expected: Object::Integer(7),
+            },
+            VmTestCase {
+                input: r#"let h = {"a": {"b": 2}}; h["a"].b"#,
+                expected: Object::Integer(2),
+            },
+            VmTestCase {
+                input: r#"{"name": "Ada"}.name.len()"#,
+                expected: Object::Integer(3),
+            },
+            VmTestCase {
+                input: r#"{"name": "Ada"}.age"#,
+                expected: Object::Null,
+            },
+        ];
+
+        run_vm_tests(tests);
+    }
+
+    #[test]
+    fn test_block_scoping() {
+        let tests = vec![
+            VmTestCase {
+                input: "let x = 1; if (true) { let x = 2; }; x",
+                expected: Object::Integer(1),
+            },
+            VmTestCase {
+                input: "let x = 1; if (true) { let x = 2; x }",
+                expected: Object::Integer(2),
+            },
+            // a branch ending in anything but an expression is null
+            VmTestCase {
+                input: "let x = 1; let y = if (true) { x = 2; }; [x, y]",
+                expected: Object::Array(vec![Rc::new(Object::Integer(2)), Rc::new(Object::Null)]),
+            },
+            VmTestCase {
+                input: "if (false) { 1 } else { }",
+                expected: Object::Null,
+            },
+            VmTestCase {
+                input: "let x = 1; if (false) { 0 } else { let x = x + 1; x }",
+                expected: Object::Integer(2),
+            },
+            VmTestCase {
+                input: "let f = fn() { let x = 1; if (true) { let x = 2; }; x }; f()",
+                expected: Object::Integer(1),
+            },
+            VmTestCase {
+                input: "let xs = [1]; if (true) { let xs = push(xs, 2); }; xs",
+                expected: Object::Array(vec![Rc::new(Object::Integer(1))]),
+            },
+            VmTestCase {
+                input: "let last = 0; for (i in [1, 2]) { let last = i; }; last",
+                expected: Object::Integer(0),
+            },
+            // the second block reuses the first one's slot; the closure kept
+            // its own copy of `a`
+            VmTestCase {
+                input: "let f = fn() { \
+                        let g = if (true) { let a = 10; fn() { a } }; \
+                        if (true) { let b = 20; }; \
+                        g() }; \
+                    f()",
+                expected: Object::Integer(10),
+            },
+            // a global defined in a block keeps its slot after the block
+            VmTestCase {
+                input: "let g = if (true) { let a = 5; fn() { a } }; \
+                    if (true) { let b = 6; }; \
+                    g()",
+                expected: Object::Integer(5),
+            },
+            VmTestCase {
+                input: "let f = fn() { \
+                        let total = 0; \
+                        if (true) { let a = 1; if (true) { let b = 2; total = a + b; } }; \
+                        total }; \
+                    f()",
+                expected: Object::Integer(3),
+            },
+        ];
+
+        run_vm_tests(tests);
+    }
+
     #[test]
     fn test_while_loops() {
         let tests = vec![
             VmTestCase {
-                input: "let x = 0; while (x < 5) { let x = x + 1; }; x",
+                input: "let x = 0; while (x < 5) { let next = x + 1; x = next; }; x",
                 expected: Object::Integer(5),
             },
             VmTestCase {
-                input: "let a = []; let i = 0; while (i < 3) { let a = push(a, i); let i = i + 1; }; a",
+                input: "let a = []; let i = 0; while (i < 3) { a = push(a, i); i = i + 1; }; a",
                 expected: Object::Array(vec![
                     Rc::new(Object::Integer(0)),
                     Rc::new(Object::Integer(1)),
@@ -522,6 +1564,138 @@ mod tests {
                 input: "while (false) { 1 }",
                 expected: Object::Null,
             },
+            VmTestCase {
+                input: "let x = 10; while (x < 5) { x = x + 1; }; x",
+                expected: Object::Integer(10),
+            },
+            // a loop evaluates to null however many times it runs
+            VmTestCase {
+                input: "let i = 0; while (i < 3) { i = i + 1; i }",
+                expected: Object::Null,
+            },
+            VmTestCase {
+                input: "let total = 0; let i = 0; \
+                        while (i < 3) { let j = 0; while (j < 4) { total = total + 1; j = j + 1; }; i = i + 1; }; \
+                        total",
+                expected: Object::Integer(12),
+            },
+            VmTestCase {
+                input: "let fact = fn(n) { let acc = 1; while (n > 1) { acc = acc * n; n = n - 1; }; acc }; fact(5)",
+                expected: Object::Integer(120),
+            },
+        ];
+
+        run_vm_tests(tests);
+    }
+
+    #[test]
+    fn test_for_in_loops() {
+        let tests = vec![
+            VmTestCase {
+                input: "let total = 0; for (x in [1, 2, 3]) { total = total + x; }; total",
+                expected: Object::Integer(6),
+            },
+            // a hash is iterated over its keys, in insertion order
+            VmTestCase {
+                input: "let keys = []; for (k in {\"b\": 1, \"a\": 2}) { keys = push(keys, k); }; keys",
+                expected: Object::Array(vec![
+                    Rc::new(Object::String("b".into())),
+                    Rc::new(Object::String("a".into())),
+                ]),
+            },
+            VmTestCase {
+                input: "let n = 0; for (x in []) { n = n + 1; }; n",
+                expected: Object::Integer(0),
+            },
+            VmTestCase {
+                input: "for (x in []) { x }",
+                expected: Object::Null,
+            },
+            VmTestCase {
+                input: "for (x in [1, 2]) { x }",
+                expected: Object::Null,
+            },
+            VmTestCase {
+                input: "let odd = 0; for (x in [1, 2, 3, 4, 5]) { if (x % 2 == 0) { continue; }; if (x > 4) { break; }; odd = odd + x; }; odd",
+                expected: Object::Integer(4),
+            },
+            VmTestCase {
+                input: "let pairs = 0; for (a in [1, 2, 3]) { for (b in [1, 2]) { pairs = pairs + a * b; } }; pairs",
+                expected: Object::Integer(18),
+            },
+            VmTestCase {
+                input: "let sum = fn(xs) { let total = 0; for (x in xs) { total = total + x; }; total }; sum([4, 5, 6])",
+                expected: Object::Integer(15),
+            },
+            // the loop variable is an ordinary binding that outlives the loop
+            VmTestCase {
+                input: "for (x in [7, 8]) { }; x",
+                expected: Object::Integer(8),
+            },
+        ];
+
+        run_vm_tests(tests);
+    }
+
+    #[test]
+    fn test_break_and_continue() {
+        let tests = vec![
+            VmTestCase {
+                input: "let i = 0; while (true) { i = i + 1; if (i == 5) { break; } }; i",
+                expected: Object::Integer(5),
+            },
+            VmTestCase {
+                input: "let i = 0; let odd = 0; \
+                        while (i < 10) { i = i + 1; if (i % 2 == 0) { continue; }; odd = odd + 1; }; \
+                        odd",
+                expected: Object::Integer(5),
+            },
+            // an inner `break` leaves the inner loop only
+            VmTestCase {
+                input: "let outer = 0; let inner = 0; \
+                        while (outer < 3) { \
+                            outer = outer + 1; \
+                            while (true) { inner = inner + 1; break; inner = 100; }; \
+                        }; \
+                        [outer, inner]",
+                expected: Object::Array(vec![
+                    Rc::new(Object::Integer(3)),
+                    Rc::new(Object::Integer(3)),
+                ]),
+            },
+            VmTestCase {
+                input: "let pairs = []; let i = 0; \
+                        while (i < 3) { \
+                            let j = 0; \
+                            while (j < 3) { j = j + 1; if (j == 2) { continue; }; if (j > i) { break; }; pairs = push(pairs, [i, j]); }; \
+                            i = i + 1; \
+                        }; \
+                        pairs",
+                expected: Object::Array(vec![
+                    Rc::new(Object::Array(vec![
+                        Rc::new(Object::Integer(1)),
+                        Rc::new(Object::Integer(1)),
+                    ])),
+                    Rc::new(Object::Array(vec![
+                        Rc::new(Object::Integer(2)),
+                        Rc::new(Object::Integer(1)),
+                    ])),
+                ]),
+            },
+            VmTestCase {
+                input: "let find = fn(xs, x) { let i = 0; let found = -1; \
+                        while (i < len(xs)) { if (xs[i] == x) { found = i; break; }; i = i + 1; }; found }; \
+                        [find([4, 5, 6], 5), find([4, 5, 6], 7)]",
+                expected: Object::Array(vec![
+                    Rc::new(Object::Integer(1)),
+                    Rc::new(Object::Integer(-1)),
+                ]),
+            },
+            // `let` with an `if` that breaks leaves nothing on the stack
+            VmTestCase {
+                input: "let n = 0; while (true) { let next = if (n > 2) { break; } else { n + 1 }; n = next; }; n",
+                expected: Object::Integer(3),
+            },
         ];
 
         run_vm_tests(tests);
@@ -554,6 +1728,23 @@ mod tests {
                 input: "6 >= 5",
                 expected: Object::Boolean(true),
             },
+            VmTestCase {
+                input: "let max = 9223372036854775807; max >= max && max <= max",
+                expected: Object::Boolean(true),
+            },
+            VmTestCase {
+                input: "let a = -1; a <= -1 && a >= -1 && !(a >= 0)",
+                expected: Object::Boolean(true),
+            },
+            VmTestCase {
+                input: "let a = 2; a >= 2.0 && a <= 2.0",
+                expected: Object::Boolean(true),
+            },
+            // every ordered comparison with NaN is false
+            VmTestCase {
+                input: "let nan = 0.0 / 0.0; nan <= 1.0 || nan >= 1.0 || nan >= nan",
+                expected: Object::Boolean(false),
+            },
             VmTestCase {
                 input: "10 % 3",
                 expected: Object::Integer(1),
@@ -576,15 +1767,15 @@ mod tests {
         let tests = vec![
             VmTestCase {
                 input: r#""\thello\nworld""#,
-                expected: Object::String("\thello\nworld".to_string()),
+                expected: Object::String("\thello\nworld".into()),
             },
             VmTestCase {
                 input: r#""hello\\world""#,
-                expected: Object::String("hello\\world".to_string()),
+                expected: Object::String("hello\\world".into()),
             },
             VmTestCase {
                 input: r#""say \"hi\"""#,
-                expected: Object::String("say \"hi\"".to_string()),
+                expected: Object::String("say \"hi\"".into()),
             },
         ];
 
@@ -602,8 +1793,41 @@ mod tests {
                 input: "let sum = fn(n, acc) { if (n == 0) { return acc; } sum(n - 1, acc + n) }; sum(100, 0)",
                 expected: Object::Integer(5050),
             },
+            VmTestCase {
+                input: "let countdown = fn(n) { if (n == 0) { 0 } else { countdown(n - 1) } }; countdown(10)",
+                expected: Object::Integer(0),
+            },
+            VmTestCase {
+                input: "let f = fn(n) { if (n == 0) { return 0; } let g = fn() { f(n - 1) }; g() }; f(3)",
+                expected: Object::Integer(0),
+            },
         ];
 
         run_vm_tests(tests);
+
+        // far deeper than the frame stack goes
+        for input in [
+            "let countdown = fn(n) { if (n == 0) { 0 } else { countdown(n - 1) } }; countdown(100000)",
+            "let countdown = fn(n) { if (n == 0) { return 0; } return countdown(n - 1); }; countdown(100000)",
+            "let countdown = fn(n) { if (n > 0) { if (n % 2 == 0) { countdown(n - 2) } else { countdown(n - 1) } } else { n } }; countdown(100000)",
+        ] {
+            let program = parse(input).unwrap();
+            let mut vm = VM::new(Compiler::new().compile(&program).unwrap());
+            vm.run().unwrap();
+            assert_eq!(
+                *vm.last_popped_stack_elm().unwrap().into_rc_object(),
+                Object::Integer(0),
+                "{}",
+                input
+            );
+        }
+
+        assert!(matches!(
+            run_vm_error_test("let f = fn(n) { if (n == 0) { return 0; } f(n - 1, 2) }; f(3)"),
+            VMError::WrongArity {
+                expected: 1,
+                got: 2
+            }
+        ));
     }
 }
@@ -0,0 +1,84 @@
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::io::{self, Write};
+    use std::rc::Rc;
+
+    use parser::parse;
+
+    use crate::compiler::Compiler;
+    use crate::vm::VM;
+
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn trace(input: &str, enabled: bool) -> String {
+        let program = parse(input).unwrap();
+        let bytecode = Compiler::new().compile(&program).unwrap();
+        let buffer = SharedBuffer::default();
+        let mut vm = VM::new(bytecode);
+        vm.enable_trace(Box::new(buffer.clone()));
+        if !enabled {
+            vm.set_trace(false);
+        }
+        vm.run().unwrap();
+        String::from_utf8(buffer.0.take()).unwrap()
+    }
+
+    #[test]
+    fn test_trace_lists_every_dispatched_instruction() {
+        let expected = "\
+0000 OpConst 0            frame=0 stack=[]
+0003 OpSetGlobal 0        frame=0 stack=[5]
+0006 OpGetGlobal 0        frame=0 stack=[]
+0009 OpConst 1            frame=0 stack=[5]
+0012 OpGreaterThan        frame=0 stack=[5, 3]
+0013 OpJumpNotTruthy 26   frame=0 stack=[true]
+0016 OpGetGlobal 0        frame=0 stack=[]
+0019 OpConst 2            frame=0 stack=[5]
+0022 OpMul                frame=0 stack=[5, 2]
+0023 OpJump 29            frame=0 stack=[10]
+0029 OpPop                frame=0 stack=[10]
+";
+        assert_eq!(
+            trace("let x = 5; if (x > 3) { x * 2 } else { 0 }", true),
+            expected
+        );
+    }
+
+    #[test]
+    fn test_trace_shows_opcodes_in_order() {
+        // `1 + 2` alone is folded into one constant
+        let output = trace("let one = 1; one + 2", true);
+        let opcodes: Vec<&str> = output
+            .lines()
+            .map(|line| line.split_whitespace().nth(1).unwrap())
+            .filter(|opcode| ["OpConst", "OpAdd", "OpPop"].contains(opcode))
+            .collect();
+        assert_eq!(opcodes, ["OpConst", "OpConst", "OpAdd", "OpPop"]);
+        assert!(output.ends_with("OpPop                frame=0 stack=[3]\n"));
+    }
+
+    #[test]
+    fn test_trace_shows_the_frame_and_top_three_values() {
+        let output = trace("let f = fn(a, b, c, d) { d }; f(1, 2, 3, 4)", true);
+        assert!(output.contains("OpCall 4             frame=0 stack=[2, 3, 4]\n"));
+        assert!(output.contains("OpGetLocal 3         frame=1 stack=[2, 3, 4]\n"));
+    }
+
+    #[test]
+    fn test_disabled_trace_writes_nothing() {
+        assert_eq!(trace("1 + 2", false), "");
+    }
+}
@@ -0,0 +1,110 @@
+use parser::lexer::token::Span;
+
+use crate::symbol_table::SymbolScope;
+
+// Warnings never stop compilation. Spans are byte offsets into the source,
+// so rendering one needs the source text to turn them into line numbers.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Warning {
+    pub kind: WarningKind,
+    pub span: Span,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum WarningKind {
+    UnreachableCode {
+        after: Terminator,
+        at: Span,
+    },
+    Shadowing {
+        name: String,
+        shadowed: SymbolScope,
+        previous: Option<Span>,
+    },
+    /// A hash literal repeats a constant key; the later value wins.
+    DuplicateHashKey {
+        key: String,
+        first: Span,
+    },
+}
+
+/// The construct that unconditionally leaves a block.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Terminator {
+    Return,
+    Break,
+    Continue,
+    /// An `if` whose branches all return, break or continue: the one they
+    /// all do, or `None` when they differ.
+    If(Option<Exit>),
+}
+
+/// How a `return`, `break` or `continue` leaves its block.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Exit {
+    Return,
+    Break,
+    Continue,
+}
+
+impl Warning {
+    pub fn render(&self, source: &str) -> String {
+        match &self.kind {
+            WarningKind::UnreachableCode { after, at } => {
+                let construct = match after {
+                    Terminator::Return => "`return`",
+                    Terminator::Break => "`break`",
+                    Terminator::Continue => "`continue`",
+                    Terminator::If(Some(Exit::Return)) => "`if` whose branches all return",
+                    Terminator::If(Some(Exit::Break)) => "`if` whose branches all break",
+                    Terminator::If(Some(Exit::Continue)) => "`if` whose branches all continue",
+                    Terminator::If(None) => "`if` whose branches all return, break or continue",
+                };
+                format!(
+                    "line {}: unreachable code after {} on line {}",
+                    line_number(source, self.span.start),
+                    construct,
+                    line_number(source, at.start)
+                )
+            }
+            WarningKind::Shadowing {
+                name,
+                shadowed,
+                previous,
+            } => {
+                let what = match shadowed {
+                    SymbolScope::Global => "global",
+                    SymbolScope::Builtin => "builtin",
+                    SymbolScope::Function => "enclosing function",
+                    SymbolScope::Local | SymbolScope::Free => "outer local",
+                };
+                let mut message = format!(
+                    "line {}: `{}` shadows the {} `{}`",
+                    line_number(source, self.span.start),
+                    name,
+                    what,
+                    name
+                );
+                if let Some(previous) = previous {
+                    message += &format!(" defined on line {}", line_number(source, previous.start));
+                }
+                message
+            }
+            WarningKind::DuplicateHashKey { key, first } => format!(
+                "line {}: hash key {} repeats the key on line {}, whose value is overwritten",
+                line_number(source, self.span.start),
+                key,
+                line_number(source, first.start)
+            ),
+        }
+    }
+}
+
+pub fn line_number(source: &str, offset: usize) -> usize {
+    let offset = offset.min(source.len());
+    source.as_bytes()[..offset]
+        .iter()
+        .filter(|&&byte| byte == b'\n')
+        .count()
+        + 1
+}
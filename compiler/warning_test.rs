@@ -0,0 +1,201 @@
+#[cfg(test)]
+mod tests {
+    use parser::parse;
+
+    use crate::compiler::Compiler;
+
+    fn compile_warnings(input: &str) -> Vec<String> {
+        warnings_with(input, false)
+    }
+
+    fn warnings_with(input: &str, warn_shadowing: bool) -> Vec<String> {
+        let program = parse(input).unwrap();
+        let mut compiler = Compiler::new();
+        compiler.set_warn_shadowing(warn_shadowing);
+        compiler.compile(&program).unwrap();
+        compiler
+            .warnings()
+            .iter()
+            .map(|warning| warning.render(input))
+            .collect()
+    }
+
+    #[test]
+    fn test_code_after_return() {
+        let input = "let f = fn() {
+    let x = 1;
+    return x;
+    x + 1;
+    x + 2;
+};";
+        assert_eq!(
+            compile_warnings(input),
+            ["line 4: unreachable code after `return` on line 3"]
+        );
+    }
+
+    #[test]
+    fn test_code_after_top_level_return() {
+        assert_eq!(
+            compile_warnings("return 1;\nlet y = 2;"),
+            ["line 2: unreachable code after `return` on line 1"]
+        );
+    }
+
+    #[test]
+    fn test_code_after_if_where_both_branches_return() {
+        let input = "fn(x) {
+    if (x) {
+        return 1;
+    } else {
+        puts(x);
+        return 2;
+    }
+    puts(x);
+}";
+        assert_eq!(
+            compile_warnings(input),
+            ["line 8: unreachable code after `if` whose branches all return on line 2"]
+        );
+    }
+
+    #[test]
+    fn test_code_after_if_names_how_its_branches_leave() {
+        for (branches, wording) in [
+            ("break; } else { break;", "all break"),
+            ("continue; } else { continue;", "all continue"),
+            ("return 1; } else { break;", "all return, break or continue"),
+            (
+                "if (false) { break; } else { break; } } else { break;",
+                "all break",
+            ),
+        ] {
+            let input = format!(
+                "while (true) {{\n    if (false) {{ {} }}\n    1\n}}",
+                branches
+            );
+            assert_eq!(
+                compile_warnings(&input),
+                [format!(
+                    "line 3: unreachable code after `if` whose branches {} on line 2",
+                    wording
+                )],
+                "{}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn test_nested_blocks_are_checked() {
+        let input = "fn(x) {
+    while (x) {
+        return x;
+        x
+    }
+}";
+        assert_eq!(
+            compile_warnings(input),
+            ["line 4: unreachable code after `return` on line 3"]
+        );
+    }
+
+    #[test]
+    fn test_code_after_break_and_continue() {
+        let input = "while (true) {
+    break;
+    puts(1);
+};
+while (false) {
+    continue;
+    puts(2);
+}";
+        assert_eq!(
+            compile_warnings(input),
+            [
+                "line 3: unreachable code after `break` on line 2",
+                "line 7: unreachable code after `continue` on line 6"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_no_warning_when_only_one_branch_returns() {
+        let inputs = [
+            "fn(x) { if (x) { return 1; } else { 2 }; x }",
+            "fn(x) { if (x) { return 1; }; x }",
+            "fn(x) { let y = x; return y; }",
+        ];
+        for input in inputs {
+            assert!(compile_warnings(input).is_empty(), "for input {}", input);
+        }
+    }
+
+    #[test]
+    fn test_local_shadowing_global() {
+        let input = "let x = 1;
+let f = fn() {
+    let x = 2;
+    x
+};";
+        assert_eq!(
+            warnings_with(input, true),
+            ["line 3: `x` shadows the global `x` defined on line 1"]
+        );
+    }
+
+    #[test]
+    fn test_parameter_shadowing_global_and_outer_local() {
+        let input = "let x = 1;
+fn(x) {
+    fn(x) { x }
+}";
+        assert_eq!(
+            warnings_with(input, true),
+            [
+                "line 2: `x` shadows the global `x` defined on line 1",
+                "line 3: `x` shadows the outer local `x` defined on line 2",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_rebinding_in_the_same_scope_is_not_shadowing() {
+        let input = "let x = 1; x = x + 1; fn() { let y = 1; y = y + 1; y }";
+        assert!(warnings_with(input, true).is_empty());
+    }
+
+    #[test]
+    fn test_builtin_shadowing_always_warns() {
+        let input = "let len = fn(x) { 0 };\nfn(puts) { puts }";
+        let expected = [
+            "line 1: `len` shadows the builtin `len`",
+            "line 2: `puts` shadows the builtin `puts`",
+        ];
+        assert_eq!(compile_warnings(input), expected);
+        assert_eq!(warnings_with(input, true), expected);
+    }
+
+    #[test]
+    fn test_shadowing_warnings_are_off_by_default() {
+        let input = "let x = 1; let f = fn(x) { let y = x; fn() { let y = 2; y } };";
+        assert!(compile_warnings(input).is_empty());
+        assert_eq!(warnings_with(input, true).len(), 2);
+    }
+
+    #[test]
+    fn test_duplicate_constant_hash_keys() {
+        assert_eq!(
+            compile_warnings("let h = {1: \"a\",\n1: \"b\", \"k\": 1, true: 2,\n\"k\": 3};"),
+            [
+                "line 2: hash key 1 repeats the key on line 1, whose value is overwritten",
+                "line 3: hash key \"k\" repeats the key on line 2, whose value is overwritten",
+            ]
+        );
+        // keys only known at runtime, and equal-looking keys of other types
+        assert_eq!(
+            compile_warnings("let k = 1; let h = {k: 1, k: 2, 1: 3, \"1\": 4, true: 5};"),
+            Vec::<String>::new()
+        );
+    }
+}
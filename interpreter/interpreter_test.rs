@@ -33,6 +33,29 @@ mod tests {
         apply_test(&test_case);
     }
 
+    #[test]
+    fn test_division_sign_semantics() {
+        // `/` and `%` truncate toward zero, `div` rounds toward negative
+        // infinity; `a == (a / b) * b + a % b` always holds.
+        let test_case = [
+            ("7 / 2", "3"),
+            ("-7 / 2", "-3"),
+            ("7 / -2", "-3"),
+            ("-7 / -2", "3"),
+            ("7 div 2", "3"),
+            ("-7 div 2", "-4"),
+            ("7 div -2", "-4"),
+            ("-7 div -2", "3"),
+            ("7 % 2", "1"),
+            ("-7 % 2", "-1"),
+            ("7 % -2", "1"),
+            ("-7 % -2", "-1"),
+            ("-8 div 2", "-4"),
+            ("7 div 0", "division by zero"),
+        ];
+        apply_test(&test_case);
+    }
+
     #[test]
     fn test_boolean_expressions() {
         let test_case = [
@@ -135,6 +158,156 @@ mod tests {
         apply_test(&test_case);
     }
 
+    #[test]
+    fn test_while_loops() {
+        let test_case = [
+            ("let i = 0; while (i < 3) { i = i + 1; i }", "null"),
+            ("while (false) { 1 }", "null"),
+            (
+                "let early = fn() { while (true) { return 7; } }; early()",
+                "7",
+            ),
+        ];
+        apply_test(&test_case);
+    }
+
+    #[test]
+    fn test_break_and_continue() {
+        let test_case = [
+            (
+                "let i = 0; while (true) { i = i + 1; if (i == 5) { break; } }; i",
+                "5",
+            ),
+            (
+                "let odd = 0; let k = 0; while (k < 10) { k = k + 1; if (k % 2 == 0) { continue; }; odd = odd + 1; }; odd",
+                "5",
+            ),
+            (
+                "let outer = 0; while (outer < 3) { outer = outer + 1; while (true) { break; }; }; outer",
+                "3",
+            ),
+            ("break;", "break outside a loop"),
+            ("if (true) { continue; }", "continue outside a loop"),
+            // the caller's loop is not the callee's
+            (
+                "let stop = fn() { break; }; while (true) { stop(); }",
+                "break outside a loop",
+            ),
+        ];
+        apply_test(&test_case);
+    }
+
+    #[test]
+    fn test_for_in_loops() {
+        let test_case = [
+            (
+                "let total = 0; for (x in [1, 2, 3]) { total = total + x; }; total",
+                "6",
+            ),
+            (
+                "let keys = []; for (k in {\"b\": 1, \"a\": 2}) { keys = push(keys, k); }; keys",
+                "[b, a]",
+            ),
+            ("for (x in []) { x }", "null"),
+            (
+                "let odd = 0; for (x in [1, 2, 3, 4, 5]) { if (x % 2 == 0) { continue; }; if (x > 4) { break; }; odd = odd + x; }; odd",
+                "4",
+            ),
+            ("for (x in 5) { x }", "cannot iterate over 5"),
+        ];
+        apply_test(&test_case);
+    }
+
+    #[test]
+    fn test_logical_operators() {
+        let test_case = [
+            ("true && false", "false"),
+            ("false || 1", "true"),
+            ("let boom = fn() { 1 + true }; false && boom()", "false"),
+            ("true || boom()", "true"),
+            (
+                "true && boom()",
+                "eval infix error for op: +, left: INTEGER, right: BOOLEAN",
+            ),
+        ];
+        apply_test(&test_case);
+    }
+
+    #[test]
+    fn test_assignment() {
+        let test_case = [
+            ("let a = 1; a = a + 1; a;", "2"),
+            ("let n = 0; while (n < 5) { n = n + 1; }; n;", "5"),
+            (
+                "let count = 0; let bump = fn() { count = count + 1; }; bump(); bump(); count",
+                "2",
+            ),
+            ("let twice = fn(x) { x = x * 2; x }; twice(4)", "8"),
+            ("undeclared = 1;", "unknown identifier undeclared"),
+            (
+                "let outer = fn() { let v = 1; fn() { v = 2; } }; outer()()",
+                "cannot assign to v, which belongs to an enclosing function",
+            ),
+        ];
+        apply_test(&test_case);
+    }
+
+    #[test]
+    fn test_block_scoping() {
+        let test_case = [
+            ("let x = 1; if (true) { let x = 2; }; x", "1"),
+            ("let y = 1; if (true) { let y = 2; y }", "2"),
+            (
+                "if (true) { let inner = 1; }; inner",
+                "unknown identifier inner",
+            ),
+            (
+                "let w = 0; while (w < 3) { let next = w + 1; w = next; }; w",
+                "3",
+            ),
+            (
+                "let last = 0; for (i in [1, 2]) { let last = i; }; last",
+                "0",
+            ),
+            ("let z = 1; if (true) { if (true) { z = 2; } }; z", "2"),
+            (
+                "let f = fn() { let v = 1; if (true) { v = 2; let v = 3; }; v }; f()",
+                "2",
+            ),
+            // top-level blocks are global, so a function may assign to them
+            (
+                "if (true) { let g = 1; let set = fn() { g = 2; }; set(); g }",
+                "2",
+            ),
+            (
+                "let h = fn() { if (true) { let v = 1; fn() { v = 2; } } }; h()()",
+                "cannot assign to v, which belongs to an enclosing function",
+            ),
+        ];
+        apply_test(&test_case);
+    }
+
+    #[test]
+    fn test_index_assignment() {
+        let test_case = [
+            ("let xs = [1, 2, 3]; xs[1] = 5; xs", "[1, 5, 3]"),
+            (
+                "let h = {\"a\": 1}; h[\"a\"] = 2; h[\"b\"] = 3; [h[\"a\"], h[\"b\"]]",
+                "[2, 3]",
+            ),
+            (
+                "let a = [1, 2]; let b = a; a[0] = 9; [a[0], b[0]]",
+                "[9, 1]",
+            ),
+            (
+                "let ys = [1, 2, 3]; ys[3] = 1;",
+                "index 3 out of range for array of length 3",
+            ),
+            ("let hs = {}; hs[[1]] = 1;", "not a valid hash key"),
+        ];
+        apply_test(&test_case);
+    }
+
     #[test]
     fn test_function_object() {
         let test_case = [("fn(x) { x + 2; };", "fn(x) { (x + 2) }")];
@@ -163,6 +336,11 @@ mod tests {
             (r#""Hello" + " " + "World!""#, "Hello World!"),
             (r#""Hello" == "Hello""#, "true"),
             (r#""Hello" == "Hi""#, "false"),
+            (
+                r#""n = " + 1"#,
+                "cannot add STRING and INTEGER; convert the other operand with to_string first",
+            ),
+            (r#""n = " + to_string(1)"#, "n = 1"),
         ];
         apply_test(&test_case);
     }
@@ -173,6 +351,13 @@ mod tests {
             (r#"len("")"#, "0"),
             (r#"len("four")"#, "4"),
             (r#"len("hello world")"#, "11"),
+            ("map([1, 2, 3], fn(x) { x * 2 })", "[2, 4, 6]"),
+            ("reduce([1, 2, 3], 0, fn(sum, x) { sum + x })", "6"),
+            ("filter([1, 2], fn(x) { x > 1 })", "[2]"),
+            (
+                "map([1, 2], fn() { 0 })",
+                "wrong number of arguments: want=0, got=1 (calling the function passed to map on element 0)",
+            ),
         ];
         apply_test(&test_case);
     }
@@ -217,7 +402,12 @@ mod tests {
             ("rest([])", "null"),
             ("push([], 1)", "[1]"),
         ];
+        // keep `puts` out of the test output
+        let output = Rc::new(RefCell::new(Vec::<u8>::new()));
+        object::builtins::set_output(Some(output.clone()));
         apply_test(&test_case);
+        object::builtins::set_output(None);
+        assert_eq!(output.borrow().as_slice(), b"hello\nworld!\n");
         // let illegal_cases = [
         //     "len(1)",
         //     r#"len("one", "two")"#,
@@ -227,6 +417,27 @@ mod tests {
         // ];
     }
 
+    #[test]
+    fn test_hash_display_follows_literal_order() {
+        let test_case = [
+            (r#"{"b": 1, "a": 2, "c": 3}"#, "[b: 1, a: 2, c: 3]"),
+            (
+                "{3: true, 1: false, 2: true}",
+                "[3: true, 1: false, 2: true]",
+            ),
+            (r#"{"x": 1, "y": 2, "x": 3}"#, "[x: 3, y: 2]"),
+        ];
+        apply_test(&test_case);
+    }
+
+    #[test]
+    fn test_hash_equality_ignores_order() {
+        let env: Env = Rc::new(RefCell::new(Default::default()));
+        let first = eval(parse(r#"{"a": 1, "b": 2}"#).unwrap(), &env).unwrap();
+        let second = eval(parse(r#"{"b": 2, "a": 1}"#).unwrap(), &env).unwrap();
+        assert_eq!(first, second);
+    }
+
     #[test]
     fn test_hash_index_expressions() {
         let test_case = [
@@ -240,4 +451,15 @@ mod tests {
         ];
         apply_test(&test_case);
     }
+
+    #[test]
+    fn test_string_index_expressions() {
+        let test_case = [
+            (r#""hello"[1]"#, "e"),
+            (r#""héllo"[1]"#, "é"),
+            (r#""hello"[-1]"#, "null"),
+            (r#""hello"[5]"#, "null"),
+        ];
+        apply_test(&test_case);
+    }
 }
@@ -1,19 +1,35 @@
-use std::cell::RefCell;
-use std::collections::HashMap;
+use std::cell::{Cell, RefCell};
 use std::rc::Rc;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
 
 use object::builtins::*;
 use object::environment::*;
-use object::{EvalError, HashKey, Object};
+use object::ordered_map::OrderedMap;
+use object::random::Random;
+use object::{
+    BuiltinFunc, Engine, EvalError, Halt, HashKey, Object, checked_floor_div, utf8_error_field,
+    values_equal,
+};
 use parser::ast::*;
 use parser::lexer::token::{Token, TokenKind};
 
 #[cfg(test)]
 mod interpreter_test;
+pub mod macro_expansion;
+#[cfg(test)]
+mod macro_expansion_test;
+
+/// How deep calls may nest, the top level included, before evaluation
+/// fails with `EvalError::MaxFramesExceeded`; the same as the VM's default.
+pub const MAX_FRAMES: usize = 1024;
 
 pub fn eval(node: Node, env: &Env) -> Result<Rc<Object>, EvalError> {
     match node {
-        Node::Program(p) => eval_block_statements(&p.body, env),
+        Node::Program(p) => {
+            check_definitions(&[], &p.body)?;
+            eval_block_statements(&p.body, env)
+        }
         Node::Statement(statements) => eval_statement(&statements, env),
         Node::Expression(expression) => eval_expression(&expression, env),
     }
@@ -34,9 +50,50 @@ fn eval_block_statements(statements: &Vec<Statement>, env: &Env) -> Result<Rc<Ob
     Ok(result)
 }
 
+// `if`, `while` and `for` bodies get an environment of their own each time
+// they run, so the names they define don't outlive them.
+fn eval_block(block: &BlockStatement, env: &Env) -> Result<Rc<Object>, EvalError> {
+    check_definitions(&[], &block.body)?;
+    let env = Rc::new(RefCell::new(Environment::new_block_environment(env)));
+    eval_block_statements(&block.body, &env)
+}
+
+// Rejects a second `let` for a name `params` or an earlier `let` in the
+// same scope defines, before any of the scope runs, as the compiler does.
+// A REPL input may still redefine what earlier inputs did.
+fn check_definitions(params: &[IDENTIFIER], statements: &[Statement]) -> Result<(), EvalError> {
+    let mut defined: Vec<&str> = Vec::new();
+    for statement in statements {
+        if let Statement::Let(Let { identifier, .. }) = statement
+            && let TokenKind::IDENTIFIER { name } = &identifier.kind
+        {
+            if defined.contains(&name.as_str()) || params.iter().any(|p| p.name == *name) {
+                return Err(EvalError::DuplicateDefinition(name.clone()));
+            }
+            defined.push(name);
+        }
+    }
+    Ok(())
+}
+
 fn eval_statement(statement: &Statement, env: &Env) -> Result<Rc<Object>, EvalError> {
     match statement {
         Statement::Expr(expr) => eval_expression(expr, env),
+        Statement::Break(_) => Err(EvalError::Break),
+        Statement::Continue(_) => Err(EvalError::Continue),
+        Statement::IndexAssign(IndexAssign {
+            name, index, value, ..
+        }) => {
+            let index = eval_expression(index, env)?;
+            let value = eval_expression(value, env)?;
+            let collection = eval_identifier(&name.name, env)?;
+            let updated = eval_index_assign(&collection, &index, value)?;
+            match env.borrow_mut().assign(&name.name, updated) {
+                Ok(()) => Ok(Rc::new(Object::Null)),
+                Err(AssignError::Undefined) => Err(EvalError::UnknownIdentifier(name.name.clone())),
+                Err(AssignError::Captured) => Err(EvalError::AssignToCaptured(name.name.clone())),
+            }
+        }
         Statement::Return(ReturnStatement { argument, .. }) => {
             let val = eval_expression(argument, env)?;
             Ok(Rc::new(Object::ReturnValue(val)))
@@ -53,6 +110,14 @@ fn eval_statement(statement: &Statement, env: &Env) -> Result<Rc<Object>, EvalEr
             }
             Ok(Rc::new(Object::Null))
         }
+        Statement::Assign(Assign { name, expr, .. }) => {
+            let val = eval_expression(expr, env)?;
+            match env.borrow_mut().assign(&name.name, val) {
+                Ok(()) => Ok(Rc::new(Object::Null)),
+                Err(AssignError::Undefined) => Err(EvalError::UnknownIdentifier(name.name.clone())),
+                Err(AssignError::Captured) => Err(EvalError::AssignToCaptured(name.name.clone())),
+            }
+        }
     }
 }
 
@@ -67,7 +132,20 @@ fn eval_expression(expression: &Expression, env: &Env) -> Result<Rc<Object>, Eva
             op, operand: expr, ..
         }) => {
             let right = eval_expression(expr, &Rc::clone(env))?;
-            eval_prefix(op, &right)
+            eval_prefix(op, right)
+        }
+        // `&&` and `||` only evaluate the right side when the left side
+        // doesn't already decide the result, and always produce a boolean
+        Expression::INFIX(BinaryExpression {
+            op, left, right, ..
+        }) if matches!(op.kind, TokenKind::AND | TokenKind::OR) => {
+            let left = is_truthy(&*eval_expression(left, &Rc::clone(env))?);
+            let result = match (&op.kind, left) {
+                (TokenKind::AND, false) => false,
+                (TokenKind::OR, true) => true,
+                _ => is_truthy(&*eval_expression(right, &Rc::clone(env))?),
+            };
+            Ok(Rc::new(Object::Boolean(result)))
         }
         Expression::INFIX(BinaryExpression {
             op, left, right, ..
@@ -84,10 +162,10 @@ fn eval_expression(expression: &Expression, env: &Env) -> Result<Rc<Object>, Eva
         }) => {
             let condition = eval_expression(condition, &Rc::clone(env))?;
             if is_truthy(&condition) {
-                eval_block_statements(&(consequent.body), env)
+                eval_block(consequent, env)
             } else {
                 match alternate {
-                    Some(alt) => eval_block_statements(&(alt.body), env),
+                    Some(alt) => eval_block(alt, env),
                     None => Ok(Rc::new(Object::Null)),
                 }
             }
@@ -95,18 +173,47 @@ fn eval_expression(expression: &Expression, env: &Env) -> Result<Rc<Object>, Eva
         Expression::While(While {
             condition, body, ..
         }) => {
-            let mut result: Rc<Object> = Rc::new(Object::Null);
+            // like the VM, a loop evaluates to null rather than its last body value
             loop {
                 let cond = eval_expression(condition, &Rc::clone(env))?;
                 if !is_truthy(&cond) {
                     break;
                 }
-                result = eval_block_statements(&body.body, env)?;
+                let result = match eval_block(body, env) {
+                    Err(EvalError::Break) => break,
+                    Err(EvalError::Continue) => continue,
+                    result => result?,
+                };
                 if let Object::ReturnValue(_) = &*result {
                     return Ok(result);
                 }
             }
-            Ok(result)
+            Ok(Rc::new(Object::Null))
+        }
+        Expression::ForIn(ForIn {
+            variable,
+            iterable,
+            body,
+            ..
+        }) => {
+            let iterable = eval_expression(iterable, &Rc::clone(env))?;
+            let items = match &*iterable {
+                Object::Array(elements) => elements.clone(),
+                Object::Hash(map) => map.keys().map(|key| Rc::new(Object::from(key))).collect(),
+                _ => return Err(EvalError::NotIterable(iterable.to_string())),
+            };
+            for item in items {
+                env.borrow_mut().set(variable.name.clone(), item);
+                let result = match eval_block(body, env) {
+                    Err(EvalError::Break) => break,
+                    Err(EvalError::Continue) => continue,
+                    result => result?,
+                };
+                if let Object::ReturnValue(_) = &*result {
+                    return Ok(result);
+                }
+            }
+            Ok(Rc::new(Object::Null))
         }
         Expression::IDENTIFIER(IDENTIFIER { name: id, .. }) => eval_identifier(id, env),
         Expression::FUNCTION(FunctionDeclaration { params, body, .. }) => Ok(Rc::new(
@@ -115,10 +222,26 @@ fn eval_expression(expression: &Expression, env: &Env) -> Result<Rc<Object>, Eva
         Expression::FunctionCall(FunctionCall {
             callee, arguments, ..
         }) => {
+            if let Expression::IDENTIFIER(IDENTIFIER { name, .. }) = &**callee
+                && name == "quote"
+            {
+                return macro_expansion::quote(arguments, env);
+            }
             let func = eval_expression(callee, &Rc::clone(env))?;
             let args = eval_expressions(arguments, env)?;
             apply_function(&func, &args)
         }
+        Expression::MethodCall(MethodCall {
+            receiver,
+            method,
+            arguments,
+            ..
+        }) => {
+            let func = eval_identifier(&method.name, env)?;
+            let mut args = vec![eval_expression(receiver, &Rc::clone(env))?];
+            args.extend(eval_expressions(arguments, env)?);
+            apply_function(&func, &args)
+        }
         Expression::Index(Index {
             object: left,
             index,
@@ -128,6 +251,11 @@ fn eval_expression(expression: &Expression, env: &Env) -> Result<Rc<Object>, Eva
             let index = eval_expression(index, env)?;
             eval_index_expression(&literal, &index)
         }
+        Expression::Macro(MacroLiteral { params, body, .. }) => Ok(Rc::new(Object::Macro(
+            params.clone(),
+            body.clone(),
+            Rc::clone(env),
+        ))),
     }
 }
 
@@ -137,6 +265,13 @@ fn eval_index_expression(left: &Rc<Object>, index: &Rc<Object>) -> Result<Rc<Obj
             Some(obj) => Ok(Rc::clone(obj)),
             None => Ok(Rc::new(Object::Null)),
         },
+        (Object::String(s), Object::Integer(idx)) => {
+            match usize::try_from(*idx).ok().and_then(|i| s.chars().nth(i)) {
+                Some(c) => Ok(Rc::new(Object::String(c.to_string().into()))),
+                None => Ok(Rc::new(Object::Null)),
+            }
+        }
+        (Object::Utf8Error { offset }, key) => Ok(Rc::new(utf8_error_field(*offset, key))),
         (Object::Hash(map), key) => {
             let hash_key = HashKey::try_from(key).map_err(|()| EvalError::InvalidHashKey)?;
             match map.get(&hash_key) {
@@ -148,6 +283,35 @@ fn eval_index_expression(left: &Rc<Object>, index: &Rc<Object>) -> Result<Rc<Obj
     }
 }
 
+fn eval_index_assign(
+    collection: &Object,
+    index: &Object,
+    value: Rc<Object>,
+) -> Result<Rc<Object>, EvalError> {
+    match (collection, index) {
+        (Object::Array(arr), Object::Integer(idx)) => {
+            let mut arr = arr.clone();
+            match usize::try_from(*idx).ok().and_then(|i| arr.get_mut(i)) {
+                Some(slot) => *slot = value,
+                None => {
+                    return Err(EvalError::IndexOutOfRange {
+                        index: *idx,
+                        len: arr.len(),
+                    });
+                }
+            }
+            Ok(Rc::new(Object::Array(arr)))
+        }
+        (Object::Hash(map), key) => {
+            let hash_key = HashKey::try_from(key).map_err(|()| EvalError::InvalidHashKey)?;
+            let mut map = map.clone();
+            map.insert(hash_key, value);
+            Ok(Rc::new(Object::Hash(map)))
+        }
+        _ => Err(EvalError::UnsupportedIndexOperator(collection.to_string())),
+    }
+}
+
 fn apply_function(function: &Rc<Object>, args: &[Rc<Object>]) -> Result<Rc<Object>, EvalError> {
     match &**function {
         Object::Function(params, body, env) => {
@@ -158,20 +322,112 @@ fn apply_function(function: &Rc<Object>, args: &[Rc<Object>]) -> Result<Rc<Objec
                 });
             }
 
+            let depth = DEPTH.with(Cell::get);
+            if depth >= MAX_FRAMES {
+                return Err(EvalError::MaxFramesExceeded { depth });
+            }
+            check_definitions(params, &body.body)?;
+
             let mut env = Environment::new_enclosed_environment(env);
 
             params.iter().enumerate().for_each(|(i, param)| {
                 env.set(param.name.clone(), args[i].clone());
             });
 
-            let evaluated = eval_block_statements(&body.body, &Rc::new(RefCell::new(env)))?;
+            DEPTH.with(|d| d.set(depth + 1));
+            let evaluated = eval_block_statements(&body.body, &Rc::new(RefCell::new(env)));
+            DEPTH.with(|d| d.set(depth));
+
+            // a loop in the caller must not see a `break` from the callee
+            let evaluated = match evaluated {
+                Err(EvalError::Break) => return Err(EvalError::OutsideLoop(TokenKind::BREAK)),
+                Err(EvalError::Continue) => {
+                    return Err(EvalError::OutsideLoop(TokenKind::CONTINUE));
+                }
+                evaluated => evaluated?,
+            };
             unwrap_return(evaluated)
         }
-        Object::Builtin(b) => Ok(b(args.to_vec())),
+        Object::Builtin(BuiltinFunc::Plain(b)) => Ok(b(args.to_vec())),
+        Object::Builtin(BuiltinFunc::WithEngine(b)) => {
+            let mut engine = EvalEngine { error: None };
+            b(args.to_vec(), &mut engine).map_err(|halt| match halt {
+                Halt::CallFailed { builtin, index } => EvalError::CallbackFailed {
+                    builtin,
+                    index,
+                    error: Box::new(engine.error.take().expect("a failed call keeps its error")),
+                },
+                Halt::AssertionFailed(message) => EvalError::AssertionFailed(message),
+            })
+        }
         f => Err(EvalError::NotFunction(f.to_string())),
     }
 }
 
+/// The interpreter as seen by a builtin. Keeps the error of the call that
+/// failed, if one did.
+struct EvalEngine {
+    error: Option<EvalError>,
+}
+
+impl Engine for EvalEngine {
+    fn call(&mut self, function: &Rc<Object>, args: Vec<Rc<Object>>) -> Option<Rc<Object>> {
+        apply_function(function, &args)
+            .map_err(|error| self.error = Some(error))
+            .ok()
+    }
+
+    fn seed_random(&mut self, seed: u64) {
+        RANDOM.with(|random| random.set(Random::new(seed)));
+    }
+
+    fn next_random(&mut self) -> u64 {
+        RANDOM.with(|random| {
+            let mut current = random.get();
+            let next = current.next_u64();
+            random.set(current);
+            next
+        })
+    }
+
+    fn io_enabled(&self) -> bool {
+        IO.with(Cell::get)
+    }
+
+    fn cancelled(&self) -> bool {
+        CANCEL.with(|cancel| {
+            cancel
+                .borrow()
+                .as_ref()
+                .is_some_and(|token| token.load(Ordering::Relaxed))
+        })
+    }
+}
+
+thread_local! {
+    // Evaluation keeps no state outside its environments, so `rand`'s
+    // generator, the file access switch and the cancel token are per
+    // thread rather than per run like the VM's.
+    static RANDOM: Cell<Random> = Cell::new(Random::from_clock());
+    static IO: Cell<bool> = const { Cell::new(false) };
+    static CANCEL: RefCell<Option<Arc<AtomicBool>>> = const { RefCell::new(None) };
+    // calls being evaluated, the top level counting as one like the VM's
+    // main frame
+    static DEPTH: Cell<usize> = const { Cell::new(1) };
+}
+
+/// Lets `read_file` and `write_file` touch the file system in evaluations
+/// on this thread, like `VM::enable_io`.
+pub fn enable_io(enabled: bool) {
+    IO.with(|io| io.set(enabled));
+}
+
+/// Once `token` is set, from any thread, a running `sleep` in evaluations
+/// on this thread wakes up with an error, like `VM::set_cancel_token`.
+pub fn set_cancel_token(token: Option<Arc<AtomicBool>>) {
+    CANCEL.with(|cancel| *cancel.borrow_mut() = token);
+}
+
 fn unwrap_return(obj: Rc<Object>) -> Result<Rc<Object>, EvalError> {
     if let Object::ReturnValue(val) = &*obj {
         Ok(Rc::clone(val))
@@ -200,10 +456,11 @@ fn eval_identifier(identifier: &str, env: &Env) -> Result<Rc<Object>, EvalError>
     }
 }
 
-fn eval_prefix(op: &Token, right: &Object) -> Result<Rc<Object>, EvalError> {
+fn eval_prefix(op: &Token, right: Rc<Object>) -> Result<Rc<Object>, EvalError> {
     match op.kind {
-        TokenKind::BANG => eval_prefix_bang(right),
-        TokenKind::MINUS => eval_prefix_minus(right),
+        TokenKind::BANG => eval_prefix_bang(&right),
+        TokenKind::MINUS => eval_prefix_minus(&right),
+        TokenKind::PLUS => Ok(right),
         _ => Err(EvalError::UnknownPrefixOperator(op.kind.clone())),
     }
 }
@@ -218,33 +475,54 @@ fn eval_prefix_bang(expr: &Object) -> Result<Rc<Object>, EvalError> {
 
 fn eval_prefix_minus(expr: &Object) -> Result<Rc<Object>, EvalError> {
     match *expr {
-        Object::Integer(i) => Ok(Rc::from(Object::Integer(-i))),
+        Object::Integer(i) => match i.checked_neg() {
+            Some(negated) => Ok(Rc::from(Object::Integer(negated))),
+            None => Err(EvalError::IntegerOverflow(TokenKind::MINUS)),
+        },
+        Object::Float(x) => Ok(Rc::from(Object::Float(-x))),
         _ => Err(EvalError::CannotApplyPrefixMinus(expr.to_string())),
     }
 }
 
-fn eval_infix(op: &Token, left: &Object, right: &Object) -> Result<Rc<Object>, EvalError> {
-    match (left, right) {
+fn eval_infix(op: &Token, left: &Rc<Object>, right: &Rc<Object>) -> Result<Rc<Object>, EvalError> {
+    match (&**left, &**right) {
         (Object::Integer(left), Object::Integer(right)) => eval_integer_infix(op, *left, *right),
+        (Object::Float(left), Object::Float(right)) => eval_float_infix(op, *left, *right),
+        (Object::Integer(left), Object::Float(right)) => eval_float_infix(op, *left as f64, *right),
+        (Object::Float(left), Object::Integer(right)) => eval_float_infix(op, *left, *right as f64),
         (Object::Boolean(left), Object::Boolean(right)) => eval_boolean_infix(op, *left, *right),
-        (Object::String(left), Object::String(right)) => {
-            eval_string_infix(op, left.to_string(), right.to_string())
+        (Object::String(left), Object::String(right)) => eval_string_infix(op, left, right),
+        (Object::String(_), _) | (_, Object::String(_)) if op.kind == TokenKind::PLUS => {
+            Err(EvalError::StringConcatenation {
+                left: left.type_name(),
+                right: right.type_name(),
+            })
         }
+        // as in the VM, other values are equal only to a value of the same
+        // type with the same contents, and functions only to themselves
+        _ if matches!(op.kind, TokenKind::EQ | TokenKind::NotEq) => Ok(Rc::new(Object::Boolean(
+            values_equal(left, right) == (op.kind == TokenKind::EQ),
+        ))),
         _ => Err(EvalError::InfixTypeMismatch {
             op: op.kind.clone(),
-            left: left.to_string(),
-            right: right.to_string(),
+            left: left.type_name(),
+            right: right.type_name(),
         }),
     }
 }
 
 fn eval_integer_infix(op: &Token, left: i64, right: i64) -> Result<Rc<Object>, EvalError> {
+    let overflow = || EvalError::IntegerOverflow(op.kind.clone());
     let result = match &op.kind {
-        TokenKind::PLUS => Object::Integer(left + right),
-        TokenKind::MINUS => Object::Integer(left - right),
-        TokenKind::ASTERISK => Object::Integer(left * right),
-        TokenKind::SLASH => Object::Integer(left / right),
-        TokenKind::PERCENT => Object::Integer(left % right),
+        TokenKind::PLUS => Object::Integer(left.checked_add(right).ok_or_else(overflow)?),
+        TokenKind::MINUS => Object::Integer(left.checked_sub(right).ok_or_else(overflow)?),
+        TokenKind::ASTERISK => Object::Integer(left.checked_mul(right).ok_or_else(overflow)?),
+        TokenKind::SLASH | TokenKind::DIV | TokenKind::PERCENT if right == 0 => {
+            return Err(EvalError::DivisionByZero);
+        }
+        TokenKind::SLASH => Object::Integer(left.checked_div(right).ok_or_else(overflow)?),
+        TokenKind::DIV => Object::Integer(checked_floor_div(left, right).ok_or_else(overflow)?),
+        TokenKind::PERCENT => Object::Integer(left.checked_rem(right).ok_or_else(overflow)?),
         TokenKind::LT => Object::Boolean(left < right),
         TokenKind::GT => Object::Boolean(left > right),
         TokenKind::LTE => Object::Boolean(left <= right),
@@ -257,6 +535,27 @@ fn eval_integer_infix(op: &Token, left: i64, right: i64) -> Result<Rc<Object>, E
     Ok(Rc::from(result))
 }
 
+// IEEE semantics: dividing by zero gives an infinity or NaN.
+fn eval_float_infix(op: &Token, left: f64, right: f64) -> Result<Rc<Object>, EvalError> {
+    let result = match &op.kind {
+        TokenKind::PLUS => Object::Float(left + right),
+        TokenKind::MINUS => Object::Float(left - right),
+        TokenKind::ASTERISK => Object::Float(left * right),
+        TokenKind::SLASH => Object::Float(left / right),
+        TokenKind::DIV => Object::Float((left / right).floor()),
+        TokenKind::PERCENT => Object::Float(left % right),
+        TokenKind::LT => Object::Boolean(left < right),
+        TokenKind::GT => Object::Boolean(left > right),
+        TokenKind::LTE => Object::Boolean(left <= right),
+        TokenKind::GTE => Object::Boolean(left >= right),
+        TokenKind::EQ => Object::Boolean(left == right),
+        TokenKind::NotEq => Object::Boolean(left != right),
+        op => return Err(EvalError::InvalidFloatOperator(op.clone())),
+    };
+
+    Ok(Rc::from(result))
+}
+
 fn eval_boolean_infix(op: &Token, left: bool, right: bool) -> Result<Rc<Object>, EvalError> {
     let result = match &op.kind {
         TokenKind::EQ => Object::Boolean(left == right),
@@ -267,11 +566,20 @@ fn eval_boolean_infix(op: &Token, left: bool, right: bool) -> Result<Rc<Object>,
     Ok(Rc::from(result))
 }
 
-fn eval_string_infix(op: &Token, left: String, right: String) -> Result<Rc<Object>, EvalError> {
+fn eval_string_infix(op: &Token, left: &str, right: &str) -> Result<Rc<Object>, EvalError> {
     let result = match &op.kind {
         TokenKind::EQ => Object::Boolean(left == right),
         TokenKind::NotEq => Object::Boolean(left != right),
-        TokenKind::PLUS => Object::String(format!("{}{}", left, right)),
+        TokenKind::LT => Object::Boolean(left < right),
+        TokenKind::GT => Object::Boolean(left > right),
+        TokenKind::LTE => Object::Boolean(left <= right),
+        TokenKind::GTE => Object::Boolean(left >= right),
+        TokenKind::PLUS => {
+            let mut result = String::with_capacity(left.len() + right.len());
+            result.push_str(left);
+            result.push_str(right);
+            Object::String(result.into())
+        }
         op => return Err(EvalError::InvalidStringOperator(op.clone())),
     };
 
@@ -281,14 +589,18 @@ fn eval_string_infix(op: &Token, left: String, right: String) -> Result<Rc<Objec
 fn eval_literal(literal: &Literal, env: &Env) -> Result<Rc<Object>, EvalError> {
     match literal {
         Literal::Integer(Integer { raw: i, .. }) => Ok(Rc::from(Object::Integer(*i))),
+        Literal::Float(Float { raw: x, .. }) => Ok(Rc::from(Object::Float(*x))),
         Literal::Boolean(Boolean { raw: b, .. }) => Ok(Rc::from(Object::Boolean(*b))),
-        Literal::String(StringType { raw: s, .. }) => Ok(Rc::from(Object::String(s.clone()))),
+        Literal::Null(_) => Ok(Rc::new(Object::Null)),
+        Literal::String(StringType { raw: s, .. }) => {
+            Ok(Rc::from(Object::String(s.as_str().into())))
+        }
         Literal::Array(Array { elements, .. }) => {
             let list = eval_expressions(elements, env)?;
             Ok(Rc::from(Object::Array(list)))
         }
         Literal::Hash(Hash { elements: map, .. }) => {
-            let mut hash_map = HashMap::new();
+            let mut hash_map = OrderedMap::with_capacity(map.len());
 
             for (k, v) in map {
                 let key = eval_expression(k, env)?;
@@ -0,0 +1,175 @@
+use std::cell::RefCell;
+use std::fmt;
+use std::rc::Rc;
+
+use object::environment::{Env, Environment};
+use object::{EvalError, Object};
+use parser::ast::{
+    Array, Boolean, Expression, Float, FunctionCall, IDENTIFIER, Integer, Let, Literal,
+    MacroLiteral, Null, Program, Statement, StringType,
+};
+use parser::lexer::token::{Span, TokenKind};
+use parser::modify::{modify_expression, modify_program};
+
+use crate::{eval_block_statements, eval_expression, unwrap_return};
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum MacroError {
+    Eval { error: EvalError, span: Span },
+    NotQuoted { value: String, span: Span },
+}
+
+impl fmt::Display for MacroError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MacroError::Eval { error, span } => write!(
+                f,
+                "macro expansion failed at {}..{}: {}",
+                span.start, span.end, error
+            ),
+            MacroError::NotQuoted { value, span } => write!(
+                f,
+                "macro call at {}..{} must return a quoted expression, got {}",
+                span.start, span.end, value
+            ),
+        }
+    }
+}
+
+/// Moves every top-level `let name = macro(...)` out of the program and into `env`.
+pub fn define_macros(program: &mut Program, env: &Env) {
+    program.body.retain(|statement| match statement {
+        Statement::Let(Let {
+            identifier,
+            expr: Expression::Macro(MacroLiteral { params, body, .. }),
+            ..
+        }) => {
+            if let TokenKind::IDENTIFIER { name } = &identifier.kind {
+                let macro_obj = Object::Macro(params.clone(), body.clone(), Rc::clone(env));
+                env.borrow_mut().set(name.clone(), Rc::new(macro_obj));
+            }
+            false
+        }
+        _ => true,
+    });
+}
+
+/// Replaces every call to a macro defined in `env` with the expression it returns.
+pub fn expand_macros(program: Program, env: &Env) -> Result<Program, MacroError> {
+    modify_program(program, &mut |expression| {
+        expand_macro_call(expression, env)
+    })
+}
+
+fn expand_macro_call(expression: Expression, env: &Env) -> Result<Expression, MacroError> {
+    let Expression::FunctionCall(call) = &expression else {
+        return Ok(expression);
+    };
+    let Some((params, body, macro_env)) = lookup_macro(&call.callee, env) else {
+        return Ok(expression);
+    };
+
+    let span = call.span.clone();
+    if params.len() != call.arguments.len() {
+        return Err(MacroError::Eval {
+            error: EvalError::WrongArity {
+                expected: params.len(),
+                got: call.arguments.len(),
+            },
+            span,
+        });
+    }
+
+    let mut extended = Environment::new_enclosed_environment(&macro_env);
+    for (param, argument) in params.iter().zip(&call.arguments) {
+        extended.set(param.name.clone(), Rc::new(Object::Quote(argument.clone())));
+    }
+
+    let evaluated = eval_block_statements(&body.body, &Rc::new(RefCell::new(extended)))
+        .and_then(unwrap_return)
+        .map_err(|error| MacroError::Eval {
+            error,
+            span: span.clone(),
+        })?;
+
+    match &*evaluated {
+        Object::Quote(expanded) => Ok(expanded.clone()),
+        other => Err(MacroError::NotQuoted {
+            value: other.to_string(),
+            span,
+        }),
+    }
+}
+
+fn lookup_macro(
+    callee: &Expression,
+    env: &Env,
+) -> Option<(Vec<IDENTIFIER>, parser::ast::BlockStatement, Env)> {
+    let Expression::IDENTIFIER(IDENTIFIER { name, .. }) = callee else {
+        return None;
+    };
+    match &*env.borrow().get(name)? {
+        Object::Macro(params, body, macro_env) => {
+            Some((params.clone(), body.clone(), Rc::clone(macro_env)))
+        }
+        _ => None,
+    }
+}
+
+pub(crate) fn quote(arguments: &[Expression], env: &Env) -> Result<Rc<Object>, EvalError> {
+    if arguments.len() != 1 {
+        return Err(EvalError::WrongArity {
+            expected: 1,
+            got: arguments.len(),
+        });
+    }
+
+    let quoted = modify_expression(arguments[0].clone(), &mut |expression| {
+        eval_unquote_call(expression, env)
+    })?;
+    Ok(Rc::new(Object::Quote(quoted)))
+}
+
+fn eval_unquote_call(expression: Expression, env: &Env) -> Result<Expression, EvalError> {
+    match expression {
+        Expression::FunctionCall(FunctionCall {
+            callee,
+            arguments,
+            span,
+        }) if matches!(&*callee, Expression::IDENTIFIER(id) if id.name == "unquote") => {
+            if arguments.len() != 1 {
+                return Err(EvalError::WrongArity {
+                    expected: 1,
+                    got: arguments.len(),
+                });
+            }
+            let value = eval_expression(&arguments[0], env)?;
+            object_to_expression(&value, &span)
+        }
+        other => Ok(other),
+    }
+}
+
+fn object_to_expression(obj: &Object, span: &Span) -> Result<Expression, EvalError> {
+    let span = span.clone();
+    let literal = match obj {
+        Object::Quote(expression) => return Ok(expression.clone()),
+        Object::Integer(raw) => Literal::Integer(Integer { raw: *raw, span }),
+        Object::Float(raw) => Literal::Float(Float { raw: *raw, span }),
+        Object::Boolean(raw) => Literal::Boolean(Boolean { raw: *raw, span }),
+        Object::Null => Literal::Null(Null { span }),
+        Object::String(raw) => Literal::String(StringType {
+            raw: raw.to_string(),
+            span,
+        }),
+        Object::Array(elements) => Literal::Array(Array {
+            elements: elements
+                .iter()
+                .map(|element| object_to_expression(element, &span))
+                .collect::<Result<_, _>>()?,
+            span,
+        }),
+        other => return Err(EvalError::CannotUnquote(other.to_string())),
+    };
+    Ok(Expression::LITERAL(literal))
+}
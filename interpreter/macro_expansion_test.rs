@@ -0,0 +1,178 @@
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    use object::environment::*;
+    use object::{EvalError, Object};
+    use parser::ast::{Node, Program};
+    use parser::lexer::token::Span;
+    use parser::*;
+
+    use crate::eval;
+    use crate::macro_expansion::{MacroError, define_macros, expand_macros};
+
+    fn new_env() -> Env {
+        Rc::new(RefCell::new(Default::default()))
+    }
+
+    fn parse_program(input: &str) -> Program {
+        match parse(input) {
+            Ok(Node::Program(program)) => program,
+            Ok(node) => panic!("expected program, got {}", node),
+            Err(e) => panic!("parse error: {}", e[0]),
+        }
+    }
+
+    fn expand(input: &str) -> Result<Program, MacroError> {
+        let env = new_env();
+        let mut program = parse_program(input);
+        define_macros(&mut program, &env);
+        expand_macros(program, &env)
+    }
+
+    fn apply_test(test_cases: &[(&str, &str)]) {
+        for (input, expected) in test_cases {
+            let evaluated = eval(parse(input).unwrap(), &new_env()).unwrap();
+            assert_eq!(&evaluated.to_string(), expected, "for input {}", input);
+        }
+    }
+
+    #[test]
+    fn test_quote() {
+        let test_case = [
+            ("quote(5)", "QUOTE(5)"),
+            ("quote(5 + 8)", "QUOTE((5 + 8))"),
+            ("quote(foobar)", "QUOTE(foobar)"),
+            ("quote(foobar + barfoo)", "QUOTE((foobar + barfoo))"),
+        ];
+        apply_test(&test_case);
+    }
+
+    #[test]
+    fn test_quote_unquote() {
+        let test_case = [
+            ("quote(unquote(4))", "QUOTE(4)"),
+            ("quote(unquote(4 + 4))", "QUOTE(8)"),
+            ("quote(8 + unquote(4 + 4))", "QUOTE((8 + 8))"),
+            ("quote(unquote(4 + 4) + 8)", "QUOTE((8 + 8))"),
+            ("let foobar = 8; quote(foobar)", "QUOTE(foobar)"),
+            ("let foobar = 8; quote(unquote(foobar))", "QUOTE(8)"),
+            ("quote(unquote(true))", "QUOTE(true)"),
+            ("quote(unquote(true == false))", "QUOTE(false)"),
+            ("quote(unquote(quote(4 + 4)))", "QUOTE((4 + 4))"),
+            (
+                "let quotedInfixExpression = quote(4 + 4); \
+                 quote(unquote(4 + 4) + unquote(quotedInfixExpression))",
+                "QUOTE((8 + (4 + 4)))",
+            ),
+        ];
+        apply_test(&test_case);
+    }
+
+    #[test]
+    fn test_unquote_rejects_functions() {
+        let err = eval(parse("quote(unquote(fn(x) { x }))").unwrap(), &new_env()).unwrap_err();
+        assert!(matches!(err, EvalError::CannotUnquote(_)));
+    }
+
+    #[test]
+    fn test_define_macros() {
+        let env = new_env();
+        let mut program = parse_program(
+            "let number = 1; \
+             let function = fn(x, y) { x + y }; \
+             let mymacro = macro(x, y) { x + y; };",
+        );
+        define_macros(&mut program, &env);
+
+        assert_eq!(program.body.len(), 2);
+        assert!(env.borrow().get("number").is_none());
+        assert!(env.borrow().get("function").is_none());
+        match &*env.borrow().get("mymacro").unwrap() {
+            Object::Macro(params, body, _) => {
+                assert_eq!(params.len(), 2);
+                assert_eq!(body.to_string(), "(x + y)");
+            }
+            other => panic!("expected macro, got {}", other),
+        }
+    }
+
+    #[test]
+    fn test_expand_macros() {
+        let test_case = [
+            (
+                "let infixExpression = macro() { quote(1 + 2); }; infixExpression();",
+                "(1 + 2)",
+            ),
+            (
+                "let reverse = macro(a, b) { quote(unquote(b) - unquote(a)); }; \
+                 reverse(2 + 2, 10 - 5);",
+                "((10 - 5) - (2 + 2))",
+            ),
+            (
+                r#"let unless = macro(condition, consequence, alternative) {
+                    quote(if (!(unquote(condition))) {
+                        unquote(consequence);
+                    } else {
+                        unquote(alternative);
+                    });
+                };
+                unless(10 > 5, puts("not greater"), puts("greater"));"#,
+                r#"if (!(10 > 5)) { puts("not greater") } else { puts("greater") }"#,
+            ),
+        ];
+
+        for (input, expected) in test_case {
+            assert_eq!(expand(input).unwrap().to_string(), expected);
+        }
+    }
+
+    #[test]
+    fn test_macro_generating_let_bindings() {
+        let input = "let bind_sum = macro(a, b) { \
+                quote(fn() { let first = unquote(a); let second = unquote(b); first + second }()) \
+            }; \
+            bind_sum(1 + 2, 4)";
+        let expanded = expand(input).unwrap();
+        assert_eq!(
+            expanded.to_string(),
+            "fn () { let first = (1 + 2);let second = 4;(first + second) }()"
+        );
+
+        let evaluated = eval(Node::Program(expanded), &new_env()).unwrap();
+        assert_eq!(*evaluated, Object::Integer(7));
+    }
+
+    #[test]
+    fn test_expansion_errors_carry_call_span() {
+        let input = "let bad = macro() { 1 }; bad();";
+        let start = input.find("bad()").unwrap();
+        assert_eq!(
+            expand(input).unwrap_err(),
+            MacroError::NotQuoted {
+                value: "1".to_string(),
+                span: Span {
+                    start,
+                    end: start + "bad()".len(),
+                },
+            }
+        );
+
+        let input = "let one = macro(a) { a }; 1 + one();";
+        let start = input.find("one()").unwrap();
+        assert_eq!(
+            expand(input).unwrap_err(),
+            MacroError::Eval {
+                error: EvalError::WrongArity {
+                    expected: 1,
+                    got: 0,
+                },
+                span: Span {
+                    start,
+                    end: start + "one()".len(),
+                },
+            }
+        );
+    }
+}
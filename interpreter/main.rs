@@ -1,12 +1,16 @@
 use interpreter::eval;
+use interpreter::macro_expansion::{define_macros, expand_macros};
 use object::environment::Env;
-use parser::parse;
+use object::format::{Limits, format_limited};
+use parser::ast::Node;
+use parser::parse_with_asi;
 use std::cell::RefCell;
 use std::io::stdin;
 use std::rc::Rc;
 
 fn main() {
     let env: Env = Rc::new(RefCell::new(Default::default()));
+    let macro_env: Env = Rc::new(RefCell::new(Default::default()));
     loop {
         let mut input = String::new();
         stdin().read_line(&mut input).unwrap();
@@ -16,12 +20,27 @@ fn main() {
             std::process::exit(0)
         }
 
-        match parse(&input) {
-            Ok(node) => match eval(node, &env) {
-                Ok(evaluated) => println!("{}", evaluated),
-                Err(e) => eprintln!("{}", e),
-            },
-            Err(e) => eprintln!("parse error: {}", e[0]),
+        let node = match parse_with_asi(&input) {
+            Ok(Node::Program(mut program)) => {
+                define_macros(&mut program, &macro_env);
+                match expand_macros(program, &macro_env) {
+                    Ok(program) => Node::Program(program),
+                    Err(e) => {
+                        eprintln!("{}", e);
+                        continue;
+                    }
+                }
+            }
+            Ok(node) => node,
+            Err(e) => {
+                eprintln!("parse error: {}", e[0]);
+                continue;
+            }
+        };
+
+        match eval(node, &env) {
+            Ok(evaluated) => println!("{}", format_limited(&evaluated, &Limits::default())),
+            Err(e) => eprintln!("{}", e),
         }
     }
 }
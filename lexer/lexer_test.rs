@@ -1,7 +1,7 @@
 #[cfg(test)]
 mod tests {
-    use crate::Lexer;
-    use crate::token::{Token, TokenKind};
+    use crate::token::{Float64, Token, TokenKind};
+    use crate::{Lexer, dump_tokens};
     use insta::*;
 
     fn test_token_set(l: &mut Lexer) -> Vec<Token> {
@@ -44,6 +44,61 @@ mod tests {
         test_lexer_common("comments", "// I am comments");
     }
 
+    #[test]
+    fn test_block_comments() {
+        let input = "let a = 1; /* one */ let b = /* two\nlines */ a; // three\n/**/a";
+        let tokens = test_token_set(&mut Lexer::new(input))
+            .into_iter()
+            .map(|t| (t.kind, &input[t.span.start..t.span.end]))
+            .collect::<Vec<_>>();
+        let ident = |name: &str| TokenKind::IDENTIFIER {
+            name: name.to_string(),
+        };
+        assert_eq!(
+            tokens,
+            [
+                (TokenKind::LET, "let"),
+                (ident("a"), "a"),
+                (TokenKind::ASSIGN, "="),
+                (TokenKind::INT(1), "1"),
+                (TokenKind::SEMICOLON, ";"),
+                (TokenKind::LET, "let"),
+                (ident("b"), "b"),
+                (TokenKind::ASSIGN, "="),
+                (ident("a"), "a"),
+                (TokenKind::SEMICOLON, ";"),
+                (ident("a"), "a"),
+                (TokenKind::EOF, ""),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_unterminated_block_comment() {
+        let input = "a /* never closed\n b";
+        let tokens = test_token_set(&mut Lexer::new(input))
+            .into_iter()
+            .map(|t| (t.kind, &input[t.span.start..t.span.end]))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            tokens,
+            [
+                (
+                    TokenKind::IDENTIFIER {
+                        name: "a".to_string()
+                    },
+                    "a"
+                ),
+                (TokenKind::ILLEGAL, "/* never closed\n b"),
+                (TokenKind::EOF, ""),
+            ]
+        );
+
+        // `*/` must follow the opening `/*` rather than share its `*`
+        let mut l = Lexer::new("/*/");
+        assert_eq!(l.next_token().kind, TokenKind::ILLEGAL);
+    }
+
     #[test]
     fn test_lexer_let_with_space() {
         test_lexer_common("let_with_space", "let x = 5");
@@ -74,6 +129,33 @@ mod tests {
         test_lexer_common("while", "while (x < 5) { x }");
     }
 
+    #[test]
+    fn test_for_in_keywords() {
+        let input = "for (x in xs) { inner }";
+        let kinds = test_token_set(&mut Lexer::new(input))
+            .into_iter()
+            .map(|t| t.kind)
+            .collect::<Vec<_>>();
+        let ident = |name: &str| TokenKind::IDENTIFIER {
+            name: name.to_string(),
+        };
+        assert_eq!(
+            kinds,
+            [
+                TokenKind::FOR,
+                TokenKind::LPAREN,
+                ident("x"),
+                TokenKind::IN,
+                ident("xs"),
+                TokenKind::RPAREN,
+                TokenKind::LBRACE,
+                ident("inner"),
+                TokenKind::RBRACE,
+                TokenKind::EOF,
+            ]
+        );
+    }
+
     #[test]
     fn test_lexer_comparison_operators() {
         test_lexer_common("comparison_operators", "5 <= 10 >= 3");
@@ -103,7 +185,7 @@ let add = fn(x, y) {
 };
 
 let result = add(five, ten);
-!-/*5;
+!-/ *5;
 5 < 10 > 5;
 
 if (5 < 10) {
@@ -116,4 +198,180 @@ if (5 < 10) {
 10 != 9;",
         );
     }
+
+    #[test]
+    fn test_newline_tokens() {
+        let kinds = |emit_newlines| {
+            let mut l = Lexer::new("a // note\n\n  b\r\nc");
+            l.set_emit_newlines(emit_newlines);
+            test_token_set(&mut l)
+                .into_iter()
+                .map(|t| t.kind)
+                .collect::<Vec<_>>()
+        };
+        let ident = |name: &str| TokenKind::IDENTIFIER {
+            name: name.to_string(),
+        };
+
+        assert_eq!(
+            kinds(true),
+            [
+                ident("a"),
+                TokenKind::NEWLINE,
+                TokenKind::NEWLINE,
+                ident("b"),
+                TokenKind::NEWLINE,
+                ident("c"),
+                TokenKind::EOF,
+            ]
+        );
+        assert_eq!(
+            kinds(false),
+            [ident("a"), ident("b"), ident("c"), TokenKind::EOF]
+        );
+    }
+
+    #[test]
+    fn test_block_comment_newlines() {
+        let mut l = Lexer::new("a /* one line */ b /* two\nlines */ c");
+        l.set_emit_newlines(true);
+        let kinds = test_token_set(&mut l)
+            .into_iter()
+            .map(|t| t.kind)
+            .collect::<Vec<_>>();
+        let ident = |name: &str| TokenKind::IDENTIFIER {
+            name: name.to_string(),
+        };
+        // a comment spanning lines still separates the lines it spans
+        assert_eq!(
+            kinds,
+            [
+                ident("a"),
+                ident("b"),
+                TokenKind::NEWLINE,
+                ident("c"),
+                TokenKind::EOF
+            ]
+        );
+    }
+
+    #[test]
+    fn test_line_comment_after_an_operand() {
+        // floor division is the `div` keyword, so `//` always comments
+        let kinds = test_token_set(&mut Lexer::new("x // comment\n(y) // 2"))
+            .into_iter()
+            .map(|t| t.kind)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            kinds,
+            [
+                TokenKind::IDENTIFIER {
+                    name: "x".to_string()
+                },
+                TokenKind::LPAREN,
+                TokenKind::IDENTIFIER {
+                    name: "y".to_string()
+                },
+                TokenKind::RPAREN,
+                TokenKind::EOF,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_shebang() {
+        test_lexer_common("shebang", "#!/usr/bin/env monkey\nlet x=5");
+    }
+
+    #[test]
+    fn test_shebang_only_at_start() {
+        let mut l = Lexer::new("let x=5\n#!/usr/bin/env monkey");
+        let kinds = test_token_set(&mut l)
+            .into_iter()
+            .map(|t| t.kind)
+            .collect::<Vec<_>>();
+        assert_eq!(kinds[4], TokenKind::ILLEGAL);
+
+        let mut l = Lexer::new(" #!/usr/bin/env monkey");
+        assert_eq!(l.next_token().kind, TokenKind::ILLEGAL);
+    }
+
+    #[test]
+    fn test_identifiers_with_digits() {
+        let mut l = Lexer::new("_1 x2y 3z");
+        let kinds = test_token_set(&mut l)
+            .into_iter()
+            .map(|t| t.kind)
+            .collect::<Vec<_>>();
+        let ident = |name: &str| TokenKind::IDENTIFIER {
+            name: name.to_string(),
+        };
+        assert_eq!(
+            kinds,
+            [
+                ident("_1"),
+                ident("x2y"),
+                TokenKind::INT(3),
+                ident("z"),
+                TokenKind::EOF
+            ]
+        );
+    }
+
+    #[test]
+    fn test_floats() {
+        let input = "2.75 1.len 1.2.3 2.";
+        let tokens = test_token_set(&mut Lexer::new(input))
+            .into_iter()
+            .map(|t| (t.kind, &input[t.span.start..t.span.end]))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            tokens,
+            [
+                (TokenKind::FLOAT(Float64(2.75)), "2.75"),
+                (TokenKind::INT(1), "1"),
+                (TokenKind::DOT, "."),
+                (
+                    TokenKind::IDENTIFIER {
+                        name: "len".to_string()
+                    },
+                    "len"
+                ),
+                // one token, so the parser can reject all of it
+                (TokenKind::ILLEGAL, "1.2.3"),
+                (TokenKind::INT(2), "2"),
+                (TokenKind::DOT, "."),
+                (TokenKind::EOF, ""),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_logical_operators() {
+        let input = "a && b || c & d | e";
+        let tokens = test_token_set(&mut Lexer::new(input))
+            .into_iter()
+            .filter(|t| !matches!(t.kind, TokenKind::IDENTIFIER { .. }))
+            .map(|t| (t.kind, &input[t.span.start..t.span.end]))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            tokens,
+            [
+                (TokenKind::AND, "&&"),
+                (TokenKind::OR, "||"),
+                (TokenKind::ILLEGAL, "&"),
+                (TokenKind::ILLEGAL, "|"),
+                (TokenKind::EOF, ""),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_dump_tokens() {
+        let input = r#"let s = "a\"b\n" == x;"#;
+        assert_snapshot!("dump_escapes", dump_tokens(input), input);
+
+        let input = "let a = 1 & 2;";
+        assert_snapshot!("dump_illegal", dump_tokens(input), input);
+    }
 }
@@ -1,4 +1,4 @@
-use crate::token::{Span, Token, TokenKind, lookup_identifier};
+use crate::token::{Float64, Span, Token, TokenKind, lookup_identifier};
 
 #[cfg(test)]
 mod lexer_test;
@@ -9,6 +9,7 @@ pub struct Lexer<'a> {
     position: usize,
     read_position: usize,
     ch: Option<char>,
+    emit_newlines: bool,
 }
 
 impl<'a> Lexer<'a> {
@@ -18,11 +19,35 @@ impl<'a> Lexer<'a> {
             position: 0,
             read_position: 0,
             ch: None,
+            emit_newlines: false,
         };
         lexer.read_char();
+        lexer.skip_shebang();
         lexer
     }
 
+    // `#!` only introduces an interpreter line at the very start of the
+    // input. The line break is kept so later spans and line counts are as
+    // if the line were still there.
+    fn skip_shebang(&mut self) {
+        if self.input.starts_with("#!") {
+            while !matches!(self.ch, Some('\n') | None) {
+                self.read_char();
+            }
+        }
+    }
+
+    /// The source text `span` covers.
+    pub fn source(&self, span: &Span) -> &'a str {
+        &self.input[span.start..span.end]
+    }
+
+    /// Emit a `NEWLINE` token for each line break instead of skipping it as
+    /// whitespace. The parser uses them to end statements without `;`.
+    pub fn set_emit_newlines(&mut self, enabled: bool) {
+        self.emit_newlines = enabled;
+    }
+
     fn read_char(&mut self) {
         if self.read_position >= self.input.len() {
             self.position = self.input.len();
@@ -58,6 +83,7 @@ impl<'a> Lexer<'a> {
         match self.ch {
             Some('=') => self.read_operator_token(start, TokenKind::ASSIGN, TokenKind::EQ),
             Some(';') => self.read_single_char_token(start, TokenKind::SEMICOLON),
+            Some('\n') => self.read_single_char_token(start, TokenKind::NEWLINE),
             Some('(') => self.read_single_char_token(start, TokenKind::LPAREN),
             Some(')') => self.read_single_char_token(start, TokenKind::RPAREN),
             Some(',') => self.read_single_char_token(start, TokenKind::COMMA),
@@ -65,14 +91,19 @@ impl<'a> Lexer<'a> {
             Some('-') => self.read_single_char_token(start, TokenKind::MINUS),
             Some('!') => self.read_operator_token(start, TokenKind::BANG, TokenKind::NotEq),
             Some('*') => self.read_single_char_token(start, TokenKind::ASTERISK),
+            Some('/') if self.peek_char() == Some('*') => self.read_block_comment(start),
             Some('/') => self.read_single_char_token(start, TokenKind::SLASH),
             Some('<') => self.read_operator_token(start, TokenKind::LT, TokenKind::LTE),
             Some('>') => self.read_operator_token(start, TokenKind::GT, TokenKind::GTE),
             Some('%') => self.read_single_char_token(start, TokenKind::PERCENT),
+            Some('&') => self.read_doubled_token(start, '&', TokenKind::AND),
+            Some('|') => self.read_doubled_token(start, '|', TokenKind::OR),
             Some('{') => self.read_single_char_token(start, TokenKind::LBRACE),
             Some('}') => self.read_single_char_token(start, TokenKind::RBRACE),
             Some('[') => self.read_single_char_token(start, TokenKind::LBRACKET),
             Some(':') => self.read_single_char_token(start, TokenKind::COLON),
+            Some('?') => self.read_single_char_token(start, TokenKind::QUESTION),
+            Some('.') => self.read_single_char_token(start, TokenKind::DOT),
             Some(']') => self.read_single_char_token(start, TokenKind::RBRACKET),
             Some('"') => {
                 let (end, string) = self.read_string();
@@ -94,9 +125,18 @@ impl<'a> Lexer<'a> {
             }
             Some(ch) if is_digit(ch) => {
                 let (end, raw_number) = self.read_number();
-                let kind = match raw_number.parse() {
-                    Ok(num) => TokenKind::INT(num),
-                    Err(_) => TokenKind::ILLEGAL,
+                // the parser reports out-of-range and malformed literals, see
+                // `ParseError::IntegerOutOfRange` and `ParseError::InvalidFloat`
+                let kind = if raw_number.contains('.') {
+                    match raw_number.parse() {
+                        Ok(num) => TokenKind::FLOAT(Float64(num)),
+                        Err(_) => TokenKind::ILLEGAL,
+                    }
+                } else {
+                    match raw_number.parse() {
+                        Ok(num) => TokenKind::INT(num),
+                        Err(_) => TokenKind::ILLEGAL,
+                    }
                 };
 
                 Token {
@@ -138,6 +178,17 @@ impl<'a> Lexer<'a> {
         }
     }
 
+    // `&&` and `||` have no single-character form, so a lone `&` or `|` is
+    // ILLEGAL
+    fn read_doubled_token(&mut self, start: usize, ch: char, double: TokenKind) -> Token {
+        if self.peek_char() == Some(ch) {
+            self.read_char();
+            self.read_single_char_token(start, double)
+        } else {
+            self.read_single_char_token(start, TokenKind::ILLEGAL)
+        }
+    }
+
     fn skip_ignored(&mut self) {
         loop {
             self.skip_whitespace();
@@ -148,7 +199,9 @@ impl<'a> Lexer<'a> {
     }
 
     fn skip_whitespace(&mut self) {
-        while matches!(self.ch, Some(ch) if ch.is_ascii_whitespace()) {
+        while matches!(self.ch, Some(ch) if ch.is_ascii_whitespace())
+            && !(self.emit_newlines && self.ch == Some('\n'))
+        {
             self.read_char();
         }
     }
@@ -159,24 +212,73 @@ impl<'a> Lexer<'a> {
                 self.read_char();
             }
             true
+        } else if self.ch == Some('/') && self.peek_char() == Some('*') {
+            match self.block_comment_end() {
+                Some(end)
+                    if !(self.emit_newlines && self.input[self.position..end].contains('\n')) =>
+                {
+                    self.skip_to(end);
+                    true
+                }
+                _ => false,
+            }
         } else {
             false
         }
     }
 
+    // The offset just past the `*/` closing the block comment that starts
+    // here. Block comments don't nest.
+    fn block_comment_end(&self) -> Option<usize> {
+        let body = self.position + 2;
+        self.input[body..]
+            .find("*/")
+            .map(|offset| body + offset + 2)
+    }
+
+    // Only reached for the block comments `skip_comment` leaves alone: an
+    // unterminated one is ILLEGAL up to the end of the input instead of
+    // silently swallowing the rest of the file, and one spanning lines is a
+    // single NEWLINE when newlines are emitted.
+    fn read_block_comment(&mut self, start: usize) -> Token {
+        let (end, kind) = match self.block_comment_end() {
+            Some(end) => (end, TokenKind::NEWLINE),
+            None => (self.input.len(), TokenKind::ILLEGAL),
+        };
+        self.skip_to(end);
+        Token {
+            span: Span { start, end },
+            kind,
+        }
+    }
+
+    fn skip_to(&mut self, end: usize) {
+        while self.position < end {
+            self.read_char();
+        }
+    }
+
     fn read_identifier(&mut self) -> (usize, String) {
         let start = self.position;
-        while matches!(self.ch, Some(ch) if is_letter(ch)) {
+        // digits may follow the first character, as in `x2` or `_1`
+        while matches!(self.ch, Some(ch) if is_letter(ch) || is_digit(ch)) {
             self.read_char();
         }
 
         (self.position, self.input[start..self.position].to_string())
     }
 
+    // A `.` only continues a number when a digit follows it, so `1.len()` is
+    // still a method call. Every such `.` is read, making `1.2.3` a single
+    // token that fails to parse rather than `1.2` followed by `.3`.
     fn read_number(&mut self) -> (usize, String) {
         let start = self.position;
-        while matches!(self.ch, Some(ch) if is_digit(ch)) {
-            self.read_char();
+        loop {
+            match self.ch {
+                Some(ch) if is_digit(ch) => self.read_char(),
+                Some('.') if self.peek_char().is_some_and(is_digit) => self.read_char(),
+                _ => break,
+            }
         }
 
         (self.position, self.input[start..self.position].to_string())
@@ -222,6 +324,48 @@ impl<'a> Lexer<'a> {
     }
 }
 
+/// Lexes all of `input`; the last token is always `EOF`.
+pub fn tokenize(input: &str) -> Vec<Token> {
+    let mut lexer = Lexer::new(input);
+    let mut tokens = vec![];
+    loop {
+        let token = lexer.next_token();
+        let done = token.kind == TokenKind::EOF;
+        tokens.push(token);
+        if done {
+            return tokens;
+        }
+    }
+}
+
+/// One line per token, e.g. `IDENTIFIER(x) @4..5`. String payloads are shown
+/// escaped, and `ILLEGAL` tokens quote the offending source text and are
+/// flagged so they stand out.
+pub fn dump_tokens(input: &str) -> String {
+    tokenize(input)
+        .iter()
+        .map(|token| {
+            let span = &token.span;
+            match &token.kind {
+                TokenKind::IDENTIFIER { name } => {
+                    format!("IDENTIFIER({}) @{}..{}", name, span.start, span.end)
+                }
+                TokenKind::INT(i) => format!("INT({}) @{}..{}", i, span.start, span.end),
+                TokenKind::FLOAT(f) => format!("FLOAT({}) @{}..{}", f, span.start, span.end),
+                TokenKind::STRING(s) => format!("STRING({:?}) @{}..{}", s, span.start, span.end),
+                TokenKind::ILLEGAL => format!(
+                    "ILLEGAL({:?}) @{}..{} <<< not a valid token",
+                    &input[span.start..span.end],
+                    span.start,
+                    span.end
+                ),
+                kind => format!("{:?} @{}..{}", kind, span.start, span.end),
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
 fn is_letter(c: char) -> bool {
     c.is_ascii_alphabetic() || c == '_'
 }
@@ -1,6 +1,8 @@
 use self::serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
 use std::fmt;
 use std::fmt::Formatter;
+use std::hash::{Hash, Hasher};
 
 extern crate serde;
 
@@ -16,6 +18,46 @@ pub struct Span {
     pub end: usize,
 }
 
+/// An `f64` that tokens can carry while staying `Eq`, `Hash` and `Ord`:
+/// all three go by the bit pattern, so unlike `==` on `f64` they treat
+/// `NaN` as equal to itself and `0.0` as distinct from `-0.0`.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct Float64(pub f64);
+
+impl PartialEq for Float64 {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.to_bits() == other.0.to_bits()
+    }
+}
+
+impl Eq for Float64 {}
+
+impl Hash for Float64 {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.0.to_bits().hash(state);
+    }
+}
+
+impl PartialOrd for Float64 {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Float64 {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.total_cmp(&other.0)
+    }
+}
+
+// `{:?}` keeps the decimal point on whole numbers, so `2.0` does not print
+// as the integer `2`.
+impl fmt::Display for Float64 {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self.0)
+    }
+}
+
 impl fmt::Display for Token {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         write!(
@@ -35,6 +77,7 @@ pub enum TokenKind {
     // Identifiers + literals
     IDENTIFIER { name: String },
     INT(i64),
+    FLOAT(Float64),
     STRING(String),
 
     // Operators
@@ -53,11 +96,17 @@ pub enum TokenKind {
 
     EQ,    // ==
     NotEq, // !=
+    AND,   // &&
+    OR,    // ||
+
+    QUESTION, // ?
 
     // delimiters
     COMMA,
     SEMICOLON,
+    NEWLINE, // only emitted when the lexer is asked to
     COLON,
+    DOT,
 
     LPAREN,
     RPAREN,
@@ -71,10 +120,17 @@ pub enum TokenKind {
     LET,
     TRUE,
     FALSE,
+    NULL,
     IF,
     ELSE,
     RETURN,
     WHILE,
+    FOR,
+    IN,
+    BREAK,
+    CONTINUE,
+    MACRO,
+    DIV,
 }
 
 pub fn lookup_identifier(identifier: &str) -> TokenKind {
@@ -83,10 +139,17 @@ pub fn lookup_identifier(identifier: &str) -> TokenKind {
         "let" => TokenKind::LET,
         "true" => TokenKind::TRUE,
         "false" => TokenKind::FALSE,
+        "null" => TokenKind::NULL,
         "if" => TokenKind::IF,
         "else" => TokenKind::ELSE,
         "return" => TokenKind::RETURN,
         "while" => TokenKind::WHILE,
+        "for" => TokenKind::FOR,
+        "in" => TokenKind::IN,
+        "break" => TokenKind::BREAK,
+        "continue" => TokenKind::CONTINUE,
+        "macro" => TokenKind::MACRO,
+        "div" => TokenKind::DIV,
         _ => TokenKind::IDENTIFIER {
             name: identifier.to_string(),
         },
@@ -98,6 +161,7 @@ impl fmt::Display for TokenKind {
         match self {
             TokenKind::IDENTIFIER { name } => write!(f, "{}", name),
             TokenKind::INT(i) => write!(f, "{}", i),
+            TokenKind::FLOAT(float) => write!(f, "{}", float),
             TokenKind::STRING(s) => write!(f, "{}", s),
             TokenKind::ASSIGN => write!(f, "="),
             TokenKind::PLUS => write!(f, "+"),
@@ -112,6 +176,9 @@ impl fmt::Display for TokenKind {
             TokenKind::PERCENT => write!(f, "%"),
             TokenKind::EQ => write!(f, "=="),
             TokenKind::NotEq => write!(f, "!="),
+            TokenKind::AND => write!(f, "&&"),
+            TokenKind::OR => write!(f, "||"),
+            TokenKind::QUESTION => write!(f, "?"),
             TokenKind::COMMA => write!(f, ","),
             TokenKind::SEMICOLON => write!(f, ";"),
             TokenKind::LPAREN => write!(f, "("),
@@ -124,13 +191,22 @@ impl fmt::Display for TokenKind {
             TokenKind::LET => write!(f, "let"),
             TokenKind::TRUE => write!(f, "true"),
             TokenKind::FALSE => write!(f, "false"),
+            TokenKind::NULL => write!(f, "null"),
             TokenKind::IF => write!(f, "if"),
             TokenKind::ELSE => write!(f, "else"),
             TokenKind::RETURN => write!(f, "return"),
             TokenKind::WHILE => write!(f, "while"),
+            TokenKind::FOR => write!(f, "for"),
+            TokenKind::IN => write!(f, "in"),
+            TokenKind::BREAK => write!(f, "break"),
+            TokenKind::CONTINUE => write!(f, "continue"),
+            TokenKind::MACRO => write!(f, "macro"),
+            TokenKind::DIV => write!(f, "div"),
+            TokenKind::NEWLINE => write!(f, "newline"),
             TokenKind::ILLEGAL => write!(f, "ILLEGAL"),
             TokenKind::EOF => write!(f, "EOF"),
             TokenKind::COLON => write!(f, ":"),
+            TokenKind::DOT => write!(f, "."),
         }
     }
 }
@@ -1,18 +1,141 @@
-use crate::{BuiltinFunc, Object};
+use crate::BuiltinFunc::{self, Plain, WithEngine};
+use crate::ordered_map::OrderedMap;
+use crate::{Engine, Halt, HashKey, Object, boolean, null, values_equal};
+use std::cell::{Cell, RefCell};
+use std::fs;
+use std::io::{self, Write};
+use std::num::IntErrorKind;
 use std::rc::Rc;
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 lazy_static! {
     pub static ref BuiltIns: Vec<(&'static str, BuiltinFunc)> = vec![
-        ("len", len),
-        ("puts", puts),
-        ("first", first),
-        ("last", last),
-        ("rest", rest),
-        ("push", push),
-        ("print", puts)
+        ("len", Plain(len)),
+        ("puts", Plain(puts)),
+        ("first", Plain(first)),
+        ("last", Plain(last)),
+        ("rest", Plain(rest)),
+        ("push", Plain(push)),
+        ("print", Plain(puts)),
+        ("new_array", Plain(new_array)),
+        ("starts_with", Plain(starts_with)),
+        ("ends_with", Plain(ends_with)),
+        ("pad_start", Plain(pad_start)),
+        ("pad_end", Plain(pad_end)),
+        ("parse_int", Plain(parse_int)),
+        ("env", Plain(env)),
+        ("args", Plain(args)),
+        ("sleep", WithEngine(sleep)),
+        ("deep_equal", Plain(deep_equal)),
+        ("clone", Plain(clone)),
+        ("zip", Plain(zip)),
+        ("flatten", Plain(flatten)),
+        ("encode", Plain(encode)),
+        ("decode", Plain(decode)),
+        ("decode_lossy", Plain(decode_lossy)),
+        ("hex", Plain(hex)),
+        ("from_hex", Plain(from_hex)),
+        ("substr", Plain(substr)),
+        ("keys", Plain(keys)),
+        ("values", Plain(values)),
+        ("has_key", Plain(has_key)),
+        ("delete", Plain(delete)),
+        ("contains", Plain(contains)),
+        ("type", Plain(type_of)),
+        ("split", Plain(split)),
+        ("join", Plain(join)),
+        ("trim", Plain(trim)),
+        ("upper", Plain(upper)),
+        ("lower", Plain(lower)),
+        ("to_int", Plain(to_int)),
+        ("to_string", Plain(to_string)),
+        ("abs", Plain(abs)),
+        ("min", Plain(min)),
+        ("max", Plain(max)),
+        ("pow", Plain(pow)),
+        ("map", WithEngine(map)),
+        ("filter", WithEngine(filter)),
+        ("reduce", WithEngine(reduce)),
+        ("range", Plain(range)),
+        ("assert", WithEngine(assert)),
+        ("clock", Plain(clock)),
+        ("rand", WithEngine(rand)),
+        ("seed", WithEngine(seed)),
+        ("read_file", WithEngine(read_file)),
+        ("write_file", WithEngine(write_file)),
+        ("sort", WithEngine(sort)),
+        ("reverse", Plain(reverse)),
+        ("slice", Plain(slice))
     ];
 }
 
+/// Largest array `new_array` or `range` will allocate unless changed with
+/// [`set_max_array_length`].
+pub const DEFAULT_MAX_ARRAY_LENGTH: usize = 1 << 24;
+
+/// The limit is per thread, like the other settings here.
+pub fn set_max_array_length(limit: usize) {
+    MAX_ARRAY_LENGTH.with(|max| max.set(limit));
+}
+
+pub fn max_array_length() -> usize {
+    MAX_ARRAY_LENGTH.with(Cell::get)
+}
+
+/// Deepest array or hash nesting `clone` copies before giving up.
+pub const MAX_CLONE_DEPTH: usize = 1024;
+
+thread_local! {
+    static ALLOW_ENV: Cell<bool> = const { Cell::new(true) };
+    static MAX_ARRAY_LENGTH: Cell<usize> = const { Cell::new(DEFAULT_MAX_ARRAY_LENGTH) };
+    static SCRIPT_ARGS: RefCell<Vec<String>> = const { RefCell::new(Vec::new()) };
+    static SLEEPER: RefCell<Option<Sleeper>> = const { RefCell::new(None) };
+    static OUTPUT: RefCell<Option<Output>> = const { RefCell::new(None) };
+}
+
+/// Longest stretch `sleep` waits before checking for cancellation again.
+pub const SLEEP_SLICE: Duration = Duration::from_millis(10);
+
+/// Stands in for `std::thread::sleep`, so tests can sleep without waiting.
+pub type Sleeper = Rc<dyn Fn(Duration)>;
+
+/// Lets embedders sandbox scripts: with access disabled, `env` returns an
+/// error instead of reading the process environment. The setting is per
+/// thread, like the VM and interpreter that consult it.
+pub fn set_allow_env(allowed: bool) {
+    ALLOW_ENV.with(|allow| allow.set(allowed));
+}
+
+pub fn allow_env() -> bool {
+    ALLOW_ENV.with(Cell::get)
+}
+
+/// Sets what `args()` returns: the command-line arguments given to the
+/// script, which are empty unless a runner passes some along.
+pub fn set_script_args(args: Vec<String>) {
+    SCRIPT_ARGS.with(|script_args| *script_args.borrow_mut() = args);
+}
+
+pub fn script_args() -> Vec<String> {
+    SCRIPT_ARGS.with(|script_args| script_args.borrow().clone())
+}
+
+/// Replaces how `sleep` waits out each slice; `None` restores
+/// `std::thread::sleep`.
+pub fn set_sleeper(sleeper: Option<Sleeper>) {
+    SLEEPER.with(|current| *current.borrow_mut() = sleeper);
+}
+
+/// Where `puts` and `print` write, so embedders and tests can capture
+/// what a script prints.
+pub type Output = Rc<RefCell<dyn Write>>;
+
+/// Redirects `puts` and `print` on this thread; `None` restores stdout.
+pub fn set_output(output: Option<Output>) {
+    OUTPUT.with(|current| *current.borrow_mut() = output);
+}
+
 fn wrong_arity(name: &str, expected: usize, got: usize) -> Rc<Object> {
     Rc::new(Object::Error(format!(
         "builtin {} expected {} argument{}, got {}",
@@ -23,20 +146,116 @@ fn wrong_arity(name: &str, expected: usize, got: usize) -> Rc<Object> {
     )))
 }
 
+/// For builtins that take either `fewer` or one more argument.
+fn wrong_arity_either(name: &str, fewer: usize, got: usize) -> Rc<Object> {
+    Rc::new(Object::Error(format!(
+        "builtin {} expected {} or {} arguments, got {}",
+        name,
+        fewer,
+        fewer + 1,
+        got
+    )))
+}
+
+fn too_few_arguments(name: &str, at_least: usize, got: usize) -> Rc<Object> {
+    Rc::new(Object::Error(format!(
+        "builtin {} expected at least {} argument{}, got {}",
+        name,
+        at_least,
+        if at_least == 1 { "" } else { "s" },
+        got
+    )))
+}
+
+fn type_error(name: &str, position: usize, expected: &str, got: &Object) -> Rc<Object> {
+    Rc::new(Object::Error(format!(
+        "builtin {} expected argument {} to be {}, got {}",
+        name,
+        position,
+        expected,
+        got.type_name()
+    )))
+}
+
+fn function_arg(name: &str, args: &[Rc<Object>], index: usize) -> Result<(), Rc<Object>> {
+    match &*args[index] {
+        Object::ClosureObj(_) | Object::Function(..) | Object::Builtin(_) => Ok(()),
+        o => Err(type_error(name, index + 1, "FUNCTION", o)),
+    }
+}
+
+fn bytes_arg<'a>(name: &str, args: &'a [Rc<Object>], index: usize) -> Result<&'a [u8], Rc<Object>> {
+    match &*args[index] {
+        Object::Bytes(b) => Ok(b),
+        o => Err(type_error(name, index + 1, "BYTES", o)),
+    }
+}
+
+fn string_arg<'a>(name: &str, args: &'a [Rc<Object>], index: usize) -> Result<&'a str, Rc<Object>> {
+    match &*args[index] {
+        Object::String(s) => Ok(s),
+        o => Err(type_error(name, index + 1, "STRING", o)),
+    }
+}
+
+fn integer_arg(name: &str, args: &[Rc<Object>], index: usize) -> Result<i64, Rc<Object>> {
+    match &*args[index] {
+        Object::Integer(i) => Ok(*i),
+        o => Err(type_error(name, index + 1, "INTEGER", o)),
+    }
+}
+
+fn count_arg(
+    name: &str,
+    what: &str,
+    args: &[Rc<Object>],
+    index: usize,
+) -> Result<usize, Rc<Object>> {
+    match &*args[index] {
+        Object::Integer(n) => usize::try_from(*n).map_err(|_| {
+            Rc::new(Object::Error(format!(
+                "builtin {} {} must not be negative, got {}",
+                name, what, n
+            )))
+        }),
+        o => Err(type_error(name, index + 1, "INTEGER", o)),
+    }
+}
+
 pub fn len(args: Vec<Rc<Object>>) -> Rc<Object> {
     if args.len() != 1 {
         return wrong_arity("len", 1, args.len());
     }
     Rc::from(match &*args[0] {
-        Object::String(s) => Object::Integer(s.len() as i64),
+        // in chars, like indexing and `substr`; `len(encode(s))` gives bytes
+        Object::String(s) => Object::Integer(s.chars().count() as i64),
         Object::Array(a) => Object::Integer(a.len() as i64),
-        o => Object::Error(format!("builtin len not supported for type {}", o)),
+        Object::Bytes(b) => Object::Integer(b.len() as i64),
+        Object::Hash(h) => Object::Integer(h.len() as i64),
+        o => return type_error("len", 1, "STRING, ARRAY, BYTES or HASH", o),
     })
 }
 
 pub fn puts(args: Vec<Rc<Object>>) -> Rc<Object> {
-    args.iter().for_each(|obj| println!("{}", obj));
-    Rc::from(Object::Null)
+    let output = OUTPUT.with(|output| output.borrow().clone());
+    let written = match output {
+        Some(output) => write_lines(&mut *output.borrow_mut(), &args),
+        None => write_lines(&mut io::stdout().lock(), &args),
+    };
+    match written {
+        Ok(()) => null(),
+        Err(e) => Rc::new(Object::Error(format!(
+            "builtin puts could not write: {}",
+            e
+        ))),
+    }
+}
+
+fn write_lines(output: &mut dyn Write, args: &[Rc<Object>]) -> io::Result<()> {
+    for obj in args {
+        writeln!(output, "{}", obj)?;
+    }
+    output.flush()
 }
 
 pub fn first(args: Vec<Rc<Object>>) -> Rc<Object> {
@@ -47,12 +266,9 @@ pub fn first(args: Vec<Rc<Object>>) -> Rc<Object> {
     match &*args[0] {
         Object::Array(s) => match s.first() {
             Some(obj) => Rc::clone(obj),
-            None => Rc::new(Object::Null),
+            None => null(),
         },
-        o => Rc::new(Object::Error(format!(
-            "builtin first not supported for type {}",
-            o
-        ))),
+        o => type_error("first", 1, "ARRAY", o),
     }
 }
 
@@ -64,12 +280,9 @@ pub fn last(args: Vec<Rc<Object>>) -> Rc<Object> {
     match &*args[0] {
         Object::Array(s) => match s.last() {
             Some(obj) => Rc::clone(obj),
-            None => Rc::new(Object::Null),
+            None => null(),
         },
-        o => Rc::new(Object::Error(format!(
-            "builtin last not supported for type {}",
-            o
-        ))),
+        o => type_error("last", 1, "ARRAY", o),
     }
 }
 
@@ -85,12 +298,9 @@ pub fn rest(args: Vec<Rc<Object>>) -> Rc<Object> {
                 let new_array = s[1..len].to_vec();
                 return Rc::new(Object::Array(new_array));
             }
-            Rc::new(Object::Null)
+            null()
         }
-        o => Rc::new(Object::Error(format!(
-            "builtin rest not supported for type {}",
-            o
-        ))),
+        o => type_error("rest", 1, "ARRAY", o),
     }
 }
 
@@ -99,17 +309,1181 @@ pub fn push(args: Vec<Rc<Object>>) -> Rc<Object> {
         return wrong_arity("push", 2, args.len());
     }
 
-    let array = &args[0];
-    let obj = Rc::clone(&args[1]);
-    match &**array {
-        Object::Array(s) => {
-            let mut new_array = s.clone();
-            new_array.push(obj);
-            Rc::new(Object::Array(new_array))
+    // Copy-on-write: an array nobody else references is extended in place,
+    // so a loop of `let xs = push(xs, x)` stays linear. A shared array is
+    // copied, leaving every other reference unchanged.
+    let mut args = args;
+    let obj = args.pop().unwrap();
+    let array = args.pop().unwrap();
+    let mut elements = match Rc::try_unwrap(array) {
+        Ok(Object::Array(elements)) => elements,
+        Ok(o) => return type_error("push", 1, "ARRAY", &o),
+        Err(shared) => match &*shared {
+            Object::Array(elements) => elements.clone(),
+            o => return type_error("push", 1, "ARRAY", o),
+        },
+    };
+    elements.push(obj);
+    Rc::new(Object::Array(elements))
+}
+
+pub fn new_array(args: Vec<Rc<Object>>) -> Rc<Object> {
+    if args.len() != 2 {
+        return wrong_arity("new_array", 2, args.len());
+    }
+
+    let length = match &*args[0] {
+        Object::Integer(n) if *n < 0 => {
+            return Rc::new(Object::Error(format!(
+                "builtin new_array length must not be negative, got {}",
+                n
+            )));
+        }
+        Object::Integer(n) => *n as u64,
+        o => {
+            return Rc::new(Object::Error(format!(
+                "builtin new_array length must be INTEGER, got {}",
+                o
+            )));
+        }
+    };
+    let limit = max_array_length();
+    if length > limit as u64 {
+        return Rc::new(Object::Error(format!(
+            "builtin new_array length {} exceeds the limit of {}",
+            length, limit
+        )));
+    }
+
+    Rc::new(Object::Array(vec![Rc::clone(&args[1]); length as usize]))
+}
+
+/// `range(start, end)` and `range(start, end, step)` count from `start`
+/// up to, but not including, `end`; a negative step counts down. A range
+/// that is empty in the step's direction gives an empty array.
+pub fn range(args: Vec<Rc<Object>>) -> Rc<Object> {
+    if args.len() != 2 && args.len() != 3 {
+        return wrong_arity_either("range", 2, args.len());
+    }
+    let mut bounds = [0; 3];
+    for (index, bound) in bounds.iter_mut().enumerate().take(args.len()) {
+        match integer_arg("range", &args, index) {
+            Ok(i) => *bound = i,
+            Err(e) => return e,
+        }
+    }
+    let [start, end, step] = bounds;
+    let step = if args.len() == 3 { step } else { 1 };
+    if step == 0 {
+        return Rc::new(Object::Error(
+            "builtin range step must not be 0".to_string(),
+        ));
+    }
+    // in i128 so neither the distance nor the rounding up can overflow
+    let (start, end, step) = (i128::from(start), i128::from(end), i128::from(step));
+    let distance = if step > 0 { end - start } else { start - end };
+    let length = if distance <= 0 {
+        0
+    } else {
+        (distance + step.abs() - 1) / step.abs()
+    };
+    let limit = max_array_length();
+    if length > limit as i128 {
+        return Rc::new(Object::Error(format!(
+            "builtin range length {} exceeds the limit of {}",
+            length, limit
+        )));
+    }
+    Rc::new(Object::Array(
+        (0..length)
+            .map(|i| Rc::new(Object::Integer((start + i * step) as i64)))
+            .collect(),
+    ))
+}
+
+pub fn starts_with(args: Vec<Rc<Object>>) -> Rc<Object> {
+    string_predicate("starts_with", args, |s, prefix| s.starts_with(prefix))
+}
+
+pub fn ends_with(args: Vec<Rc<Object>>) -> Rc<Object> {
+    string_predicate("ends_with", args, |s, suffix| s.ends_with(suffix))
+}
+
+// `start` and `len` count chars, not bytes. A range running past the end
+// is cut short, so `substr("abc", 1, 10)` is "bc" and `substr("abc", 5, 1)`
+// is "".
+pub fn substr(args: Vec<Rc<Object>>) -> Rc<Object> {
+    if args.len() != 3 {
+        return wrong_arity("substr", 3, args.len());
+    }
+    let s = match string_arg("substr", &args, 0) {
+        Ok(s) => s,
+        Err(e) => return e,
+    };
+    let start = match count_arg("substr", "start", &args, 1) {
+        Ok(start) => start,
+        Err(e) => return e,
+    };
+    let len = match count_arg("substr", "length", &args, 2) {
+        Ok(len) => len,
+        Err(e) => return e,
+    };
+    Rc::new(Object::String(
+        s.chars().skip(start).take(len).collect::<String>().into(),
+    ))
+}
+
+pub fn pad_start(args: Vec<Rc<Object>>) -> Rc<Object> {
+    pad("pad_start", args, |s, padding| padding + s)
+}
+
+pub fn pad_end(args: Vec<Rc<Object>>) -> Rc<Object> {
+    pad("pad_end", args, |s, padding| s.to_string() + &padding)
+}
+
+// Widths count chars, not bytes. The fill repeats as often as needed and is
+// cut short on the last repetition, so `pad_start("7", 4, "ab")` is "aba7".
+fn pad(name: &str, args: Vec<Rc<Object>>, join: fn(&str, String) -> String) -> Rc<Object> {
+    if args.len() != 2 && args.len() != 3 {
+        return wrong_arity_either(name, 2, args.len());
+    }
+    let s = match string_arg(name, &args, 0) {
+        Ok(s) => s,
+        Err(e) => return e,
+    };
+    let width = match &*args[1] {
+        Object::Integer(width) => *width,
+        o => return type_error(name, 2, "INTEGER", o),
+    };
+    let fill = match args.len() {
+        3 => match string_arg(name, &args, 2) {
+            Ok(fill) => fill,
+            Err(e) => return e,
+        },
+        _ => " ",
+    };
+    if fill.is_empty() {
+        return Rc::new(Object::Error(format!(
+            "builtin {} fill string must not be empty",
+            name
+        )));
+    }
+
+    let length = s.chars().count() as i64;
+    if width <= length {
+        return Rc::clone(&args[0]);
+    }
+    let limit = max_array_length();
+    if width as u64 > limit as u64 {
+        return Rc::new(Object::Error(format!(
+            "builtin {} width {} exceeds the limit of {}",
+            name, width, limit
+        )));
+    }
+    let padding = fill
+        .chars()
+        .cycle()
+        .take((width - length) as usize)
+        .collect();
+    Rc::new(Object::String(join(s, padding).into()))
+}
+
+pub fn parse_int(args: Vec<Rc<Object>>) -> Rc<Object> {
+    if args.len() != 1 && args.len() != 2 {
+        return wrong_arity_either("parse_int", 1, args.len());
+    }
+    let s = match string_arg("parse_int", &args, 0) {
+        Ok(s) => s,
+        Err(e) => return e,
+    };
+    let radix = match args.get(1).map(|radix| &**radix) {
+        None => 10,
+        Some(Object::Integer(radix @ 2..=36)) => *radix as u32,
+        Some(Object::Integer(radix)) => {
+            return Rc::new(Object::Error(format!(
+                "builtin parse_int radix must be between 2 and 36, got {}",
+                radix
+            )));
         }
-        o => Rc::new(Object::Error(format!(
-            "builtin push not supported for type {}",
-            o
+        Some(o) => return type_error("parse_int", 2, "INTEGER", o),
+    };
+
+    match i64::from_str_radix(s.trim(), radix) {
+        Ok(n) => Rc::new(Object::Integer(n)),
+        Err(e) => Rc::new(Object::Error(match e.kind() {
+            IntErrorKind::Empty => "builtin parse_int cannot parse an empty string".to_string(),
+            IntErrorKind::PosOverflow | IntErrorKind::NegOverflow => {
+                format!("builtin parse_int {:?} does not fit in a 64-bit integer", s)
+            }
+            _ => format!("builtin parse_int cannot parse {:?} in radix {}", s, radix),
+        })),
+    }
+}
+
+// Names and values that are not valid UTF-8 are converted lossily, with
+// invalid sequences replaced by U+FFFD.
+pub fn env(args: Vec<Rc<Object>>) -> Rc<Object> {
+    if args.len() > 1 {
+        return wrong_arity_either("env", 0, args.len());
+    }
+    if !allow_env() {
+        return Rc::new(Object::Error(
+            "builtin env is unavailable: environment access disabled".to_string(),
+        ));
+    }
+
+    if args.is_empty() {
+        let mut vars = std::env::vars_os()
+            .map(|(name, value)| {
+                (
+                    name.to_string_lossy().into_owned(),
+                    value.to_string_lossy().into_owned(),
+                )
+            })
+            .collect::<Vec<_>>();
+        vars.sort();
+        let map: OrderedMap<HashKey, Rc<Object>> = vars
+            .into_iter()
+            .map(|(name, value)| {
+                (
+                    HashKey::String(name.into()),
+                    Rc::new(Object::String(value.into())),
+                )
+            })
+            .collect();
+        return Rc::new(Object::Hash(map));
+    }
+
+    let name = match string_arg("env", &args, 0) {
+        Ok(name) => name,
+        Err(e) => return e,
+    };
+    // `var_os` may panic on names like these; none of them can be set anyway
+    if name.is_empty() || name.contains(['=', '\0']) {
+        return null();
+    }
+    match std::env::var_os(name) {
+        Some(value) => Rc::new(Object::String(value.to_string_lossy().into_owned().into())),
+        None => null(),
+    }
+}
+
+pub fn args(args: Vec<Rc<Object>>) -> Rc<Object> {
+    if !args.is_empty() {
+        return wrong_arity("args", 0, args.len());
+    }
+    let elements = script_args()
+        .into_iter()
+        .map(|arg| Rc::new(Object::String(arg.into())))
+        .collect();
+    Rc::new(Object::Array(elements))
+}
+
+// Waits in slices of at most `SLEEP_SLICE` so that cancelling the engine
+// takes effect while a script is asleep.
+pub fn sleep(args: Vec<Rc<Object>>, engine: &mut dyn Engine) -> Result<Rc<Object>, Halt> {
+    if args.len() != 1 {
+        return Ok(wrong_arity("sleep", 1, args.len()));
+    }
+    let millis = match &*args[0] {
+        Object::Integer(n) if *n < 0 => {
+            return Ok(Rc::new(Object::Error(format!(
+                "builtin sleep duration must not be negative, got {}",
+                n
+            ))));
+        }
+        Object::Integer(n) => *n as u64,
+        o => return Ok(type_error("sleep", 1, "INTEGER", o)),
+    };
+
+    let sleeper = SLEEPER.with(|sleeper| sleeper.borrow().clone());
+    let mut remaining = Duration::from_millis(millis);
+    while !remaining.is_zero() {
+        if engine.cancelled() {
+            return Ok(Rc::new(Object::Error(
+                "builtin sleep interrupted".to_string(),
+            )));
+        }
+        let slice = remaining.min(SLEEP_SLICE);
+        match &sleeper {
+            Some(sleeper) => sleeper(slice),
+            None => thread::sleep(slice),
+        }
+        remaining -= slice;
+    }
+    Ok(null())
+}
+
+pub fn deep_equal(args: Vec<Rc<Object>>) -> Rc<Object> {
+    if args.len() != 2 {
+        return wrong_arity("deep_equal", 2, args.len());
+    }
+    boolean(values_equal(&args[0], &args[1]))
+}
+
+pub fn clone(args: Vec<Rc<Object>>) -> Rc<Object> {
+    if args.len() != 1 {
+        return wrong_arity("clone", 1, args.len());
+    }
+    deep_copy(&args[0], 0).unwrap_or_else(|()| {
+        Rc::new(Object::Error(format!(
+            "builtin clone nesting exceeds the limit of {}",
+            MAX_CLONE_DEPTH
+        )))
+    })
+}
+
+// Arrays and hashes get fresh `Rc`s all the way down; anything else is
+// immutable and is shared with the original.
+fn deep_copy(object: &Rc<Object>, depth: usize) -> Result<Rc<Object>, ()> {
+    match &**object {
+        Object::Array(_) | Object::Hash(_) if depth >= MAX_CLONE_DEPTH => Err(()),
+        Object::Array(elements) => {
+            let mut copy = Vec::with_capacity(elements.len());
+            for element in elements {
+                copy.push(deep_copy(element, depth + 1)?);
+            }
+            Ok(Rc::new(Object::Array(copy)))
+        }
+        Object::Hash(pairs) => {
+            let mut copy = OrderedMap::with_capacity(pairs.len());
+            for (key, value) in pairs.iter() {
+                copy.insert(key.clone(), deep_copy(value, depth + 1)?);
+            }
+            Ok(Rc::new(Object::Hash(copy)))
+        }
+        _ => Ok(Rc::clone(object)),
+    }
+}
+
+fn string_predicate(
+    name: &str,
+    args: Vec<Rc<Object>>,
+    predicate: fn(&str, &str) -> bool,
+) -> Rc<Object> {
+    if args.len() != 2 {
+        return wrong_arity(name, 2, args.len());
+    }
+    let s = match string_arg(name, &args, 0) {
+        Ok(s) => s,
+        Err(e) => return e,
+    };
+    let affix = match string_arg(name, &args, 1) {
+        Ok(affix) => affix,
+        Err(e) => return e,
+    };
+    boolean(predicate(s, affix))
+}
+
+/// `zip([1, 2, 3], ["a", "b"])` is `[[1, "a"], [2, "b"]]`: the i-th element
+/// of every array, stopping at the end of the shortest.
+pub fn zip(args: Vec<Rc<Object>>) -> Rc<Object> {
+    if args.is_empty() {
+        return too_few_arguments("zip", 1, 0);
+    }
+    let mut arrays = Vec::with_capacity(args.len());
+    for (index, arg) in args.iter().enumerate() {
+        match &**arg {
+            Object::Array(elements) => arrays.push(elements),
+            o => return type_error("zip", index + 1, "ARRAY", o),
+        }
+    }
+    let len = arrays
+        .iter()
+        .map(|elements| elements.len())
+        .min()
+        .unwrap_or(0);
+    let rows = (0..len)
+        .map(|i| {
+            let row = arrays.iter().map(|elements| Rc::clone(&elements[i]));
+            Rc::new(Object::Array(row.collect()))
+        })
+        .collect();
+    Rc::new(Object::Array(rows))
+}
+
+/// `flatten(arr)` splices nested arrays into `arr` one level deep;
+/// `flatten(arr, depth)` goes `depth` levels, and a depth of -1 flattens
+/// completely. Walks an explicit stack, so any nesting is safe.
+pub fn flatten(args: Vec<Rc<Object>>) -> Rc<Object> {
+    if args.len() != 1 && args.len() != 2 {
+        return wrong_arity_either("flatten", 1, args.len());
+    }
+    let elements = match &*args[0] {
+        Object::Array(elements) => elements,
+        o => return type_error("flatten", 1, "ARRAY", o),
+    };
+    let depth = match args.get(1).map(|depth| &**depth) {
+        None => 1,
+        Some(Object::Integer(-1)) => usize::MAX,
+        Some(Object::Integer(depth)) if *depth < 0 => {
+            return Rc::new(Object::Error(format!(
+                "builtin flatten depth must be -1 or at least 0, got {}",
+                depth
+            )));
+        }
+        Some(Object::Integer(depth)) => *depth as usize,
+        Some(o) => return type_error("flatten", 2, "INTEGER", o),
+    };
+
+    let mut flat = Vec::with_capacity(elements.len());
+    // each entry is an array being copied and how many more levels below it
+    // may still be spliced in
+    let mut pending = vec![(elements.iter(), depth)];
+    while let Some((iter, remaining)) = pending.last_mut() {
+        let remaining = *remaining;
+        match iter.next() {
+            Some(element) => match &**element {
+                Object::Array(inner) if remaining > 0 => {
+                    pending.push((inner.iter(), remaining - 1));
+                }
+                _ => flat.push(Rc::clone(element)),
+            },
+            None => {
+                pending.pop();
+            }
+        }
+    }
+    Rc::new(Object::Array(flat))
+}
+
+/// The UTF-8 encoding of a string.
+pub fn encode(args: Vec<Rc<Object>>) -> Rc<Object> {
+    if args.len() != 1 {
+        return wrong_arity("encode", 1, args.len());
+    }
+    match string_arg("encode", &args, 0) {
+        Ok(s) => Rc::new(Object::Bytes(s.as_bytes().to_vec())),
+        Err(e) => e,
+    }
+}
+
+/// The string whose UTF-8 encoding is `b`, or a `Utf8Error` value holding
+/// the offset of the first invalid byte.
+pub fn decode(args: Vec<Rc<Object>>) -> Rc<Object> {
+    if args.len() != 1 {
+        return wrong_arity("decode", 1, args.len());
+    }
+    let bytes = match bytes_arg("decode", &args, 0) {
+        Ok(bytes) => bytes,
+        Err(e) => return e,
+    };
+    match std::str::from_utf8(bytes) {
+        Ok(s) => Rc::new(Object::String(Rc::from(s))),
+        Err(e) => Rc::new(Object::Utf8Error {
+            offset: e.valid_up_to(),
+        }),
+    }
+}
+
+/// Like `decode`, but replaces invalid sequences with U+FFFD.
+pub fn decode_lossy(args: Vec<Rc<Object>>) -> Rc<Object> {
+    if args.len() != 1 {
+        return wrong_arity("decode_lossy", 1, args.len());
+    }
+    match bytes_arg("decode_lossy", &args, 0) {
+        Ok(bytes) => Rc::new(Object::String(
+            String::from_utf8_lossy(bytes).into_owned().into(),
+        )),
+        Err(e) => e,
+    }
+}
+
+/// Lowercase hex, two digits per byte.
+pub fn hex(args: Vec<Rc<Object>>) -> Rc<Object> {
+    if args.len() != 1 {
+        return wrong_arity("hex", 1, args.len());
+    }
+    match bytes_arg("hex", &args, 0) {
+        Ok(bytes) => Rc::new(Object::String(
+            bytes
+                .iter()
+                .map(|byte| format!("{:02x}", byte))
+                .collect::<String>()
+                .into(),
+        )),
+        Err(e) => e,
+    }
+}
+
+/// The bytes spelled by pairs of hex digits, in either case.
+pub fn from_hex(args: Vec<Rc<Object>>) -> Rc<Object> {
+    if args.len() != 1 {
+        return wrong_arity("from_hex", 1, args.len());
+    }
+    let s = match string_arg("from_hex", &args, 0) {
+        Ok(s) => s,
+        Err(e) => return e,
+    };
+    if s.len() % 2 != 0 {
+        return Rc::new(Object::Error(format!(
+            "builtin from_hex expected an even number of digits, got {}",
+            s.len()
+        )));
+    }
+    let mut bytes = Vec::with_capacity(s.len() / 2);
+    for (index, pair) in s.as_bytes().chunks(2).enumerate() {
+        let digits = std::str::from_utf8(pair).ok();
+        match digits.and_then(|digits| u8::from_str_radix(digits, 16).ok()) {
+            // from_str_radix would also take a sign such as "+f"
+            Some(byte) if pair.iter().all(u8::is_ascii_hexdigit) => bytes.push(byte),
+            _ => {
+                return Rc::new(Object::Error(format!(
+                    "builtin from_hex found an invalid digit at offset {}",
+                    index * 2
+                )));
+            }
+        }
+    }
+    Rc::new(Object::Bytes(bytes))
+}
+
+/// The keys of a hash, sorted so the result does not depend on how the
+/// hash was built: integers, then booleans, then strings (see `HashKey`).
+pub fn keys(args: Vec<Rc<Object>>) -> Rc<Object> {
+    if args.len() != 1 {
+        return wrong_arity("keys", 1, args.len());
+    }
+    match &*args[0] {
+        Object::Hash(hash) => {
+            let keys = sorted_entries(hash).map(|(key, _)| Rc::new(Object::from(key)));
+            Rc::new(Object::Array(keys.collect()))
+        }
+        o => type_error("keys", 1, "HASH", o),
+    }
+}
+
+/// The values of a hash, in the order `keys` returns their keys.
+pub fn values(args: Vec<Rc<Object>>) -> Rc<Object> {
+    if args.len() != 1 {
+        return wrong_arity("values", 1, args.len());
+    }
+    match &*args[0] {
+        Object::Hash(hash) => {
+            let values = sorted_entries(hash).map(|(_, value)| Rc::clone(value));
+            Rc::new(Object::Array(values.collect()))
+        }
+        o => type_error("values", 1, "HASH", o),
+    }
+}
+
+fn sorted_entries(
+    hash: &OrderedMap<HashKey, Rc<Object>>,
+) -> impl Iterator<Item = (&HashKey, &Rc<Object>)> {
+    let mut entries: Vec<_> = hash.iter().collect();
+    entries.sort_by_key(|(key, _)| *key);
+    entries.into_iter()
+}
+
+pub fn has_key(args: Vec<Rc<Object>>) -> Rc<Object> {
+    if args.len() != 2 {
+        return wrong_arity("has_key", 2, args.len());
+    }
+    let hash = match &*args[0] {
+        Object::Hash(hash) => hash,
+        o => return type_error("has_key", 1, "HASH", o),
+    };
+    match HashKey::try_from(&*args[1]) {
+        Ok(key) => boolean(hash.contains_key(&key)),
+        Err(()) => type_error("has_key", 2, "INTEGER, BOOLEAN or STRING", &args[1]),
+    }
+}
+
+/// A copy of the hash without `key`, made in place when nothing else
+/// references the hash, like `push`. A missing key is not an error.
+pub fn delete(args: Vec<Rc<Object>>) -> Rc<Object> {
+    if args.len() != 2 {
+        return wrong_arity("delete", 2, args.len());
+    }
+    let Ok(key) = HashKey::try_from(&*args[1]) else {
+        return type_error("delete", 2, "INTEGER, BOOLEAN or STRING", &args[1]);
+    };
+    let mut args = args;
+    args.truncate(1);
+    let mut entries = match Rc::try_unwrap(args.pop().unwrap()) {
+        Ok(Object::Hash(entries)) => entries,
+        Ok(o) => return type_error("delete", 1, "HASH", &o),
+        Err(shared) => match &*shared {
+            Object::Hash(entries) => entries.clone(),
+            o => return type_error("delete", 1, "HASH", o),
+        },
+    };
+    entries.remove(&key);
+    Rc::new(Object::Hash(entries))
+}
+
+/// Whether any element of the array equals `value`.
+pub fn contains(args: Vec<Rc<Object>>) -> Rc<Object> {
+    if args.len() != 2 {
+        return wrong_arity("contains", 2, args.len());
+    }
+    match &*args[0] {
+        Object::Array(elements) => boolean(elements.iter().any(|element| **element == *args[1])),
+        o => type_error("contains", 1, "ARRAY", o),
+    }
+}
+
+/// The name error messages use for the argument's type, such as
+/// `"INTEGER"`. Functions are `"CLOSURE"` under both the VM and the
+/// interpreter, so scripts see the same answer from either.
+pub fn type_of(args: Vec<Rc<Object>>) -> Rc<Object> {
+    if args.len() != 1 {
+        return wrong_arity("type", 1, args.len());
+    }
+    let name = match &*args[0] {
+        Object::Function(..) => "CLOSURE",
+        o => o.type_name(),
+    };
+    Rc::new(Object::String(Rc::from(name)))
+}
+
+/// `split("a,b", ",")` is `["a", "b"]`. An empty separator splits the
+/// string into its chars.
+pub fn split(args: Vec<Rc<Object>>) -> Rc<Object> {
+    if args.len() != 2 {
+        return wrong_arity("split", 2, args.len());
+    }
+    let s = match string_arg("split", &args, 0) {
+        Ok(s) => s,
+        Err(e) => return e,
+    };
+    let separator = match string_arg("split", &args, 1) {
+        Ok(separator) => separator,
+        Err(e) => return e,
+    };
+    let string = |part: &str| Rc::new(Object::String(Rc::from(part)));
+    let parts = if separator.is_empty() {
+        s.chars()
+            .map(|c| string(c.encode_utf8(&mut [0; 4])))
+            .collect()
+    } else {
+        s.split(separator).map(string).collect()
+    };
+    Rc::new(Object::Array(parts))
+}
+
+/// The strings of an array with `separator` between them.
+pub fn join(args: Vec<Rc<Object>>) -> Rc<Object> {
+    if args.len() != 2 {
+        return wrong_arity("join", 2, args.len());
+    }
+    let elements = match &*args[0] {
+        Object::Array(elements) => elements,
+        o => return type_error("join", 1, "ARRAY", o),
+    };
+    let separator = match string_arg("join", &args, 1) {
+        Ok(separator) => separator,
+        Err(e) => return e,
+    };
+    let mut parts = Vec::with_capacity(elements.len());
+    for (index, element) in elements.iter().enumerate() {
+        match &**element {
+            Object::String(part) => parts.push(&**part),
+            o => {
+                return Rc::new(Object::Error(format!(
+                    "builtin join expected element {} to be STRING, got {}",
+                    index,
+                    o.type_name()
+                )));
+            }
+        }
+    }
+    Rc::new(Object::String(parts.join(separator).into()))
+}
+
+pub fn trim(args: Vec<Rc<Object>>) -> Rc<Object> {
+    map_string("trim", args, |s| s.trim().to_string())
+}
+
+pub fn upper(args: Vec<Rc<Object>>) -> Rc<Object> {
+    map_string("upper", args, str::to_uppercase)
+}
+
+pub fn lower(args: Vec<Rc<Object>>) -> Rc<Object> {
+    map_string("lower", args, str::to_lowercase)
+}
+
+fn map_string(name: &str, args: Vec<Rc<Object>>, map: fn(&str) -> String) -> Rc<Object> {
+    if args.len() != 1 {
+        return wrong_arity(name, 1, args.len());
+    }
+    match string_arg(name, &args, 0) {
+        Ok(s) => Rc::new(Object::String(map(s).into())),
+        Err(e) => e,
+    }
+}
+
+/// Integers pass through and booleans become 1 or 0. A string is parsed
+/// as a decimal integer after trimming whitespace; one that does not
+/// parse, or does not fit, gives null rather than an error.
+pub fn to_int(args: Vec<Rc<Object>>) -> Rc<Object> {
+    if args.len() != 1 {
+        return wrong_arity("to_int", 1, args.len());
+    }
+    match &*args[0] {
+        Object::Integer(_) => Rc::clone(&args[0]),
+        Object::Boolean(b) => Rc::new(Object::Integer(i64::from(*b))),
+        Object::String(s) => match s.trim().parse::<i64>() {
+            Ok(i) => Rc::new(Object::Integer(i)),
+            Err(_) => null(),
+        },
+        o => type_error("to_int", 1, "STRING, INTEGER or BOOLEAN", o),
+    }
+}
+
+/// Any value as `puts` would print it.
+pub fn to_string(args: Vec<Rc<Object>>) -> Rc<Object> {
+    if args.len() != 1 {
+        return wrong_arity("to_string", 1, args.len());
+    }
+    Rc::new(Object::String(args[0].to_string().into()))
+}
+
+fn overflow(name: &str) -> Rc<Object> {
+    Rc::new(Object::Error(format!("builtin {} overflowed", name)))
+}
+
+pub fn abs(args: Vec<Rc<Object>>) -> Rc<Object> {
+    if args.len() != 1 {
+        return wrong_arity("abs", 1, args.len());
+    }
+    match integer_arg("abs", &args, 0) {
+        Ok(i) => i
+            .checked_abs()
+            .map_or_else(|| overflow("abs"), |i| Rc::new(Object::Integer(i))),
+        Err(e) => e,
+    }
+}
+
+pub fn min(args: Vec<Rc<Object>>) -> Rc<Object> {
+    extremum("min", args, Iterator::min)
+}
+
+pub fn max(args: Vec<Rc<Object>>) -> Rc<Object> {
+    extremum("max", args, Iterator::max)
+}
+
+fn extremum(
+    name: &str,
+    args: Vec<Rc<Object>>,
+    pick: fn(std::vec::IntoIter<i64>) -> Option<i64>,
+) -> Rc<Object> {
+    if args.len() < 2 {
+        return too_few_arguments(name, 2, args.len());
+    }
+    let mut integers = Vec::with_capacity(args.len());
+    for index in 0..args.len() {
+        match integer_arg(name, &args, index) {
+            Ok(i) => integers.push(i),
+            Err(e) => return e,
+        }
+    }
+    Rc::new(Object::Integer(
+        pick(integers.into_iter()).expect("at least two arguments"),
+    ))
+}
+
+/// `base` to the power `exponent`, which must not be negative. A result
+/// that does not fit in an integer is an error.
+pub fn pow(args: Vec<Rc<Object>>) -> Rc<Object> {
+    if args.len() != 2 {
+        return wrong_arity("pow", 2, args.len());
+    }
+    let mut base = match integer_arg("pow", &args, 0) {
+        Ok(base) => base,
+        Err(e) => return e,
+    };
+    let mut exponent = match count_arg("pow", "exponent", &args, 1) {
+        Ok(exponent) => exponent,
+        Err(e) => return e,
+    };
+    // square-and-multiply; base is only squared when a later bit needs it
+    let mut result: i64 = 1;
+    while exponent > 0 {
+        if exponent & 1 == 1 {
+            match result.checked_mul(base) {
+                Some(product) => result = product,
+                None => return overflow("pow"),
+            }
+        }
+        exponent >>= 1;
+        if exponent > 0 {
+            match base.checked_mul(base) {
+                Some(square) => base = square,
+                None => return overflow("pow"),
+            }
+        }
+    }
+    Rc::new(Object::Integer(result))
+}
+
+/// Calls `function` for the element at `index` of a higher-order builtin's
+/// array.
+fn call_on(
+    engine: &mut dyn Engine,
+    builtin: &'static str,
+    function: &Rc<Object>,
+    args: Vec<Rc<Object>>,
+    index: usize,
+) -> Result<Rc<Object>, Halt> {
+    engine
+        .call(function, args)
+        .ok_or(Halt::CallFailed { builtin, index })
+}
+
+/// `map(arr, f)` is a new array of `f` applied to each element.
+pub fn map(args: Vec<Rc<Object>>, engine: &mut dyn Engine) -> Result<Rc<Object>, Halt> {
+    if args.len() != 2 {
+        return Ok(wrong_arity("map", 2, args.len()));
+    }
+    let elements = match &*args[0] {
+        Object::Array(elements) => elements,
+        o => return Ok(type_error("map", 1, "ARRAY", o)),
+    };
+    if let Err(e) = function_arg("map", &args, 1) {
+        return Ok(e);
+    }
+    let mut mapped = Vec::with_capacity(elements.len());
+    for (index, element) in elements.iter().enumerate() {
+        let result = call_on(engine, "map", &args[1], vec![Rc::clone(element)], index)?;
+        // an error object from a builtin callback ends the call, as it
+        // would if the builtin had been called directly
+        if let Object::Error(_) = &*result {
+            return Ok(result);
+        }
+        mapped.push(result);
+    }
+    Ok(Rc::new(Object::Array(mapped)))
+}
+
+/// `filter(arr, f)` is a new array of the elements `f` returns a truthy
+/// value for: anything but `false` and `null`.
+pub fn filter(args: Vec<Rc<Object>>, engine: &mut dyn Engine) -> Result<Rc<Object>, Halt> {
+    if args.len() != 2 {
+        return Ok(wrong_arity("filter", 2, args.len()));
+    }
+    let elements = match &*args[0] {
+        Object::Array(elements) => elements,
+        o => return Ok(type_error("filter", 1, "ARRAY", o)),
+    };
+    if let Err(e) = function_arg("filter", &args, 1) {
+        return Ok(e);
+    }
+    let mut kept = Vec::new();
+    for (index, element) in elements.iter().enumerate() {
+        let result = call_on(engine, "filter", &args[1], vec![Rc::clone(element)], index)?;
+        match &*result {
+            Object::Error(_) => return Ok(result),
+            Object::Null | Object::Boolean(false) => {}
+            _ => kept.push(Rc::clone(element)),
+        }
+    }
+    Ok(Rc::new(Object::Array(kept)))
+}
+
+/// `reduce(arr, init, f)` folds the array from the left, calling
+/// `f(accumulator, element)` and starting from `init`.
+pub fn reduce(args: Vec<Rc<Object>>, engine: &mut dyn Engine) -> Result<Rc<Object>, Halt> {
+    if args.len() != 3 {
+        return Ok(wrong_arity("reduce", 3, args.len()));
+    }
+    let elements = match &*args[0] {
+        Object::Array(elements) => elements,
+        o => return Ok(type_error("reduce", 1, "ARRAY", o)),
+    };
+    if let Err(e) = function_arg("reduce", &args, 2) {
+        return Ok(e);
+    }
+    let mut accumulator = Rc::clone(&args[1]);
+    for (index, element) in elements.iter().enumerate() {
+        let call_args = vec![accumulator, Rc::clone(element)];
+        accumulator = call_on(engine, "reduce", &args[2], call_args, index)?;
+        if let Object::Error(_) = &*accumulator {
+            return Ok(accumulator);
+        }
+    }
+    Ok(accumulator)
+}
+
+/// `assert(cond)` and `assert(cond, message)` stop the program when `cond`
+/// is `false` or `null`, and return `null` otherwise.
+pub fn assert(args: Vec<Rc<Object>>, _: &mut dyn Engine) -> Result<Rc<Object>, Halt> {
+    if args.is_empty() || args.len() > 2 {
+        return Ok(wrong_arity_either("assert", 1, args.len()));
+    }
+    let message = match args.get(1) {
+        Some(_) => match string_arg("assert", &args, 1) {
+            Ok(message) => message.to_string(),
+            Err(e) => return Ok(e),
+        },
+        None => String::new(),
+    };
+    match &*args[0] {
+        Object::Null | Object::Boolean(false) => Err(Halt::AssertionFailed(message)),
+        _ => Ok(null()),
+    }
+}
+
+/// Milliseconds since the UNIX epoch.
+pub fn clock(args: Vec<Rc<Object>>) -> Rc<Object> {
+    if !args.is_empty() {
+        return wrong_arity("clock", 0, args.len());
+    }
+    let millis = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_millis() as i64);
+    Rc::new(Object::Integer(millis))
+}
+
+/// `rand(n)` is a uniformly drawn integer in `[0, n)`. Until the script
+/// calls `seed`, the sequence starts from the clock.
+pub fn rand(args: Vec<Rc<Object>>, engine: &mut dyn Engine) -> Result<Rc<Object>, Halt> {
+    if args.len() != 1 {
+        return Ok(wrong_arity("rand", 1, args.len()));
+    }
+    let n = match integer_arg("rand", &args, 0) {
+        Ok(n) if n > 0 => n as u64,
+        Ok(n) => {
+            return Ok(Rc::new(Object::Error(format!(
+                "builtin rand bound must be positive, got {}",
+                n
+            ))));
+        }
+        Err(e) => return Ok(e),
+    };
+    // draws past the last whole multiple of n are retried so that every
+    // result is equally likely
+    let limit = u64::MAX - u64::MAX % n;
+    loop {
+        let draw = engine.next_random();
+        if draw < limit {
+            return Ok(Rc::new(Object::Integer((draw % n) as i64)));
+        }
+    }
+}
+
+/// `seed(x)` restarts `rand`'s sequence, which is the same for the same
+/// `x` on both the VM and the interpreter.
+pub fn seed(args: Vec<Rc<Object>>, engine: &mut dyn Engine) -> Result<Rc<Object>, Halt> {
+    if args.len() != 1 {
+        return Ok(wrong_arity("seed", 1, args.len()));
+    }
+    match integer_arg("seed", &args, 0) {
+        Ok(seed) => {
+            engine.seed_random(seed as u64);
+            Ok(null())
+        }
+        Err(e) => Ok(e),
+    }
+}
+
+fn io_disabled(name: &str) -> Rc<Object> {
+    Rc::new(Object::Error(format!(
+        "builtin {} is unavailable: file access disabled",
+        name
+    )))
+}
+
+/// The contents of the file at `path`, which must be UTF-8. Needs the host
+/// to have enabled file access.
+pub fn read_file(args: Vec<Rc<Object>>, engine: &mut dyn Engine) -> Result<Rc<Object>, Halt> {
+    if args.len() != 1 {
+        return Ok(wrong_arity("read_file", 1, args.len()));
+    }
+    if !engine.io_enabled() {
+        return Ok(io_disabled("read_file"));
+    }
+    let path = match string_arg("read_file", &args, 0) {
+        Ok(path) => path,
+        Err(e) => return Ok(e),
+    };
+    Ok(Rc::new(match fs::read_to_string(path) {
+        Ok(contents) => Object::String(contents.into()),
+        Err(e) => Object::Error(format!("builtin read_file cannot read {}: {}", path, e)),
+    }))
+}
+
+/// Writes a string or bytes to the file at `path`, replacing what was
+/// there, and returns the number of bytes written. Needs the host to have
+/// enabled file access.
+pub fn write_file(args: Vec<Rc<Object>>, engine: &mut dyn Engine) -> Result<Rc<Object>, Halt> {
+    if args.len() != 2 {
+        return Ok(wrong_arity("write_file", 2, args.len()));
+    }
+    if !engine.io_enabled() {
+        return Ok(io_disabled("write_file"));
+    }
+    let path = match string_arg("write_file", &args, 0) {
+        Ok(path) => path,
+        Err(e) => return Ok(e),
+    };
+    let contents = match &*args[1] {
+        Object::String(s) => s.as_bytes(),
+        Object::Bytes(b) => b,
+        o => return Ok(type_error("write_file", 2, "STRING or BYTES", o)),
+    };
+    Ok(Rc::new(match fs::write(path, contents) {
+        Ok(()) => Object::Integer(contents.len() as i64),
+        Err(e) => Object::Error(format!("builtin write_file cannot write {}: {}", path, e)),
+    }))
+}
+
+/// `sort(arr)` is a new array of the integers or strings in `arr` in
+/// ascending order. `sort(arr, f)` orders any elements by `f(a, b)`, which
+/// returns a negative integer when `a` comes first, a positive one when
+/// `b` does and 0 when either may. Both sorts are stable.
+pub fn sort(args: Vec<Rc<Object>>, engine: &mut dyn Engine) -> Result<Rc<Object>, Halt> {
+    if args.len() != 1 && args.len() != 2 {
+        return Ok(wrong_arity_either("sort", 1, args.len()));
+    }
+    let elements = match &*args[0] {
+        Object::Array(elements) => elements,
+        o => return Ok(type_error("sort", 1, "ARRAY", o)),
+    };
+    if args.len() == 1 {
+        return Ok(sort_naturally(elements));
+    }
+    if let Err(e) = function_arg("sort", &args, 1) {
+        return Ok(e);
+    }
+
+    // Sorting indices lets a failure name the element being compared.
+    let order = merge_sort((0..elements.len()).collect(), |&a, &b| {
+        let call_args = vec![Rc::clone(&elements[a]), Rc::clone(&elements[b])];
+        let result = call_on(engine, "sort", &args[1], call_args, a).map_err(Err)?;
+        match &*result {
+            Object::Integer(i) => Ok(*i < 0),
+            Object::Error(_) => Err(Ok(result)),
+            o => Err(Ok(Rc::new(Object::Error(format!(
+                "builtin sort comparator must return INTEGER, got {}",
+                o.type_name()
+            ))))),
+        }
+    });
+    match order {
+        Ok(order) => Ok(Rc::new(Object::Array(
+            order.into_iter().map(|i| Rc::clone(&elements[i])).collect(),
         ))),
+        Err(stopped) => stopped,
+    }
+}
+
+// A stable bottom-up merge sort that gives up at the first failed
+// comparison. Unlike `slice::sort_by` it never panics when `less` is not a
+// consistent order, which a comparator written in Monkey need not be.
+fn merge_sort<T: Copy, E>(
+    mut items: Vec<T>,
+    mut less: impl FnMut(&T, &T) -> Result<bool, E>,
+) -> Result<Vec<T>, E> {
+    let n = items.len();
+    let mut merged = Vec::with_capacity(n);
+    let mut width = 1;
+    while width < n {
+        merged.clear();
+        for start in (0..n).step_by(2 * width) {
+            let middle = (start + width).min(n);
+            let end = (start + 2 * width).min(n);
+            let (mut left, mut right) = (start, middle);
+            while left < middle && right < end {
+                // the right run only goes first when strictly less, so
+                // equal elements keep their order
+                if less(&items[right], &items[left])? {
+                    merged.push(items[right]);
+                    right += 1;
+                } else {
+                    merged.push(items[left]);
+                    left += 1;
+                }
+            }
+            merged.extend_from_slice(&items[left..middle]);
+            merged.extend_from_slice(&items[right..end]);
+        }
+        std::mem::swap(&mut items, &mut merged);
+        width *= 2;
+    }
+    Ok(items)
+}
+
+fn sort_naturally(elements: &[Rc<Object>]) -> Rc<Object> {
+    let mut sorted = elements.to_vec();
+    let Some(first) = elements.first() else {
+        return Rc::new(Object::Array(sorted));
+    };
+    if !matches!(&**first, Object::Integer(_) | Object::String(_)) {
+        return Rc::new(Object::Error(format!(
+            "builtin sort cannot order {} elements without a comparator",
+            first.type_name()
+        )));
+    }
+    if let Some(other) = elements
+        .iter()
+        .find(|element| std::mem::discriminant(&***element) != std::mem::discriminant(&**first))
+    {
+        return Rc::new(Object::Error(format!(
+            "builtin sort cannot compare {} with {}",
+            first.type_name(),
+            other.type_name()
+        )));
+    }
+    sorted.sort_by(|a, b| match (&**a, &**b) {
+        (Object::Integer(a), Object::Integer(b)) => a.cmp(b),
+        (Object::String(a), Object::String(b)) => a.cmp(b),
+        _ => unreachable!("elements were checked to share a sortable type"),
+    });
+    Rc::new(Object::Array(sorted))
+}
+
+/// An array's elements, or a string's chars, in reverse order.
+pub fn reverse(args: Vec<Rc<Object>>) -> Rc<Object> {
+    if args.len() != 1 {
+        return wrong_arity("reverse", 1, args.len());
+    }
+    match &*args[0] {
+        Object::Array(elements) => Rc::new(Object::Array(elements.iter().rev().cloned().collect())),
+        Object::String(s) => Rc::new(Object::String(s.chars().rev().collect::<String>().into())),
+        o => type_error("reverse", 1, "ARRAY or STRING", o),
+    }
+}
+
+/// `slice(x, start, end)` is the part of an array, or of a string counted
+/// in chars, from `start` up to but not including `end`. Negative bounds
+/// count from the end and bounds past either end are clamped, so the
+/// result is empty rather than an error when nothing is in range.
+pub fn slice(args: Vec<Rc<Object>>) -> Rc<Object> {
+    if args.len() != 3 {
+        return wrong_arity("slice", 3, args.len());
+    }
+    let mut bounds = [0; 2];
+    for (index, bound) in bounds.iter_mut().enumerate() {
+        match integer_arg("slice", &args, index + 1) {
+            Ok(i) => *bound = i,
+            Err(e) => return e,
+        }
+    }
+    let range = |len: usize| {
+        let clamp = |bound: i64| {
+            let bound = if bound < 0 {
+                (len as i64).saturating_add(bound)
+            } else {
+                bound
+            };
+            bound.clamp(0, len as i64) as usize
+        };
+        let (start, end) = (clamp(bounds[0]), clamp(bounds[1]));
+        start..end.max(start)
+    };
+    match &*args[0] {
+        Object::Array(elements) => Rc::new(Object::Array(elements[range(elements.len())].to_vec())),
+        Object::String(s) => {
+            let range = range(s.chars().count());
+            Rc::new(Object::String(
+                s.chars()
+                    .skip(range.start)
+                    .take(range.len())
+                    .collect::<String>()
+                    .into(),
+            ))
+        }
+        o => type_error("slice", 1, "ARRAY or STRING", o),
     }
 }
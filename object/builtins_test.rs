@@ -0,0 +1,99 @@
+#[cfg(test)]
+mod tests {
+    use crate::builtins::{MAX_CLONE_DEPTH, clone, deep_equal};
+    use crate::ordered_map::OrderedMap;
+    use crate::{HashKey, Object};
+    use std::rc::Rc;
+
+    fn int(n: i64) -> Rc<Object> {
+        Rc::new(Object::Integer(n))
+    }
+
+    fn array(elements: Vec<Rc<Object>>) -> Rc<Object> {
+        Rc::new(Object::Array(elements))
+    }
+
+    fn nested(depth: usize) -> Rc<Object> {
+        (0..depth).fold(array(vec![]), |inner, _| array(vec![inner]))
+    }
+
+    #[test]
+    fn test_clone_copies_arrays_and_hashes() {
+        let inner = array(vec![int(1), int(2)]);
+        let hash: OrderedMap<HashKey, Rc<Object>> =
+            [(HashKey::String("xs".into()), Rc::clone(&inner))]
+                .into_iter()
+                .collect();
+        let original = array(vec![Rc::clone(&inner), Rc::new(Object::Hash(hash)), int(3)]);
+
+        let copy = clone(vec![Rc::clone(&original)]);
+        assert_eq!(copy, original);
+        assert!(!Rc::ptr_eq(&copy, &original));
+        let (Object::Array(copied), Object::Array(elements)) = (&*copy, &*original) else {
+            panic!("clone returned {}", copy);
+        };
+        assert!(!Rc::ptr_eq(&copied[0], &inner));
+        let Object::Hash(copied_hash) = &*copied[1] else {
+            panic!("clone returned {}", copy);
+        };
+        let copied_inner = copied_hash.get(&HashKey::String("xs".into())).unwrap();
+        assert!(!Rc::ptr_eq(copied_inner, &inner));
+        // scalars are shared
+        assert!(Rc::ptr_eq(&copied[2], &elements[2]));
+    }
+
+    #[test]
+    fn test_clone_passes_scalars_through() {
+        let s = Rc::new(Object::String("monkey".into()));
+        assert!(Rc::ptr_eq(&clone(vec![Rc::clone(&s)]), &s));
+    }
+
+    #[test]
+    fn test_clone_depth_limit() {
+        assert_eq!(
+            clone(vec![nested(MAX_CLONE_DEPTH - 1)]),
+            nested(MAX_CLONE_DEPTH - 1)
+        );
+        assert_eq!(
+            *clone(vec![nested(MAX_CLONE_DEPTH)]),
+            Object::Error(format!(
+                "builtin clone nesting exceeds the limit of {}",
+                MAX_CLONE_DEPTH
+            ))
+        );
+    }
+
+    #[test]
+    fn test_deep_equal_on_deep_nesting() {
+        let depth = 100_000;
+        let (a, b, shallower) = (nested(depth), nested(depth), nested(depth - 1));
+        assert_eq!(
+            *deep_equal(vec![Rc::clone(&a), Rc::clone(&b)]),
+            Object::Boolean(true)
+        );
+        assert_eq!(
+            *deep_equal(vec![Rc::clone(&a), Rc::clone(&shallower)]),
+            Object::Boolean(false)
+        );
+        for value in [a, b, shallower] {
+            unnest(value);
+        }
+    }
+
+    // Dropping a deeply nested array recursively would overflow the stack.
+    fn unnest(mut value: Rc<Object>) {
+        while let Ok(Object::Array(mut elements)) = Rc::try_unwrap(value) {
+            match elements.pop() {
+                Some(inner) => value = inner,
+                None => break,
+            }
+        }
+    }
+
+    #[test]
+    fn test_bytes_display_escapes_non_ascii() {
+        let bytes = Object::Bytes(vec![b'h', 0xc3, 0xa9, b'"', b'\n']);
+        assert_eq!(bytes.to_string(), r#"b"h\xc3\xa9\"\n""#);
+        assert_eq!(Object::Bytes(vec![]).to_string(), r#"b"""#);
+    }
+}
@@ -0,0 +1,206 @@
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::fmt;
+use std::rc::Rc;
+
+use serde::ser::{Error as _, Serialize, SerializeMap, SerializeSeq, Serializer};
+
+use crate::ordered_map::OrderedMap;
+use crate::{HashKey, Object};
+
+/// Why an `Object` could not become the Rust type asked for.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum ConversionError {
+    WrongType {
+        expected: &'static str,
+        got: &'static str,
+    },
+    /// A hash converted to a `HashMap<String, _>` had a key that is not a
+    /// string.
+    NonStringKey(HashKey),
+}
+
+impl fmt::Display for ConversionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConversionError::WrongType { expected, got } => {
+                write!(f, "expected {}, got {}", expected, got)
+            }
+            ConversionError::NonStringKey(key) => {
+                write!(f, "expected STRING hash keys, got the key {}", key)
+            }
+        }
+    }
+}
+
+impl From<i64> for Object {
+    fn from(value: i64) -> Self {
+        Object::Integer(value)
+    }
+}
+
+impl From<f64> for Object {
+    fn from(value: f64) -> Self {
+        Object::Float(value)
+    }
+}
+
+impl From<bool> for Object {
+    fn from(value: bool) -> Self {
+        Object::Boolean(value)
+    }
+}
+
+impl From<String> for Object {
+    fn from(value: String) -> Self {
+        Object::String(value.into())
+    }
+}
+
+impl From<&str> for Object {
+    fn from(value: &str) -> Self {
+        Object::String(Rc::from(value))
+    }
+}
+
+impl From<Vec<Object>> for Object {
+    fn from(values: Vec<Object>) -> Self {
+        Object::Array(values.into_iter().map(Rc::new).collect())
+    }
+}
+
+/// The entries are inserted sorted by key, so the hash iterates the same way
+/// every time.
+impl From<HashMap<String, Object>> for Object {
+    fn from(values: HashMap<String, Object>) -> Self {
+        let mut entries: Vec<_> = values.into_iter().collect();
+        entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+        Object::Hash(
+            entries
+                .into_iter()
+                .map(|(key, value)| (HashKey::String(key.into()), Rc::new(value)))
+                .collect::<OrderedMap<_, _>>(),
+        )
+    }
+}
+
+fn wrong_type(expected: &'static str, obj: &Object) -> ConversionError {
+    ConversionError::WrongType {
+        expected,
+        got: obj.type_name(),
+    }
+}
+
+impl TryFrom<&Object> for i64 {
+    type Error = ConversionError;
+
+    fn try_from(obj: &Object) -> Result<Self, Self::Error> {
+        match obj {
+            Object::Integer(i) => Ok(*i),
+            other => Err(wrong_type("INTEGER", other)),
+        }
+    }
+}
+
+/// Integers convert too, as they do in arithmetic with a float.
+impl TryFrom<&Object> for f64 {
+    type Error = ConversionError;
+
+    fn try_from(obj: &Object) -> Result<Self, Self::Error> {
+        match obj {
+            Object::Float(x) => Ok(*x),
+            Object::Integer(i) => Ok(*i as f64),
+            other => Err(wrong_type("FLOAT", other)),
+        }
+    }
+}
+
+impl TryFrom<&Object> for bool {
+    type Error = ConversionError;
+
+    fn try_from(obj: &Object) -> Result<Self, Self::Error> {
+        match obj {
+            Object::Boolean(b) => Ok(*b),
+            other => Err(wrong_type("BOOLEAN", other)),
+        }
+    }
+}
+
+impl TryFrom<&Object> for String {
+    type Error = ConversionError;
+
+    fn try_from(obj: &Object) -> Result<Self, Self::Error> {
+        match obj {
+            Object::String(s) => Ok(s.to_string()),
+            other => Err(wrong_type("STRING", other)),
+        }
+    }
+}
+
+impl TryFrom<&Object> for Vec<Object> {
+    type Error = ConversionError;
+
+    fn try_from(obj: &Object) -> Result<Self, Self::Error> {
+        match obj {
+            Object::Array(elements) => Ok(elements.iter().map(|e| (**e).clone()).collect()),
+            other => Err(wrong_type("ARRAY", other)),
+        }
+    }
+}
+
+impl TryFrom<&Object> for HashMap<String, Object> {
+    type Error = ConversionError;
+
+    fn try_from(obj: &Object) -> Result<Self, Self::Error> {
+        let Object::Hash(map) = obj else {
+            return Err(wrong_type("HASH", obj));
+        };
+        map.iter()
+            .map(|(key, value)| match key {
+                HashKey::String(key) => Ok((key.to_string(), (**value).clone())),
+                other => Err(ConversionError::NonStringKey(other.clone())),
+            })
+            .collect()
+    }
+}
+
+/// Data serializes as the matching JSON value: bytes as an array of
+/// numbers and hashes as objects, whose keys are always strings. Functions,
+/// builtins, quotes and macros have no data form and fail to serialize.
+impl Serialize for Object {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Object::Integer(i) => serializer.serialize_i64(*i),
+            Object::Float(x) => serializer.serialize_f64(*x),
+            Object::Boolean(b) => serializer.serialize_bool(*b),
+            Object::String(s) => serializer.serialize_str(s),
+            Object::Bytes(bytes) => {
+                let mut seq = serializer.serialize_seq(Some(bytes.len()))?;
+                for byte in bytes {
+                    seq.serialize_element(byte)?;
+                }
+                seq.end()
+            }
+            Object::Array(elements) => {
+                let mut seq = serializer.serialize_seq(Some(elements.len()))?;
+                for element in elements {
+                    seq.serialize_element(&**element)?;
+                }
+                seq.end()
+            }
+            Object::Hash(map) => {
+                let mut entries = serializer.serialize_map(Some(map.len()))?;
+                for (key, value) in map.iter() {
+                    entries.serialize_entry(&key.to_string(), &**value)?;
+                }
+                entries.end()
+            }
+            Object::Null => serializer.serialize_unit(),
+            Object::ReturnValue(value) => value.serialize(serializer),
+            other => Err(S::Error::custom(format!(
+                "cannot serialize a {}",
+                other.type_name()
+            ))),
+        }
+    }
+}
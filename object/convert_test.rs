@@ -0,0 +1,103 @@
+#[cfg(test)]
+mod tests {
+    use crate::convert::ConversionError;
+    use crate::ordered_map::OrderedMap;
+    use crate::{HashKey, Object};
+    use std::collections::HashMap;
+    use std::convert::TryFrom;
+    use std::rc::Rc;
+
+    fn nested() -> Object {
+        let inner = HashMap::from([
+            (
+                "xs".to_string(),
+                Object::from(vec![Object::from(1), Object::from(true)]),
+            ),
+            ("name".to_string(), Object::from("monkey")),
+        ]);
+        Object::from(vec![
+            Object::from(inner),
+            Object::from(vec![Object::from(vec![]), Object::Null]),
+            Object::from(2.5),
+        ])
+    }
+
+    #[test]
+    fn test_scalars_round_trip() {
+        assert_eq!(i64::try_from(&Object::from(-7)), Ok(-7));
+        assert_eq!(bool::try_from(&Object::from(true)), Ok(true));
+        assert_eq!(
+            String::try_from(&Object::from("hi".to_string())),
+            Ok("hi".to_string())
+        );
+        assert_eq!(f64::try_from(&Object::from(1.5)), Ok(1.5));
+        assert_eq!(f64::try_from(&Object::from(2)), Ok(2.0));
+    }
+
+    #[test]
+    fn test_nested_arrays_and_hashes_round_trip() {
+        let value = nested();
+        let elements = Vec::<Object>::try_from(&value).unwrap();
+        assert_eq!(elements.len(), 3);
+        let inner = HashMap::<String, Object>::try_from(&elements[0]).unwrap();
+        assert_eq!(inner["name"], Object::from("monkey"));
+        let xs = Vec::<Object>::try_from(&inner["xs"]).unwrap();
+        assert_eq!(xs, [Object::Integer(1), Object::Boolean(true)]);
+        assert_eq!(Object::from(inner), elements[0]);
+        assert_eq!(Object::from(elements), value);
+    }
+
+    #[test]
+    fn test_hashes_from_rust_iterate_by_key() {
+        let hash = Object::from(HashMap::from([
+            ("b".to_string(), Object::from(2)),
+            ("a".to_string(), Object::from(1)),
+        ]));
+        assert_eq!(hash.to_string(), "[a: 1, b: 2]");
+    }
+
+    #[test]
+    fn test_conversion_failures() {
+        let err = i64::try_from(&Object::from("1")).unwrap_err();
+        assert_eq!(
+            err,
+            ConversionError::WrongType {
+                expected: "INTEGER",
+                got: "STRING"
+            }
+        );
+        assert_eq!(err.to_string(), "expected INTEGER, got STRING");
+        assert!(bool::try_from(&Object::Null).is_err());
+        assert!(String::try_from(&Object::from(1)).is_err());
+        assert!(Vec::<Object>::try_from(&Object::from("abc")).is_err());
+
+        let hash: OrderedMap<HashKey, Rc<Object>> = [(HashKey::Integer(1), Rc::new(Object::Null))]
+            .into_iter()
+            .collect();
+        let err = HashMap::<String, Object>::try_from(&Object::Hash(hash)).unwrap_err();
+        assert_eq!(err, ConversionError::NonStringKey(HashKey::Integer(1)));
+        assert_eq!(err.to_string(), "expected STRING hash keys, got the key 1");
+    }
+
+    #[test]
+    fn test_serialize_as_json() {
+        assert_eq!(
+            serde_json::to_string(&nested()).unwrap(),
+            r#"[{"name":"monkey","xs":[1,true]},[[],null],2.5]"#
+        );
+
+        let hash: OrderedMap<HashKey, Rc<Object>> = [
+            (HashKey::Integer(1), Rc::new(Object::Bytes(vec![0, 255]))),
+            (HashKey::Boolean(true), Rc::new(Object::from("yes"))),
+        ]
+        .into_iter()
+        .collect();
+        assert_eq!(
+            serde_json::to_string(&Object::Hash(hash)).unwrap(),
+            r#"{"1":[0,255],"true":"yes"}"#
+        );
+
+        let err = serde_json::to_string(&Object::Error("boom".to_string())).unwrap_err();
+        assert_eq!(err.to_string(), "cannot serialize a ERROR");
+    }
+}
@@ -9,6 +9,9 @@ pub type Env = Rc<RefCell<Environment>>;
 pub struct Environment {
     store: HashMap<String, Rc<Object>>,
     outer: Option<Env>,
+    /// The environment of an `if`, `while` or `for` body rather than of a
+    /// function call.
+    block: bool,
 }
 
 impl Environment {
@@ -19,6 +22,15 @@ impl Environment {
         }
     }
 
+    /// An environment for a block, whose `let`s stay inside it.
+    pub fn new_block_environment(outer: &Env) -> Self {
+        Environment {
+            outer: Some(Rc::clone(outer)),
+            block: true,
+            ..Default::default()
+        }
+    }
+
     pub fn get(&self, name: &str) -> Option<Rc<Object>> {
         match self.store.get(name) {
             Some(obj) => Some(Rc::clone(obj)),
@@ -35,4 +47,51 @@ impl Environment {
     pub fn set(&mut self, name: String, val: Rc<Object>) {
         self.store.insert(name, val);
     }
+
+    /// Rebinds `name` in the environment that defined it, which must belong
+    /// to the current function or to the top level. Like compiled code, a
+    /// function cannot assign to the variables of an enclosing function.
+    pub fn assign(&mut self, name: &str, val: Rc<Object>) -> Result<(), AssignError> {
+        if let Some(slot) = self.store.get_mut(name) {
+            *slot = val;
+            return Ok(());
+        }
+        let mut in_function = !self.block;
+        let mut env = match &self.outer {
+            Some(outer) => Rc::clone(outer),
+            None => return Err(AssignError::Undefined),
+        };
+        loop {
+            if env.borrow().store.contains_key(name) {
+                if in_function && !env.borrow().is_top_level() {
+                    return Err(AssignError::Captured);
+                }
+                env.borrow_mut().store.insert(name.to_string(), val);
+                return Ok(());
+            }
+            in_function |= !env.borrow().block;
+            let outer = env.borrow().outer.clone();
+            match outer {
+                Some(outer) => env = outer,
+                None => return Err(AssignError::Undefined),
+            }
+        }
+    }
+
+    // The global environment, or a block nested only in blocks at the top
+    // level; compiled code keeps the variables of both in global slots.
+    fn is_top_level(&self) -> bool {
+        match &self.outer {
+            None => true,
+            Some(outer) => self.block && outer.borrow().is_top_level(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum AssignError {
+    Undefined,
+    /// Defined by an enclosing function rather than the current one or the
+    /// global scope.
+    Captured,
 }
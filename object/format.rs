@@ -0,0 +1,78 @@
+use crate::Object;
+
+/// How much of a value `format_limited` prints before eliding the rest.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Limits {
+    /// Elements shown per array or hash.
+    pub items: usize,
+    /// Arrays and hashes nested deeper than this print as `[...]`.
+    pub depth: usize,
+    /// Characters shown per string.
+    pub string_chars: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits {
+            items: 100,
+            depth: 8,
+            string_chars: 1000,
+        }
+    }
+}
+
+/// Like `Display`, but cuts large values down to size for an interactive
+/// result: long arrays and hashes end in `... (N more)`, deep nesting turns
+/// into `[...]` and long strings are truncated with their full length noted.
+pub fn format_limited(obj: &Object, limits: &Limits) -> String {
+    let mut output = String::new();
+    write_limited(&mut output, obj, limits, 0);
+    output
+}
+
+fn write_limited(output: &mut String, obj: &Object, limits: &Limits, depth: usize) {
+    match obj {
+        Object::String(s) => match s.char_indices().nth(limits.string_chars) {
+            Some((end, _)) => {
+                output.push_str(&s[..end]);
+                output.push_str(&format!("... ({} chars)", s.chars().count()));
+            }
+            None => output.push_str(s),
+        },
+        Object::ReturnValue(value) => write_limited(output, value, limits, depth),
+        Object::Array(_) | Object::Hash(_) if depth >= limits.depth => output.push_str("[...]"),
+        Object::Array(elements) => {
+            output.push('[');
+            for (index, element) in elements.iter().take(limits.items).enumerate() {
+                if index > 0 {
+                    output.push_str(", ");
+                }
+                write_limited(output, element, limits, depth + 1);
+            }
+            write_more(output, elements.len(), limits.items);
+            output.push(']');
+        }
+        Object::Hash(map) => {
+            output.push('[');
+            for (index, (key, value)) in map.iter().take(limits.items).enumerate() {
+                if index > 0 {
+                    output.push_str(", ");
+                }
+                output.push_str(&format!("{}: ", key));
+                write_limited(output, value, limits, depth + 1);
+            }
+            write_more(output, map.len(), limits.items);
+            output.push(']');
+        }
+        other => output.push_str(&other.to_string()),
+    }
+}
+
+fn write_more(output: &mut String, len: usize, shown: usize) {
+    if len > shown {
+        if shown > 0 {
+            output.push_str(", ");
+        }
+        output.push_str(&format!("... ({} more)", len - shown));
+    }
+}
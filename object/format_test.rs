@@ -0,0 +1,106 @@
+#[cfg(test)]
+mod tests {
+    use crate::format::{Limits, format_limited};
+    use crate::ordered_map::OrderedMap;
+    use crate::{HashKey, Object};
+    use std::rc::Rc;
+
+    fn int(n: i64) -> Rc<Object> {
+        Rc::new(Object::Integer(n))
+    }
+
+    fn range(n: i64) -> Object {
+        Object::Array((0..n).map(int).collect())
+    }
+
+    fn limits(items: usize, depth: usize, string_chars: usize) -> Limits {
+        Limits {
+            items,
+            depth,
+            string_chars,
+        }
+    }
+
+    #[test]
+    fn test_small_values_print_like_display() {
+        let hash: OrderedMap<HashKey, Rc<Object>> = [
+            (HashKey::String("a".into()), int(1)),
+            (HashKey::Integer(2), Rc::new(range(2))),
+        ]
+        .into_iter()
+        .collect();
+        let values = [
+            range(3),
+            Object::Hash(hash),
+            Object::String("hello".into()),
+            Object::Boolean(true),
+            Object::Null,
+        ];
+        for value in values {
+            assert_eq!(
+                format_limited(&value, &Limits::default()),
+                value.to_string()
+            );
+        }
+    }
+
+    #[test]
+    fn test_long_arrays_are_elided() {
+        assert_eq!(
+            format_limited(&range(100000), &limits(3, 8, 100)),
+            "[0, 1, 2, ... (99997 more)]"
+        );
+        assert_eq!(format_limited(&range(3), &limits(3, 8, 100)), "[0, 1, 2]");
+        assert_eq!(
+            format_limited(&range(2), &limits(0, 8, 100)),
+            "[... (2 more)]"
+        );
+    }
+
+    #[test]
+    fn test_long_hashes_are_elided() {
+        let hash: OrderedMap<HashKey, Rc<Object>> =
+            (0..5).map(|n| (HashKey::Integer(n), int(n * 10))).collect();
+        assert_eq!(
+            format_limited(&Object::Hash(hash), &limits(2, 8, 100)),
+            "[0: 0, 1: 10, ... (3 more)]"
+        );
+    }
+
+    #[test]
+    fn test_deep_nesting_is_elided() {
+        let nested = (0..4).fold(range(1), |inner, _| Object::Array(vec![Rc::new(inner)]));
+        assert_eq!(format_limited(&nested, &limits(10, 2, 100)), "[[[...]]]");
+        assert_eq!(format_limited(&nested, &limits(10, 0, 100)), "[...]");
+        assert_eq!(format_limited(&nested, &limits(10, 5, 100)), "[[[[[0]]]]]");
+    }
+
+    #[test]
+    fn test_long_strings_are_truncated() {
+        let long = Object::String("ab".repeat(50).into());
+        assert_eq!(
+            format_limited(&long, &limits(10, 8, 5)),
+            "ababa... (100 chars)"
+        );
+
+        // the limit counts characters, not bytes
+        let unicode = Object::String("héllo wörld".into());
+        assert_eq!(
+            format_limited(&unicode, &limits(10, 8, 4)),
+            "héll... (11 chars)"
+        );
+        assert_eq!(format_limited(&unicode, &limits(10, 8, 11)), "héllo wörld");
+    }
+
+    #[test]
+    fn test_limits_apply_inside_collections() {
+        let value = Object::Array(vec![
+            Rc::new(Object::String("x".repeat(10).into())),
+            Rc::new(range(5)),
+        ]);
+        assert_eq!(
+            format_limited(&value, &limits(2, 8, 3)),
+            "[xxx... (10 chars), [0, 1, ... (3 more)]]"
+        );
+    }
+}
@@ -1,29 +1,92 @@
-use std::collections::HashMap;
 use std::convert::TryFrom;
 use std::fmt;
 use std::fmt::Formatter;
 use std::hash::{Hash, Hasher};
 use std::rc::Rc;
 
-use parser::ast::{BlockStatement, IDENTIFIER};
+use parser::ast::{BlockStatement, Expression, IDENTIFIER};
 use parser::lexer::token::TokenKind;
 
 #[macro_use]
 extern crate lazy_static;
 
 use crate::environment::Env;
+use crate::ordered_map::OrderedMap;
 
 pub mod builtins;
+#[cfg(test)]
+mod builtins_test;
+pub mod convert;
+#[cfg(test)]
+mod convert_test;
 pub mod environment;
+pub mod format;
+#[cfg(test)]
+mod format_test;
+pub mod ordered_map;
+#[cfg(test)]
+mod ordered_map_test;
+pub mod random;
+#[cfg(test)]
+mod random_test;
 
-pub type BuiltinFunc = fn(Vec<Rc<Object>>) -> Rc<Object>;
+pub type PlainBuiltin = fn(Vec<Rc<Object>>) -> Rc<Object>;
+pub type EngineBuiltin = fn(Vec<Rc<Object>>, &mut dyn Engine) -> Result<Rc<Object>, Halt>;
+
+/// A builtin's implementation.
+#[derive(Debug, Clone, Copy)]
+pub enum BuiltinFunc {
+    Plain(PlainBuiltin),
+    /// Needs the engine running the script, to call Monkey functions among
+    /// its arguments or to stop the program.
+    WithEngine(EngineBuiltin),
+}
+
+impl PartialEq for BuiltinFunc {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (BuiltinFunc::Plain(a), BuiltinFunc::Plain(b)) => std::ptr::fn_addr_eq(*a, *b),
+            (BuiltinFunc::WithEngine(a), BuiltinFunc::WithEngine(b)) => {
+                std::ptr::fn_addr_eq(*a, *b)
+            }
+            _ => false,
+        }
+    }
+}
+
+/// What a builtin can ask of the VM or interpreter running it.
+pub trait Engine {
+    /// The function's result, or `None` if the call failed. The engine
+    /// keeps the error and reports it once the builtin returns
+    /// [`Halt::CallFailed`].
+    fn call(&mut self, function: &Rc<Object>, args: Vec<Rc<Object>>) -> Option<Rc<Object>>;
+    /// Restarts the generator `rand` draws from.
+    fn seed_random(&mut self, seed: u64);
+    fn next_random(&mut self) -> u64;
+    /// Whether the host lets scripts read and write files.
+    fn io_enabled(&self) -> bool;
+    /// Whether the host has asked the script to stop.
+    fn cancelled(&self) -> bool;
+}
+
+/// Why a builtin stopped the program, rather than returning an error
+/// object for the script to see.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum Halt {
+    /// Calling the builtin's function argument failed while handling the
+    /// element at `index`.
+    CallFailed { builtin: &'static str, index: usize },
+    /// `assert` on a falsy condition. Empty when no message was given.
+    AssertionFailed(String),
+}
 
 /// Immutable key type for hash maps. Only hashable variants to satisfy clippy::mutable_key_type.
-#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+/// Keys order integers first, then booleans, then strings, each by value.
+#[derive(Debug, Clone, Eq, PartialEq, Hash, PartialOrd, Ord)]
 pub enum HashKey {
     Integer(i64),
     Boolean(bool),
-    String(String),
+    String(Rc<str>),
 }
 
 impl fmt::Display for HashKey {
@@ -36,6 +99,16 @@ impl fmt::Display for HashKey {
     }
 }
 
+impl From<&HashKey> for Object {
+    fn from(key: &HashKey) -> Self {
+        match key {
+            HashKey::Integer(i) => Object::Integer(*i),
+            HashKey::Boolean(b) => Object::Boolean(*b),
+            HashKey::String(s) => Object::String(s.clone()),
+        }
+    }
+}
+
 impl TryFrom<&Object> for HashKey {
     type Error = ();
 
@@ -59,17 +132,54 @@ pub enum EvalError {
     },
     NotFunction(String),
     UnknownIdentifier(String),
+    AssignToCaptured(String),
     UnknownPrefixOperator(TokenKind),
     CannotApplyPrefixMinus(String),
     InfixTypeMismatch {
         op: TokenKind,
-        left: String,
-        right: String,
+        left: &'static str,
+        right: &'static str,
+    },
+    /// `+` with a string on only one side.
+    StringConcatenation {
+        left: &'static str,
+        right: &'static str,
     },
     InvalidIntegerOperator(TokenKind),
+    InvalidFloatOperator(TokenKind),
     InvalidBooleanOperator(TokenKind),
     InvalidStringOperator(TokenKind),
     KeyNotHashable(String),
+    IndexOutOfRange {
+        index: i64,
+        len: usize,
+    },
+    NotIterable(String),
+    DivisionByZero,
+    IntegerOverflow(TokenKind),
+    CannotUnquote(String),
+    /// Unwinds to the innermost enclosing loop, and is only reported when
+    /// there is none.
+    Break,
+    Continue,
+    /// A `break` or `continue` that would leave the function it appears in.
+    OutsideLoop(TokenKind),
+    /// `error`, from calling the function passed to a higher-order builtin
+    /// on the element at `index`.
+    CallbackFailed {
+        builtin: &'static str,
+        index: usize,
+        error: Box<EvalError>,
+    },
+    /// `assert` on a falsy condition. Empty when no message was given.
+    AssertionFailed(String),
+    /// A call would have nested more than `depth` calls deep, counting the
+    /// top level.
+    MaxFramesExceeded {
+        depth: usize,
+    },
+    /// A `let` for a name the same scope already defines.
+    DuplicateDefinition(String),
 }
 
 impl fmt::Display for EvalError {
@@ -90,6 +200,11 @@ impl fmt::Display for EvalError {
             EvalError::UnknownIdentifier(identifier) => {
                 write!(f, "unknown identifier {}", identifier)
             }
+            EvalError::AssignToCaptured(name) => write!(
+                f,
+                "cannot assign to {}, which belongs to an enclosing function",
+                name
+            ),
             EvalError::UnknownPrefixOperator(op) => {
                 write!(f, "unknown prefix operator: {}", op)
             }
@@ -103,9 +218,17 @@ impl fmt::Display for EvalError {
                     op, left, right
                 )
             }
+            EvalError::StringConcatenation { left, right } => write!(
+                f,
+                "cannot add {} and {}; convert the other operand with to_string first",
+                left, right
+            ),
             EvalError::InvalidIntegerOperator(op) => {
                 write!(f, "Invalid infix operator {} for int", op)
             }
+            EvalError::InvalidFloatOperator(op) => {
+                write!(f, "Invalid infix operator {} for float", op)
+            }
             EvalError::InvalidBooleanOperator(op) => {
                 write!(f, "Invalid infix operator for boolean: {}", op)
             }
@@ -113,32 +236,78 @@ impl fmt::Display for EvalError {
                 write!(f, "Invalid infix {} operator for string", op)
             }
             EvalError::KeyNotHashable(value) => write!(f, "key {} is not hashable", value),
+            EvalError::DivisionByZero => write!(f, "division by zero"),
+            EvalError::IntegerOverflow(op) => write!(f, "integer overflow in {}", op),
+            EvalError::CannotUnquote(value) => {
+                write!(f, "cannot convert {} back into an expression", value)
+            }
+            EvalError::IndexOutOfRange { index, len } => write!(
+                f,
+                "index {} out of range for array of length {}",
+                index, len
+            ),
+            EvalError::NotIterable(value) => write!(f, "cannot iterate over {}", value),
+            EvalError::Break => write!(f, "break outside a loop"),
+            EvalError::Continue => write!(f, "continue outside a loop"),
+            EvalError::OutsideLoop(keyword) => write!(f, "{} outside a loop", keyword),
+            EvalError::CallbackFailed {
+                builtin,
+                index,
+                error,
+            } => write!(
+                f,
+                "{} (calling the function passed to {} on element {})",
+                error, builtin, index
+            ),
+            EvalError::AssertionFailed(message) if message.is_empty() => {
+                write!(f, "assertion failed")
+            }
+            EvalError::AssertionFailed(message) => write!(f, "assertion failed: {}", message),
+            EvalError::MaxFramesExceeded { depth } => {
+                write!(f, "maximum call depth of {} frames exceeded", depth)
+            }
+            EvalError::DuplicateDefinition(name) => {
+                write!(f, "'{}' is already defined in this scope", name)
+            }
         }
     }
 }
 
-#[derive(Debug, Clone, Eq)]
+#[derive(Debug, Clone)]
 pub enum Object {
     Integer(i64),
+    Float(f64),
     Boolean(bool),
-    String(String),
+    String(Rc<str>),
+    Bytes(Vec<u8>),
     Array(Vec<Rc<Object>>),
-    Hash(HashMap<HashKey, Rc<Object>>),
+    Hash(OrderedMap<HashKey, Rc<Object>>),
     Null,
     ReturnValue(Rc<Object>),
     Function(Vec<IDENTIFIER>, BlockStatement, Env),
     Builtin(BuiltinFunc),
     Error(String),
+    /// `decode`'s error for bytes that aren't UTF-8. Unlike `Error`, a
+    /// script can read where it failed: `err.offset` is the first bad byte.
+    Utf8Error {
+        offset: usize,
+    },
     CompiledFunction(Rc<CompiledFunction>),
     ClosureObj(Closure),
+    Quote(Expression),
+    Macro(Vec<IDENTIFIER>, BlockStatement, Env),
 }
 
 impl PartialEq for Object {
     fn eq(&self, other: &Self) -> bool {
         match (self, other) {
             (Object::Integer(a), Object::Integer(b)) => a == b,
+            // bitwise, so `Object` can be `Eq`; the language's `==` on floats
+            // is IEEE and lives in the evaluators
+            (Object::Float(a), Object::Float(b)) => a.to_bits() == b.to_bits(),
             (Object::Boolean(a), Object::Boolean(b)) => a == b,
             (Object::String(a), Object::String(b)) => a == b,
+            (Object::Bytes(a), Object::Bytes(b)) => a == b,
             (Object::Array(a), Object::Array(b)) => a == b,
             (Object::Hash(a), Object::Hash(b)) => a == b,
             (Object::Null, Object::Null) => true,
@@ -146,21 +315,30 @@ impl PartialEq for Object {
             (Object::Function(ap, ab, ae), Object::Function(bp, bb, be)) => {
                 ap == bp && ab == bb && ae == be
             }
-            (Object::Builtin(a), Object::Builtin(b)) => std::ptr::fn_addr_eq(*a, *b),
+            (Object::Builtin(a), Object::Builtin(b)) => a == b,
             (Object::Error(a), Object::Error(b)) => a == b,
+            (Object::Utf8Error { offset: a }, Object::Utf8Error { offset: b }) => a == b,
             (Object::CompiledFunction(a), Object::CompiledFunction(b)) => a == b,
             (Object::ClosureObj(a), Object::ClosureObj(b)) => a == b,
+            (Object::Quote(a), Object::Quote(b)) => a == b,
+            (Object::Macro(ap, ab, ae), Object::Macro(bp, bb, be)) => {
+                ap == bp && ab == bb && ae == be
+            }
             _ => false,
         }
     }
 }
 
+impl Eq for Object {}
+
 impl fmt::Display for Object {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         match self {
             Object::Integer(i) => write!(f, "{}", i),
+            Object::Float(x) => write!(f, "{:?}", x),
             Object::Boolean(b) => write!(f, "{}", b),
             Object::String(s) => write!(f, "{}", s),
+            Object::Bytes(b) => write!(f, "b\"{}\"", b.escape_ascii()),
             Object::Null => write!(f, "null"),
             Object::ReturnValue(expr) => write!(f, "{}", expr),
             Object::Function(params, body, _env) => {
@@ -173,6 +351,9 @@ impl fmt::Display for Object {
             }
             Object::Builtin(_) => write!(f, "[builtin function]"),
             Object::Error(e) => write!(f, "{}", e),
+            Object::Utf8Error { offset } => {
+                write!(f, "builtin decode found invalid UTF-8 at byte {}", offset)
+            }
             Object::Array(e) => write!(
                 f,
                 "[{}]",
@@ -195,11 +376,42 @@ impl fmt::Display for Object {
             Object::ClosureObj(_) => {
                 write!(f, "[closure function]")
             }
+            Object::Quote(expr) => write!(f, "QUOTE({})", expr),
+            Object::Macro(params, body, _env) => {
+                let macro_params = params
+                    .iter()
+                    .map(|param| param.to_string())
+                    .collect::<Vec<String>>()
+                    .join(", ");
+                write!(f, "macro({}) {{ {} }}", macro_params, body)
+            }
         }
     }
 }
 
 impl Object {
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Object::Integer(_) => "INTEGER",
+            Object::Float(_) => "FLOAT",
+            Object::Boolean(_) => "BOOLEAN",
+            Object::String(_) => "STRING",
+            Object::Bytes(_) => "BYTES",
+            Object::Array(_) => "ARRAY",
+            Object::Hash(_) => "HASH",
+            Object::Null => "NULL",
+            Object::ReturnValue(_) => "RETURN_VALUE",
+            Object::Function(_, _, _) => "FUNCTION",
+            Object::Builtin(_) => "BUILTIN",
+            Object::Error(_) => "ERROR",
+            Object::Utf8Error { .. } => "UTF8_ERROR",
+            Object::CompiledFunction(_) => "COMPILED_FUNCTION",
+            Object::ClosureObj(_) => "CLOSURE",
+            Object::Quote(_) => "QUOTE",
+            Object::Macro(_, _, _) => "MACRO",
+        }
+    }
+
     pub fn is_hashable(&self) -> bool {
         matches!(
             self,
@@ -219,11 +431,95 @@ impl Hash for Object {
     }
 }
 
+thread_local! {
+    // `Rc` can't cross threads, so each thread gets its own copies.
+    static TRUE: Rc<Object> = Rc::new(Object::Boolean(true));
+    static FALSE: Rc<Object> = Rc::new(Object::Boolean(false));
+    static NULL: Rc<Object> = Rc::new(Object::Null);
+}
+
+/// The shared `true` or `false`, so handing out a boolean doesn't
+/// allocate.
+/// The language's `==` for everything but numbers, booleans and strings,
+/// which the engines compare themselves. Arrays and hashes are equal when
+/// their elements are, hashes regardless of insertion order, and a function
+/// is equal only to itself, however alike another one's code is. Works
+/// through a list rather than recursion, so deep nesting cannot overflow
+/// the stack.
+pub fn values_equal(left: &Rc<Object>, right: &Rc<Object>) -> bool {
+    let mut pending = vec![(Rc::clone(left), Rc::clone(right))];
+    while let Some((left, right)) = pending.pop() {
+        if Rc::ptr_eq(&left, &right) {
+            continue;
+        }
+        match (&*left, &*right) {
+            (Object::Array(l), Object::Array(r)) if l.len() == r.len() => {
+                pending.extend(l.iter().cloned().zip(r.iter().cloned()));
+            }
+            (Object::Hash(l), Object::Hash(r)) if l.len() == r.len() => {
+                for (key, value) in l.iter() {
+                    match r.get(key) {
+                        Some(other) => pending.push((Rc::clone(value), Rc::clone(other))),
+                        None => return false,
+                    }
+                }
+            }
+            (Object::Array(_) | Object::Hash(_), _) => return false,
+            // not the same allocation, so not the same function
+            (
+                Object::Function(..)
+                | Object::ClosureObj(_)
+                | Object::CompiledFunction(_)
+                | Object::Macro(..),
+                _,
+            ) => return false,
+            (l, r) if l != r => return false,
+            _ => {}
+        }
+    }
+    true
+}
+
+pub fn boolean(value: bool) -> Rc<Object> {
+    match value {
+        true => TRUE.with(Rc::clone),
+        false => FALSE.with(Rc::clone),
+    }
+}
+
+/// The shared `null`.
+pub fn null() -> Rc<Object> {
+    NULL.with(Rc::clone)
+}
+
+/// `err.offset` on a `Utf8Error`. Any other key reads as `null`, as a
+/// missing hash key does.
+pub fn utf8_error_field(offset: usize, key: &Object) -> Object {
+    match key {
+        Object::String(name) if &**name == "offset" => Object::Integer(offset as i64),
+        _ => Object::Null,
+    }
+}
+
+/// Integer division rounded toward negative infinity, so `-7 div 2` is `-4`.
+/// `/` and `%` truncate toward zero instead. Returns `None` when `right` is
+/// zero or the quotient overflows.
+pub fn checked_floor_div(left: i64, right: i64) -> Option<i64> {
+    let quotient = left.checked_div(right)?;
+    if left % right != 0 && (left < 0) != (right < 0) {
+        Some(quotient - 1)
+    } else {
+        Some(quotient)
+    }
+}
+
 #[derive(Debug, Clone, Eq, PartialEq)]
 pub struct CompiledFunction {
     pub instructions: Vec<u8>,
     pub num_locals: usize,
     pub num_parameters: usize,
+    /// Empty for anonymous functions.
+    pub name: String,
 }
 
 #[derive(Debug, Clone, Eq, PartialEq)]
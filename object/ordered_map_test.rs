@@ -0,0 +1,53 @@
+#[cfg(test)]
+mod tests {
+    use crate::ordered_map::OrderedMap;
+
+    #[test]
+    fn test_iterates_in_insertion_order() {
+        let map: OrderedMap<&str, i64> = [("b", 1), ("a", 2), ("c", 3)].into_iter().collect();
+        assert_eq!(map.keys().copied().collect::<Vec<_>>(), ["b", "a", "c"]);
+        assert_eq!(map.values().copied().collect::<Vec<_>>(), [1, 2, 3]);
+    }
+
+    #[test]
+    fn test_reinsert_keeps_position_and_replaces_value() {
+        let mut map = OrderedMap::new();
+        assert_eq!(map.insert("x", 1), None);
+        map.insert("y", 2);
+        assert_eq!(map.insert("x", 3), Some(1));
+
+        assert_eq!(map.len(), 2);
+        assert_eq!(map.get(&"x"), Some(&3));
+        assert_eq!(
+            map.iter().map(|(k, v)| (*k, *v)).collect::<Vec<_>>(),
+            [("x", 3), ("y", 2)]
+        );
+    }
+
+    #[test]
+    fn test_remove_keeps_the_rest_in_order() {
+        let mut map: OrderedMap<&str, i64> = [("a", 1), ("b", 2), ("c", 3)].into_iter().collect();
+        assert_eq!(map.remove(&"b"), Some(2));
+        assert_eq!(map.remove(&"b"), None);
+
+        assert_eq!(map.get(&"c"), Some(&3));
+        map.insert("b", 4);
+        assert_eq!(
+            map.iter().map(|(k, v)| (*k, *v)).collect::<Vec<_>>(),
+            [("a", 1), ("c", 3), ("b", 4)]
+        );
+    }
+
+    #[test]
+    fn test_equality_ignores_order() {
+        let a: OrderedMap<i64, i64> = [(1, 10), (2, 20)].into_iter().collect();
+        let b: OrderedMap<i64, i64> = [(2, 20), (1, 10)].into_iter().collect();
+        let c: OrderedMap<i64, i64> = [(1, 10), (2, 21)].into_iter().collect();
+        let d: OrderedMap<i64, i64> = [(1, 10)].into_iter().collect();
+
+        assert_eq!(a, b);
+        assert_ne!(a, c);
+        assert_ne!(a, d);
+        assert_ne!(d, a);
+    }
+}
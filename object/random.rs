@@ -0,0 +1,40 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// The xorshift64 generator behind `rand`. Not suitable for anything that
+/// needs unpredictable numbers.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct Random {
+    state: u64,
+}
+
+impl Random {
+    /// The same seed always gives the same sequence.
+    pub fn new(seed: u64) -> Self {
+        // splitmix64 spreads small seeds over the whole state; xorshift
+        // would be stuck at an all-zero state forever
+        let mut z = seed.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^= z >> 31;
+        Random {
+            state: if z == 0 { 1 } else { z },
+        }
+    }
+
+    /// Seeded from the system clock, for scripts that never call `seed`.
+    pub fn from_clock() -> Self {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_nanos() as u64);
+        Random::new(nanos)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        let mut x = self.state;
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        self.state = x;
+        x
+    }
+}
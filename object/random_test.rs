@@ -0,0 +1,22 @@
+#[cfg(test)]
+mod tests {
+    use crate::random::Random;
+
+    #[test]
+    fn test_same_seed_same_sequence() {
+        let draw = |seed| {
+            let mut random = Random::new(seed);
+            (0..5).map(|_| random.next_u64()).collect::<Vec<_>>()
+        };
+        assert_eq!(draw(7), draw(7));
+        assert_ne!(draw(7), draw(8));
+    }
+
+    #[test]
+    fn test_zero_seed_is_not_stuck() {
+        let mut random = Random::new(0);
+        let first = random.next_u64();
+        assert_ne!(first, 0);
+        assert_ne!(random.next_u64(), first);
+    }
+}
@@ -0,0 +1,163 @@
+#[cfg(test)]
+mod tests {
+    use crate::{parse, parse_with_asi};
+
+    fn display(result: Result<crate::ast::Node, crate::ParseErrors>) -> String {
+        match result {
+            Ok(node) => node.to_string(),
+            Err(errors) => format!("error: {}", errors[0]),
+        }
+    }
+
+    // (input, with ASI, without ASI)
+    fn verify(test_cases: &[(&str, &str, &str)]) {
+        for (input, asi, plain) in test_cases {
+            assert_eq!(
+                &display(parse_with_asi(input)),
+                asi,
+                "ASI mode, input {:?}",
+                input
+            );
+            assert_eq!(
+                &display(parse(input)),
+                plain,
+                "plain mode, input {:?}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn test_newline_ends_statement_before_ambiguous_start() {
+        verify(&[
+            ("a\n(b)", "ab", "a(b)"),
+            (
+                "let f = g\n(1 + 2)",
+                "let f = g;(1 + 2)",
+                "let f = g((1 + 2));",
+            ),
+            ("a\n[1]", "a[1]", "(a[1])"),
+            ("a\n-b", "a(-b)", "(a - b)"),
+            // unary `+` starts a statement just as unary `-` does
+            ("a\n+b", "a(+b)", "(a + b)"),
+            ("let x = 1\n+ 2", "let x = 1;(+2)", "let x = (1 + 2);"),
+            ("h.name\n(1)", "(h[\"name\"])1", "h.name(1)"),
+            ("return x\n-1", "return x;(-1)", "return (x - 1);"),
+            (
+                "fn() { return\n}",
+                "fn () { return null; }",
+                "fn () { return null; }",
+            ),
+            ("return\nx", "return null;x", "return x;"),
+        ]);
+    }
+
+    #[test]
+    fn test_statements_without_semicolons() {
+        verify(&[
+            (
+                "let a = 1\nlet b = 2",
+                "let a = 1;let b = 2;",
+                "let a = 1;let b = 2;",
+            ),
+            (
+                "let a = 1\n\n\nlet b = a",
+                "let a = 1;let b = a;",
+                "let a = 1;let b = a;",
+            ),
+            ("x\ny\nz", "xyz", "xyz"),
+            (
+                "let a = 1 // one\nlet b = 2",
+                "let a = 1;let b = 2;",
+                "let a = 1;let b = 2;",
+            ),
+            ("\n\nlet a = 1\n\n", "let a = 1;", "let a = 1;"),
+            (
+                "let a = 1;\nlet b = 2;",
+                "let a = 1;let b = 2;",
+                "let a = 1;let b = 2;",
+            ),
+        ]);
+    }
+
+    #[test]
+    fn test_expressions_spanning_lines() {
+        verify(&[
+            ("1 +\n2", "(1 + 2)", "(1 + 2)"),
+            ("a ==\nb", "(a == b)", "(a == b)"),
+            ("a\n* b\n* c", "((a * b) * c)", "((a * b) * c)"),
+            ("(a\n-b)", "(a - b)", "(a - b)"),
+            ("f(a,\nb)", "f(a, b)", "f(a, b)"),
+            ("f(\na\n,\nb\n)", "f(a, b)", "f(a, b)"),
+            ("[1,\n2,\n-3]", "[1, 2, (-3)]", "[1, 2, (-3)]"),
+            ("[\n1\n-2\n]", "[(1 - 2)]", "[(1 - 2)]"),
+            ("a[\nb\n]", "(a[b])", "(a[b])"),
+            (
+                "{\"a\": 1,\n\"b\": x\n-1}",
+                r#"{"a": 1, "b": (x - 1)}"#,
+                r#"{"a": 1, "b": (x - 1)}"#,
+            ),
+            ("xs\n.rest()\n.len()", "xs.rest().len()", "xs.rest().len()"),
+            ("if (a\n> b) { a }", "if (a > b) { a }", "if (a > b) { a }"),
+            (
+                "while (a\n< b) { a }",
+                "while ((a < b)) { a }",
+                "while ((a < b)) { a }",
+            ),
+        ]);
+    }
+
+    #[test]
+    fn test_blocks_restore_newline_termination() {
+        verify(&[
+            (
+                "let f = fn(x) {\n  let y = x\n  y\n  (1)\n}",
+                "let f = fn f(x) { let y = x;y1 };",
+                "let f = fn f(x) { let y = x;y(1) };",
+            ),
+            (
+                "f(fn() {\n  a\n  -b\n})",
+                "f(fn () { a(-b) })",
+                "f(fn () { (a - b) })",
+            ),
+            (
+                "if (x) {\n  a\n  [1]\n}\nelse {\n  b\n}",
+                "if x { a[1] } else { b }",
+                "if x { (a[1]) } else { b }",
+            ),
+            (
+                "while (x) {\n  let x = x\n  -1\n}",
+                "while (x) { let x = x;(-1) }",
+                "while (x) { let x = (x - 1); }",
+            ),
+        ]);
+    }
+
+    #[test]
+    fn test_block_comments_spanning_lines() {
+        verify(&[
+            ("a /* note\n */ (b)", "ab", "a(b)"),
+            (
+                "let a = 1 /* note */\nlet b = 2",
+                "let a = 1;let b = 2;",
+                "let a = 1;let b = 2;",
+            ),
+        ]);
+    }
+
+    #[test]
+    fn test_asi_errors() {
+        verify(&[
+            (
+                "let a =\n",
+                "error: no prefix function for token start: 8, end: 8, kind: EOF",
+                "error: no prefix function for token start: 8, end: 8, kind: EOF",
+            ),
+            (
+                "(a\nb)",
+                "error: expected token ), got start: 3, end: 4, kind: b",
+                "error: expected token ), got start: 3, end: 4, kind: b",
+            ),
+        ]);
+    }
+}
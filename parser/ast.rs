@@ -53,7 +53,11 @@ impl fmt::Display for Program {
 #[serde(untagged)]
 pub enum Statement {
     Let(Let),
+    Assign(Assign),
+    IndexAssign(IndexAssign),
     Return(ReturnStatement),
+    Break(Break),
+    Continue(Continue),
     Expr(Expression),
 }
 
@@ -65,6 +69,26 @@ pub struct Let {
     pub span: Span,
 }
 
+/// `name = expr;`, rebinding a variable an earlier `let` defined.
+#[derive(Clone, Debug, Eq, Serialize, Deserialize, Hash, PartialEq)]
+#[serde(tag = "type")]
+pub struct Assign {
+    pub name: IDENTIFIER,
+    pub expr: Expression,
+    pub span: Span,
+}
+
+/// `name[index] = value;`, rebinding `name` to a copy of its array or hash
+/// with one element replaced.
+#[derive(Clone, Debug, Eq, Serialize, Deserialize, Hash, PartialEq)]
+#[serde(tag = "type")]
+pub struct IndexAssign {
+    pub name: IDENTIFIER,
+    pub index: Expression,
+    pub value: Expression,
+    pub span: Span,
+}
+
 #[derive(Clone, Debug, Eq, Serialize, Deserialize, Hash, PartialEq)]
 #[serde(tag = "type")]
 pub struct ReturnStatement {
@@ -72,6 +96,34 @@ pub struct ReturnStatement {
     pub span: Span,
 }
 
+/// Leaves the innermost enclosing `while` loop.
+#[derive(Clone, Debug, Eq, Serialize, Deserialize, Hash, PartialEq)]
+#[serde(tag = "type")]
+pub struct Break {
+    pub span: Span,
+}
+
+/// Skips to the condition check of the innermost enclosing `while` loop.
+#[derive(Clone, Debug, Eq, Serialize, Deserialize, Hash, PartialEq)]
+#[serde(tag = "type")]
+pub struct Continue {
+    pub span: Span,
+}
+
+impl Statement {
+    pub fn span(&self) -> &Span {
+        match self {
+            Statement::Let(let_statement) => &let_statement.span,
+            Statement::Assign(assign) => &assign.span,
+            Statement::IndexAssign(assign) => &assign.span,
+            Statement::Return(return_statement) => &return_statement.span,
+            Statement::Break(break_statement) => &break_statement.span,
+            Statement::Continue(continue_statement) => &continue_statement.span,
+            Statement::Expr(expr) => expr.span(),
+        }
+    }
+}
+
 impl fmt::Display for Statement {
     fn fmt(&self, f: &mut Formatter<'_>) -> Result {
         match self {
@@ -85,9 +137,17 @@ impl fmt::Display for Statement {
                 }
                 write!(f, "let {} = {};", id.kind, expr)
             }
+            Statement::Assign(Assign { name, expr, .. }) => {
+                write!(f, "{} = {};", name.name, expr)
+            }
+            Statement::IndexAssign(IndexAssign {
+                name, index, value, ..
+            }) => write!(f, "{}[{}] = {};", name.name, index, value),
             Statement::Return(ReturnStatement { argument, .. }) => {
                 write!(f, "return {};", argument)
             }
+            Statement::Break(_) => write!(f, "break;"),
+            Statement::Continue(_) => write!(f, "continue;"),
             Statement::Expr(expr) => write!(f, "{}", expr),
         }
     }
@@ -115,9 +175,12 @@ pub enum Expression {
     INFIX(BinaryExpression),
     IF(IF),
     While(While),
+    ForIn(ForIn),
     FUNCTION(FunctionDeclaration),
     FunctionCall(FunctionCall),
+    MethodCall(MethodCall),
     Index(Index),
+    Macro(MacroLiteral),
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize, Eq, Hash, PartialEq)]
@@ -167,6 +230,17 @@ pub struct While {
     pub span: Span,
 }
 
+/// `for (variable in iterable) { body }`: runs the body once per array
+/// element, or once per key of a hash.
+#[derive(Clone, Debug, Serialize, Deserialize, Eq, Hash, PartialEq)]
+#[serde(tag = "type")]
+pub struct ForIn {
+    pub variable: IDENTIFIER,
+    pub iterable: Box<Expression>,
+    pub body: BlockStatement,
+    pub span: Span,
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize, Eq, Hash, PartialEq)]
 #[serde(tag = "type")]
 pub struct FunctionDeclaration {
@@ -176,6 +250,14 @@ pub struct FunctionDeclaration {
     pub name: String,
 }
 
+#[derive(Clone, Debug, Serialize, Deserialize, Eq, Hash, PartialEq)]
+#[serde(tag = "type")]
+pub struct MacroLiteral {
+    pub params: Vec<IDENTIFIER>,
+    pub body: BlockStatement,
+    pub span: Span,
+}
+
 // function can be Identifier or FunctionLiteral (think iife)
 #[derive(Clone, Debug, Serialize, Deserialize, Eq, Hash, PartialEq)]
 #[serde(tag = "type")]
@@ -185,6 +267,18 @@ pub struct FunctionCall {
     pub span: Span,
 }
 
+// `receiver.method(args)` is sugar for `method(receiver, args)`
+#[derive(Clone, Debug, Serialize, Deserialize, Eq, Hash, PartialEq)]
+#[serde(tag = "type")]
+pub struct MethodCall {
+    pub receiver: Box<Expression>,
+    /// The `.`, which an unknown method name is reported at.
+    pub dot: Span,
+    pub method: IDENTIFIER,
+    pub arguments: Vec<Expression>,
+    pub span: Span,
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize, Eq, Hash, PartialEq)]
 #[serde(tag = "type")]
 pub struct Index {
@@ -193,6 +287,25 @@ pub struct Index {
     pub span: Span,
 }
 
+impl Expression {
+    pub fn span(&self) -> &Span {
+        match self {
+            Expression::IDENTIFIER(identifier) => &identifier.span,
+            Expression::LITERAL(literal) => literal.span(),
+            Expression::PREFIX(prefix) => &prefix.span,
+            Expression::INFIX(infix) => &infix.span,
+            Expression::IF(if_node) => &if_node.span,
+            Expression::While(while_node) => &while_node.span,
+            Expression::ForIn(for_node) => &for_node.span,
+            Expression::FUNCTION(function) => &function.span,
+            Expression::FunctionCall(call) => &call.span,
+            Expression::MethodCall(call) => &call.span,
+            Expression::Index(index) => &index.span,
+            Expression::Macro(macro_literal) => &macro_literal.span,
+        }
+    }
+}
+
 impl fmt::Display for Expression {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         match self {
@@ -229,6 +342,14 @@ impl fmt::Display for Expression {
             }) => {
                 write!(f, "while ({}) {{ {} }}", condition, body)
             }
+            Expression::ForIn(ForIn {
+                variable,
+                iterable,
+                body,
+                ..
+            }) => {
+                write!(f, "for ({} in {}) {{ {} }}", variable, iterable, body)
+            }
             Expression::FUNCTION(FunctionDeclaration {
                 name, params, body, ..
             }) => {
@@ -244,9 +365,31 @@ impl fmt::Display for Expression {
             }) => {
                 write!(f, "{}({})", callee, format_expressions(arguments))
             }
+            Expression::MethodCall(MethodCall {
+                receiver,
+                method,
+                arguments,
+                ..
+            }) => {
+                write!(
+                    f,
+                    "{}.{}({})",
+                    receiver,
+                    method,
+                    format_expressions(arguments)
+                )
+            }
             Expression::Index(Index { object, index, .. }) => {
                 write!(f, "({}[{}])", object, index)
             }
+            Expression::Macro(MacroLiteral { params, body, .. }) => {
+                let macro_params = params
+                    .iter()
+                    .map(|param| param.to_string())
+                    .collect::<Vec<String>>()
+                    .join(", ");
+                write!(f, "macro({}) {{ {} }}", macro_params, body)
+            }
         }
     }
 }
@@ -255,7 +398,9 @@ impl fmt::Display for Expression {
 #[serde(tag = "type")]
 pub enum Literal {
     Integer(Integer),
+    Float(Float),
     Boolean(Boolean),
+    Null(Null),
     String(StringType),
     Array(Array),
     Hash(Hash),
@@ -267,12 +412,39 @@ pub struct Integer {
     pub span: Span,
 }
 
+/// Compares and hashes `raw` by its bits, like `Float64` in tokens.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Float {
+    pub raw: f64,
+    pub span: Span,
+}
+
+impl PartialEq for Float {
+    fn eq(&self, other: &Self) -> bool {
+        self.raw.to_bits() == other.raw.to_bits() && self.span == other.span
+    }
+}
+
+impl Eq for Float {}
+
+impl std::hash::Hash for Float {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.raw.to_bits().hash(state);
+        self.span.hash(state);
+    }
+}
+
 #[derive(Clone, Debug, Eq, Serialize, Deserialize, Hash, PartialEq)]
 pub struct Boolean {
     pub raw: bool,
     pub span: Span,
 }
 
+#[derive(Clone, Debug, Eq, Serialize, Deserialize, Hash, PartialEq)]
+pub struct Null {
+    pub span: Span,
+}
+
 #[derive(Clone, Debug, Eq, Serialize, Deserialize, Hash, PartialEq)]
 pub struct StringType {
     pub raw: String,
@@ -291,11 +463,27 @@ pub struct Hash {
     pub span: Span,
 }
 
+impl Literal {
+    pub fn span(&self) -> &Span {
+        match self {
+            Literal::Integer(integer) => &integer.span,
+            Literal::Float(float) => &float.span,
+            Literal::Boolean(boolean) => &boolean.span,
+            Literal::Null(null) => &null.span,
+            Literal::String(string) => &string.span,
+            Literal::Array(array) => &array.span,
+            Literal::Hash(hash) => &hash.span,
+        }
+    }
+}
+
 impl fmt::Display for Literal {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         match self {
             Literal::Integer(Integer { raw: i, .. }) => write!(f, "{}", i),
+            Literal::Float(Float { raw, .. }) => write!(f, "{:?}", raw),
             Literal::Boolean(Boolean { raw: b, .. }) => write!(f, "{}", b),
+            Literal::Null(_) => write!(f, "null"),
             Literal::String(StringType { raw: s, .. }) => write!(f, "\"{}\"", s),
             Literal::Array(Array { elements: e, .. }) => write!(f, "[{}]", format_expressions(e)),
             Literal::Hash(Hash { elements: map, .. }) => {
@@ -53,6 +53,41 @@ mod tests {
         test_ast_tree("test_unary", input)
     }
 
+    #[test]
+    fn test_unary_plus() {
+        test_ast_tree("test_unary_plus", "+5")
+    }
+
+    #[test]
+    fn test_minus_plus() {
+        test_ast_tree("test_minus_plus", "-+5")
+    }
+
+    #[test]
+    fn test_null() {
+        test_ast_tree("test_null", "[null, !null]")
+    }
+
+    #[test]
+    fn test_conditional() {
+        test_ast_tree("test_conditional", "x > 1 ? 2 : 3")
+    }
+
+    #[test]
+    fn test_nested_conditional() {
+        test_ast_tree("test_nested_conditional", "a ? b : c ? d : e")
+    }
+
+    #[test]
+    fn test_double_bang() {
+        test_ast_tree("test_double_bang", "!!true")
+    }
+
+    #[test]
+    fn test_negated_group() {
+        test_ast_tree("test_negated_group", "-(-5)")
+    }
+
     #[test]
     fn test_binary() {
         let input = "1 + 2 * 3";
@@ -65,6 +100,18 @@ mod tests {
         test_ast_tree("test_binary_nested", input)
     }
 
+    #[test]
+    fn test_block_comments() {
+        let input = "let /* a */ a = /* b */ 3";
+        test_ast_tree("test_block_comments", input)
+    }
+
+    #[test]
+    fn test_modulo() {
+        let input = "8*3%5";
+        test_ast_tree("test_modulo", input)
+    }
+
     #[test]
     fn test_if() {
         let input = "if (x < y) { x } else { y }";
@@ -83,6 +130,18 @@ mod tests {
         test_ast_tree("test_func_call", input)
     }
 
+    #[test]
+    fn test_method_call() {
+        let input = "xs.push(1)";
+        test_ast_tree("test_method_call", input)
+    }
+
+    #[test]
+    fn test_dot_access() {
+        let input = "a.b";
+        test_ast_tree("test_dot_access", input)
+    }
+
     #[test]
     fn test_index() {
         let input = "a[1]";
@@ -0,0 +1,81 @@
+#[cfg(test)]
+mod tests {
+    use crate::{MAX_EXPRESSION_HEIGHT, MAX_NESTING_DEPTH, ParseError, parse};
+    use std::fs;
+    use std::path::Path;
+
+    // Every file under `corpus/` must produce Ok or Err; a panic or a stack
+    // overflow fails the test (and names the offending file).
+    #[test]
+    fn test_corpus_never_panics() {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("corpus");
+        let mut paths = fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .collect::<Vec<_>>();
+        paths.sort();
+        assert!(!paths.is_empty(), "empty corpus at {}", dir.display());
+
+        for path in paths {
+            println!("parsing {}", path.display());
+            let bytes = fs::read(&path).unwrap();
+            let input = String::from_utf8_lossy(&bytes);
+            if let Ok(node) = parse(&input) {
+                let _ = node.to_string();
+            }
+        }
+    }
+
+    #[test]
+    fn test_nesting_limit() {
+        let within = format!(
+            "{}1{}",
+            "(".repeat(MAX_NESTING_DEPTH - 1),
+            ")".repeat(MAX_NESTING_DEPTH - 1)
+        );
+        assert!(parse(&within).is_ok());
+
+        let inputs = [
+            format!("{}1", "-".repeat(MAX_NESTING_DEPTH * 4)),
+            format!("{}1", "(1 + ".repeat(MAX_NESTING_DEPTH * 4)),
+            format!("f({}", "f(".repeat(MAX_NESTING_DEPTH * 4)),
+        ];
+        for input in inputs {
+            let errors = parse(&input).unwrap_err();
+            assert!(
+                errors
+                    .iter()
+                    .any(|e| matches!(e, ParseError::NestingTooDeep { .. })),
+                "expected nesting error, got {:?}",
+                errors
+            );
+        }
+    }
+
+    #[test]
+    fn test_flat_chains_are_not_nesting() {
+        let chains = |links: usize| {
+            [
+                format!("{}1", "1 + ".repeat(links)),
+                format!("f{}", "()".repeat(links)),
+                format!("xs{}", "[0]".repeat(links)),
+            ]
+        };
+
+        for input in chains(MAX_NESTING_DEPTH * 4) {
+            assert!(parse(&input).is_ok(), "{}", input);
+        }
+
+        // the links still make the tree taller
+        for input in chains(MAX_EXPRESSION_HEIGHT) {
+            let errors = parse(&input).unwrap_err();
+            assert!(
+                errors
+                    .iter()
+                    .any(|e| matches!(e, ParseError::ExpressionTooTall { .. })),
+                "expected height error, got {:?}",
+                errors
+            );
+        }
+    }
+}
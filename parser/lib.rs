@@ -1,16 +1,24 @@
+#[cfg(test)]
+mod asi_test;
 pub mod ast;
 #[cfg(test)]
 mod ast_test;
 #[cfg(test)]
+mod corpus_test;
+pub mod modify;
+#[cfg(test)]
+mod modify_test;
+#[cfg(test)]
 mod parser_test;
 mod precedences;
 
 pub extern crate lexer;
 
 use crate::ast::{
-    Array, BinaryExpression, BlockStatement, Boolean, Expression, FunctionCall,
-    FunctionDeclaration, Hash, IDENTIFIER, IF, Index, Integer, Let, Literal, Node, Program,
-    ReturnStatement, Statement, StringType, UnaryExpression, While,
+    Array, Assign, BinaryExpression, BlockStatement, Boolean, Break, Continue, Expression, Float,
+    ForIn, FunctionCall, FunctionDeclaration, Hash, IDENTIFIER, IF, Index, IndexAssign, Integer,
+    Let, Literal, MacroLiteral, MethodCall, Node, Null, Program, ReturnStatement, Statement,
+    StringType, UnaryExpression, While,
 };
 use crate::precedences::{Precedence, get_token_precedence};
 use lexer::Lexer;
@@ -19,10 +27,45 @@ use std::fmt;
 
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub enum ParseError {
-    ExpectedToken { expected: String, got: Token },
-    ExpectedIdentifier { got: Token },
-    InvalidFunctionParameter { got: Token },
-    NoPrefixParseFn { token: Token },
+    ExpectedToken {
+        expected: String,
+        got: Token,
+    },
+    ExpectedIdentifier {
+        got: Token,
+    },
+    InvalidFunctionParameter {
+        got: Token,
+    },
+    NoPrefixParseFn {
+        token: Token,
+    },
+    NestingTooDeep {
+        token: Token,
+    },
+    /// An expression whose tree, chains of operators included, would be
+    /// more than `MAX_EXPRESSION_HEIGHT` levels tall.
+    ExpressionTooTall {
+        token: Token,
+    },
+    /// A decimal literal whose value does not fit in an `i64`.
+    IntegerOutOfRange {
+        token: Token,
+    },
+    /// A number with more than one decimal point, such as `1.2.3`.
+    InvalidFloat {
+        literal: String,
+        token: Token,
+    },
+    /// An `=` after something other than a variable or `variable[index]`.
+    InvalidAssignmentTarget {
+        target: String,
+        token: Token,
+    },
+    /// A `/*` with no matching `*/`.
+    UnterminatedComment {
+        token: Token,
+    },
     SerializeAst(String),
 }
 
@@ -45,37 +88,164 @@ impl fmt::Display for ParseError {
             ParseError::NoPrefixParseFn { token } => {
                 write!(f, "no prefix function for token {}", token)
             }
+            ParseError::NestingTooDeep { token } => {
+                write!(
+                    f,
+                    "expression nesting exceeds maximum depth of {} at {}",
+                    MAX_NESTING_DEPTH, token
+                )
+            }
+            ParseError::ExpressionTooTall { token } => {
+                write!(
+                    f,
+                    "expression is more than {} operations deep at {}",
+                    MAX_EXPRESSION_HEIGHT, token
+                )
+            }
+            ParseError::IntegerOutOfRange { .. } => {
+                write!(f, "integer literal out of range for 64-bit integer")
+            }
+            ParseError::InvalidFloat { literal, .. } => write!(
+                f,
+                "invalid number literal {}: a number has at most one decimal point",
+                literal
+            ),
+            ParseError::InvalidAssignmentTarget { target, .. } => write!(
+                f,
+                "cannot assign to {}: only a variable, variable[index] or variable.field can be assigned",
+                target
+            ),
+            ParseError::UnterminatedComment { .. } => write!(f, "unterminated block comment"),
             ParseError::SerializeAst(err) => write!(f, "failed to serialize AST: {}", err),
         }
     }
 }
 
+impl ParseError {
+    pub fn span(&self) -> Option<&Span> {
+        match self {
+            ParseError::ExpectedToken { got: token, .. }
+            | ParseError::ExpectedIdentifier { got: token }
+            | ParseError::InvalidFunctionParameter { got: token }
+            | ParseError::NoPrefixParseFn { token }
+            | ParseError::NestingTooDeep { token }
+            | ParseError::ExpressionTooTall { token }
+            | ParseError::IntegerOutOfRange { token }
+            | ParseError::InvalidFloat { token, .. }
+            | ParseError::InvalidAssignmentTarget { token, .. }
+            | ParseError::UnterminatedComment { token } => Some(&token.span),
+            ParseError::SerializeAst(_) => None,
+        }
+    }
+
+    /// The message followed by the offending source line with the span
+    /// underlined, e.g.
+    ///
+    /// ```text
+    /// integer literal out of range for 64-bit integer
+    ///    1 | let x = 99999999999999999999;
+    ///      |         ^^^^^^^^^^^^^^^^^^^^
+    /// ```
+    pub fn render(&self, source: &str) -> String {
+        let Some(span) = self.span() else {
+            return self.to_string();
+        };
+        let mut start = span.start.min(source.len());
+        while !source.is_char_boundary(start) {
+            start -= 1;
+        }
+        let line_start = source[..start].rfind('\n').map_or(0, |newline| newline + 1);
+        let line_end = source[start..]
+            .find('\n')
+            .map_or(source.len(), |newline| start + newline);
+        let line = source[..start].matches('\n').count() + 1;
+        let column = source[line_start..start].chars().count();
+        let end = span.end.clamp(start, line_end);
+        let width = source
+            .get(start..end)
+            .map_or(0, |text| text.chars().count());
+        format!(
+            "{}\n{:>4} | {}\n     | {}{}",
+            self,
+            line,
+            source[line_start..line_end].trim_end(),
+            " ".repeat(column),
+            "^".repeat(width.max(1))
+        )
+    }
+}
+
 pub type ParseErrors = Vec<ParseError>;
 
+/// Upper bound on nested expressions, so hostile input produces a
+/// `ParseError` instead of overflowing the stack.
+pub const MAX_NESTING_DEPTH: usize = 128;
+/// Upper bound on the height of an expression's tree. A chain such as
+/// `1 + 1 + ... + 1` only nests once, but each link is a level the
+/// compiler and the interpreter recurse through.
+pub const MAX_EXPRESSION_HEIGHT: usize = 1024;
+
+const I64_MIN_MAGNITUDE: &str = "9223372036854775808";
+
+// Newline tokens never reach the parsing functions: `next_token` skips them
+// and records whether the peek token started a new line. When the lexer does
+// not emit them, `peek_after_newline` is always false and parsing is
+// unchanged.
 pub struct Parser<'a> {
     lexer: Lexer<'a>,
     current_token: Token,
     peek_token: Token,
+    peek_after_newline: bool,
+    newlines_significant: bool,
     errors: ParseErrors,
+    depth: usize,
+    height: usize,
 }
 
 impl<'a> Parser<'a> {
     pub fn new(mut lexer: Lexer<'a>) -> Parser<'a> {
-        let cur = lexer.next_token();
-        let next = lexer.next_token();
+        let (cur, _) = read_token(&mut lexer);
+        let (next, after_newline) = read_token(&mut lexer);
         let errors = Vec::new();
 
         Parser {
             lexer,
             current_token: cur,
             peek_token: next,
+            peek_after_newline: after_newline,
+            newlines_significant: true,
             errors,
+            depth: 0,
+            height: 0,
         }
     }
 
     fn next_token(&mut self) {
-        self.current_token = self.peek_token.clone();
-        self.peek_token = self.lexer.next_token();
+        let (next, after_newline) = read_token(&mut self.lexer);
+        self.current_token = std::mem::replace(&mut self.peek_token, next);
+        self.peek_after_newline = after_newline;
+    }
+
+    // A line break ends the current statement when the next line starts
+    // something new; a next line starting with e.g. `+` or `.` continues the
+    // expression. Inside parentheses, brackets and hash literals line breaks
+    // never end anything.
+    fn at_statement_end(&self) -> bool {
+        self.peek_token_is(&TokenKind::SEMICOLON)
+            || (self.peek_after_newline
+                && self.newlines_significant
+                && can_start_statement(&self.peek_token.kind))
+    }
+
+    fn with_newlines_significant<T>(
+        &mut self,
+        significant: bool,
+        parse: impl FnOnce(&mut Self) -> Result<T, ParseError>,
+    ) -> Result<T, ParseError> {
+        let saved = std::mem::replace(&mut self.newlines_significant, significant);
+        let result = parse(self);
+        self.newlines_significant = saved;
+        result
     }
 
     fn current_token_is(&self, token: &TokenKind) -> bool {
@@ -120,10 +290,48 @@ impl<'a> Parser<'a> {
         match self.current_token.kind {
             TokenKind::LET => self.parse_let_statement(),
             TokenKind::RETURN => self.parse_return_statement(),
+            TokenKind::BREAK | TokenKind::CONTINUE => Ok(self.parse_loop_control_statement()),
+            TokenKind::IDENTIFIER { .. } if self.peek_token_is(&TokenKind::ASSIGN) => {
+                self.parse_assign_statement()
+            }
             _ => self.parse_expression_statement(),
         }
     }
 
+    fn parse_identifier(&self) -> Result<IDENTIFIER, ParseError> {
+        match &self.current_token.kind {
+            TokenKind::IDENTIFIER { name } => Ok(IDENTIFIER {
+                name: name.clone(),
+                span: self.current_token.span.clone(),
+            }),
+            _ => Err(ParseError::ExpectedIdentifier {
+                got: self.current_token.clone(),
+            }),
+        }
+    }
+
+    fn parse_assign_statement(&mut self) -> Result<Statement, ParseError> {
+        let start = self.current_token.span.start;
+        let name = self.parse_identifier()?;
+
+        self.expect_peek(&TokenKind::ASSIGN)?;
+        self.next_token();
+
+        let expr = self.parse_expression(Precedence::Lowest)?.0;
+
+        if self.peek_token_is(&TokenKind::SEMICOLON) {
+            self.next_token();
+        }
+
+        let end = self.current_token.span.end;
+
+        Ok(Statement::Assign(Assign {
+            name,
+            expr,
+            span: Span { start, end },
+        }))
+    }
+
     fn parse_let_statement(&mut self) -> Result<Statement, ParseError> {
         let start = self.current_token.span.start;
         self.next_token();
@@ -161,6 +369,44 @@ impl<'a> Parser<'a> {
 
     fn parse_return_statement(&mut self) -> Result<Statement, ParseError> {
         let start = self.current_token.span.start;
+        // a bare `return` gives back null
+        let value = if self.at_statement_end()
+            || self.peek_token_is(&TokenKind::RBRACE)
+            || self.peek_token_is(&TokenKind::EOF)
+        {
+            Expression::LITERAL(Literal::Null(Null {
+                span: self.current_token.span.clone(),
+            }))
+        } else {
+            self.next_token();
+            self.parse_expression(Precedence::Lowest)?.0
+        };
+
+        if self.peek_token_is(&TokenKind::SEMICOLON) {
+            self.next_token();
+        }
+        let end = self.current_token.span.end;
+
+        Ok(Statement::Return(ReturnStatement {
+            argument: value,
+            span: Span { start, end },
+        }))
+    }
+
+    // `target` has already been parsed and the `=` is the peek token
+    fn parse_index_assign_statement(
+        &mut self,
+        target: Expression,
+    ) -> Result<Statement, ParseError> {
+        self.next_token();
+        let (name, index) = match &target {
+            Expression::Index(Index { object, index, .. }) => match &**object {
+                Expression::IDENTIFIER(name) => (name.clone(), (**index).clone()),
+                _ => return Err(self.invalid_assignment_target(&target)),
+            },
+            _ => return Err(self.invalid_assignment_target(&target)),
+        };
+        let start = name.span.start;
         self.next_token();
 
         let value = self.parse_expression(Precedence::Lowest)?.0;
@@ -170,14 +416,43 @@ impl<'a> Parser<'a> {
         }
         let end = self.current_token.span.end;
 
-        Ok(Statement::Return(ReturnStatement {
-            argument: value,
+        Ok(Statement::IndexAssign(IndexAssign {
+            name,
+            index,
+            value,
             span: Span { start, end },
         }))
     }
 
+    fn invalid_assignment_target(&self, target: &Expression) -> ParseError {
+        ParseError::InvalidAssignmentTarget {
+            target: target.to_string(),
+            token: self.current_token.clone(),
+        }
+    }
+
+    fn parse_loop_control_statement(&mut self) -> Statement {
+        let start = self.current_token.span.start;
+        let kind = self.current_token.kind.clone();
+        if self.peek_token_is(&TokenKind::SEMICOLON) {
+            self.next_token();
+        }
+        let span = Span {
+            start,
+            end: self.current_token.span.end,
+        };
+
+        match kind {
+            TokenKind::BREAK => Statement::Break(Break { span }),
+            _ => Statement::Continue(Continue { span }),
+        }
+    }
+
     fn parse_expression_statement(&mut self) -> Result<Statement, ParseError> {
         let expr = self.parse_expression(Precedence::Lowest)?.0;
+        if self.peek_token_is(&TokenKind::ASSIGN) {
+            return self.parse_index_assign_statement(expr);
+        }
         if self.peek_token_is(&TokenKind::SEMICOLON) {
             self.next_token();
         }
@@ -189,15 +464,49 @@ impl<'a> Parser<'a> {
         &mut self,
         precedence: Precedence,
     ) -> Result<(Expression, Span), ParseError> {
+        let (depth, height) = (self.depth, self.height);
+        let result = self.parse_nested_expression(precedence);
+        (self.depth, self.height) = (depth, height);
+        result
+    }
+
+    // Counts recursive calls only, so a long left-associative chain such
+    // as `1 + 1 + ... + 1`, parsed in a loop, isn't nesting.
+    fn enter_nesting(&mut self) -> Result<(), ParseError> {
+        if self.depth >= MAX_NESTING_DEPTH {
+            return Err(ParseError::NestingTooDeep {
+                token: self.current_token.clone(),
+            });
+        }
+        self.depth += 1;
+        self.grow_tree()
+    }
+
+    // Counts recursive calls and chain links alike, one per level of the
+    // tree being built.
+    fn grow_tree(&mut self) -> Result<(), ParseError> {
+        if self.height >= MAX_EXPRESSION_HEIGHT {
+            return Err(ParseError::ExpressionTooTall {
+                token: self.current_token.clone(),
+            });
+        }
+        self.height += 1;
+        Ok(())
+    }
+
+    fn parse_nested_expression(
+        &mut self,
+        precedence: Precedence,
+    ) -> Result<(Expression, Span), ParseError> {
+        self.enter_nesting()?;
         let mut left = self.parse_prefix_expression()?;
-        let mut left_start = expression_start(&left);
-        while self.peek_token.kind != TokenKind::SEMICOLON
-            && precedence < get_token_precedence(&self.peek_token.kind)
-        {
+        let mut left_start = left.span().start;
+        while !self.at_statement_end() && precedence < get_token_precedence(&self.peek_token.kind) {
+            self.grow_tree()?;
             match self.parse_infix_expression(&left, left_start) {
                 Some(infix) => {
                     left = infix?;
-                    left_start = expression_start(&left);
+                    left_start = left.span().start;
                 }
                 None => {
                     return Ok((
@@ -233,6 +542,10 @@ impl<'a> Parser<'a> {
                 raw: *i,
                 span: self.current_token.clone().span,
             }))),
+            TokenKind::FLOAT(float) => Ok(Expression::LITERAL(Literal::Float(Float {
+                raw: float.0,
+                span: self.current_token.clone().span,
+            }))),
             TokenKind::STRING(s) => Ok(Expression::LITERAL(Literal::String(StringType {
                 raw: s.to_string(),
                 span: self.current_token.clone().span,
@@ -243,7 +556,61 @@ impl<'a> Parser<'a> {
                     span: self.current_token.clone().span,
                 })))
             }
-            TokenKind::BANG | TokenKind::MINUS => {
+            TokenKind::NULL => Ok(Expression::LITERAL(Literal::Null(Null {
+                span: self.current_token.clone().span,
+            }))),
+            // `-9223372036854775808` is i64::MIN even though its magnitude
+            // alone is out of range
+            TokenKind::MINUS
+                if self.peek_token.kind == TokenKind::ILLEGAL
+                    && self.lexer.source(&self.peek_token.span) == I64_MIN_MAGNITUDE =>
+            {
+                let start = self.current_token.span.start;
+                self.next_token();
+                Ok(Expression::LITERAL(Literal::Integer(Integer {
+                    raw: i64::MIN,
+                    span: Span {
+                        start,
+                        end: self.current_token.span.end,
+                    },
+                })))
+            }
+            TokenKind::ILLEGAL
+                if self
+                    .lexer
+                    .source(&self.current_token.span)
+                    .starts_with(|ch: char| ch.is_ascii_digit()) =>
+            {
+                // record the error and stand in a literal for the number, so
+                // parsing carries on after it rather than reporting the rest
+                // of the statement as well
+                let token = self.current_token.clone();
+                let literal = self.lexer.source(&token.span);
+                self.errors.push(match literal.contains('.') {
+                    true => ParseError::InvalidFloat {
+                        literal: literal.to_string(),
+                        token: token.clone(),
+                    },
+                    false => ParseError::IntegerOutOfRange {
+                        token: token.clone(),
+                    },
+                });
+                Ok(Expression::LITERAL(Literal::Integer(Integer {
+                    raw: 0,
+                    span: token.span,
+                })))
+            }
+            TokenKind::ILLEGAL
+                if self
+                    .lexer
+                    .source(&self.current_token.span)
+                    .starts_with("/*") =>
+            {
+                Err(ParseError::UnterminatedComment {
+                    token: self.current_token.clone(),
+                })
+            }
+            TokenKind::BANG | TokenKind::MINUS | TokenKind::PLUS => {
                 let start = self.current_token.span.start;
                 let prefix_op = self.current_token.clone();
                 self.next_token();
@@ -257,15 +624,17 @@ impl<'a> Parser<'a> {
                     },
                 }))
             }
-            TokenKind::LPAREN => {
-                self.next_token();
-                let expr = self.parse_expression(Precedence::Lowest)?.0;
-                self.expect_peek(&TokenKind::RPAREN)?;
+            TokenKind::LPAREN => self.with_newlines_significant(false, |parser| {
+                parser.next_token();
+                let expr = parser.parse_expression(Precedence::Lowest)?.0;
+                parser.expect_peek(&TokenKind::RPAREN)?;
                 Ok(expr)
-            }
+            }),
             TokenKind::IF => self.parse_if_expression(),
             TokenKind::WHILE => self.parse_while_expression(),
+            TokenKind::FOR => self.parse_for_expression(),
             TokenKind::FUNCTION => self.parse_fn_expression(),
+            TokenKind::MACRO => self.parse_macro_expression(),
             TokenKind::LBRACKET => {
                 let (elements, span) = self.parse_expression_list(&TokenKind::RBRACKET)?;
                 Ok(Expression::LITERAL(Literal::Array(Array {
@@ -290,13 +659,16 @@ impl<'a> Parser<'a> {
             | TokenKind::MINUS
             | TokenKind::ASTERISK
             | TokenKind::SLASH
+            | TokenKind::DIV
             | TokenKind::PERCENT
             | TokenKind::EQ
             | TokenKind::NotEq
             | TokenKind::LT
             | TokenKind::GT
             | TokenKind::LTE
-            | TokenKind::GTE => {
+            | TokenKind::GTE
+            | TokenKind::AND
+            | TokenKind::OR => {
                 self.next_token();
                 let infix_op = self.current_token.clone();
                 let precedence_value = get_token_precedence(&self.current_token.kind);
@@ -323,17 +695,21 @@ impl<'a> Parser<'a> {
                 self.next_token();
                 Some(self.parse_index_expression(left.clone()))
             }
+            TokenKind::DOT => {
+                self.next_token();
+                Some(self.parse_dot_expression(left.clone(), left_start))
+            }
+            TokenKind::QUESTION => {
+                self.next_token();
+                Some(self.parse_conditional_expression(left.clone(), left_start))
+            }
             _ => None,
         }
     }
 
     fn parse_if_expression(&mut self) -> Result<Expression, ParseError> {
         let start = self.current_token.span.start;
-        self.expect_peek(&TokenKind::LPAREN)?;
-        self.next_token();
-
-        let condition = self.parse_expression(Precedence::Lowest)?.0;
-        self.expect_peek(&TokenKind::RPAREN)?;
+        let condition = self.parse_condition()?;
         self.expect_peek(&TokenKind::LBRACE)?;
 
         let consequent = self.parse_block_statement()?;
@@ -356,7 +732,50 @@ impl<'a> Parser<'a> {
         }))
     }
 
+    // `cond ? a : b` is sugar for `if (cond) { a } else { b }`. The
+    // alternate is parsed at the lowest precedence, so a conditional in it
+    // nests to the right: `a ? b : c ? d : e` is `a ? b : (c ? d : e)`.
+    fn parse_conditional_expression(
+        &mut self,
+        condition: Expression,
+        start: usize,
+    ) -> Result<Expression, ParseError> {
+        self.next_token();
+        let (consequent, consequent_span) = self.parse_expression(Precedence::Lowest)?;
+        self.expect_peek(&TokenKind::COLON)?;
+        self.next_token();
+        let (alternate, alternate_span) = self.parse_expression(Precedence::Lowest)?;
+        let end = alternate_span.end;
+
+        Ok(Expression::IF(IF {
+            condition: Box::new(condition),
+            consequent: BlockStatement {
+                body: vec![Statement::Expr(consequent)],
+                span: consequent_span,
+            },
+            alternate: Some(BlockStatement {
+                body: vec![Statement::Expr(alternate)],
+                span: alternate_span,
+            }),
+            span: Span { start, end },
+        }))
+    }
+
+    fn parse_condition(&mut self) -> Result<Expression, ParseError> {
+        self.expect_peek(&TokenKind::LPAREN)?;
+        self.with_newlines_significant(false, |parser| {
+            parser.next_token();
+            let condition = parser.parse_expression(Precedence::Lowest)?.0;
+            parser.expect_peek(&TokenKind::RPAREN)?;
+            Ok(condition)
+        })
+    }
+
     fn parse_block_statement(&mut self) -> Result<BlockStatement, ParseError> {
+        self.with_newlines_significant(true, Self::parse_block_body)
+    }
+
+    fn parse_block_body(&mut self) -> Result<BlockStatement, ParseError> {
         let start = self.current_token.span.start;
         self.next_token();
         let mut block_statement = Vec::new();
@@ -381,11 +800,7 @@ impl<'a> Parser<'a> {
 
     fn parse_while_expression(&mut self) -> Result<Expression, ParseError> {
         let start = self.current_token.span.start;
-        self.expect_peek(&TokenKind::LPAREN)?;
-        self.next_token();
-
-        let condition = self.parse_expression(Precedence::Lowest)?.0;
-        self.expect_peek(&TokenKind::RPAREN)?;
+        let condition = self.parse_condition()?;
         self.expect_peek(&TokenKind::LBRACE)?;
 
         let body = self.parse_block_statement()?;
@@ -398,6 +813,31 @@ impl<'a> Parser<'a> {
         }))
     }
 
+    fn parse_for_expression(&mut self) -> Result<Expression, ParseError> {
+        let start = self.current_token.span.start;
+        self.expect_peek(&TokenKind::LPAREN)?;
+        let (variable, iterable) = self.with_newlines_significant(false, |parser| {
+            parser.next_token();
+            let variable = parser.parse_identifier()?;
+            parser.expect_peek(&TokenKind::IN)?;
+            parser.next_token();
+            let iterable = parser.parse_expression(Precedence::Lowest)?.0;
+            parser.expect_peek(&TokenKind::RPAREN)?;
+            Ok((variable, iterable))
+        })?;
+        self.expect_peek(&TokenKind::LBRACE)?;
+
+        let body = self.parse_block_statement()?;
+        let end = self.current_token.span.end;
+
+        Ok(Expression::ForIn(ForIn {
+            variable,
+            iterable: Box::new(iterable),
+            body,
+            span: Span { start, end },
+        }))
+    }
+
     fn parse_fn_expression(&mut self) -> Result<Expression, ParseError> {
         let start = self.current_token.span.start;
         self.expect_peek(&TokenKind::LPAREN)?;
@@ -418,6 +858,25 @@ impl<'a> Parser<'a> {
         }))
     }
 
+    fn parse_macro_expression(&mut self) -> Result<Expression, ParseError> {
+        let start = self.current_token.span.start;
+        self.expect_peek(&TokenKind::LPAREN)?;
+
+        let params = self.parse_fn_parameters()?;
+
+        self.expect_peek(&TokenKind::LBRACE)?;
+
+        let body = self.parse_block_statement()?;
+
+        let end = self.current_token.span.end;
+
+        Ok(Expression::Macro(MacroLiteral {
+            params,
+            body,
+            span: Span { start, end },
+        }))
+    }
+
     fn parse_fn_parameters(&mut self) -> Result<Vec<IDENTIFIER>, ParseError> {
         let mut params = Vec::new();
         if self.peek_token_is(&TokenKind::RPAREN) {
@@ -461,7 +920,7 @@ impl<'a> Parser<'a> {
     }
 
     fn parse_fn_call_expression(&mut self, expr: Expression) -> Result<Expression, ParseError> {
-        let start = expression_start(&expr);
+        let start = expr.span().start;
         let (arguments, ..) = self.parse_expression_list(&TokenKind::RPAREN)?;
         let end = self.current_token.span.end;
         let callee = Box::new(expr);
@@ -473,9 +932,55 @@ impl<'a> Parser<'a> {
         }))
     }
 
+    // `value.name(args)` is a method call; `value.name` without a following `(`
+    // is field access and parses as `value["name"]`.
+    fn parse_dot_expression(
+        &mut self,
+        receiver: Expression,
+        start: usize,
+    ) -> Result<Expression, ParseError> {
+        let dot = self.current_token.span.clone();
+        self.next_token();
+        let method = self.parse_identifier()?;
+
+        if !self.peek_token_is(&TokenKind::LPAREN) || self.at_statement_end() {
+            let end = method.span.end;
+            return Ok(Expression::Index(Index {
+                object: Box::new(receiver),
+                index: Box::new(Expression::LITERAL(Literal::String(StringType {
+                    raw: method.name,
+                    span: method.span,
+                }))),
+                span: Span {
+                    start: dot.start,
+                    end,
+                },
+            }));
+        }
+
+        self.next_token();
+        let (arguments, ..) = self.parse_expression_list(&TokenKind::RPAREN)?;
+        let end = self.current_token.span.end;
+
+        Ok(Expression::MethodCall(MethodCall {
+            receiver: Box::new(receiver),
+            dot,
+            method,
+            arguments,
+            span: Span { start, end },
+        }))
+    }
+
     fn parse_expression_list(
         &mut self,
         end: &TokenKind,
+    ) -> Result<(Vec<Expression>, Span), ParseError> {
+        self.with_newlines_significant(false, |parser| parser.parse_expression_list_items(end))
+    }
+
+    fn parse_expression_list_items(
+        &mut self,
+        end: &TokenKind,
     ) -> Result<(Vec<Expression>, Span), ParseError> {
         let start = self.current_token.span.start;
         let mut expr_list = Vec::new();
@@ -503,10 +1008,12 @@ impl<'a> Parser<'a> {
 
     fn parse_index_expression(&mut self, left: Expression) -> Result<Expression, ParseError> {
         let start = self.current_token.span.start;
-        self.next_token();
-        let index = self.parse_expression(Precedence::Lowest)?.0;
-
-        self.expect_peek(&TokenKind::RBRACKET)?;
+        let index = self.with_newlines_significant(false, |parser| {
+            parser.next_token();
+            let index = parser.parse_expression(Precedence::Lowest)?.0;
+            parser.expect_peek(&TokenKind::RBRACKET)?;
+            Ok(index)
+        })?;
 
         let end = self.current_token.span.end;
 
@@ -518,6 +1025,10 @@ impl<'a> Parser<'a> {
     }
 
     fn parse_hash_expression(&mut self) -> Result<Expression, ParseError> {
+        self.with_newlines_significant(false, Self::parse_hash_entries)
+    }
+
+    fn parse_hash_entries(&mut self) -> Result<Expression, ParseError> {
         let mut map = Vec::new();
         let start = self.current_token.span.start;
         while !self.peek_token_is(&TokenKind::RBRACE) {
@@ -555,32 +1066,58 @@ pub fn parse(input: &str) -> Result<Node, ParseErrors> {
     Ok(Node::Program(program))
 }
 
+/// Like `parse`, but a line break also ends a statement when the next line
+/// starts a new one, so `let a = 1\nlet b = a` needs no semicolons.
+pub fn parse_with_asi(input: &str) -> Result<Node, ParseErrors> {
+    let mut lexer = Lexer::new(input);
+    lexer.set_emit_newlines(true);
+    let mut parser = Parser::new(lexer);
+    let program = parser.parse_program()?;
+
+    Ok(Node::Program(program))
+}
+
 pub fn parse_ast_json_string(input: &str) -> Result<String, ParseErrors> {
     let node = parse(input)?;
     serde_json::to_string_pretty(&node)
         .map_err(|err| vec![ParseError::SerializeAst(err.to_string())])
 }
 
-fn expression_start(expr: &Expression) -> usize {
-    match expr {
-        Expression::IDENTIFIER(identifier) => identifier.span.start,
-        Expression::LITERAL(literal) => literal_start(literal),
-        Expression::PREFIX(prefix) => prefix.span.start,
-        Expression::INFIX(infix) => infix.span.start,
-        Expression::IF(if_expr) => if_expr.span.start,
-        Expression::While(while_expr) => while_expr.span.start,
-        Expression::FUNCTION(function) => function.span.start,
-        Expression::FunctionCall(call) => call.span.start,
-        Expression::Index(index) => index.span.start,
+fn read_token(lexer: &mut Lexer) -> (Token, bool) {
+    let mut after_newline = false;
+    loop {
+        let token = lexer.next_token();
+        if token.kind != TokenKind::NEWLINE {
+            return (token, after_newline);
+        }
+        after_newline = true;
     }
 }
 
-fn literal_start(literal: &Literal) -> usize {
-    match literal {
-        Literal::Integer(integer) => integer.span.start,
-        Literal::Boolean(boolean) => boolean.span.start,
-        Literal::String(string) => string.span.start,
-        Literal::Array(array) => array.span.start,
-        Literal::Hash(hash) => hash.span.start,
-    }
+fn can_start_statement(kind: &TokenKind) -> bool {
+    matches!(
+        kind,
+        TokenKind::IDENTIFIER { .. }
+            | TokenKind::INT(_)
+            | TokenKind::FLOAT(_)
+            | TokenKind::STRING(_)
+            | TokenKind::TRUE
+            | TokenKind::FALSE
+            | TokenKind::NULL
+            | TokenKind::BANG
+            | TokenKind::MINUS
+            | TokenKind::PLUS
+            | TokenKind::LPAREN
+            | TokenKind::LBRACKET
+            | TokenKind::LBRACE
+            | TokenKind::IF
+            | TokenKind::WHILE
+            | TokenKind::FOR
+            | TokenKind::FUNCTION
+            | TokenKind::MACRO
+            | TokenKind::LET
+            | TokenKind::RETURN
+            | TokenKind::BREAK
+            | TokenKind::CONTINUE
+    )
 }
@@ -12,7 +12,8 @@ pub fn main() {
             std::process::exit(0)
         }
 
-        let lexer = Lexer::new(&input);
+        let mut lexer = Lexer::new(&input);
+        lexer.set_emit_newlines(true);
         let mut parser = Parser::new(lexer);
         match parser.parse_program() {
             Ok(program) => println!("{}", program),
@@ -0,0 +1,220 @@
+use crate::ast::{
+    Array, Assign, BinaryExpression, BlockStatement, Expression, ForIn, FunctionCall,
+    FunctionDeclaration, Hash, IF, Index, IndexAssign, Let, Literal, MethodCall, Program,
+    ReturnStatement, Statement, UnaryExpression, While,
+};
+
+// Bottom-up rewriting: children are rebuilt first, then `modifier` sees the
+// node itself. Macro literals are templates and are left untouched.
+pub fn modify_program<E, F>(program: Program, modifier: &mut F) -> Result<Program, E>
+where
+    F: FnMut(Expression) -> Result<Expression, E>,
+{
+    Ok(Program {
+        body: modify_statements(program.body, modifier)?,
+        span: program.span,
+    })
+}
+
+pub fn modify_statement<E, F>(statement: Statement, modifier: &mut F) -> Result<Statement, E>
+where
+    F: FnMut(Expression) -> Result<Expression, E>,
+{
+    Ok(match statement {
+        Statement::Let(Let {
+            identifier,
+            expr,
+            span,
+        }) => Statement::Let(Let {
+            identifier,
+            expr: modify_expression(expr, modifier)?,
+            span,
+        }),
+        Statement::Assign(Assign { name, expr, span }) => Statement::Assign(Assign {
+            name,
+            expr: modify_expression(expr, modifier)?,
+            span,
+        }),
+        Statement::IndexAssign(IndexAssign {
+            name,
+            index,
+            value,
+            span,
+        }) => Statement::IndexAssign(IndexAssign {
+            name,
+            index: modify_expression(index, modifier)?,
+            value: modify_expression(value, modifier)?,
+            span,
+        }),
+        Statement::Return(ReturnStatement { argument, span }) => {
+            Statement::Return(ReturnStatement {
+                argument: modify_expression(argument, modifier)?,
+                span,
+            })
+        }
+        statement @ (Statement::Break(_) | Statement::Continue(_)) => statement,
+        Statement::Expr(expr) => Statement::Expr(modify_expression(expr, modifier)?),
+    })
+}
+
+pub fn modify_expression<E, F>(expression: Expression, modifier: &mut F) -> Result<Expression, E>
+where
+    F: FnMut(Expression) -> Result<Expression, E>,
+{
+    let rebuilt = match expression {
+        Expression::LITERAL(Literal::Array(Array { elements, span })) => {
+            Expression::LITERAL(Literal::Array(Array {
+                elements: modify_expressions(elements, modifier)?,
+                span,
+            }))
+        }
+        Expression::LITERAL(Literal::Hash(Hash { elements, span })) => {
+            let mut pairs = Vec::with_capacity(elements.len());
+            for (key, value) in elements {
+                pairs.push((
+                    modify_expression(key, modifier)?,
+                    modify_expression(value, modifier)?,
+                ));
+            }
+            Expression::LITERAL(Literal::Hash(Hash {
+                elements: pairs,
+                span,
+            }))
+        }
+        Expression::PREFIX(UnaryExpression { op, operand, span }) => {
+            Expression::PREFIX(UnaryExpression {
+                op,
+                operand: Box::new(modify_expression(*operand, modifier)?),
+                span,
+            })
+        }
+        Expression::INFIX(BinaryExpression {
+            op,
+            left,
+            right,
+            span,
+        }) => Expression::INFIX(BinaryExpression {
+            op,
+            left: Box::new(modify_expression(*left, modifier)?),
+            right: Box::new(modify_expression(*right, modifier)?),
+            span,
+        }),
+        Expression::IF(IF {
+            condition,
+            consequent,
+            alternate,
+            span,
+        }) => Expression::IF(IF {
+            condition: Box::new(modify_expression(*condition, modifier)?),
+            consequent: modify_block(consequent, modifier)?,
+            alternate: match alternate {
+                Some(block) => Some(modify_block(block, modifier)?),
+                None => None,
+            },
+            span,
+        }),
+        Expression::While(While {
+            condition,
+            body,
+            span,
+        }) => Expression::While(While {
+            condition: Box::new(modify_expression(*condition, modifier)?),
+            body: modify_block(body, modifier)?,
+            span,
+        }),
+        Expression::ForIn(ForIn {
+            variable,
+            iterable,
+            body,
+            span,
+        }) => Expression::ForIn(ForIn {
+            variable,
+            iterable: Box::new(modify_expression(*iterable, modifier)?),
+            body: modify_block(body, modifier)?,
+            span,
+        }),
+        Expression::FUNCTION(FunctionDeclaration {
+            params,
+            body,
+            span,
+            name,
+        }) => Expression::FUNCTION(FunctionDeclaration {
+            params,
+            body: modify_block(body, modifier)?,
+            span,
+            name,
+        }),
+        Expression::FunctionCall(FunctionCall {
+            callee,
+            arguments,
+            span,
+        }) => Expression::FunctionCall(FunctionCall {
+            callee: Box::new(modify_expression(*callee, modifier)?),
+            arguments: modify_expressions(arguments, modifier)?,
+            span,
+        }),
+        Expression::MethodCall(MethodCall {
+            receiver,
+            dot,
+            method,
+            arguments,
+            span,
+        }) => Expression::MethodCall(MethodCall {
+            receiver: Box::new(modify_expression(*receiver, modifier)?),
+            dot,
+            method,
+            arguments: modify_expressions(arguments, modifier)?,
+            span,
+        }),
+        Expression::Index(Index {
+            object,
+            index,
+            span,
+        }) => Expression::Index(Index {
+            object: Box::new(modify_expression(*object, modifier)?),
+            index: Box::new(modify_expression(*index, modifier)?),
+            span,
+        }),
+        other @ (Expression::IDENTIFIER(_) | Expression::LITERAL(_) | Expression::Macro(_)) => {
+            other
+        }
+    };
+
+    modifier(rebuilt)
+}
+
+pub fn modify_block<E, F>(block: BlockStatement, modifier: &mut F) -> Result<BlockStatement, E>
+where
+    F: FnMut(Expression) -> Result<Expression, E>,
+{
+    Ok(BlockStatement {
+        body: modify_statements(block.body, modifier)?,
+        span: block.span,
+    })
+}
+
+fn modify_statements<E, F>(
+    statements: Vec<Statement>,
+    modifier: &mut F,
+) -> Result<Vec<Statement>, E>
+where
+    F: FnMut(Expression) -> Result<Expression, E>,
+{
+    statements
+        .into_iter()
+        .map(|statement| modify_statement(statement, modifier))
+        .collect()
+}
+
+fn modify_expressions<E, F>(
+    expressions: Vec<Expression>,
+    modifier: &mut F,
+) -> Result<Vec<Expression>, E>
+where
+    F: FnMut(Expression) -> Result<Expression, E>,
+{
+    expressions
+        .into_iter()
+        .map(|expression| modify_expression(expression, modifier))
+        .collect()
+}
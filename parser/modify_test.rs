@@ -0,0 +1,45 @@
+#[cfg(test)]
+mod tests {
+    use crate::ast::{Expression, Integer, Literal, Node};
+    use crate::modify::modify_program;
+    use crate::parse;
+
+    fn turn_one_into_two(expression: Expression) -> Result<Expression, ()> {
+        match expression {
+            Expression::LITERAL(Literal::Integer(Integer { raw: 1, span })) => {
+                Ok(Expression::LITERAL(Literal::Integer(Integer {
+                    raw: 2,
+                    span,
+                })))
+            }
+            other => Ok(other),
+        }
+    }
+
+    #[test]
+    fn test_modify_rewrites_every_position() {
+        let tests = [
+            ("1", "2"),
+            ("1 + 1", "(2 + 2)"),
+            ("-1", "(-2)"),
+            ("a[1]", "(a[2])"),
+            ("if (1) { 1 } else { 1 }", "if 2 { 2 } else { 2 }"),
+            ("while (1) { 1 }", "while (2) { 2 }"),
+            ("return 1;", "return 2;"),
+            ("let x = 1;", "let x = 2;"),
+            ("fn() { 1 }", "fn () { 2 }"),
+            ("f(1, 1)", "f(2, 2)"),
+            ("[1, 1]", "[2, 2]"),
+            ("{1: 1}", "{2: 2}"),
+            ("macro(x) { 1 }", "macro(x) { 1 }"),
+        ];
+
+        for (input, expected) in tests {
+            let Node::Program(program) = parse(input).unwrap() else {
+                panic!("expected a program");
+            };
+            let modified = modify_program(program, &mut turn_one_into_two).unwrap();
+            assert_eq!(modified.to_string(), expected);
+        }
+    }
+}
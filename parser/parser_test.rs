@@ -1,6 +1,8 @@
 #[cfg(test)]
 mod tests {
-    use crate::parse;
+    use crate::ast::{Expression, Literal, Node, Statement};
+    use crate::lexer::token::Span;
+    use crate::{ParseError, parse};
 
     fn verify_program(test_cases: &[(&str, &str)]) {
         for (input, expected) in test_cases {
@@ -27,6 +29,15 @@ mod tests {
             ("return 5", "return 5;"),
             ("return true;", "return true;"),
             ("return foobar;", "return foobar;"),
+            // a bare `return` gives back null
+            ("return;", "return null;"),
+            ("return", "return null;"),
+            ("fn() { return }", "fn () { return null; }"),
+            ("fn() { return; }", "fn () { return null; }"),
+            (
+                "if (x) { return } else { 1 }",
+                "if x { return null; } else { 1 }",
+            ),
         ];
 
         verify_program(&let_tests);
@@ -41,6 +52,11 @@ mod tests {
             ("-foobar;", "(-foobar)"),
             ("!true;", "(!true)"),
             ("!false;", "(!false)"),
+            ("+5;", "(+5)"),
+            ("-+5;", "(-(+5))"),
+            ("--5;", "(-(-5))"),
+            ("- -5;", "(-(-5))"),
+            ("!!true;", "(!(!true))"),
         ];
 
         verify_program(&let_tests);
@@ -93,6 +109,17 @@ mod tests {
             ),
             ("true", "true"),
             ("false", "false"),
+            ("null", "null"),
+            ("a || b ? c + 1 : d", "if (a || b) { (c + 1) } else { d }"),
+            (
+                "a ? b : c ? d : e",
+                "if a { b } else { if c { d } else { e } }",
+            ),
+            (
+                "a ? b ? c : d : e",
+                "if a { if b { c } else { d } } else { e }",
+            ),
+            ("!null == true", "((!null) == true)"),
             ("3 > 5 == false", "((3 > 5) == false)"),
             ("3 < 5 == true", "((3 < 5) == true)"),
         ];
@@ -136,6 +163,15 @@ mod tests {
         verify_program(&tt);
     }
 
+    #[test]
+    fn test_macro_literal_expression() {
+        let tt = [
+            ("macro() {};", "macro() {  }"),
+            ("macro(x, y) { x + y; };", "macro(x, y) { (x + y) }"),
+        ];
+        verify_program(&tt);
+    }
+
     #[test]
     fn test_fn_call_else_expression() {
         let tt = [("add(1, 2 * 3, 4 + 5);", "add(1, (2 * 3), (4 + 5))")];
@@ -154,6 +190,33 @@ mod tests {
         verify_program(&test_case);
     }
 
+    #[test]
+    fn test_method_call_expression() {
+        let tt = [
+            ("xs.len()", "xs.len()"),
+            ("xs.push(1, 2 * 3)", "xs.push(1, (2 * 3))"),
+            ("xs.rest().first()", "xs.rest().first()"),
+            ("-xs.len()", "(-xs.len())"),
+            ("a + b.len() * 2", "(a + (b.len() * 2))"),
+            ("xs[0].len()", "(xs[0]).len()"),
+            ("f(x).len()", "f(x).len()"),
+        ];
+        verify_program(&tt);
+    }
+
+    #[test]
+    fn test_dot_access_expression() {
+        let tt = [
+            ("person.name", r#"(person["name"])"#),
+            ("person.address.city", r#"((person["address"])["city"])"#),
+            ("h.keys()", "h.keys()"),
+            ("h.keys", r#"(h["keys"])"#),
+            ("h.items[0].name", r#"(((h["items"])[0])["name"])"#),
+            ("h.name.len()", r#"(h["name"]).len()"#),
+        ];
+        verify_program(&tt);
+    }
+
     #[test]
     fn test_index_expression() {
         let test_case = [("a[1]", "(a[1])"), ("a[1 + 1]", "(a[(1 + 1)])")];
@@ -179,7 +242,13 @@ mod tests {
             ("a >= b", "(a >= b)"),
             ("a % b", "(a % b)"),
             ("a + b % c", "(a + (b % c))"),
+            ("a * b % c", "((a * b) % c)"),
+            ("a % b * c", "((a % b) * c)"),
             ("a <= b == true", "((a <= b) == true)"),
+            ("7 div 2;", "(7 div 2)"),
+            ("a + b div c", "(a + (b div c))"),
+            ("a div b * c", "((a div b) * c)"),
+            ("-a div b", "((-a) div b)"),
         ];
         verify_program(&tt);
     }
@@ -200,4 +269,259 @@ mod tests {
         ];
         verify_program(&test_case);
     }
+
+    #[test]
+    fn test_shebang_keeps_spans() {
+        let input = "#!/usr/bin/env monkey\nlet x = 5;";
+        let start = input.find("let").unwrap();
+        match parse(input).unwrap() {
+            crate::ast::Node::Program(program) => {
+                assert_eq!(program.body.len(), 1);
+                assert_eq!(program.body[0].span().start, start);
+                assert_eq!(program.body[0].span().end, input.len());
+            }
+            node => panic!("expected program, got {}", node),
+        }
+
+        assert!(parse("let x = 5;\n#!/usr/bin/env monkey").is_err());
+    }
+
+    fn integer_literal(input: &str) -> (i64, Span) {
+        match parse(input).unwrap() {
+            Node::Program(program) => match &program.body[..] {
+                [Statement::Expr(Expression::LITERAL(Literal::Integer(integer)))] => {
+                    (integer.raw, integer.span.clone())
+                }
+                body => panic!("expected one integer literal, got {:?}", body),
+            },
+            node => panic!("expected program, got {}", node),
+        }
+    }
+
+    #[test]
+    fn test_integer_literal_limits() {
+        assert_eq!(
+            integer_literal("9223372036854775807"),
+            (i64::MAX, Span { start: 0, end: 19 })
+        );
+        // the minus sign is folded into the literal only for i64::MIN
+        assert_eq!(
+            integer_literal("-9223372036854775808"),
+            (i64::MIN, Span { start: 0, end: 20 })
+        );
+        assert_eq!(
+            integer_literal("- 9223372036854775808"),
+            (i64::MIN, Span { start: 0, end: 21 })
+        );
+        verify_program(&[
+            ("-9223372036854775807", "(-9223372036854775807)"),
+            ("1 - -9223372036854775808", "(1 - -9223372036854775808)"),
+        ]);
+    }
+
+    #[test]
+    fn test_integer_literal_out_of_range() {
+        for (input, start, end) in [
+            ("9223372036854775808", 0, 19),
+            ("let x = 99999999999999999999;", 8, 28),
+            ("1 - 9223372036854775808", 4, 23),
+            ("-9223372036854775809", 1, 20),
+        ] {
+            let errors = parse(input).unwrap_err();
+            assert_eq!(errors.len(), 1, "{}", input);
+            match &errors[0] {
+                ParseError::IntegerOutOfRange { token } => {
+                    assert_eq!(token.span, Span { start, end }, "{}", input)
+                }
+                error => panic!("expected IntegerOutOfRange for {}, got {}", input, error),
+            }
+        }
+    }
+
+    #[test]
+    fn test_float_literals() {
+        verify_program(&[
+            ("3.14", "3.14"),
+            ("let half = 0.50;", "let half = 0.5;"),
+            ("1 + 2.0 * x", "(1 + (2.0 * x))"),
+            ("-0.5", "(-0.5)"),
+            ("1.len()", "1.len()"),
+        ]);
+
+        let errors = parse("let v = 1.2.3;").unwrap_err();
+        assert_eq!(errors.len(), 1);
+        match &errors[0] {
+            ParseError::InvalidFloat { token, .. } => {
+                assert_eq!(token.span, Span { start: 8, end: 13 })
+            }
+            error => panic!("expected InvalidFloat, got {}", error),
+        }
+        assert_eq!(
+            errors[0].to_string(),
+            "invalid number literal 1.2.3: a number has at most one decimal point"
+        );
+    }
+
+    #[test]
+    fn test_logical_operator_precedence() {
+        verify_program(&[
+            ("a && b || c", "((a && b) || c)"),
+            ("a || b && c", "(a || (b && c))"),
+            ("a == b && c < d", "((a == b) && (c < d))"),
+            ("!a || b", "((!a) || b)"),
+        ]);
+    }
+
+    #[test]
+    fn test_assign_statement() {
+        verify_program(&[
+            ("x = x + 1;", "x = (x + 1);"),
+            ("x = 5", "x = 5;"),
+            ("x == 5", "(x == 5)"),
+            (
+                "let f = fn(a) { a = 2; a };",
+                "let f = fn f(a) { a = 2;a };",
+            ),
+        ]);
+
+        match parse("x = 1;").unwrap() {
+            Node::Program(program) => match &program.body[..] {
+                [Statement::Assign(assign)] => {
+                    assert_eq!(assign.name.name, "x");
+                    assert_eq!(assign.name.span, Span { start: 0, end: 1 });
+                    assert_eq!(assign.span, Span { start: 0, end: 6 });
+                }
+                body => panic!("expected one assignment, got {:?}", body),
+            },
+            node => panic!("expected program, got {}", node),
+        }
+    }
+
+    #[test]
+    fn test_index_assign_statement() {
+        verify_program(&[
+            ("xs[0] = 1;", "xs[0] = 1;"),
+            ("h[\"a\" + b] = h[c]", "h[(\"a\" + b)] = (h[c]);"),
+        ]);
+
+        match parse("xs[0] = 1;").unwrap() {
+            Node::Program(program) => match &program.body[..] {
+                [Statement::IndexAssign(assign)] => {
+                    assert_eq!(assign.name.name, "xs");
+                    assert_eq!(assign.span, Span { start: 0, end: 10 });
+                }
+                body => panic!("expected one index assignment, got {:?}", body),
+            },
+            node => panic!("expected program, got {}", node),
+        }
+
+        for (source, target) in [
+            ("a[0][1] = 2;", "((a[0])[1])"),
+            ("h.x.y = 5;", "((h[\"x\"])[\"y\"])"),
+            ("1 = 2;", "1"),
+        ] {
+            let errors = parse(source).unwrap_err();
+            match &errors[0] {
+                ParseError::InvalidAssignmentTarget { target: t, .. } => {
+                    assert_eq!(t, target, "{}", source)
+                }
+                error => panic!(
+                    "expected InvalidAssignmentTarget for {}, got {}",
+                    source, error
+                ),
+            }
+        }
+
+        // only one level of indexing can be assigned through
+        assert_eq!(
+            parse("a[0][0] = 7;").unwrap_err()[0].to_string(),
+            "cannot assign to ((a[0])[0]): only a variable, variable[index] or variable.field can be assigned"
+        );
+    }
+
+    #[test]
+    fn test_for_in_expression() {
+        verify_program(&[
+            ("for (x in xs) { x }", "for (x in xs) { x }"),
+            (
+                "for (k in {1: 2}) { puts(k); }",
+                "for (k in {1: 2}) { puts(k) }",
+            ),
+            (
+                "for (x in\n f(y)\n) { break; }",
+                "for (x in f(y)) { break; }",
+            ),
+        ]);
+
+        match parse("for (x in xs) { x }").unwrap() {
+            Node::Program(program) => match &program.body[..] {
+                [Statement::Expr(Expression::ForIn(for_node))] => {
+                    assert_eq!(for_node.variable.name, "x");
+                    assert_eq!(for_node.variable.span, Span { start: 5, end: 6 });
+                    assert_eq!(for_node.span, Span { start: 0, end: 19 });
+                }
+                body => panic!("expected one for loop, got {:?}", body),
+            },
+            node => panic!("expected program, got {}", node),
+        }
+
+        for source in ["for (1 in xs) { }", "for (x of xs) { }", "for x in xs { }"] {
+            assert!(parse(source).is_err(), "{}", source);
+        }
+    }
+
+    #[test]
+    fn test_break_and_continue() {
+        verify_program(&[
+            ("while (x) { break; }", "while (x) { break; }"),
+            ("while (x) { continue }", "while (x) { continue; }"),
+            (
+                "while (x) { if (y) { break; } }",
+                "while (x) { if y { break; } }",
+            ),
+        ]);
+
+        match parse("break;\ncontinue").unwrap() {
+            Node::Program(program) => match &program.body[..] {
+                [Statement::Break(b), Statement::Continue(c)] => {
+                    assert_eq!(b.span, Span { start: 0, end: 6 });
+                    assert_eq!(c.span, Span { start: 7, end: 15 });
+                }
+                body => panic!("expected break and continue, got {:?}", body),
+            },
+            node => panic!("expected program, got {}", node),
+        }
+    }
+
+    #[test]
+    fn test_block_comments() {
+        verify_program(&[
+            ("let /* a */ a = /* b */ 3", "let a = 3;"),
+            ("1 /* - 2 */ + 3", "(1 + 3)"),
+            ("/* leading */ x /* trailing */", "x"),
+        ]);
+
+        for source in ["let a = 1; /* oops", "let a = 1 /* oops", "f(/*"] {
+            let errors = parse(source).unwrap_err();
+            match &errors[0] {
+                ParseError::UnterminatedComment { token } => {
+                    assert_eq!(token.span.end, source.len(), "{}", source)
+                }
+                error => panic!("expected UnterminatedComment for {}, got {}", source, error),
+            }
+            assert_eq!(errors[0].to_string(), "unterminated block comment");
+        }
+    }
+
+    #[test]
+    fn test_render_underlines_the_span() {
+        let source = "let a = 1;\nlet x = 99999999999999999999;\n";
+        let errors = parse(source).unwrap_err();
+        assert_eq!(
+            errors[0].render(source),
+            "integer literal out of range for 64-bit integer\n\
+             \x20  2 | let x = 99999999999999999999;\n\
+             \x20    |         ^^^^^^^^^^^^^^^^^^^^"
+        );
+    }
 }
@@ -3,6 +3,9 @@ use lexer::token::TokenKind;
 #[derive(Debug, Copy, Clone, PartialEq, PartialOrd)]
 pub enum Precedence {
     Lowest,
+    Conditional, // a ? b : c
+    LogicalOr,   // ||
+    LogicalAnd,  // &&
     Equals,      // ==
     LessGreater, // > or <
     Sum,         // + or =
@@ -10,10 +13,14 @@ pub enum Precedence {
     Prefix,      // -X or !X
     Call,        // myFunction(x)
     Index,       // array[index]
+    Member,      // value.method()
 }
 
 pub fn get_token_precedence(token: &TokenKind) -> Precedence {
     match token {
+        TokenKind::QUESTION => Precedence::Conditional,
+        TokenKind::OR => Precedence::LogicalOr,
+        TokenKind::AND => Precedence::LogicalAnd,
         TokenKind::EQ => Precedence::Equals,
         TokenKind::NotEq => Precedence::Equals,
         TokenKind::LT => Precedence::LessGreater,
@@ -25,8 +32,10 @@ pub fn get_token_precedence(token: &TokenKind) -> Precedence {
         TokenKind::ASTERISK => Precedence::Product,
         TokenKind::SLASH => Precedence::Product,
         TokenKind::PERCENT => Precedence::Product,
+        TokenKind::DIV => Precedence::Product,
         TokenKind::LPAREN => Precedence::Call,
         TokenKind::LBRACKET => Precedence::Index,
+        TokenKind::DOT => Precedence::Member,
         _ => Precedence::Lowest,
     }
 }